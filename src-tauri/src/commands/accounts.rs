@@ -0,0 +1,81 @@
+// Email account command handlers
+
+use crate::db::repo::EmailAccountRepo;
+use crate::db::{DatabaseType, DbState};
+use crate::models::EmailAccount;
+use crate::services::credentials::{self, Secret};
+use crate::services::oauth::{OAuthClient, OAuthConfig};
+use crate::utils::{AppError, AppResult};
+use tauri::{AppHandle, State};
+use tauri_plugin_opener::OpenerExt;
+
+#[tauri::command]
+pub async fn get_email_accounts(db: State<'_, DbState>, test_mode: bool) -> AppResult<Vec<EmailAccount>> {
+    db.run(DatabaseType::from_test_mode(test_mode), |conn| EmailAccountRepo::new(conn).list()).await
+}
+
+#[tauri::command]
+pub async fn get_email_account_by_id(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<EmailAccount> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| EmailAccountRepo::new(conn).get(id)).await
+}
+
+#[tauri::command]
+pub async fn create_email_account(db: State<'_, DbState>, account: EmailAccount, test_mode: bool) -> AppResult<i64> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| EmailAccountRepo::new(conn).create(&account)).await
+}
+
+#[tauri::command]
+pub async fn update_email_account(db: State<'_, DbState>, account: EmailAccount, test_mode: bool) -> AppResult<()> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| EmailAccountRepo::new(conn).update(&account)).await
+}
+
+/// Delete the account and forget its keyring entries
+#[tauri::command]
+pub async fn delete_email_account(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<()> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    db.run(db_type, move |conn| EmailAccountRepo::new(conn).delete(id)).await?;
+    credentials::delete_all(db_type, id)
+}
+
+#[tauri::command]
+pub fn save_account_password(account_id: i64, password: String, test_mode: bool) -> AppResult<()> {
+    credentials::save(DatabaseType::from_test_mode(test_mode), account_id, Secret::Password, &password)
+}
+
+/// Sign in with the account's OAuth provider in the browser and keep the refresh token
+#[tauri::command]
+pub async fn authorize_account_oauth(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    account_id: i64,
+    test_mode: bool,
+) -> AppResult<()> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let account = db.run(db_type, move |conn| EmailAccountRepo::new(conn).get(account_id)).await?;
+    let client = OAuthClient::new(OAuthConfig::from_account(&account)?);
+
+    let tokens = client
+        .authorize(|url| {
+            app.opener()
+                .open_url(url, None::<&str>)
+                .map_err(|e| AppError::Internal(format!("Failed to open browser: {}", e)))
+        })
+        .await?;
+
+    let refresh_token = tokens
+        .refresh_token
+        .ok_or_else(|| AppError::Validation("Provider did not return a refresh token".to_string()))?;
+    credentials::save(db_type, account_id, Secret::OAuthRefreshToken, &refresh_token)
+}
+
+#[tauri::command]
+pub fn is_account_oauth_authorized(account_id: i64, test_mode: bool) -> AppResult<bool> {
+    let token = credentials::get(DatabaseType::from_test_mode(test_mode), account_id, Secret::OAuthRefreshToken)?;
+    Ok(token.is_some())
+}
+
+/// Forget the stored refresh token
+#[tauri::command]
+pub fn disconnect_account_oauth(account_id: i64, test_mode: bool) -> AppResult<()> {
+    credentials::delete(DatabaseType::from_test_mode(test_mode), account_id, Secret::OAuthRefreshToken)
+}
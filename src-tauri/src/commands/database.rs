@@ -1,41 +1,61 @@
 // Database management command handlers
 
-use crate::db::{clear_test_database, get_db_connection, get_db_path, DatabaseType};
-use crate::utils::AppResult;
-use std::fs;
+use crate::db::{clear_test_database, DatabaseType, DbState};
+use crate::utils::{AppError, AppResult};
+use rusqlite::{Connection, OpenFlags};
+use tauri::State;
 
 #[tauri::command]
-pub fn clear_test_db() -> AppResult<()> {
-    let conn = get_db_connection(DatabaseType::Test)?;
-    clear_test_database(&conn)?;
-    Ok(())
+pub async fn clear_test_db(db: State<'_, DbState>) -> AppResult<()> {
+    let attachments = db.attachments(DatabaseType::Test).clone();
+
+    db.run(DatabaseType::Test, move |conn| {
+        clear_test_database(conn)?;
+        attachments.prune(conn)?;
+        Ok(())
+    })
+    .await
 }
 
+/// Export the database and the attachment files it refers to into a new
+/// folder in Downloads; returns the folder's path
 #[tauri::command]
-pub fn export_database(test_mode: bool) -> AppResult<String> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let db_path = get_db_path(db_type)?;
-
+pub async fn export_database(db: State<'_, DbState>, test_mode: bool) -> AppResult<String> {
     // Get user's downloads directory
     let export_dir = dirs::download_dir()
-        .ok_or_else(|| crate::utils::error::AppError::NotFound("Could not find downloads directory".to_string()))?;
+        .ok_or_else(|| AppError::NotFound("Could not find downloads directory".to_string()))?;
 
     let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-    let export_filename = if test_mode {
-        format!("subscript_test_export_{}.db", timestamp)
+    let export_name = if test_mode {
+        format!("subscript_test_export_{}", timestamp)
     } else {
-        format!("subscript_export_{}.db", timestamp)
+        format!("subscript_export_{}", timestamp)
     };
 
-    let export_path = export_dir.join(export_filename);
-
-    // Copy the database file
-    fs::copy(&db_path, &export_path)?;
+    let export_path = export_dir.join(export_name);
+    std::fs::create_dir_all(&export_path)?;
+    let db_path = export_path.join("subscript.db");
+
+    // In WAL mode the main file alone may be stale, so write a consistent
+    // snapshot through SQLite instead of copying the file
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let target = db_path.to_string_lossy().to_string();
+    db.run(db_type, move |conn| {
+        conn.execute("VACUUM INTO ?1", [target])?;
+        Ok(())
+    })
+    .await?;
+
+    // Copy the files the snapshot refers to, not whatever the live database
+    // refers to by now
+    let attachments = db.attachments(db_type).clone();
+    let attachments_path = export_path.join("attachments");
+    tokio::task::spawn_blocking(move || {
+        let snapshot = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        attachments.export(&snapshot, &attachments_path)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Failed to export attachments: {}", e)))??;
 
     Ok(export_path.to_string_lossy().to_string())
 }
@@ -5,6 +5,7 @@ pub mod subscriptions;
 pub mod domains;
 pub mod pending_imports;
 pub mod settings;
+pub mod accounts;
 pub mod receipts;
 pub mod sync;
 pub mod database;
@@ -1,62 +1,116 @@
 // Receipt command handlers
 
-use crate::db::{get_db_connection, DatabaseType};
-use crate::models::Receipt;
-use crate::utils::AppResult;
+use crate::db::repo::{Page, ReceiptFilter, ReceiptRepo, ReceiptSourceRepo};
+use crate::db::{DatabaseType, DbState};
+use crate::models::{Receipt, ReceiptSource, ReprocessSummary};
+use crate::services::scheduler::SyncScheduler;
+use crate::utils::{AppError, AppResult};
 use chrono::{Duration, Utc};
+use tauri::ipc::Response;
+use tauri::State;
 
 #[tauri::command]
-pub fn get_receipt_by_id(id: i64, test_mode: bool) -> AppResult<Receipt> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    let receipt = conn
-        .query_row(
-            "SELECT id, subscription_id, domain_id, email_subject, email_from, email_date, file_type, file_data, raw_email_body, created_at FROM receipts WHERE id = ?1",
-            [id],
-            |row| {
-                Ok(Receipt {
-                    id: Some(row.get(0)?),
-                    subscription_id: row.get(1)?,
-                    domain_id: row.get(2)?,
-                    email_subject: row.get(3)?,
-                    email_from: row.get(4)?,
-                    email_date: row.get(5)?,
-                    file_type: row.get(6)?,
-                    file_data: row.get(7)?,
-                    raw_email_body: row.get(8)?,
-                    created_at: row.get(9)?,
-                })
-            },
-        )?;
-
-    Ok(receipt)
-}
-
-#[tauri::command]
-pub fn delete_old_receipts(test_mode: bool) -> AppResult<usize> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
+pub async fn get_receipts(
+    db: State<'_, DbState>,
+    test_mode: bool,
+    filter: Option<ReceiptFilter>,
+    page: Option<Page>,
+) -> AppResult<Vec<Receipt>> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| {
+        ReceiptRepo::new(conn).list(&filter.unwrap_or_default(), page)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_receipt_by_id(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<Receipt> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| ReceiptRepo::new(conn).get(id)).await
+}
+
+/// The attachments and body considered for the receipt, best first
+#[tauri::command]
+pub async fn get_receipt_sources(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<Vec<ReceiptSource>> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| ReceiptSourceRepo::new(conn).list(id)).await
+}
+
+/// Raw attachment bytes, delivered to the frontend as an `ArrayBuffer`
+#[tauri::command]
+pub async fn get_receipt_attachment(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<Response> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let attachments = db.attachments(db_type).clone();
+
+    let data = db
+        .run(db_type, move |conn| {
+            let hash = ReceiptRepo::new(conn)
+                .get(id)?
+                .file_hash
+                .ok_or_else(|| AppError::NotFound(format!("Attachment for receipt {}", id)))?;
+            attachments.read(&hash)
+        })
+        .await?;
+
+    Ok(Response::new(data))
+}
+
+/// Path of the attachment on disk, for opening it with the system viewer
+#[tauri::command]
+pub async fn get_receipt_attachment_path(
+    db: State<'_, DbState>,
+    id: i64,
+    test_mode: bool,
+) -> AppResult<Option<String>> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let attachments = db.attachments(db_type).clone();
 
+    db.run(db_type, move |conn| {
+        let receipt = ReceiptRepo::new(conn).get(id)?;
+        receipt
+            .file_hash
+            .map(|hash| Ok(attachments.path(&hash)?.to_string_lossy().to_string()))
+            .transpose()
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_receipt(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<()> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let attachments = db.attachments(db_type).clone();
+
+    db.run(db_type, move |conn| {
+        ReceiptRepo::new(conn).delete(id)?;
+        attachments.prune(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_old_receipts(db: State<'_, DbState>, test_mode: bool) -> AppResult<usize> {
     // Calculate date 1 year ago
     let one_year_ago = Utc::now() - Duration::days(365);
     let cutoff_date = one_year_ago.format("%Y-%m-%d %H:%M:%S").to_string();
 
-    let deleted = conn
-        .execute(
-            "DELETE FROM receipts WHERE created_at < ?1",
-            [cutoff_date],
-        )?;
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let attachments = db.attachments(db_type).clone();
 
-    Ok(deleted)
+    db.run(db_type, move |conn| {
+        let deleted = ReceiptRepo::new(conn).delete_created_before(&cutoff_date)?;
+        attachments.prune(conn)?;
+        Ok(deleted)
+    })
+    .await
+}
+
+/// Convert and extract a stored receipt again, e.g. after Ollama was down.
+/// Waits for a sync in progress, and `cancel_email_sync` stops it.
+#[tauri::command]
+pub async fn reprocess_receipt(scheduler: State<'_, SyncScheduler>, id: i64, test_mode: bool) -> AppResult<Receipt> {
+    scheduler.reprocess_receipt(test_mode, id).await
+}
+
+/// Retry every receipt whose extraction failed, like `reprocess_receipt`
+#[tauri::command]
+pub async fn reprocess_failed_receipts(scheduler: State<'_, SyncScheduler>, test_mode: bool) -> AppResult<ReprocessSummary> {
+    scheduler.reprocess_failed_receipts(test_mode).await
 }
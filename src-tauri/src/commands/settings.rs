@@ -1,43 +1,53 @@
 // Settings command handlers
 
-use crate::db::{get_db_connection, DatabaseType};
-use crate::models::AppSettings;
-use crate::services::ollama::OllamaService;
-use crate::utils::{get_current_timestamp, AppResult};
-use keyring::Entry;
-
+use crate::db::{DatabaseType, DbState};
+use crate::models::{AppSettings, EmailAccount, ImapAuthMethod, LlmProviderKind};
+use crate::services::llm;
+use crate::services::scheduler::SyncScheduler;
+use crate::services::sync::SyncService;
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::Connection;
+use tauri::State;
+
+/// Models served by `provider` at `endpoint`; none for the rules
 #[tauri::command]
-pub async fn get_ollama_models(endpoint: String) -> AppResult<Vec<String>> {
-    OllamaService::list_models(&endpoint).await
+pub async fn get_llm_models(provider: LlmProviderKind, endpoint: String) -> AppResult<Vec<String>> {
+    llm::list_models(provider, &endpoint).await
 }
 
 #[tauri::command]
-pub fn save_imap_password(password: String) -> AppResult<()> {
-    let entry = Entry::new("subscript", "imap_password")
-        .map_err(|e| crate::utils::AppError::Internal(format!("Keyring error: {}", e)))?;
-    entry.set_password(&password)
-        .map_err(|e| crate::utils::AppError::Internal(format!("Failed to save password: {}", e)))?;
-    Ok(())
+pub async fn get_settings(db: State<'_, DbState>, test_mode: bool) -> AppResult<AppSettings> {
+    db.run(DatabaseType::from_test_mode(test_mode), load_settings).await
 }
 
 #[tauri::command]
-pub fn get_imap_password() -> AppResult<String> {
-    let entry = Entry::new("subscript", "imap_password")
-        .map_err(|e| crate::utils::AppError::Internal(format!("Keyring error: {}", e)))?;
-    entry.get_password()
-        .map_err(|e| crate::utils::AppError::Internal(format!("Failed to get password: {}", e)))
+pub async fn update_settings(
+    db: State<'_, DbState>,
+    scheduler: State<'_, SyncScheduler>,
+    settings: AppSettings,
+    test_mode: bool,
+) -> AppResult<()> {
+    validate_settings(&settings)?;
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| save_settings(conn, settings)).await?;
+    // The background scheduler follows the production settings
+    if !test_mode {
+        scheduler.reschedule();
+    }
+    Ok(())
 }
 
-#[tauri::command]
-pub fn get_settings(test_mode: bool) -> AppResult<AppSettings> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
+/// Reject values the sync cannot run with
+pub(crate) fn validate_settings(settings: &AppSettings) -> AppResult<()> {
+    if !(1..=llm::MAX_CONCURRENCY).contains(&settings.extraction_concurrency) {
+        return Err(AppError::Validation(format!(
+            "Parallel extractions must be between 1 and {}",
+            llm::MAX_CONCURRENCY
+        )));
+    }
+    Ok(())
+}
 
+pub(crate) fn load_settings(conn: &Connection) -> AppResult<AppSettings> {
     let mut stmt = conn
         .prepare("SELECT key, value FROM settings")?;
 
@@ -46,19 +56,10 @@ pub fn get_settings(test_mode: bool) -> AppResult<AppSettings> {
         .collect::<Result<_, _>>()?;
 
     let settings = AppSettings {
-        imap_server: settings_map.get("imap_server").cloned().unwrap_or_default(),
-        imap_port: settings_map
-            .get("imap_port")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(993),
-        imap_username: settings_map
-            .get("imap_username")
-            .cloned()
+        llm_provider: settings_map
+            .get("llm_provider")
+            .and_then(|s| LlmProviderKind::parse(s))
             .unwrap_or_default(),
-        imap_use_ssl: settings_map
-            .get("imap_use_ssl")
-            .map(|s| s == "true")
-            .unwrap_or(true),
         ollama_endpoint: settings_map
             .get("ollama_endpoint")
             .cloned()
@@ -67,6 +68,11 @@ pub fn get_settings(test_mode: bool) -> AppResult<AppSettings> {
             .get("ollama_model")
             .cloned()
             .unwrap_or_else(|| "llama3".to_string()),
+        openai_endpoint: settings_map
+            .get("openai_endpoint")
+            .cloned()
+            .unwrap_or_else(|| "http://localhost:1234/v1".to_string()),
+        openai_model: settings_map.get("openai_model").cloned().unwrap_or_default(),
         default_currency: settings_map
             .get("default_currency")
             .cloned()
@@ -75,6 +81,12 @@ pub fn get_settings(test_mode: bool) -> AppResult<AppSettings> {
             .get("sync_interval_minutes")
             .and_then(|s| s.parse().ok())
             .unwrap_or(30),
+        push_sync: settings_map.get("push_sync").is_some_and(|s| s == "true"),
+        extraction_concurrency: settings_map
+            .get("extraction_concurrency")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2),
+        ocr_model: settings_map.get("ocr_model").cloned().unwrap_or_default(),
         theme: settings_map
             .get("theme")
             .cloned()
@@ -84,31 +96,24 @@ pub fn get_settings(test_mode: bool) -> AppResult<AppSettings> {
     Ok(settings)
 }
 
-#[tauri::command]
-pub fn update_settings(settings: AppSettings, test_mode: bool) -> AppResult<()> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
+pub(crate) fn save_settings(conn: &Connection, settings: AppSettings) -> AppResult<()> {
     let now = get_current_timestamp();
 
     // Update each setting
     let settings_to_update = vec![
-        ("imap_server", settings.imap_server),
-        ("imap_port", settings.imap_port.to_string()),
-        ("imap_username", settings.imap_username),
-        ("imap_use_ssl", settings.imap_use_ssl.to_string()),
+        ("llm_provider", settings.llm_provider.as_str().to_string()),
         ("ollama_endpoint", settings.ollama_endpoint),
         ("ollama_model", settings.ollama_model),
+        ("openai_endpoint", settings.openai_endpoint),
+        ("openai_model", settings.openai_model),
         ("default_currency", settings.default_currency),
         (
             "sync_interval_minutes",
             settings.sync_interval_minutes.to_string(),
         ),
+        ("push_sync", settings.push_sync.to_string()),
+        ("extraction_concurrency", settings.extraction_concurrency.to_string()),
+        ("ocr_model", settings.ocr_model),
         ("theme", settings.theme),
     ];
 
@@ -122,25 +127,26 @@ pub fn update_settings(settings: AppSettings, test_mode: bool) -> AppResult<()>
     Ok(())
 }
 
-use crate::services::imap::ImapService;
-
+/// Log in with `account` as edited, before it is saved. Password accounts
+/// use `password`, or the stored one when it is empty; OAuth2 accounts need
+/// to have been authorized.
 #[tauri::command]
-pub async fn test_imap_connection(
-    server: String,
-    port: i32,
-    username: String,
-    password: String,
-    use_ssl: bool,
-) -> Result<String, String> {
-    let imap_service = ImapService::new(
-        server,
-        port as u16,
-        username,
-        password,
-        use_ssl,
-    );
-
-    match imap_service.test_connection().await {
+pub async fn test_imap_connection(account: EmailAccount, password: Option<String>, test_mode: bool) -> Result<String, String> {
+    let db_type = DatabaseType::from_test_mode(test_mode);
+    let secret = match (&account.auth_method, password.filter(|p| !p.is_empty())) {
+        (ImapAuthMethod::Password, Some(password)) => Some(password),
+        _ => SyncService::account_secret(db_type, &account),
+    }
+    .ok_or_else(|| match account.auth_method {
+        ImapAuthMethod::Password => "Enter the server, username and password first".to_string(),
+        ImapAuthMethod::OAuth2 => "Save and authorize the account first".to_string(),
+    })?;
+
+    let connected = async {
+        let imap_service = SyncService::imap_service(db_type, &account, secret).await?;
+        imap_service.test_connection().await
+    };
+    match connected.await {
         Ok(_) => Ok("Successfully connected to IMAP server".to_string()),
         Err(e) => Err(format!("Failed to connect to IMAP server: {}", e)),
     }
@@ -1,249 +1,53 @@
 // Subscription command handlers
 
-use crate::db::{get_db_connection, DatabaseType};
+use crate::db::repo::{Page, SubscriptionFilter, SubscriptionRepo};
+use crate::db::{DatabaseType, DbState};
 use crate::models::Subscription;
-use crate::utils::{get_current_timestamp, AppResult};
+use crate::utils::AppResult;
+use tauri::State;
 
 #[tauri::command]
-pub fn get_subscriptions(test_mode: bool) -> AppResult<Vec<Subscription>> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    let mut stmt = conn
-        .prepare("SELECT id, name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at FROM subscriptions ORDER BY created_at DESC")?;
-
-    let subscriptions = stmt
-        .query_map([], |row| {
-            Ok(Subscription {
-                id: Some(row.get(0)?),
-                name: row.get(1)?,
-                cost: row.get(2)?,
-                currency: row.get(3)?,
-                periodicity: row.get(4)?,
-                next_date: row.get(5)?,
-                category: row.get(6)?,
-                status: row.get(7)?,
-                notes: row.get(8)?,
-                created_at: row.get(9)?,
-                updated_at: row.get(10)?,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-
-    Ok(subscriptions)
+pub async fn get_subscriptions(
+    db: State<'_, DbState>,
+    test_mode: bool,
+    filter: Option<SubscriptionFilter>,
+    page: Option<Page>,
+) -> AppResult<Vec<Subscription>> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| {
+        SubscriptionRepo::new(conn).list(&filter.unwrap_or_default(), page)
+    })
+    .await
 }
 
+/// Total matching `filter`, for paging through the list
 #[tauri::command]
-pub fn get_subscription_by_id(id: i64, test_mode: bool) -> AppResult<Subscription> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    let subscription = conn
-        .query_row(
-            "SELECT id, name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at FROM subscriptions WHERE id = ?1",
-            [id],
-            |row| {
-                Ok(Subscription {
-                    id: Some(row.get(0)?),
-                    name: row.get(1)?,
-                    cost: row.get(2)?,
-                    currency: row.get(3)?,
-                    periodicity: row.get(4)?,
-                    next_date: row.get(5)?,
-                    category: row.get(6)?,
-                    status: row.get(7)?,
-                    notes: row.get(8)?,
-                    created_at: row.get(9)?,
-                    updated_at: row.get(10)?,
-                })
-            },
-        )?;
-
-    Ok(subscription)
+pub async fn count_subscriptions(
+    db: State<'_, DbState>,
+    test_mode: bool,
+    filter: Option<SubscriptionFilter>,
+) -> AppResult<i64> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| {
+        SubscriptionRepo::new(conn).count(&filter.unwrap_or_default())
+    })
+    .await
 }
 
 #[tauri::command]
-pub fn create_subscription(subscription: Subscription, test_mode: bool) -> AppResult<i64> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    let now = get_current_timestamp();
-
-    conn.execute(
-        "INSERT INTO subscriptions (name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-        rusqlite::params![
-            subscription.name,
-            subscription.cost,
-            subscription.currency,
-            subscription.periodicity,
-            subscription.next_date,
-            subscription.category,
-            subscription.status,
-            subscription.notes,
-            now,
-            now,
-        ],
-    )?;
-
-    Ok(conn.last_insert_rowid())
+pub async fn get_subscription_by_id(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<Subscription> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| SubscriptionRepo::new(conn).get(id)).await
 }
 
 #[tauri::command]
-pub fn update_subscription(subscription: Subscription, test_mode: bool) -> AppResult<()> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    let now = get_current_timestamp();
-
-    let id = subscription.id.ok_or_else(|| crate::utils::error::AppError::Validation("Subscription ID is required for update".to_string()))?;
-
-    conn.execute(
-        "UPDATE subscriptions SET name = ?1, cost = ?2, currency = ?3, periodicity = ?4, next_date = ?5, category = ?6, status = ?7, notes = ?8, updated_at = ?9 WHERE id = ?10",
-        rusqlite::params![
-            subscription.name,
-            subscription.cost,
-            subscription.currency,
-            subscription.periodicity,
-            subscription.next_date,
-            subscription.category,
-            subscription.status,
-            subscription.notes,
-            now,
-            id,
-        ],
-    )?;
-
-    Ok(())
+pub async fn create_subscription(db: State<'_, DbState>, subscription: Subscription, test_mode: bool) -> AppResult<i64> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| SubscriptionRepo::new(conn).create(&subscription)).await
 }
 
 #[tauri::command]
-pub fn delete_subscription(id: i64, test_mode: bool) -> AppResult<()> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
-
-    let conn = get_db_connection(db_type)?;
-
-    conn.execute("DELETE FROM subscriptions WHERE id = ?1", [id])?;
-
-    Ok(())
+pub async fn update_subscription(db: State<'_, DbState>, subscription: Subscription, test_mode: bool) -> AppResult<()> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| SubscriptionRepo::new(conn).update(&subscription)).await
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::db::{get_db_connection, DatabaseType, init_database, clear_test_database};
-
-    fn setup_test_db() {
-        let conn = get_db_connection(DatabaseType::Test).unwrap();
-        init_database(&conn).unwrap();
-        clear_test_database(&conn).unwrap();
-    }
-
-    #[test]
-    fn test_create_and_get_subscription() {
-        setup_test_db();
-        
-        let sub = Subscription {
-            id: None,
-            name: "Test Sub".to_string(),
-            cost: 9.99,
-            currency: "USD".to_string(),
-            periodicity: "monthly".to_string(),
-            next_date: Some("2024-01-01".to_string()),
-            category: Some("Entertainment".to_string()),
-            status: "active".to_string(),
-            notes: None,
-            created_at: "".to_string(),
-            updated_at: "".to_string(),
-        };
-
-        let id = create_subscription(sub, true).unwrap();
-        assert!(id > 0);
-
-        let subs = get_subscriptions(true).unwrap();
-        assert_eq!(subs.len(), 1);
-        assert_eq!(subs[0].name, "Test Sub");
-        assert_eq!(subs[0].cost, 9.99);
-    }
-
-    #[test]
-    fn test_update_subscription() {
-        setup_test_db();
-        
-        let sub = Subscription {
-            id: None,
-            name: "Original Name".to_string(),
-            cost: 10.0,
-            currency: "USD".to_string(),
-            periodicity: "monthly".to_string(),
-            next_date: None,
-            category: None,
-            status: "active".to_string(),
-            notes: None,
-            created_at: "".to_string(),
-            updated_at: "".to_string(),
-        };
-
-        let id = create_subscription(sub.clone(), true).unwrap();
-        
-        let mut updated_sub = sub;
-        updated_sub.id = Some(id);
-        updated_sub.name = "Updated Name".to_string();
-        updated_sub.cost = 15.0;
-
-        update_subscription(updated_sub, true).unwrap();
-
-        let fetched = get_subscription_by_id(id, true).unwrap();
-        assert_eq!(fetched.name, "Updated Name");
-        assert_eq!(fetched.cost, 15.0);
-    }
-
-    #[test]
-    fn test_delete_subscription() {
-        setup_test_db();
-        
-        let sub = Subscription {
-            id: None,
-            name: "To Delete".to_string(),
-            cost: 5.0,
-            currency: "USD".to_string(),
-            periodicity: "monthly".to_string(),
-            next_date: None,
-            category: None,
-            status: "active".to_string(),
-            notes: None,
-            created_at: "".to_string(),
-            updated_at: "".to_string(),
-        };
-
-        let id = create_subscription(sub, true).unwrap();
-        delete_subscription(id, true).unwrap();
-
-        let subs = get_subscriptions(true).unwrap();
-        assert_eq!(subs.len(), 0);
-    }
+#[tauri::command]
+pub async fn delete_subscription(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<()> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| SubscriptionRepo::new(conn).delete(id)).await
 }
@@ -1,67 +1,96 @@
 // Email sync command handlers
 
-use crate::db::{get_db_connection, DatabaseType};
-use crate::utils::{get_current_timestamp, AppResult};
-use crate::services::sync::SyncService;
-use rusqlite::OptionalExtension;
+use crate::db::repo::{Page, SyncLogItemRepo, SyncLogRepo};
+use crate::db::{DatabaseType, DbState};
+use crate::models::{ImportSummary, SchedulerStatus, SyncLog, SyncLogDetails, SyncLogItem};
+use crate::utils::AppResult;
+use crate::services::scheduler::SyncScheduler;
+use crate::services::sync::SyncOutcome;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
 
 #[tauri::command]
-pub async fn trigger_email_sync(test_mode: bool) -> AppResult<String> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
+/// Sync every enabled account; each account records its own sync log row.
+/// Waits for a scheduled sync that is already running instead of overlapping it.
+pub async fn trigger_email_sync(scheduler: State<'_, SyncScheduler>, test_mode: bool) -> AppResult<String> {
+    let message = match scheduler.run_exclusive(test_mode).await? {
+        SyncOutcome::Completed => "Email sync completed successfully",
+        SyncOutcome::Cancelled => "Email sync cancelled",
     };
+    Ok(message.to_string())
+}
 
-    let conn = get_db_connection(db_type)?;
+/// Stop the sync in progress; it records a `cancelled` sync log row
+#[tauri::command]
+pub fn cancel_email_sync(scheduler: State<'_, SyncScheduler>) {
+    scheduler.cancel();
+}
 
-    let now = get_current_timestamp();
+#[tauri::command]
+pub fn get_sync_status(scheduler: State<'_, SyncScheduler>) -> SchedulerStatus {
+    scheduler.status()
+}
 
-    // Create sync log entry
-    conn.execute(
-        "INSERT INTO sync_log (sync_started_at, status, created_at) VALUES (?1, 'running', ?2)",
-        rusqlite::params![now.clone(), now.clone()],
-    )?;
-    let log_id = conn.last_insert_rowid();
+/// Start the next scheduled sync now, in the background
+#[tauri::command]
+pub fn sync_now(scheduler: State<'_, SyncScheduler>) {
+    scheduler.sync_now();
+}
 
-    // Run sync
-    match SyncService::run_sync(test_mode).await {
-        Ok(_) => {
-            let completed_at = get_current_timestamp();
-            conn.execute(
-                "UPDATE sync_log SET sync_completed_at = ?1, status = 'completed' WHERE id = ?2",
-                rusqlite::params![completed_at, log_id],
-            )?;
-            Ok("Email sync completed successfully".to_string())
-        }
-        Err(e) => {
-            let error_msg = e.to_string();
-            conn.execute(
-                "UPDATE sync_log SET status = 'failed', error_message = ?1 WHERE id = ?2",
-                rusqlite::params![error_msg, log_id],
-            )?;
-            Err(e)
-        }
-    }
+#[tauri::command]
+pub fn pause_sync(scheduler: State<'_, SyncScheduler>) {
+    scheduler.pause();
 }
 
 #[tauri::command]
-pub fn get_last_sync_time(test_mode: bool) -> AppResult<Option<String>> {
-    let db_type = if test_mode {
-        DatabaseType::Test
-    } else {
-        DatabaseType::Production
-    };
+pub fn resume_sync(scheduler: State<'_, SyncScheduler>) {
+    scheduler.resume();
+}
 
-    let conn = get_db_connection(db_type)?;
+#[tauri::command]
+pub async fn get_last_sync_time(db: State<'_, DbState>, test_mode: bool) -> AppResult<Option<String>> {
+    db.run(DatabaseType::from_test_mode(test_mode), |conn| SyncLogRepo::new(conn).last_completed_at()).await
+}
+
+#[tauri::command]
+pub async fn get_sync_logs(db: State<'_, DbState>, test_mode: bool, page: Option<Page>) -> AppResult<Vec<SyncLog>> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| SyncLogRepo::new(conn).list(page)).await
+}
+
+/// One sync run with a row per message it handled
+#[tauri::command]
+pub async fn get_sync_log_details(db: State<'_, DbState>, id: i64, test_mode: bool) -> AppResult<SyncLogDetails> {
+    db.run(DatabaseType::from_test_mode(test_mode), move |conn| {
+        Ok(SyncLogDetails {
+            log: SyncLogRepo::new(conn).get(id)?,
+            items: SyncLogItemRepo::new(conn).list_for_log(id)?,
+        })
+    })
+    .await
+}
 
-    let last_sync = conn
-        .query_row(
-            "SELECT sync_completed_at FROM sync_log WHERE status = 'completed' ORDER BY sync_completed_at DESC LIMIT 1",
-            [],
-            |row| row.get(0),
-        )
-        .optional()?;
+/// Process a failed message again; returns its updated row. Waits for a
+/// sync in progress, and `cancel_email_sync` stops it.
+#[tauri::command]
+pub async fn retry_sync_log_item(scheduler: State<'_, SyncScheduler>, id: i64, test_mode: bool) -> AppResult<SyncLogItem> {
+    scheduler.retry_item(test_mode, id).await
+}
 
-    Ok(last_sync)
+/// Import an mbox file, a Maildir or `.eml` files as pending imports.
+/// Progress is emitted as "import-progress" events; waits for a sync in
+/// progress, and `cancel_email_sync` stops it.
+#[tauri::command]
+pub async fn import_local_mail(
+    app: AppHandle,
+    scheduler: State<'_, SyncScheduler>,
+    path: String,
+    test_mode: bool,
+) -> AppResult<ImportSummary> {
+    let progress = Arc::new(move |progress| {
+        if let Err(e) = app.emit("import-progress", progress) {
+            eprintln!("Failed to emit import progress: {}", e);
+        }
+    });
+    scheduler.import_local(test_mode, PathBuf::from(path), progress).await
 }
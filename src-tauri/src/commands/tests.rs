@@ -1,20 +1,54 @@
 #[cfg(test)]
 mod tests {
-    use crate::commands::subscriptions::{get_subscriptions, create_subscription, delete_subscription};
-    use crate::commands::database::clear_test_db;
+    use crate::db::repo::SubscriptionRepo;
+    use crate::db::{DatabaseType, DbConnection, DbState};
+    use crate::commands::settings::{load_settings, validate_settings};
     use crate::models::Subscription;
+    use crate::services::llm::extraction_concurrency;
+
+    fn test_db() -> DbConnection {
+        DbState::in_memory()
+            .get(DatabaseType::Test)
+            .expect("Failed to open test DB")
+    }
 
     #[test]
     fn test_subscription_lifecycle() {
-        // ... existing code ...
+        let conn = test_db();
+
+        let sub = Subscription {
+            id: None,
+            name: "Netflix".to_string(),
+            cost: 15.99,
+            currency: "USD".to_string(),
+            periodicity: "monthly".to_string(),
+            next_date: Some("2024-02-01".to_string()),
+            category: Some("Entertainment".to_string()),
+            status: "active".to_string(),
+            notes: None,
+            created_at: "".to_string(),
+            updated_at: "".to_string(),
+        };
+
+        let repo = SubscriptionRepo::new(&conn);
+        let id = repo.create(&sub).expect("Failed to create subscription");
+        assert!(id > 0);
+
+        let subs = repo.list(&Default::default(), None).expect("Failed to get subscriptions");
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].name, "Netflix");
+
+        repo.delete(id).expect("Failed to delete subscription");
+        let subs_after = repo.list(&Default::default(), None).expect("Failed to get subscriptions");
+        assert_eq!(subs_after.len(), 0);
     }
 
     #[test]
     fn test_domain_lifecycle() {
-        use crate::commands::domains::{get_domains, create_domain, delete_domain};
+        use crate::db::repo::DomainRepo;
         use crate::models::Domain;
 
-        clear_test_db().expect("Failed to clear test DB");
+        let conn = test_db();
 
         let domain = Domain {
             id: None,
@@ -31,41 +65,67 @@ mod tests {
             updated_at: "".to_string(),
         };
 
-        let id = create_domain(domain, true).expect("Failed to create domain");
+        let repo = DomainRepo::new(&conn);
+        let id = repo.create(&domain).expect("Failed to create domain");
         assert!(id > 0);
 
-        let domains = get_domains(true).expect("Failed to get domains");
+        let domains = repo.list(&Default::default(), None).expect("Failed to get domains");
         assert_eq!(domains.len(), 1);
         assert_eq!(domains[0].name, "example.com");
 
-        delete_domain(id, true).expect("Failed to delete domain");
-        let domains_after = get_domains(true).expect("Failed to get domains");
+        repo.delete(id).expect("Failed to delete domain");
+        let domains_after = repo.list(&Default::default(), None).expect("Failed to get domains");
         assert_eq!(domains_after.len(), 0);
     }
 
     #[test]
     fn test_pending_import_lifecycle() {
-        use crate::commands::pending_imports::{get_pending_imports, create_pending_import, reject_pending_import};
-
-        clear_test_db().expect("Failed to clear test DB");
-
-        let id = create_pending_import(
-            "Subject".to_string(),
-            "from@example.com".to_string(),
-            "2024-01-01".to_string(),
-            "subscription".to_string(),
-            "{}".to_string(),
-            0.95,
-            true
-        ).expect("Failed to create pending import");
+        use crate::db::repo::{PendingImportFilter, PendingImportRepo};
+        use crate::models::PendingImport;
+
+        let conn = test_db();
+
+        let repo = PendingImportRepo::new(&conn);
+        let id = repo
+            .create(&PendingImport {
+                id: None,
+                email_subject: Some("Subject".to_string()),
+                email_from: "from@example.com".to_string(),
+                email_date: Some("2024-01-01".to_string()),
+                classification: Some("subscription".to_string()),
+                confidence: Some(0.95),
+                extracted_data: "{}".to_string(),
+                receipt_id: None,
+                status: "pending".to_string(),
+                created_at: "".to_string(),
+                message_id: None,
+            })
+            .expect("Failed to create pending import");
         assert!(id > 0);
 
-        let imports = get_pending_imports(true).expect("Failed to get pending imports");
+        let imports = repo.list(&PendingImportFilter::pending(), None).expect("Failed to get pending imports");
         assert_eq!(imports.len(), 1);
         assert_eq!(imports[0].email_subject, Some("Subject".to_string()));
 
-        reject_pending_import(id, true).expect("Failed to reject pending import");
-        let imports_after = get_pending_imports(true).expect("Failed to get pending imports");
+        repo.reject(id).expect("Failed to reject pending import");
+        let imports_after = repo.list(&PendingImportFilter::pending(), None).expect("Failed to get pending imports");
         assert_eq!(imports_after.len(), 0);
     }
+
+    #[test]
+    fn test_extraction_concurrency_is_bounded() {
+        let conn = test_db();
+        let mut settings = load_settings(&conn).expect("Failed to load settings");
+
+        for (concurrency, valid) in [(0, false), (1, true), (16, true), (17, false)] {
+            settings.extraction_concurrency = concurrency;
+            assert_eq!(validate_settings(&settings).is_ok(), valid, "{} parallel extractions", concurrency);
+        }
+
+        // Values saved before the limit are clamped instead
+        settings.extraction_concurrency = 64;
+        assert_eq!(extraction_concurrency(&settings), 16);
+        settings.extraction_concurrency = 0;
+        assert_eq!(extraction_concurrency(&settings), 1);
+    }
 }
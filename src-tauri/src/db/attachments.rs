@@ -0,0 +1,414 @@
+// Attachment storage
+// Receipt files live on disk next to the database, addressed by their SHA-256
+
+use super::connection::{get_db_path, DatabaseType};
+use crate::utils::{sha256_hex, AppError, AppResult};
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Files newer than this are never pruned, so a sync that has written a file
+/// but not yet committed its receipt cannot lose it
+const PRUNE_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// Reference to a file in the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    pub hash: String,
+    pub size: i64,
+}
+
+/// Content-addressed directory of receipt attachments.
+/// Identical content is stored once, at `<root>/<first two hex chars>/<hash>`.
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    root: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The store that belongs to a database file
+    pub fn for_database(db_type: DatabaseType) -> anyhow::Result<Self> {
+        let db_path = get_db_path(db_type)?;
+        let dir_name = match db_type {
+            DatabaseType::Production => "attachments",
+            DatabaseType::Test => "attachments_test",
+        };
+
+        let root = db_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Database path has no parent directory"))?
+            .join(dir_name);
+
+        Ok(Self::new(root))
+    }
+
+    pub fn hash(data: &[u8]) -> String {
+        sha256_hex(data)
+    }
+
+    /// Location of a stored file. Rejects anything that is not a SHA-256 hex
+    /// digest, so a hash can never escape the store directory.
+    pub fn path(&self, hash: &str) -> AppResult<PathBuf> {
+        let valid = hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if !valid {
+            return Err(AppError::Validation(format!("Invalid attachment hash: {}", hash)));
+        }
+
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+
+    /// Write `data` to the store, or reuse the existing copy
+    pub fn put(&self, data: &[u8]) -> AppResult<StoredFile> {
+        let hash = Self::hash(data);
+        let path = self.path(&hash)?;
+        let stored = StoredFile {
+            hash,
+            size: data.len() as i64,
+        };
+
+        if path.exists() {
+            // Refresh the timestamp so a concurrent prune leaves it alone
+            fs::File::options()
+                .append(true)
+                .open(&path)?
+                .set_modified(SystemTime::now())?;
+            return Ok(stored);
+        }
+
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)?;
+
+        // Write under a temporary name first so readers never see a partial file
+        let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+        temp_file.write_all(data)?;
+        temp_file.as_file().sync_all()?;
+        temp_file
+            .persist(&path)
+            .map_err(|e| AppError::Internal(format!("Failed to store attachment: {}", e)))?;
+
+        Ok(stored)
+    }
+
+    pub fn read(&self, hash: &str) -> AppResult<Vec<u8>> {
+        let path = self.path(hash)?;
+        fs::read(&path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => AppError::NotFound(format!("Attachment {}", hash)),
+            _ => e.into(),
+        })
+    }
+
+    /// Delete stored files no receipt refers to. Returns how many were removed.
+    pub fn prune(&self, conn: &Connection) -> AppResult<usize> {
+        let referenced = referenced_hashes(conn)?;
+
+        let mut removed = 0;
+        for path in self.files()? {
+            let Some(hash) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if referenced.contains(hash) || Self::is_recent(&path) {
+                continue;
+            }
+
+            match fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Copy every file the database behind `conn` refers to into a new store
+    /// at `target`. Returns how many were copied; files missing from this
+    /// store are logged and left out.
+    pub fn export(&self, conn: &Connection, target: &Path) -> AppResult<usize> {
+        let export = Self::new(target);
+        let mut copied = 0;
+        for hash in referenced_hashes(conn)? {
+            let from = self.path(&hash)?;
+            let to = export.path(&hash)?;
+            fs::create_dir_all(to.parent().unwrap_or(target))?;
+            match fs::copy(&from, &to) {
+                Ok(_) => copied += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    eprintln!("Attachment {} is missing, leaving it out of the export", hash);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(copied)
+    }
+
+    /// Move attachments parked in `legacy_receipt_files` by the v3 migration
+    /// into the store. Returns how many receipts were updated. Rows of deleted
+    /// receipts are dropped; rows that aren't valid base64 are logged and
+    /// marked with the error, so one corrupt receipt can't keep the app from
+    /// starting and isn't retried on every start.
+    pub fn import_legacy(&self, conn: &Connection) -> AppResult<usize> {
+        conn.execute(
+            "DELETE FROM legacy_receipt_files WHERE receipt_id NOT IN (SELECT id FROM receipts)",
+            [],
+        )?;
+        let legacy: Vec<(i64, String)> = conn
+            .prepare("SELECT receipt_id, file_data FROM legacy_receipt_files WHERE import_error IS NULL")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let mut imported = 0;
+        for (receipt_id, file_data) in &legacy {
+            let data = match base64::Engine::decode(&base64::engine::general_purpose::STANDARD, file_data) {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("Leaving the attachment of receipt {} in legacy_receipt_files: {}", receipt_id, e);
+                    conn.execute(
+                        "UPDATE legacy_receipt_files SET import_error = ?1 WHERE receipt_id = ?2",
+                        rusqlite::params![e.to_string(), receipt_id],
+                    )?;
+                    continue;
+                }
+            };
+            let stored = self.put(&data)?;
+
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "UPDATE receipts SET file_hash = ?1, file_size = ?2 WHERE id = ?3",
+                rusqlite::params![stored.hash, stored.size, receipt_id],
+            )?;
+            tx.execute("DELETE FROM legacy_receipt_files WHERE receipt_id = ?1", [receipt_id])?;
+            tx.commit()?;
+            imported += 1;
+        }
+
+        Ok(imported)
+    }
+
+    fn files(&self) -> AppResult<Vec<PathBuf>> {
+        let shards = match fs::read_dir(&self.root) {
+            Ok(shards) => shards,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = Vec::new();
+        for shard in shards {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&shard)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn is_recent(path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age < PRUNE_GRACE)
+    }
+}
+
+/// Hashes of every stored file a row refers to
+fn referenced_hashes(conn: &Connection) -> AppResult<HashSet<String>> {
+    let referenced = conn
+        .prepare(
+            "SELECT file_hash FROM receipts WHERE file_hash IS NOT NULL
+             UNION SELECT raw_message_hash FROM receipts WHERE raw_message_hash IS NOT NULL
+             UNION SELECT file_hash FROM receipt_sources WHERE file_hash IS NOT NULL",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(referenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::test_connection;
+
+    fn backdate(path: &Path) {
+        fs::File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - PRUNE_GRACE * 2)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_identical_content_is_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path());
+
+        let first = store.put(b"%PDF-1.4 invoice").unwrap();
+        let second = store.put(b"%PDF-1.4 invoice").unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first.size, 16);
+        assert_eq!(store.read(&first.hash).unwrap(), b"%PDF-1.4 invoice");
+        assert_eq!(store.files().unwrap().len(), 1);
+        assert!(store.path(&first.hash).unwrap().starts_with(dir.path().join(&first.hash[..2])));
+    }
+
+    #[test]
+    fn test_rejects_invalid_hashes() {
+        let store = AttachmentStore::new("/tmp/unused");
+
+        assert!(matches!(store.path("../../etc/passwd"), Err(AppError::Validation(_))));
+        assert!(matches!(store.path(&"A".repeat(64)), Err(AppError::Validation(_))));
+        assert!(matches!(
+            store.read(&AttachmentStore::hash(b"missing")),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_prune_keeps_referenced_and_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path());
+        let conn = test_connection();
+
+        let referenced = store.put(b"referenced").unwrap();
+        let source = store.put(b"second attachment").unwrap();
+        let message = store.put(b"Subject: Receipt").unwrap();
+        let orphan = store.put(b"orphan").unwrap();
+        let fresh = store.put(b"fresh").unwrap();
+        backdate(&store.path(&referenced.hash).unwrap());
+        backdate(&store.path(&source.hash).unwrap());
+        backdate(&store.path(&message.hash).unwrap());
+        backdate(&store.path(&orphan.hash).unwrap());
+
+        conn.execute(
+            "INSERT INTO receipts (id, email_date, file_hash, file_size, raw_message_hash) VALUES (1, '2024-01-01', ?1, ?2, ?3)",
+            rusqlite::params![referenced.hash, referenced.size, message.hash],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO receipt_sources (receipt_id, position, name, content_type, file_hash) VALUES (1, 1, 'terms.pdf', 'application/pdf', ?1)",
+            [&source.hash],
+        )
+        .unwrap();
+
+        assert_eq!(store.prune(&conn).unwrap(), 1);
+        assert!(store.read(&referenced.hash).is_ok());
+        assert!(store.read(&source.hash).is_ok());
+        assert!(store.read(&message.hash).is_ok());
+        assert!(store.read(&fresh.hash).is_ok());
+        assert!(matches!(store.read(&orphan.hash), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_export_copies_referenced_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path().join("store"));
+        let conn = test_connection();
+
+        let attachment = store.put(b"%PDF-1.4 invoice").unwrap();
+        let message = store.put(b"Subject: Receipt").unwrap();
+        let orphan = store.put(b"orphan").unwrap();
+        conn.execute(
+            "INSERT INTO receipts (id, email_date, file_hash, file_size, raw_message_hash) VALUES (1, '2024-01-01', ?1, ?2, ?3)",
+            rusqlite::params![attachment.hash, attachment.size, message.hash],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO receipts (id, email_date, file_hash, file_size) VALUES (2, '2024-02-01', ?1, 7)",
+            [AttachmentStore::hash(b"missing")],
+        )
+        .unwrap();
+
+        let target = dir.path().join("export");
+        assert_eq!(store.export(&conn, &target).unwrap(), 2);
+
+        let exported = AttachmentStore::new(&target);
+        assert_eq!(exported.read(&attachment.hash).unwrap(), b"%PDF-1.4 invoice");
+        assert_eq!(exported.read(&message.hash).unwrap(), b"Subject: Receipt");
+        assert!(matches!(exported.read(&orphan.hash), Err(AppError::NotFound(_))));
+        assert_eq!(exported.files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_legacy_moves_inline_data_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path());
+        let conn = test_connection();
+
+        conn.execute_batch(
+            "INSERT INTO receipts (id, email_date, file_type) VALUES (1, '2024-01-01', 'application/pdf');
+             INSERT INTO receipts (id, email_date, file_type) VALUES (2, '2024-02-01', 'application/pdf');
+             INSERT INTO legacy_receipt_files (receipt_id, file_data) VALUES (1, 'UERGREFUQQ==');
+             INSERT INTO legacy_receipt_files (receipt_id, file_data) VALUES (2, 'UERGREFUQQ==');",
+        )
+        .unwrap();
+
+        assert_eq!(store.import_legacy(&conn).unwrap(), 2);
+        assert_eq!(store.import_legacy(&conn).unwrap(), 0);
+
+        let hashes: Vec<(String, i64)> = conn
+            .prepare("SELECT file_hash, file_size FROM receipts ORDER BY id")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(store.read(&hashes[0].0).unwrap(), b"PDFDATA");
+        assert_eq!(store.files().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_import_legacy_marks_corrupt_rows_and_drops_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path());
+        let conn = test_connection();
+
+        conn.execute_batch(
+            "INSERT INTO receipts (id, email_date, file_type) VALUES (1, '2024-01-01', 'application/pdf');
+             INSERT INTO receipts (id, email_date, file_type) VALUES (2, '2024-02-01', 'application/pdf');
+             INSERT INTO legacy_receipt_files (receipt_id, file_data) VALUES (1, 'not base64!');
+             INSERT INTO legacy_receipt_files (receipt_id, file_data) VALUES (2, 'UERGREFUQQ==');
+             INSERT INTO legacy_receipt_files (receipt_id, file_data) VALUES (3, 'UERGREFUQQ==');",
+        )
+        .unwrap();
+
+        assert_eq!(store.import_legacy(&conn).unwrap(), 1);
+        assert_eq!(store.import_legacy(&conn).unwrap(), 0);
+
+        let parked: Vec<(i64, Option<String>)> = conn
+            .prepare("SELECT receipt_id, import_error FROM legacy_receipt_files")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(parked.len(), 1);
+        assert_eq!(parked[0].0, 1);
+        assert!(parked[0].1.is_some());
+        let hashes: Vec<Option<String>> = conn
+            .prepare("SELECT file_hash FROM receipts ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(hashes[0], None);
+        assert_eq!(store.read(hashes[1].as_deref().unwrap()).unwrap(), b"PDFDATA");
+    }
+}
@@ -1,16 +1,42 @@
 // Database connection management
-// Handles database path resolution and connection creation
+// Handles database path resolution and pooled connection creation
 
+use r2d2::Pool;
+#[cfg(test)]
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
 use std::path::PathBuf;
+use std::time::Duration;
 use anyhow::Result;
 
-#[derive(Debug, Clone, Copy)]
+pub type DbPool = Pool<SqliteConnectionManager>;
+#[cfg(test)]
+pub type DbConnection = PooledConnection<SqliteConnectionManager>;
+
+/// Maximum number of open connections per database file
+const POOL_SIZE: u32 = 8;
+
+/// How long a connection waits on a locked database before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DatabaseType {
     Production,
     Test,
 }
 
+impl DatabaseType {
+    /// Map the `test_mode` flag every command receives to a database
+    pub fn from_test_mode(test_mode: bool) -> Self {
+        if test_mode {
+            DatabaseType::Test
+        } else {
+            DatabaseType::Production
+        }
+    }
+}
+
 /// Get the database file path based on type
 pub fn get_db_path(db_type: DatabaseType) -> Result<PathBuf> {
     let app_dir = dirs::data_local_dir()
@@ -28,13 +54,58 @@ pub fn get_db_path(db_type: DatabaseType) -> Result<PathBuf> {
     Ok(app_dir.join(db_name))
 }
 
-/// Get a database connection
-pub fn get_db_connection(db_type: DatabaseType) -> Result<Connection> {
-    let db_path = get_db_path(db_type)?;
-    let conn = Connection::open(db_path)?;
+/// Per-connection setup, run once when the pool opens a connection
+fn configure_connection(conn: &mut Connection) -> rusqlite::Result<()> {
+    // WAL lets the background sync write while UI commands keep reading
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA foreign_keys = ON;",
+    )?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(())
+}
+
+/// Create a connection pool for the given database
+pub fn create_pool(db_type: DatabaseType) -> Result<DbPool> {
+    create_file_pool(get_db_path(db_type)?)
+}
+
+/// Create a connection pool for a database file
+fn create_file_pool(db_path: PathBuf) -> Result<DbPool> {
+    let manager = SqliteConnectionManager::file(db_path).with_init(configure_connection);
+    let pool = Pool::builder().max_size(POOL_SIZE).build(manager)?;
+    Ok(pool)
+}
+
+/// Create a single-connection pool over a private in-memory database
+#[cfg(test)]
+pub fn create_memory_pool() -> Result<DbPool> {
+    // Every in-memory connection is its own database, so the pool must not grow
+    let manager = SqliteConnectionManager::memory().with_init(configure_connection);
+    let pool = Pool::builder().max_size(1).build(manager)?;
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Enable foreign keys
-    conn.execute("PRAGMA foreign_keys = ON", [])?;
+    #[test]
+    fn test_pooled_connections_are_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = create_file_pool(dir.path().join("subscript.db")).unwrap();
 
-    Ok(conn)
+        // Every connection the pool opens gets the same setup
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        for conn in [&first, &second] {
+            let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+            let foreign_keys: i64 = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0)).unwrap();
+            let busy_timeout: u64 = conn.query_row("PRAGMA busy_timeout", [], |row| row.get(0)).unwrap();
+            assert_eq!(journal_mode, "wal");
+            assert_eq!(foreign_keys, 1);
+            assert_eq!(busy_timeout as u128, BUSY_TIMEOUT.as_millis());
+        }
+    }
 }
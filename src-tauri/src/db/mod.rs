@@ -3,6 +3,12 @@
 
 pub mod schema;
 pub mod connection;
+pub mod state;
+pub mod attachments;
+pub mod repo;
 
-pub use schema::{init_database, clear_test_database};
-pub use connection::{get_db_connection, get_db_path, DatabaseType};
+pub use schema::clear_test_database;
+pub use connection::DatabaseType;
+#[cfg(test)]
+pub use connection::DbConnection;
+pub use state::DbState;
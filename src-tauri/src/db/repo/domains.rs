@@ -0,0 +1,329 @@
+// Domain repository
+
+use super::{like_pattern, Conditions, Page};
+use crate::models::{Domain, DomainExtraction};
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Deserialize;
+
+const COLUMNS: &str = "id, name, registrar, cost, currency, registration_date, expiry_date, auto_renew, status, notes, created_at, updated_at";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainFilter {
+    pub status: Option<String>,
+    pub registrar: Option<String>,
+    /// Only domains expiring on or before this date (YYYY-MM-DD)
+    pub expiring_before: Option<String>,
+    /// Case-insensitive match on the domain name
+    pub search: Option<String>,
+}
+
+pub struct DomainRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> DomainRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<Domain> {
+        Ok(Domain {
+            id: Some(row.get(0)?),
+            name: row.get(1)?,
+            registrar: row.get(2)?,
+            cost: row.get(3)?,
+            currency: row.get(4)?,
+            registration_date: row.get(5)?,
+            expiry_date: row.get(6)?,
+            auto_renew: row.get::<_, i32>(7)? != 0,
+            status: row.get(8)?,
+            notes: row.get(9)?,
+            created_at: row.get(10)?,
+            updated_at: row.get(11)?,
+        })
+    }
+
+    fn conditions(filter: &DomainFilter) -> Conditions {
+        let mut conditions = Conditions::default();
+        conditions.push_opt("status = ?", filter.status.clone());
+        conditions.push_opt("registrar = ?", filter.registrar.clone());
+        conditions.push_opt("expiry_date <= ?", filter.expiring_before.clone());
+        conditions.push_opt("name LIKE ?", filter.search.as_deref().map(like_pattern));
+        conditions
+    }
+
+    /// Soonest expiry first
+    pub fn list(&self, filter: &DomainFilter, page: Option<Page>) -> AppResult<Vec<Domain>> {
+        let conditions = Self::conditions(filter);
+        let sql = format!(
+            "SELECT {} FROM domains{} ORDER BY expiry_date ASC, id ASC{}",
+            COLUMNS,
+            conditions.sql(),
+            Page::sql(page)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let domains = stmt
+            .query_map(conditions.params(), Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(domains)
+    }
+
+    pub fn count(&self, filter: &DomainFilter) -> AppResult<i64> {
+        let conditions = Self::conditions(filter);
+        let sql = format!("SELECT COUNT(*) FROM domains{}", conditions.sql());
+        Ok(self.conn.query_row(&sql, conditions.params(), |row| row.get(0))?)
+    }
+
+    pub fn find(&self, id: i64) -> AppResult<Option<Domain>> {
+        let sql = format!("SELECT {} FROM domains WHERE id = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [id], Self::map_row).optional()?)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> AppResult<Option<Domain>> {
+        let sql = format!("SELECT {} FROM domains WHERE name = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [name], Self::map_row).optional()?)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<Domain> {
+        self.find(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Domain {}", id)))
+    }
+
+    pub fn create(&self, domain: &Domain) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO domains (name, registrar, cost, currency, registration_date, expiry_date, auto_renew, status, notes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params![
+                domain.name,
+                domain.registrar,
+                domain.cost,
+                domain.currency,
+                domain.registration_date,
+                domain.expiry_date,
+                if domain.auto_renew { 1 } else { 0 },
+                domain.status,
+                domain.notes,
+                now,
+                now,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Insert a domain from LLM-extracted data, or refresh the existing
+    /// record with the same name (renewal notices for a known domain)
+    pub fn upsert_from_extraction(&self, extraction: &DomainExtraction) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        if let Some(existing) = self.find_by_name(&extraction.name)? {
+            let id = existing.id.unwrap_or_default();
+            self.conn.execute(
+                "UPDATE domains SET
+                    registrar = COALESCE(?1, registrar),
+                    cost = COALESCE(?2, cost),
+                    currency = COALESCE(?3, currency),
+                    registration_date = COALESCE(?4, registration_date),
+                    expiry_date = ?5,
+                    auto_renew = COALESCE(?6, auto_renew),
+                    updated_at = ?7
+                 WHERE id = ?8",
+                rusqlite::params![
+                    extraction.registrar,
+                    extraction.cost,
+                    extraction.currency,
+                    extraction.registration_date,
+                    extraction.expiry_date,
+                    extraction.auto_renew.map(|b| if b { 1 } else { 0 }),
+                    now,
+                    id
+                ],
+            )?;
+            return Ok(id);
+        }
+
+        self.conn.execute(
+            "INSERT INTO domains (name, registrar, cost, currency, registration_date, expiry_date, auto_renew, status, notes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            rusqlite::params![
+                extraction.name,
+                extraction.registrar,
+                extraction.cost,
+                extraction.currency,
+                extraction.registration_date,
+                extraction.expiry_date,
+                if extraction.auto_renew.unwrap_or(false) { 1 } else { 0 },
+                "active",       // Default status
+                None::<String>, // No notes from extraction
+                now,
+                now,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update(&self, domain: &Domain) -> AppResult<()> {
+        let now = get_current_timestamp();
+
+        let id = domain.id.ok_or_else(|| AppError::Validation("Domain ID is required for update".to_string()))?;
+
+        let updated = self.conn.execute(
+            "UPDATE domains SET name = ?1, registrar = ?2, cost = ?3, currency = ?4, registration_date = ?5, expiry_date = ?6, auto_renew = ?7, status = ?8, notes = ?9, updated_at = ?10 WHERE id = ?11",
+            rusqlite::params![
+                domain.name,
+                domain.registrar,
+                domain.cost,
+                domain.currency,
+                domain.registration_date,
+                domain.expiry_date,
+                if domain.auto_renew { 1 } else { 0 },
+                domain.status,
+                domain.notes,
+                now,
+                id,
+            ],
+        )?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Domain {}", id)));
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM domains WHERE id = ?1", [id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::test_connection;
+
+    fn domain(name: &str, expiry_date: &str) -> Domain {
+        Domain {
+            id: None,
+            name: name.to_string(),
+            registrar: Some("Namecheap".to_string()),
+            cost: Some(12.99),
+            currency: Some("USD".to_string()),
+            registration_date: Some("2023-01-01".to_string()),
+            expiry_date: expiry_date.to_string(),
+            auto_renew: true,
+            status: "active".to_string(),
+            notes: None,
+            created_at: "".to_string(),
+            updated_at: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_create_and_get_domain() {
+        let conn = test_connection();
+        let repo = DomainRepo::new(&conn);
+
+        let id = repo.create(&domain("example.com", "2024-01-01")).unwrap();
+        assert!(id > 0);
+
+        let domains = repo.list(&DomainFilter::default(), None).unwrap();
+        assert_eq!(domains.len(), 1);
+        assert_eq!(domains[0].name, "example.com");
+        assert!(domains[0].auto_renew);
+    }
+
+    #[test]
+    fn test_update_domain() {
+        let conn = test_connection();
+        let repo = DomainRepo::new(&conn);
+
+        let mut original = domain("original.com", "2024-01-01");
+        original.registrar = None;
+        let id = repo.create(&original).unwrap();
+
+        let mut updated_domain = original;
+        updated_domain.id = Some(id);
+        updated_domain.name = "updated.com".to_string();
+        updated_domain.registrar = Some("GoDaddy".to_string());
+
+        repo.update(&updated_domain).unwrap();
+
+        let fetched = repo.get(id).unwrap();
+        assert_eq!(fetched.name, "updated.com");
+        assert_eq!(fetched.registrar, Some("GoDaddy".to_string()));
+    }
+
+    #[test]
+    fn test_upsert_from_extraction_refreshes_existing_domain() {
+        let conn = test_connection();
+        let repo = DomainRepo::new(&conn);
+
+        let id = repo.create(&domain("example.com", "2024-01-01")).unwrap();
+
+        let extraction = DomainExtraction {
+            name: "example.com".to_string(),
+            registrar: None,
+            cost: Some(14.99),
+            currency: None,
+            registration_date: None,
+            expiry_date: "2025-01-01".to_string(),
+            auto_renew: None,
+        };
+        assert_eq!(repo.upsert_from_extraction(&extraction).unwrap(), id);
+
+        let fetched = repo.get(id).unwrap();
+        assert_eq!(fetched.expiry_date, "2025-01-01");
+        assert_eq!(fetched.cost, Some(14.99));
+        assert_eq!(fetched.registrar, Some("Namecheap".to_string()));
+
+        let new_id = repo
+            .upsert_from_extraction(&DomainExtraction {
+                name: "other.org".to_string(),
+                ..extraction
+            })
+            .unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(repo.get(new_id).unwrap().status, "active");
+    }
+
+    #[test]
+    fn test_filter_and_paginate() {
+        let conn = test_connection();
+        let repo = DomainRepo::new(&conn);
+
+        repo.create(&domain("a.com", "2024-03-01")).unwrap();
+        repo.create(&domain("b.com", "2024-01-01")).unwrap();
+        repo.create(&domain("c.net", "2025-01-01")).unwrap();
+
+        let expiring = DomainFilter {
+            expiring_before: Some("2024-12-31".to_string()),
+            ..Default::default()
+        };
+        let names: Vec<String> = repo
+            .list(&expiring, None)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["b.com", "a.com"]);
+
+        let search = DomainFilter {
+            search: Some(".net".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.count(&search).unwrap(), 1);
+
+        let page = repo
+            .list(&DomainFilter::default(), Some(Page { limit: 1, offset: 1 }))
+            .unwrap();
+        assert_eq!(page[0].name, "a.com");
+    }
+}
@@ -0,0 +1,370 @@
+// Email account repository
+
+use crate::models::{ConnectionSecurity, EmailAccount, ImapAuthMethod, OAuthProvider, SearchCriteria};
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use chrono::NaiveDate;
+use rusqlite::types::Type;
+use rusqlite::{Connection, OptionalExtension, Row};
+
+const COLUMNS: &str = "id, name, server, port, username, security, ca_certificate, pinned_certificate, auth_method, oauth_provider, oauth_client_id, oauth_client_secret, enabled, created_at, updated_at, folders, search_since, search_from, search_subject, imported_action, junk_action";
+
+pub struct EmailAccountRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> EmailAccountRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<EmailAccount> {
+        Ok(EmailAccount {
+            id: Some(row.get(0)?),
+            name: row.get(1)?,
+            server: row.get(2)?,
+            port: row.get(3)?,
+            username: row.get(4)?,
+            security: ConnectionSecurity::parse(&row.get::<_, String>(5)?).unwrap_or_default(),
+            ca_certificate: row.get(6)?,
+            pinned_certificate: row.get(7)?,
+            auth_method: ImapAuthMethod::parse(&row.get::<_, String>(8)?).unwrap_or_default(),
+            oauth_provider: OAuthProvider::parse(&row.get::<_, String>(9)?).unwrap_or_default(),
+            oauth_client_id: row.get(10)?,
+            oauth_client_secret: row.get(11)?,
+            enabled: row.get::<_, i32>(12)? != 0,
+            created_at: row.get(13)?,
+            updated_at: row.get(14)?,
+            folders: json_column(row, 15)?,
+            search: SearchCriteria {
+                since: row
+                    .get::<_, Option<String>>(16)?
+                    .and_then(|s| NaiveDate::parse_from_str(&s, "%Y-%m-%d").ok()),
+                from: json_column(row, 17)?,
+                subject: row.get(18)?,
+            },
+            imported_action: json_column(row, 19)?,
+            junk_action: json_column(row, 20)?,
+        })
+    }
+
+    /// Accounts in the order they were added
+    pub fn list(&self) -> AppResult<Vec<EmailAccount>> {
+        let sql = format!("SELECT {} FROM email_accounts ORDER BY id", COLUMNS);
+        let mut stmt = self.conn.prepare(&sql)?;
+        let accounts = stmt
+            .query_map([], Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(accounts)
+    }
+
+    /// Accounts sync should visit
+    pub fn list_enabled(&self) -> AppResult<Vec<EmailAccount>> {
+        Ok(self.list()?.into_iter().filter(|a| a.enabled).collect())
+    }
+
+    pub fn find(&self, id: i64) -> AppResult<Option<EmailAccount>> {
+        let sql = format!("SELECT {} FROM email_accounts WHERE id = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [id], Self::map_row).optional()?)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<EmailAccount> {
+        self.find(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Email account {}", id)))
+    }
+
+    pub fn create(&self, account: &EmailAccount) -> AppResult<i64> {
+        validate(account)?;
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO email_accounts (name, server, port, username, security, ca_certificate, pinned_certificate, auth_method, oauth_provider, oauth_client_id, oauth_client_secret, enabled, created_at, updated_at, folders, search_since, search_from, search_subject, imported_action, junk_action)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            rusqlite::params![
+                account.name,
+                account.server,
+                account.port,
+                account.username,
+                account.security.as_str(),
+                account.ca_certificate,
+                account.pinned_certificate,
+                account.auth_method.as_str(),
+                account.oauth_provider.as_str(),
+                account.oauth_client_id,
+                account.oauth_client_secret,
+                if account.enabled { 1 } else { 0 },
+                now,
+                now,
+                serde_json::to_string(&account.folders)?,
+                account.search.since.map(|d| d.to_string()),
+                serde_json::to_string(&account.search.from)?,
+                account.search.subject,
+                serde_json::to_string(&account.imported_action)?,
+                serde_json::to_string(&account.junk_action)?,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update(&self, account: &EmailAccount) -> AppResult<()> {
+        let id = account
+            .id
+            .ok_or_else(|| AppError::Validation("Email account ID is required for update".to_string()))?;
+        validate(account)?;
+
+        let updated = self.conn.execute(
+            "UPDATE email_accounts SET name = ?1, server = ?2, port = ?3, username = ?4, security = ?5, ca_certificate = ?6, pinned_certificate = ?7, auth_method = ?8, oauth_provider = ?9, oauth_client_id = ?10, oauth_client_secret = ?11, enabled = ?12, updated_at = ?13, folders = ?14, search_since = ?15, search_from = ?16, search_subject = ?17, imported_action = ?18, junk_action = ?19 WHERE id = ?20",
+            rusqlite::params![
+                account.name,
+                account.server,
+                account.port,
+                account.username,
+                account.security.as_str(),
+                account.ca_certificate,
+                account.pinned_certificate,
+                account.auth_method.as_str(),
+                account.oauth_provider.as_str(),
+                account.oauth_client_id,
+                account.oauth_client_secret,
+                if account.enabled { 1 } else { 0 },
+                get_current_timestamp(),
+                serde_json::to_string(&account.folders)?,
+                account.search.since.map(|d| d.to_string()),
+                serde_json::to_string(&account.search.from)?,
+                account.search.subject,
+                serde_json::to_string(&account.imported_action)?,
+                serde_json::to_string(&account.junk_action)?,
+                id,
+            ],
+        )?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Email account {}", id)));
+        }
+
+        Ok(())
+    }
+
+    /// Receipts and sync logs keep their rows with the account cleared;
+    /// mailbox cursors go with the account
+    pub fn delete(&self, id: i64) -> AppResult<()> {
+        let deleted = self.conn.execute("DELETE FROM email_accounts WHERE id = ?1", [id])?;
+        if deleted == 0 {
+            return Err(AppError::NotFound(format!("Email account {}", id)));
+        }
+        Ok(())
+    }
+}
+
+fn json_column<T: serde::de::DeserializeOwned>(row: &Row, idx: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+/// Folder names and search strings end up in IMAP commands, where line
+/// breaks cannot be sent
+fn validate(account: &EmailAccount) -> AppResult<()> {
+    if account.folders.iter().all(|f| f.trim().is_empty()) {
+        return Err(AppError::Validation("At least one folder is required".to_string()));
+    }
+
+    let search = &account.search;
+    let actions = [&account.imported_action, &account.junk_action];
+    let values = account
+        .folders
+        .iter()
+        .chain(&search.from)
+        .chain(search.subject.iter())
+        .chain(actions.iter().flat_map(|a| a.copy_to.iter().chain(a.move_to.iter())));
+    for value in values {
+        if value.contains(['\r', '\n', '\0']) {
+            return Err(AppError::Validation(format!("Invalid folder or search text: {:?}", value)));
+        }
+    }
+
+    for keyword in actions.iter().filter_map(|a| a.keyword.as_deref()) {
+        validate_keyword(keyword.trim())?;
+    }
+
+    Ok(())
+}
+
+/// Keywords are IMAP atoms, and a leading backslash would make them a system flag
+fn validate_keyword(keyword: &str) -> AppResult<()> {
+    let valid = !keyword.starts_with('\\')
+        && keyword
+            .chars()
+            .all(|c| c.is_ascii_graphic() && !matches!(c, '(' | ')' | '{' | '%' | '*' | '"' | '\\' | ']'));
+    if !valid {
+        return Err(AppError::Validation(format!("Invalid IMAP keyword: {:?}", keyword)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) fn test_account(name: &str) -> EmailAccount {
+    EmailAccount {
+        id: None,
+        name: name.to_string(),
+        server: "imap.example.com".to_string(),
+        port: 993,
+        username: format!("{}@example.com", name.to_lowercase()),
+        security: ConnectionSecurity::Tls,
+        ca_certificate: None,
+        pinned_certificate: None,
+        auth_method: ImapAuthMethod::Password,
+        oauth_provider: OAuthProvider::Google,
+        oauth_client_id: None,
+        oauth_client_secret: None,
+        folders: vec!["INBOX".to_string()],
+        search: SearchCriteria::default(),
+        imported_action: Default::default(),
+        junk_action: Default::default(),
+        enabled: true,
+        created_at: String::new(),
+        updated_at: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::{test_connection, MailboxCursorRepo, ReceiptFilter, ReceiptRepo, SyncLogRepo};
+    use crate::models::{MailboxCursor, MessageAction, Receipt};
+
+    #[test]
+    fn test_account_crud() {
+        let conn = test_connection();
+        let repo = EmailAccountRepo::new(&conn);
+
+        let personal = repo.create(&test_account("Personal")).unwrap();
+        let mut work = test_account("Work");
+        work.auth_method = ImapAuthMethod::OAuth2;
+        work.oauth_provider = OAuthProvider::Microsoft;
+        work.oauth_client_id = Some("client".to_string());
+        let work_id = repo.create(&work).unwrap();
+
+        let mut fetched = repo.get(personal).unwrap();
+        assert_eq!(fetched.username, "personal@example.com");
+        assert_eq!(repo.get(work_id).unwrap().oauth_provider, OAuthProvider::Microsoft);
+
+        fetched.enabled = false;
+        fetched.security = ConnectionSecurity::StartTls;
+        repo.update(&fetched).unwrap();
+        assert_eq!(repo.get(personal).unwrap().security, ConnectionSecurity::StartTls);
+
+        let enabled: Vec<_> = repo.list_enabled().unwrap().into_iter().map(|a| a.id).collect();
+        assert_eq!(enabled, vec![Some(work_id)]);
+        assert_eq!(repo.list().unwrap().len(), 2);
+
+        repo.delete(personal).unwrap();
+        assert!(matches!(repo.get(personal), Err(AppError::NotFound(_))));
+        assert!(matches!(repo.delete(personal), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_folders_and_search_round_trip() {
+        let conn = test_connection();
+        let repo = EmailAccountRepo::new(&conn);
+
+        let mut account = test_account("Filtered");
+        account.folders = vec!["Receipts".to_string(), "[Gmail]/All Mail".to_string()];
+        account.search = SearchCriteria {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1),
+            from: vec!["@stripe.com".to_string(), "billing@".to_string()],
+            subject: Some("receipt".to_string()),
+        };
+        let id = repo.create(&account).unwrap();
+
+        let fetched = repo.get(id).unwrap();
+        assert_eq!(fetched.folders, account.folders);
+        assert_eq!(fetched.search, account.search);
+
+        account.id = Some(id);
+        account.folders = vec![" ".to_string()];
+        assert!(matches!(repo.update(&account), Err(AppError::Validation(_))));
+
+        account.folders = vec!["INBOX".to_string()];
+        account.search.subject = Some("receipt\r\nA1 LOGOUT".to_string());
+        assert!(matches!(repo.update(&account), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn test_message_actions_round_trip() {
+        let conn = test_connection();
+        let repo = EmailAccountRepo::new(&conn);
+
+        let mut account = test_account("Actions");
+        account.imported_action = MessageAction {
+            mark_seen: true,
+            keyword: Some("$SubScriptImported".to_string()),
+            copy_to: None,
+            move_to: Some("Receipts".to_string()),
+        };
+        account.junk_action.move_to = Some("Junk".to_string());
+        let id = repo.create(&account).unwrap();
+
+        let fetched = repo.get(id).unwrap();
+        assert_eq!(fetched.imported_action, account.imported_action);
+        assert_eq!(fetched.junk_action, account.junk_action);
+
+        account.id = Some(id);
+        for keyword in ["two words", "\\Deleted", "bad)"] {
+            account.imported_action.keyword = Some(keyword.to_string());
+            assert!(matches!(repo.update(&account), Err(AppError::Validation(_))), "{}", keyword);
+        }
+    }
+
+    #[test]
+    fn test_delete_keeps_receipts_and_logs() {
+        let conn = test_connection();
+        let repo = EmailAccountRepo::new(&conn);
+        let account_id = repo.create(&test_account("Shared")).unwrap();
+
+        let receipt_id = ReceiptRepo::new(&conn)
+            .create(&Receipt {
+                id: None,
+                subscription_id: None,
+                domain_id: None,
+                email_subject: None,
+                email_from: None,
+                email_date: "2024-01-01".to_string(),
+                file_type: None,
+                file_hash: None,
+                file_size: None,
+                raw_email_body: None,
+                created_at: String::new(),
+                message_id: None,
+                account_id: Some(account_id),
+                imap_action: None,
+                processing_state: "extracted".to_string(),
+                processing_error: None,
+                raw_message_hash: None,
+                ocr_text: None,
+                ocr_confidence: None,
+            })
+            .unwrap();
+        let filter = ReceiptFilter {
+            account_id: Some(account_id),
+            ..Default::default()
+        };
+        assert_eq!(ReceiptRepo::new(&conn).list(&filter, None).unwrap().len(), 1);
+
+        SyncLogRepo::new(&conn).start(account_id).unwrap();
+        MailboxCursorRepo::new(&conn)
+            .save(&MailboxCursor {
+                account_id,
+                mailbox: "INBOX".to_string(),
+                uid_validity: 1,
+                last_uid: 10,
+            })
+            .unwrap();
+
+        repo.delete(account_id).unwrap();
+
+        assert_eq!(ReceiptRepo::new(&conn).get(receipt_id).unwrap().account_id, None);
+        assert_eq!(SyncLogRepo::new(&conn).list(None).unwrap()[0].account_id, None);
+        assert_eq!(MailboxCursorRepo::new(&conn).find(account_id, "INBOX").unwrap(), None);
+    }
+}
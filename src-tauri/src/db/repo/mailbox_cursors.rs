@@ -0,0 +1,88 @@
+// Mailbox cursor repository
+
+use crate::models::MailboxCursor;
+use crate::utils::{get_current_timestamp, AppResult};
+use rusqlite::{Connection, OptionalExtension};
+
+pub struct MailboxCursorRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> MailboxCursorRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn find(&self, account_id: i64, mailbox: &str) -> AppResult<Option<MailboxCursor>> {
+        let cursor = self
+            .conn
+            .query_row(
+                "SELECT account_id, mailbox, uid_validity, last_uid FROM mailbox_cursors WHERE account_id = ?1 AND mailbox = ?2",
+                rusqlite::params![account_id, mailbox],
+                |row| {
+                    Ok(MailboxCursor {
+                        account_id: row.get(0)?,
+                        mailbox: row.get(1)?,
+                        uid_validity: row.get(2)?,
+                        last_uid: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(cursor)
+    }
+
+    /// Insert or replace the cursor for `cursor.mailbox` of `cursor.account_id`
+    pub fn save(&self, cursor: &MailboxCursor) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT INTO mailbox_cursors (account_id, mailbox, uid_validity, last_uid, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(account_id, mailbox) DO UPDATE SET
+                uid_validity = excluded.uid_validity,
+                last_uid = excluded.last_uid,
+                updated_at = excluded.updated_at",
+            rusqlite::params![
+                cursor.account_id,
+                cursor.mailbox,
+                cursor.uid_validity,
+                cursor.last_uid,
+                get_current_timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::email_accounts::test_account;
+    use crate::db::repo::{test_connection, EmailAccountRepo};
+
+    #[test]
+    fn test_save_and_replace_cursor() {
+        let conn = test_connection();
+        let repo = MailboxCursorRepo::new(&conn);
+        let accounts = EmailAccountRepo::new(&conn);
+        let account_id = accounts.create(&test_account("Personal")).unwrap();
+        let other_id = accounts.create(&test_account("Work")).unwrap();
+
+        assert_eq!(repo.find(account_id, "INBOX").unwrap(), None);
+
+        let mut cursor = MailboxCursor {
+            account_id,
+            mailbox: "INBOX".to_string(),
+            uid_validity: 1700000000,
+            last_uid: 42,
+        };
+        repo.save(&cursor).unwrap();
+        assert_eq!(repo.find(account_id, "INBOX").unwrap(), Some(cursor.clone()));
+        assert_eq!(repo.find(other_id, "INBOX").unwrap(), None);
+
+        cursor.uid_validity = 1800000000;
+        cursor.last_uid = 7;
+        repo.save(&cursor).unwrap();
+        assert_eq!(repo.find(account_id, "INBOX").unwrap(), Some(cursor));
+        assert_eq!(repo.find(account_id, "Receipts").unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+// Repository layer
+// Typed queries over a borrowed connection, one repository per table
+
+pub mod subscriptions;
+pub mod domains;
+pub mod pending_imports;
+pub mod receipts;
+pub mod receipt_sources;
+pub mod sync_log;
+pub mod sync_log_items;
+pub mod mailbox_cursors;
+pub mod email_accounts;
+
+pub use subscriptions::{SubscriptionFilter, SubscriptionRepo};
+pub use domains::{DomainFilter, DomainRepo};
+pub use pending_imports::{PendingImportFilter, PendingImportRepo};
+pub use receipts::{ReceiptFilter, ReceiptRepo};
+pub use receipt_sources::ReceiptSourceRepo;
+pub use sync_log::SyncLogRepo;
+pub use sync_log_items::SyncLogItemRepo;
+pub use mailbox_cursors::MailboxCursorRepo;
+pub use email_accounts::EmailAccountRepo;
+
+use rusqlite::types::Value;
+use serde::Deserialize;
+
+/// Limit/offset window for list queries
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page {
+    pub limit: u32,
+    #[serde(default)]
+    pub offset: u32,
+}
+
+impl Page {
+    fn sql(page: Option<Page>) -> String {
+        match page {
+            Some(p) => format!(" LIMIT {} OFFSET {}", p.limit, p.offset),
+            None => String::new(),
+        }
+    }
+}
+
+/// WHERE clauses and their positional parameters, built up from a filter
+#[derive(Default)]
+struct Conditions {
+    clauses: Vec<&'static str>,
+    params: Vec<Value>,
+}
+
+impl Conditions {
+    /// Add a clause with a single `?` placeholder
+    fn push(&mut self, clause: &'static str, value: impl Into<Value>) {
+        self.clauses.push(clause);
+        self.params.push(value.into());
+    }
+
+    fn push_opt<T: Into<Value>>(&mut self, clause: &'static str, value: Option<T>) {
+        if let Some(value) = value {
+            self.push(clause, value);
+        }
+    }
+
+    fn sql(&self) -> String {
+        if self.clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.clauses.join(" AND "))
+        }
+    }
+
+    fn params(&self) -> rusqlite::ParamsFromIter<std::slice::Iter<'_, Value>> {
+        rusqlite::params_from_iter(self.params.iter())
+    }
+}
+
+/// Wrap a search term for a case-insensitive `LIKE` match
+fn like_pattern(term: &str) -> String {
+    format!("%{}%", term.trim())
+}
+
+#[cfg(test)]
+pub(crate) fn test_connection() -> rusqlite::Connection {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+    crate::db::schema::init_database(&conn).unwrap();
+    conn
+}
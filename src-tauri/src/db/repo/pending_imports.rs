@@ -0,0 +1,352 @@
+// Pending import repository
+
+use super::{Conditions, DomainRepo, Page, ReceiptRepo, SubscriptionRepo};
+use crate::models::{DomainExtraction, PendingImport, SubscriptionExtraction};
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Deserialize;
+
+const COLUMNS: &str = "id, email_subject, email_from, email_date, classification, confidence, extracted_data, receipt_id, status, created_at, message_id";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingImportFilter {
+    /// "pending", "approved" or "rejected"
+    pub status: Option<String>,
+    /// "subscription", "domain" or "junk"
+    pub classification: Option<String>,
+}
+
+impl PendingImportFilter {
+    /// Imports still waiting for review
+    pub fn pending() -> Self {
+        Self {
+            status: Some("pending".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+pub struct PendingImportRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> PendingImportRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<PendingImport> {
+        Ok(PendingImport {
+            id: Some(row.get(0)?),
+            email_subject: row.get(1)?,
+            email_from: row.get(2)?,
+            email_date: row.get(3)?,
+            classification: row.get(4)?,
+            confidence: row.get(5)?,
+            extracted_data: row.get(6)?,
+            receipt_id: row.get(7)?,
+            status: row.get(8)?,
+            created_at: row.get(9)?,
+            message_id: row.get(10)?,
+        })
+    }
+
+    fn conditions(filter: &PendingImportFilter) -> Conditions {
+        let mut conditions = Conditions::default();
+        conditions.push_opt("status = ?", filter.status.clone());
+        conditions.push_opt("classification = ?", filter.classification.clone());
+        conditions
+    }
+
+    /// Newest first
+    pub fn list(&self, filter: &PendingImportFilter, page: Option<Page>) -> AppResult<Vec<PendingImport>> {
+        let conditions = Self::conditions(filter);
+        let sql = format!(
+            "SELECT {} FROM pending_imports{} ORDER BY created_at DESC, id DESC{}",
+            COLUMNS,
+            conditions.sql(),
+            Page::sql(page)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let imports = stmt
+            .query_map(conditions.params(), Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(imports)
+    }
+
+    pub fn count(&self, filter: &PendingImportFilter) -> AppResult<i64> {
+        let conditions = Self::conditions(filter);
+        let sql = format!("SELECT COUNT(*) FROM pending_imports{}", conditions.sql());
+        Ok(self.conn.query_row(&sql, conditions.params(), |row| row.get(0))?)
+    }
+
+    pub fn find(&self, id: i64) -> AppResult<Option<PendingImport>> {
+        let sql = format!("SELECT {} FROM pending_imports WHERE id = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [id], Self::map_row).optional()?)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<PendingImport> {
+        self.find(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Pending import {}", id)))
+    }
+
+    /// Store a new import awaiting review. `id`, `status` and `created_at` are ignored.
+    pub fn create(&self, import: &PendingImport) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO pending_imports (email_subject, email_from, email_date, classification, confidence, extracted_data, receipt_id, status, created_at, message_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'pending', ?8, ?9)",
+            rusqlite::params![
+                import.email_subject,
+                import.email_from,
+                import.email_date,
+                import.classification,
+                import.confidence,
+                import.extracted_data,
+                import.receipt_id,
+                get_current_timestamp(),
+                import.message_id,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Whether an import was already created from this email
+    pub fn has_message(&self, message_id: &str) -> AppResult<bool> {
+        Ok(self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pending_imports WHERE message_id = ?1)",
+            [message_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn set_status(&self, id: i64, status: &str) -> AppResult<()> {
+        let updated = self.conn.execute(
+            "UPDATE pending_imports SET status = ?1 WHERE id = ?2",
+            rusqlite::params![status, id],
+        )?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Pending import {}", id)));
+        }
+
+        Ok(())
+    }
+
+    /// Turn an import into a subscription or domain, link its receipt and
+    /// mark it approved, all in one transaction. Returns the created record's id.
+    pub fn approve(&self, id: i64, edited_data: Option<String>) -> AppResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let pending_import = self.get(id)?;
+
+        // Use edited data if provided, otherwise use extracted data
+        let data_to_use = edited_data.unwrap_or(pending_import.extracted_data);
+
+        // Parse the JSON data based on classification
+        let created_id = match pending_import.classification.as_deref() {
+            Some("subscription") => {
+                let extraction: SubscriptionExtraction = serde_json::from_str(&data_to_use)?;
+                let created_id = SubscriptionRepo::new(&tx).create_from_extraction(&extraction)?;
+                if let Some(receipt_id) = pending_import.receipt_id {
+                    ReceiptRepo::new(&tx).link_subscription(receipt_id, created_id)?;
+                }
+                created_id
+            }
+            Some("domain") => {
+                let extraction: DomainExtraction = serde_json::from_str(&data_to_use)?;
+                let created_id = DomainRepo::new(&tx).upsert_from_extraction(&extraction)?;
+                if let Some(receipt_id) = pending_import.receipt_id {
+                    ReceiptRepo::new(&tx).link_domain(receipt_id, created_id)?;
+                }
+                created_id
+            }
+            _ => {
+                return Err(AppError::Validation(
+                    "Invalid classification or junk email".to_string(),
+                ));
+            }
+        };
+
+        PendingImportRepo::new(&tx).set_status(id, "approved")?;
+        tx.commit()?;
+
+        Ok(created_id)
+    }
+
+    pub fn reject(&self, id: i64) -> AppResult<()> {
+        self.set_status(id, "rejected")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::{test_connection, DomainFilter, SubscriptionFilter};
+    use crate::models::Receipt;
+
+    fn import(subject: &str, classification: &str, extracted_data: &str) -> PendingImport {
+        PendingImport {
+            id: None,
+            email_subject: Some(subject.to_string()),
+            email_from: "billing@example.com".to_string(),
+            email_date: Some("2024-01-01".to_string()),
+            classification: Some(classification.to_string()),
+            confidence: Some(0.95),
+            extracted_data: extracted_data.to_string(),
+            receipt_id: None,
+            status: "pending".to_string(),
+            created_at: "".to_string(),
+            message_id: None,
+        }
+    }
+
+    #[test]
+    fn test_create_and_get_pending_imports() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let id = repo
+            .create(&import("Test Subject", "subscription", "{\"name\":\"Test\"}"))
+            .unwrap();
+        assert!(id > 0);
+
+        let imports = repo.list(&PendingImportFilter::pending(), None).unwrap();
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].email_subject, Some("Test Subject".to_string()));
+        assert_eq!(imports[0].email_from, "billing@example.com");
+        assert_eq!(imports[0].extracted_data, "{\"name\":\"Test\"}");
+        assert_eq!(imports[0].status, "pending");
+    }
+
+    #[test]
+    fn test_message_id_is_unique() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let mut first = import("Invoice", "subscription", "{}");
+        first.message_id = Some("<invoice-1@example.com>".to_string());
+        repo.create(&first).unwrap();
+
+        assert!(repo.has_message("<invoice-1@example.com>").unwrap());
+        assert!(!repo.has_message("<invoice-2@example.com>").unwrap());
+        assert!(repo.create(&first).is_err());
+
+        // Manually created imports have no Message-ID and never collide
+        repo.create(&import("Manual", "subscription", "{}")).unwrap();
+        repo.create(&import("Manual", "subscription", "{}")).unwrap();
+        assert_eq!(repo.count(&PendingImportFilter::default()).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_approve_subscription_links_receipt() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let receipt_id = ReceiptRepo::new(&conn)
+            .create(&Receipt {
+                id: None,
+                subscription_id: None,
+                domain_id: None,
+                email_subject: Some("Netflix Receipt".to_string()),
+                email_from: Some("info@netflix.com".to_string()),
+                email_date: "2024-01-01".to_string(),
+                file_type: None,
+                file_hash: None,
+                file_size: None,
+                raw_email_body: None,
+                created_at: "".to_string(),
+                message_id: None,
+                account_id: None,
+                imap_action: None,
+                processing_state: "extracted".to_string(),
+                processing_error: None,
+                raw_message_hash: None,
+                ocr_text: None,
+                ocr_confidence: None,
+            })
+            .unwrap();
+
+        let mut pending = import(
+            "Netflix Receipt",
+            "subscription",
+            "{\"name\":\"Netflix\",\"cost\":15.99,\"currency\":\"USD\",\"billingCycle\":\"monthly\"}",
+        );
+        pending.receipt_id = Some(receipt_id);
+        let id = repo.create(&pending).unwrap();
+
+        let subscription_id = repo.approve(id, None).unwrap();
+
+        // Verify it's no longer in pending
+        assert_eq!(repo.count(&PendingImportFilter::pending()).unwrap(), 0);
+        assert_eq!(repo.get(id).unwrap().status, "approved");
+
+        // Verify it's in subscriptions and the receipt points at it
+        let subscription = SubscriptionRepo::new(&conn).get(subscription_id).unwrap();
+        assert_eq!(subscription.name, "Netflix");
+        assert_eq!(
+            ReceiptRepo::new(&conn).get(receipt_id).unwrap().subscription_id,
+            Some(subscription_id)
+        );
+    }
+
+    #[test]
+    fn test_approve_with_edited_domain_data() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let id = repo
+            .create(&import("Renewal", "domain", "{\"domainName\":\"wrong.com\",\"expiryDate\":\"2025-01-01\"}"))
+            .unwrap();
+
+        repo.approve(
+            id,
+            Some("{\"domainName\":\"right.com\",\"expiryDate\":\"2025-01-01\"}".to_string()),
+        )
+        .unwrap();
+
+        let domains = DomainRepo::new(&conn).list(&DomainFilter::default(), None).unwrap();
+        assert_eq!(domains.len(), 1);
+        assert_eq!(domains[0].name, "right.com");
+    }
+
+    #[test]
+    fn test_approve_junk_fails_without_side_effects() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let id = repo.create(&import("Spam", "junk", "{}")).unwrap();
+
+        assert!(matches!(repo.approve(id, None), Err(AppError::Validation(_))));
+        assert_eq!(repo.get(id).unwrap().status, "pending");
+
+        // Malformed data must not leave a half-approved import behind
+        let bad = repo.create(&import("Broken", "subscription", "{\"name\":")).unwrap();
+        assert!(repo.approve(bad, None).is_err());
+        assert_eq!(repo.get(bad).unwrap().status, "pending");
+        assert_eq!(
+            SubscriptionRepo::new(&conn).count(&SubscriptionFilter::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_reject_pending_import() {
+        let conn = test_connection();
+        let repo = PendingImportRepo::new(&conn);
+
+        let id = repo.create(&import("Spam", "junk", "{}")).unwrap();
+        repo.reject(id).unwrap();
+
+        assert_eq!(repo.count(&PendingImportFilter::pending()).unwrap(), 0);
+        let rejected = PendingImportFilter {
+            status: Some("rejected".to_string()),
+            classification: Some("junk".to_string()),
+        };
+        assert_eq!(repo.list(&rejected, None).unwrap().len(), 1);
+        assert!(matches!(repo.reject(id + 100), Err(AppError::NotFound(_))));
+    }
+}
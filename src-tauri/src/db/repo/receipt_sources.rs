@@ -0,0 +1,98 @@
+// Receipt source repository
+
+use crate::models::ReceiptSource;
+use crate::utils::AppResult;
+use rusqlite::{Connection, Row};
+
+const COLUMNS: &str = "name, content_type, file_hash, file_size, inline, score, used";
+
+pub struct ReceiptSourceRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> ReceiptSourceRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<ReceiptSource> {
+        Ok(ReceiptSource {
+            name: row.get(0)?,
+            content_type: row.get(1)?,
+            file_hash: row.get(2)?,
+            file_size: row.get(3)?,
+            inline: row.get(4)?,
+            score: row.get(5)?,
+            used: row.get(6)?,
+        })
+    }
+
+    /// In the order they were saved, best first
+    pub fn list(&self, receipt_id: i64) -> AppResult<Vec<ReceiptSource>> {
+        let sql = format!("SELECT {} FROM receipt_sources WHERE receipt_id = ?1 ORDER BY position", COLUMNS);
+        let mut stmt = self.conn.prepare(&sql)?;
+        let sources = stmt
+            .query_map([receipt_id], Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sources)
+    }
+
+    /// Replace the receipt's sources. Call inside a transaction to keep the
+    /// old ones when an insert fails.
+    pub fn replace(&self, receipt_id: i64, sources: &[ReceiptSource]) -> AppResult<()> {
+        self.conn.execute("DELETE FROM receipt_sources WHERE receipt_id = ?1", [receipt_id])?;
+
+        let mut stmt = self.conn.prepare(
+            "INSERT INTO receipt_sources (receipt_id, position, name, content_type, file_hash, file_size, inline, score, used)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for (position, source) in sources.iter().enumerate() {
+            stmt.execute(rusqlite::params![
+                receipt_id,
+                position as i64,
+                source.name,
+                source.content_type,
+                source.file_hash,
+                source.file_size,
+                source.inline,
+                source.score,
+                source.used,
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::test_connection;
+
+    fn source(name: &str, file_hash: Option<&str>, used: bool) -> ReceiptSource {
+        ReceiptSource {
+            name: name.to_string(),
+            content_type: "application/pdf".to_string(),
+            file_hash: file_hash.map(str::to_string),
+            file_size: 7,
+            inline: false,
+            score: 80,
+            used,
+        }
+    }
+
+    #[test]
+    fn test_replace_and_list_sources() {
+        let conn = test_connection();
+        conn.execute("INSERT INTO receipts (id, email_date) VALUES (1, '2024-01-01')", []).unwrap();
+        let repo = ReceiptSourceRepo::new(&conn);
+
+        let first = vec![source("invoice.pdf", Some("ab"), false), source("body", None, false)];
+        repo.replace(1, &first).unwrap();
+        assert_eq!(repo.list(1).unwrap(), first);
+
+        let second = vec![source("invoice.pdf", Some("ab"), true)];
+        repo.replace(1, &second).unwrap();
+        assert_eq!(repo.list(1).unwrap(), second);
+        assert!(repo.list(2).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+// Receipt repository
+
+use super::{Conditions, Page};
+use crate::models::Receipt;
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Deserialize;
+
+const COLUMNS: &str = "id, subscription_id, domain_id, email_subject, email_from, email_date, file_type, file_hash, file_size, raw_email_body, created_at, message_id, account_id, imap_action, processing_state, processing_error, raw_message_hash, ocr_text, ocr_confidence";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptFilter {
+    pub subscription_id: Option<i64>,
+    pub domain_id: Option<i64>,
+    pub account_id: Option<i64>,
+    pub processing_state: Option<String>,
+    /// Only receipts stored before this timestamp
+    pub created_before: Option<String>,
+}
+
+pub struct ReceiptRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> ReceiptRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<Receipt> {
+        Ok(Receipt {
+            id: Some(row.get(0)?),
+            subscription_id: row.get(1)?,
+            domain_id: row.get(2)?,
+            email_subject: row.get(3)?,
+            email_from: row.get(4)?,
+            email_date: row.get(5)?,
+            file_type: row.get(6)?,
+            file_hash: row.get(7)?,
+            file_size: row.get(8)?,
+            raw_email_body: row.get(9)?,
+            created_at: row.get(10)?,
+            message_id: row.get(11)?,
+            account_id: row.get(12)?,
+            imap_action: row.get(13)?,
+            processing_state: row.get(14)?,
+            processing_error: row.get(15)?,
+            raw_message_hash: row.get(16)?,
+            ocr_text: row.get(17)?,
+            ocr_confidence: row.get(18)?,
+        })
+    }
+
+    fn conditions(filter: &ReceiptFilter) -> Conditions {
+        let mut conditions = Conditions::default();
+        conditions.push_opt("subscription_id = ?", filter.subscription_id);
+        conditions.push_opt("domain_id = ?", filter.domain_id);
+        conditions.push_opt("account_id = ?", filter.account_id);
+        conditions.push_opt("processing_state = ?", filter.processing_state.clone());
+        conditions.push_opt("created_at < ?", filter.created_before.clone());
+        conditions
+    }
+
+    /// Most recent email first
+    pub fn list(&self, filter: &ReceiptFilter, page: Option<Page>) -> AppResult<Vec<Receipt>> {
+        let conditions = Self::conditions(filter);
+        let sql = format!(
+            "SELECT {} FROM receipts{} ORDER BY email_date DESC, id DESC{}",
+            COLUMNS,
+            conditions.sql(),
+            Page::sql(page)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let receipts = stmt
+            .query_map(conditions.params(), Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(receipts)
+    }
+
+    pub fn find(&self, id: i64) -> AppResult<Option<Receipt>> {
+        let sql = format!("SELECT {} FROM receipts WHERE id = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [id], Self::map_row).optional()?)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<Receipt> {
+        self.find(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Receipt {}", id)))
+    }
+
+    pub fn create(&self, receipt: &Receipt) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO receipts (subscription_id, domain_id, email_subject, email_from, email_date, file_type, file_hash, file_size, raw_email_body, created_at, message_id, account_id, imap_action, processing_state, processing_error, raw_message_hash, ocr_text, ocr_confidence)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            rusqlite::params![
+                receipt.subscription_id,
+                receipt.domain_id,
+                receipt.email_subject,
+                receipt.email_from,
+                receipt.email_date,
+                receipt.file_type,
+                receipt.file_hash,
+                receipt.file_size,
+                receipt.raw_email_body,
+                get_current_timestamp(),
+                receipt.message_id,
+                receipt.account_id,
+                receipt.imap_action,
+                receipt.processing_state,
+                receipt.processing_error,
+                receipt.raw_message_hash,
+                receipt.ocr_text,
+                receipt.ocr_confidence,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn find_id_by_message_id(&self, message_id: &str) -> AppResult<Option<i64>> {
+        let id = self
+            .conn
+            .query_row("SELECT id FROM receipts WHERE message_id = ?1", [message_id], |row| row.get(0))
+            .optional()?;
+        Ok(id)
+    }
+
+    /// Move a receipt along the extraction pipeline; `error` is kept only for "failed"
+    pub fn set_processing_state(&self, id: i64, state: &str, error: Option<&str>) -> AppResult<()> {
+        let updated = self.conn.execute(
+            "UPDATE receipts SET processing_state = ?1, processing_error = ?2 WHERE id = ?3",
+            rusqlite::params![state, error, id],
+        )?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Receipt {}", id)));
+        }
+        Ok(())
+    }
+
+    pub fn set_ocr(&self, id: i64, text: &str, confidence: f64) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE receipts SET ocr_text = ?1, ocr_confidence = ?2 WHERE id = ?3",
+            rusqlite::params![text, confidence, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn link_subscription(&self, id: i64, subscription_id: i64) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE receipts SET subscription_id = ?1, domain_id = NULL WHERE id = ?2",
+            [subscription_id, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn link_domain(&self, id: i64, domain_id: i64) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE receipts SET domain_id = ?1, subscription_id = NULL WHERE id = ?2",
+            [domain_id, id],
+        )?;
+        Ok(())
+    }
+
+    /// Record what was done to the source message on the IMAP server
+    pub fn set_imap_action(&self, id: i64, action: &str) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE receipts SET imap_action = ?1 WHERE id = ?2",
+            rusqlite::params![action, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM receipts WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    /// Delete receipts stored before `cutoff`, returning how many were removed
+    pub fn delete_created_before(&self, cutoff: &str) -> AppResult<usize> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM receipts WHERE created_at < ?1", [cutoff])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::{test_connection, SubscriptionFilter, SubscriptionRepo};
+    use crate::models::Subscription;
+
+    fn receipt(subject: &str, email_date: &str) -> Receipt {
+        Receipt {
+            id: None,
+            subscription_id: None,
+            domain_id: None,
+            email_subject: Some(subject.to_string()),
+            email_from: Some("billing@example.com".to_string()),
+            email_date: email_date.to_string(),
+            file_type: Some("application/pdf".to_string()),
+            file_hash: Some("ab".repeat(32)),
+            file_size: Some(7),
+            raw_email_body: Some("Thanks for your payment".to_string()),
+            created_at: "".to_string(),
+            message_id: None,
+            account_id: None,
+            imap_action: None,
+            processing_state: "extracted".to_string(),
+            processing_error: None,
+            raw_message_hash: None,
+            ocr_text: None,
+            ocr_confidence: None,
+        }
+    }
+
+    #[test]
+    fn test_columns_match_schema() {
+        let conn = test_connection();
+        let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('receipts')").unwrap();
+        let schema_columns: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let repo_columns: Vec<&str> = COLUMNS.split(", ").collect();
+        assert_eq!(schema_columns, repo_columns);
+    }
+
+    #[test]
+    fn test_create_and_get_receipt() {
+        let conn = test_connection();
+        let repo = ReceiptRepo::new(&conn);
+
+        let id = repo.create(&receipt("Invoice", "2024-01-01")).unwrap();
+        let fetched = repo.get(id).unwrap();
+
+        assert_eq!(fetched.email_subject, Some("Invoice".to_string()));
+        assert_eq!(fetched.file_type, Some("application/pdf".to_string()));
+        assert_eq!(fetched.file_hash, Some("ab".repeat(32)));
+        assert_eq!(fetched.file_size, Some(7));
+        assert!(matches!(repo.get(id + 1), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_processing_state_and_filter() {
+        let conn = test_connection();
+        let repo = ReceiptRepo::new(&conn);
+
+        let id = repo
+            .create(&Receipt {
+                message_id: Some("<stored@example.com>".to_string()),
+                processing_state: "fetched".to_string(),
+                ..receipt("Invoice", "2024-01-01")
+            })
+            .unwrap();
+        repo.create(&receipt("Older", "2023-01-01")).unwrap();
+        assert_eq!(repo.find_id_by_message_id("<stored@example.com>").unwrap(), Some(id));
+        assert_eq!(repo.find_id_by_message_id("<other@example.com>").unwrap(), None);
+
+        repo.set_processing_state(id, "failed", Some("Ollama is not running")).unwrap();
+        let failed = ReceiptFilter { processing_state: Some("failed".to_string()), ..Default::default() };
+        let listed = repo.list(&failed, None).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].processing_error, Some("Ollama is not running".to_string()));
+
+        repo.set_processing_state(id, "extracted", None).unwrap();
+        assert!(repo.list(&failed, None).unwrap().is_empty());
+        assert_eq!(repo.get(id).unwrap().processing_error, None);
+        assert!(matches!(repo.set_processing_state(id + 10, "failed", None), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_link_and_filter_by_subscription() {
+        let conn = test_connection();
+        let repo = ReceiptRepo::new(&conn);
+
+        let subscription_id = SubscriptionRepo::new(&conn)
+            .create(&Subscription {
+                id: None,
+                name: "Netflix".to_string(),
+                cost: 15.99,
+                currency: "USD".to_string(),
+                periodicity: "monthly".to_string(),
+                next_date: None,
+                category: None,
+                status: "active".to_string(),
+                notes: None,
+                created_at: "".to_string(),
+                updated_at: "".to_string(),
+            })
+            .unwrap();
+
+        let linked = repo.create(&receipt("January", "2024-01-01")).unwrap();
+        repo.create(&receipt("Unrelated", "2024-02-01")).unwrap();
+        repo.link_subscription(linked, subscription_id).unwrap();
+
+        let filter = ReceiptFilter {
+            subscription_id: Some(subscription_id),
+            ..Default::default()
+        };
+        let receipts = repo.list(&filter, None).unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].id, Some(linked));
+
+        // Deleting the subscription unlinks rather than deletes the receipt
+        SubscriptionRepo::new(&conn).delete(subscription_id).unwrap();
+        assert_eq!(repo.get(linked).unwrap().subscription_id, None);
+        assert_eq!(
+            SubscriptionRepo::new(&conn).count(&SubscriptionFilter::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_delete_created_before() {
+        let conn = test_connection();
+        let repo = ReceiptRepo::new(&conn);
+
+        let old = repo.create(&receipt("Old", "2020-01-01")).unwrap();
+        conn.execute(
+            "UPDATE receipts SET created_at = '2020-01-02 00:00:00' WHERE id = ?1",
+            [old],
+        )
+        .unwrap();
+        let recent = repo.create(&receipt("Recent", "2024-01-01")).unwrap();
+
+        assert_eq!(repo.delete_created_before("2021-01-01 00:00:00").unwrap(), 1);
+        assert!(repo.find(old).unwrap().is_none());
+        assert!(repo.find(recent).unwrap().is_some());
+    }
+}
@@ -0,0 +1,288 @@
+// Subscription repository
+
+use super::{like_pattern, Conditions, Page};
+use crate::models::{Subscription, SubscriptionExtraction};
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Deserialize;
+
+const COLUMNS: &str =
+    "id, name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionFilter {
+    pub status: Option<String>,
+    #[serde(rename = "billingCycle")]
+    pub periodicity: Option<String>,
+    pub category: Option<String>,
+    /// Case-insensitive match on the subscription name
+    pub search: Option<String>,
+}
+
+pub struct SubscriptionRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> SubscriptionRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<Subscription> {
+        Ok(Subscription {
+            id: Some(row.get(0)?),
+            name: row.get(1)?,
+            cost: row.get(2)?,
+            currency: row.get(3)?,
+            periodicity: row.get(4)?,
+            next_date: row.get(5)?,
+            category: row.get(6)?,
+            status: row.get(7)?,
+            notes: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+        })
+    }
+
+    fn conditions(filter: &SubscriptionFilter) -> Conditions {
+        let mut conditions = Conditions::default();
+        conditions.push_opt("status = ?", filter.status.clone());
+        conditions.push_opt("periodicity = ?", filter.periodicity.clone());
+        conditions.push_opt("category = ?", filter.category.clone());
+        conditions.push_opt("name LIKE ?", filter.search.as_deref().map(like_pattern));
+        conditions
+    }
+
+    /// Newest first
+    pub fn list(&self, filter: &SubscriptionFilter, page: Option<Page>) -> AppResult<Vec<Subscription>> {
+        let conditions = Self::conditions(filter);
+        let sql = format!(
+            "SELECT {} FROM subscriptions{} ORDER BY created_at DESC, id DESC{}",
+            COLUMNS,
+            conditions.sql(),
+            Page::sql(page)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let subscriptions = stmt
+            .query_map(conditions.params(), Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(subscriptions)
+    }
+
+    pub fn count(&self, filter: &SubscriptionFilter) -> AppResult<i64> {
+        let conditions = Self::conditions(filter);
+        let sql = format!("SELECT COUNT(*) FROM subscriptions{}", conditions.sql());
+        Ok(self.conn.query_row(&sql, conditions.params(), |row| row.get(0))?)
+    }
+
+    pub fn find(&self, id: i64) -> AppResult<Option<Subscription>> {
+        let sql = format!("SELECT {} FROM subscriptions WHERE id = ?1", COLUMNS);
+        Ok(self.conn.query_row(&sql, [id], Self::map_row).optional()?)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<Subscription> {
+        self.find(id)?
+            .ok_or_else(|| AppError::NotFound(format!("Subscription {}", id)))
+    }
+
+    pub fn create(&self, subscription: &Subscription) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO subscriptions (name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                subscription.name,
+                subscription.cost,
+                subscription.currency,
+                subscription.periodicity,
+                subscription.next_date,
+                subscription.category,
+                subscription.status,
+                subscription.notes,
+                now,
+                now,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Create an active subscription from LLM-extracted data
+    pub fn create_from_extraction(&self, extraction: &SubscriptionExtraction) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO subscriptions (name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                extraction.name,
+                extraction.cost,
+                extraction.currency,
+                extraction.periodicity,
+                extraction.next_date,
+                extraction.category,
+                "active", // Default status
+                None::<String>, // No notes from extraction
+                now,
+                now,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update(&self, subscription: &Subscription) -> AppResult<()> {
+        let now = get_current_timestamp();
+
+        let id = subscription.id.ok_or_else(|| AppError::Validation("Subscription ID is required for update".to_string()))?;
+
+        let updated = self.conn.execute(
+            "UPDATE subscriptions SET name = ?1, cost = ?2, currency = ?3, periodicity = ?4, next_date = ?5, category = ?6, status = ?7, notes = ?8, updated_at = ?9 WHERE id = ?10",
+            rusqlite::params![
+                subscription.name,
+                subscription.cost,
+                subscription.currency,
+                subscription.periodicity,
+                subscription.next_date,
+                subscription.category,
+                subscription.status,
+                subscription.notes,
+                now,
+                id,
+            ],
+        )?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Subscription {}", id)));
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM subscriptions WHERE id = ?1", [id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::test_connection;
+
+    fn subscription(name: &str, status: &str, periodicity: &str) -> Subscription {
+        Subscription {
+            id: None,
+            name: name.to_string(),
+            cost: 9.99,
+            currency: "USD".to_string(),
+            periodicity: periodicity.to_string(),
+            next_date: Some("2024-01-01".to_string()),
+            category: Some("Entertainment".to_string()),
+            status: status.to_string(),
+            notes: None,
+            created_at: "".to_string(),
+            updated_at: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_create_and_get_subscription() {
+        let conn = test_connection();
+        let repo = SubscriptionRepo::new(&conn);
+
+        let id = repo.create(&subscription("Test Sub", "active", "monthly")).unwrap();
+        assert!(id > 0);
+
+        let subs = repo.list(&SubscriptionFilter::default(), None).unwrap();
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].name, "Test Sub");
+        assert_eq!(subs[0].cost, 9.99);
+        assert!(!subs[0].created_at.is_empty());
+    }
+
+    #[test]
+    fn test_update_subscription() {
+        let conn = test_connection();
+        let repo = SubscriptionRepo::new(&conn);
+
+        let sub = subscription("Original Name", "active", "monthly");
+        let id = repo.create(&sub).unwrap();
+
+        let mut updated_sub = sub;
+        updated_sub.id = Some(id);
+        updated_sub.name = "Updated Name".to_string();
+        updated_sub.cost = 15.0;
+
+        repo.update(&updated_sub).unwrap();
+
+        let fetched = repo.get(id).unwrap();
+        assert_eq!(fetched.name, "Updated Name");
+        assert_eq!(fetched.cost, 15.0);
+    }
+
+    #[test]
+    fn test_update_requires_existing_id() {
+        let conn = test_connection();
+        let repo = SubscriptionRepo::new(&conn);
+
+        let mut sub = subscription("Ghost", "active", "monthly");
+        assert!(matches!(repo.update(&sub), Err(AppError::Validation(_))));
+
+        sub.id = Some(42);
+        assert!(matches!(repo.update(&sub), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_delete_subscription() {
+        let conn = test_connection();
+        let repo = SubscriptionRepo::new(&conn);
+
+        let id = repo.create(&subscription("To Delete", "active", "monthly")).unwrap();
+        repo.delete(id).unwrap();
+
+        assert!(repo.find(id).unwrap().is_none());
+        assert!(matches!(repo.get(id), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_filter_and_paginate() {
+        let conn = test_connection();
+        let repo = SubscriptionRepo::new(&conn);
+
+        repo.create(&subscription("Netflix", "active", "monthly")).unwrap();
+        repo.create(&subscription("Spotify", "paused", "monthly")).unwrap();
+        repo.create(&subscription("iCloud", "active", "yearly")).unwrap();
+
+        let active = SubscriptionFilter {
+            status: Some("active".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.count(&active).unwrap(), 2);
+
+        let monthly_active = SubscriptionFilter {
+            periodicity: Some("monthly".to_string()),
+            ..active.clone()
+        };
+        let subs = repo.list(&monthly_active, None).unwrap();
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].name, "Netflix");
+
+        let search = SubscriptionFilter {
+            search: Some("cloud".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.list(&search, None).unwrap()[0].name, "iCloud");
+
+        let all = SubscriptionFilter::default();
+        let first_page = repo.list(&all, Some(Page { limit: 2, offset: 0 })).unwrap();
+        let second_page = repo.list(&all, Some(Page { limit: 2, offset: 2 })).unwrap();
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page[0].name, "Netflix");
+    }
+}
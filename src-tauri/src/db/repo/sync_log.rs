@@ -0,0 +1,158 @@
+// Sync log repository
+
+use super::Page;
+use crate::models::SyncLog;
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+
+const COLUMNS: &str = "id, sync_started_at, sync_completed_at, emails_processed, emails_imported, emails_skipped, status, error_message, created_at, account_id";
+
+pub struct SyncLogRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> SyncLogRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<SyncLog> {
+        Ok(SyncLog {
+            id: Some(row.get(0)?),
+            sync_started_at: row.get(1)?,
+            sync_completed_at: row.get(2)?,
+            emails_processed: row.get(3)?,
+            emails_imported: row.get(4)?,
+            emails_skipped: row.get(5)?,
+            status: row.get(6)?,
+            error_message: row.get(7)?,
+            created_at: row.get(8)?,
+            account_id: row.get(9)?,
+        })
+    }
+
+    /// Most recent run first
+    pub fn list(&self, page: Option<Page>) -> AppResult<Vec<SyncLog>> {
+        let sql = format!(
+            "SELECT {} FROM sync_log ORDER BY sync_started_at DESC, id DESC{}",
+            COLUMNS,
+            Page::sql(page)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let logs = stmt
+            .query_map([], Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(logs)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<SyncLog> {
+        let sql = format!("SELECT {} FROM sync_log WHERE id = ?1", COLUMNS);
+        self.conn
+            .query_row(&sql, [id], Self::map_row)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Sync log {}", id)))
+    }
+
+    /// Record the start of a sync run for an account
+    pub fn start(&self, account_id: i64) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO sync_log (sync_started_at, status, created_at, account_id) VALUES (?1, 'running', ?2, ?3)",
+            rusqlite::params![now, now, account_id],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn complete(&self, id: i64, emails_processed: i32, emails_imported: i32, emails_skipped: i32) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE sync_log SET status = 'completed', emails_processed = ?1, emails_imported = ?2, emails_skipped = ?3, sync_completed_at = ?4 WHERE id = ?5",
+            rusqlite::params![emails_processed, emails_imported, emails_skipped, get_current_timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    /// The run was stopped by the user; counts cover what was done before that
+    pub fn cancel(&self, id: i64, emails_processed: i32, emails_imported: i32, emails_skipped: i32) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE sync_log SET status = 'cancelled', emails_processed = ?1, emails_imported = ?2, emails_skipped = ?3, sync_completed_at = ?4 WHERE id = ?5",
+            rusqlite::params![emails_processed, emails_imported, emails_skipped, get_current_timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn fail(&self, id: i64, error_message: &str) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE sync_log SET status = 'failed', error_message = ?1, sync_completed_at = ?2 WHERE id = ?3",
+            rusqlite::params![error_message, get_current_timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn last_completed_at(&self) -> AppResult<Option<String>> {
+        let last_sync = self
+            .conn
+            .query_row(
+                "SELECT sync_completed_at FROM sync_log WHERE status = 'completed' ORDER BY sync_completed_at DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(last_sync)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::email_accounts::test_account;
+    use crate::db::repo::{test_connection, EmailAccountRepo};
+
+    #[test]
+    fn test_sync_log_lifecycle() {
+        let conn = test_connection();
+        let repo = SyncLogRepo::new(&conn);
+        let account_id = EmailAccountRepo::new(&conn).create(&test_account("Personal")).unwrap();
+
+        assert_eq!(repo.last_completed_at().unwrap(), None);
+
+        let completed = repo.start(account_id).unwrap();
+        assert_eq!(repo.list(None).unwrap()[0].status, "running");
+        repo.complete(completed, 5, 3, 2).unwrap();
+
+        let failed = repo.start(account_id).unwrap();
+        repo.fail(failed, "IMAP login failed").unwrap();
+
+        let cancelled = repo.start(account_id).unwrap();
+        repo.cancel(cancelled, 2, 1, 0).unwrap();
+
+        let logs = repo.list(None).unwrap();
+        assert_eq!(logs.len(), 3);
+        let find = |id| logs.iter().find(|l| l.id == Some(id)).unwrap();
+
+        let log = find(completed);
+        assert_eq!(log.status, "completed");
+        assert_eq!(log.emails_processed, 5);
+        assert_eq!(log.emails_imported, 3);
+        assert_eq!(log.emails_skipped, 2);
+        assert_eq!(log.account_id, Some(account_id));
+        assert_eq!(repo.last_completed_at().unwrap(), log.sync_completed_at);
+
+        let failed_log = find(failed);
+        assert_eq!(failed_log.status, "failed");
+        assert_eq!(failed_log.error_message, Some("IMAP login failed".to_string()));
+
+        let cancelled_log = find(cancelled);
+        assert_eq!(cancelled_log.status, "cancelled");
+        assert_eq!(cancelled_log.emails_imported, 1);
+        assert!(cancelled_log.sync_completed_at.is_some());
+
+        let latest = repo.list(Some(Page { limit: 1, offset: 0 })).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].id, Some(cancelled));
+    }
+}
@@ -0,0 +1,184 @@
+// Sync log item repository
+
+use crate::models::SyncLogItem;
+use crate::utils::{get_current_timestamp, AppError, AppResult};
+use rusqlite::{Connection, OptionalExtension, Row};
+
+const COLUMNS: &str = "id, sync_log_id, mailbox, uid, uid_validity, message_id, subject, status, stage, duration_ms, classification, error_message, receipt_id, attempts, created_at, updated_at";
+
+pub struct SyncLogItemRepo<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> SyncLogItemRepo<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn map_row(row: &Row) -> rusqlite::Result<SyncLogItem> {
+        Ok(SyncLogItem {
+            id: Some(row.get(0)?),
+            sync_log_id: row.get(1)?,
+            mailbox: row.get(2)?,
+            uid: row.get(3)?,
+            uid_validity: row.get(4)?,
+            message_id: row.get(5)?,
+            subject: row.get(6)?,
+            status: row.get(7)?,
+            stage: row.get(8)?,
+            duration_ms: row.get(9)?,
+            classification: row.get(10)?,
+            error_message: row.get(11)?,
+            receipt_id: row.get(12)?,
+            attempts: row.get(13)?,
+            created_at: row.get(14)?,
+            updated_at: row.get(15)?,
+        })
+    }
+
+    /// Items of one run in the order they were processed
+    pub fn list_for_log(&self, sync_log_id: i64) -> AppResult<Vec<SyncLogItem>> {
+        let sql = format!("SELECT {} FROM sync_log_items WHERE sync_log_id = ?1 ORDER BY id", COLUMNS);
+        let mut stmt = self.conn.prepare(&sql)?;
+        let items = stmt
+            .query_map([sync_log_id], Self::map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(items)
+    }
+
+    pub fn get(&self, id: i64) -> AppResult<SyncLogItem> {
+        let sql = format!("SELECT {} FROM sync_log_items WHERE id = ?1", COLUMNS);
+        self.conn
+            .query_row(&sql, [id], Self::map_row)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("Sync log item {}", id)))
+    }
+
+    pub fn create(&self, item: &SyncLogItem) -> AppResult<i64> {
+        let now = get_current_timestamp();
+
+        self.conn.execute(
+            "INSERT INTO sync_log_items (sync_log_id, mailbox, uid, uid_validity, message_id, subject, status, stage, duration_ms, classification, error_message, receipt_id, attempts, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            rusqlite::params![
+                item.sync_log_id,
+                item.mailbox,
+                item.uid,
+                item.uid_validity,
+                item.message_id,
+                item.subject,
+                item.status,
+                item.stage,
+                item.duration_ms,
+                item.classification,
+                item.error_message,
+                item.receipt_id,
+                item.attempts,
+                now,
+                now,
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Store the outcome of another attempt at the item's message
+    pub fn record_attempt(&self, item: &SyncLogItem) -> AppResult<()> {
+        let id = item
+            .id
+            .ok_or_else(|| AppError::Validation("Sync log item ID is required for update".to_string()))?;
+
+        self.conn.execute(
+            "UPDATE sync_log_items SET message_id = ?1, subject = ?2, status = ?3, stage = ?4, duration_ms = ?5, classification = ?6,
+                error_message = ?7, receipt_id = ?8, attempts = attempts + 1, updated_at = ?9
+             WHERE id = ?10",
+            rusqlite::params![
+                item.message_id,
+                item.subject,
+                item.status,
+                item.stage,
+                item.duration_ms,
+                item.classification,
+                item.error_message,
+                item.receipt_id,
+                get_current_timestamp(),
+                id,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::email_accounts::test_account;
+    use crate::db::repo::{test_connection, EmailAccountRepo, SyncLogRepo};
+
+    fn failed_item(sync_log_id: i64, uid: u32) -> SyncLogItem {
+        SyncLogItem {
+            id: None,
+            sync_log_id,
+            mailbox: "INBOX".to_string(),
+            uid,
+            uid_validity: 7,
+            message_id: Some(format!("<{}@example.com>", uid)),
+            subject: Some("Invoice".to_string()),
+            status: "failed".to_string(),
+            stage: Some("extracting".to_string()),
+            duration_ms: 1200,
+            classification: None,
+            error_message: Some("Ollama is not running".to_string()),
+            receipt_id: None,
+            attempts: 1,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_items_belong_to_their_run() {
+        let conn = test_connection();
+        let account_id = EmailAccountRepo::new(&conn).create(&test_account("Personal")).unwrap();
+        let logs = SyncLogRepo::new(&conn);
+        let first = logs.start(account_id).unwrap();
+        let second = logs.start(account_id).unwrap();
+
+        let repo = SyncLogItemRepo::new(&conn);
+        let a = repo.create(&failed_item(first, 10)).unwrap();
+        let b = repo.create(&failed_item(first, 11)).unwrap();
+        repo.create(&failed_item(second, 12)).unwrap();
+
+        let items = repo.list_for_log(first).unwrap();
+        assert_eq!(items.iter().map(|i| i.id.unwrap()).collect::<Vec<_>>(), vec![a, b]);
+        let mut stored = items[0].clone();
+        stored.created_at.clear();
+        stored.updated_at.clear();
+        assert_eq!(stored, SyncLogItem { id: Some(a), ..failed_item(first, 10) });
+    }
+
+    #[test]
+    fn test_record_attempt_counts_attempts() {
+        let conn = test_connection();
+        let account_id = EmailAccountRepo::new(&conn).create(&test_account("Personal")).unwrap();
+        let sync_log_id = SyncLogRepo::new(&conn).start(account_id).unwrap();
+        let repo = SyncLogItemRepo::new(&conn);
+        let id = repo.create(&failed_item(sync_log_id, 10)).unwrap();
+
+        let mut item = repo.get(id).unwrap();
+        item.status = "imported".to_string();
+        item.stage = Some("saved".to_string());
+        item.classification = Some("subscription".to_string());
+        item.error_message = None;
+        repo.record_attempt(&item).unwrap();
+
+        let retried = repo.get(id).unwrap();
+        assert_eq!(retried.status, "imported");
+        assert_eq!(retried.error_message, None);
+        assert_eq!(retried.attempts, 2);
+        assert_eq!(retried.sync_log_id, sync_log_id);
+
+        assert!(matches!(repo.get(id + 1), Err(AppError::NotFound(_))));
+    }
+}
@@ -1,15 +1,208 @@
 // Database schema initialization
-// Contains all SQL statements for creating tables and indexes
+// Contains the ordered list of schema migrations and the runner that applies them
 
-use rusqlite::Connection;
-use anyhow::Result;
+use rusqlite::{Connection, Transaction};
+use anyhow::{bail, Result};
 
-const SCHEMA_VERSION: i32 = 1;
+/// Schema version this build expects. Always equal to the last migration's version.
+pub const SCHEMA_VERSION: i32 = 14;
 
-/// Initialize database with complete schema
+/// A single, ordered schema change
+struct Migration {
+    version: i32,
+    description: &'static str,
+    apply: fn(&Transaction) -> Result<()>,
+}
+
+/// All migrations, in the order they must be applied.
+/// Never edit a migration that has shipped - append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        apply: migrate_v1_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "Require status on subscriptions and domains",
+        apply: migrate_v2_require_status,
+    },
+    Migration {
+        version: 3,
+        description: "Move receipt attachments out of the database",
+        apply: migrate_v3_external_attachments,
+    },
+    Migration {
+        version: 4,
+        description: "Track IMAP sync position per mailbox",
+        apply: migrate_v4_mailbox_cursors,
+    },
+    Migration {
+        version: 5,
+        description: "Deduplicate imported emails by Message-ID",
+        apply: migrate_v5_message_ids,
+    },
+    Migration {
+        version: 6,
+        description: "Replace imap_use_ssl with imap_security",
+        apply: migrate_v6_imap_security,
+    },
+    Migration {
+        version: 7,
+        description: "Support multiple email accounts",
+        apply: migrate_v7_email_accounts,
+    },
+    Migration {
+        version: 8,
+        description: "Configurable folders and search criteria per email account",
+        apply: migrate_v8_account_folders,
+    },
+    Migration {
+        version: 9,
+        description: "Post-processing actions for synced messages",
+        apply: migrate_v9_message_actions,
+    },
+    Migration {
+        version: 10,
+        description: "Allow cancelled sync runs",
+        apply: migrate_v10_cancelled_sync,
+    },
+    Migration {
+        version: 11,
+        description: "Record each message of a sync run",
+        apply: migrate_v11_sync_log_items,
+    },
+    Migration {
+        version: 12,
+        description: "Track extraction progress on receipts",
+        apply: migrate_v12_receipt_processing_state,
+    },
+    Migration {
+        version: 13,
+        description: "Keep OCR text on receipts",
+        apply: migrate_v13_receipt_ocr,
+    },
+    Migration {
+        version: 14,
+        description: "Record which parts of an email were read",
+        apply: migrate_v14_receipt_sources,
+    },
+];
+
+/// Initialize database with complete schema, applying any pending migrations
 pub fn init_database(conn: &Connection) -> Result<()> {
-    // Create subscriptions table
     conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    let current = current_schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        bail!(
+            "Database schema version {} is newer than this version of SubScript supports ({}). Please update the app.",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+
+    if current < SCHEMA_VERSION {
+        run_migrations(conn, current)?;
+    }
+
+    // Insert default settings if they don't exist
+    initialize_default_settings(conn)?;
+
+    Ok(())
+}
+
+/// Read the highest applied schema version (0 for a brand new database)
+pub fn current_schema_version(conn: &Connection) -> Result<i32> {
+    let version: Option<i32> =
+        conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Apply every migration newer than `current`, each inside its own transaction
+fn run_migrations(conn: &Connection, current: i32) -> Result<()> {
+    // Table rebuilds drop and rename tables that other tables reference, so
+    // foreign key enforcement has to be off while migrating. It cannot be
+    // toggled inside a transaction; integrity is verified per migration instead.
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+
+    let result = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .try_for_each(|migration| apply_migration(conn, migration));
+
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+
+    result
+}
+
+fn apply_migration(conn: &Connection, migration: &Migration) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+
+    (migration.apply)(&tx).map_err(|e| {
+        anyhow::anyhow!(
+            "Migration {} ({}) failed: {}",
+            migration.version,
+            migration.description,
+            e
+        )
+    })?;
+
+    let violations: i64 =
+        tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+    if violations > 0 {
+        bail!(
+            "Migration {} ({}) left {} foreign key violations",
+            migration.version,
+            migration.description,
+            violations
+        );
+    }
+
+    tx.execute(
+        "INSERT INTO schema_version (version) VALUES (?1)",
+        [migration.version],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Rebuild a table with a new definition, copying `columns` across.
+///
+/// SQLite cannot alter CHECK or NOT NULL constraints in place, so the table is
+/// recreated under a temporary name, filled from the old one and renamed back.
+/// `create_sql` must create a table named `{table}_new`; `select_sql` may
+/// transform values (e.g. with COALESCE) as long as it yields `columns` in order.
+fn rebuild_table(
+    tx: &Transaction,
+    table: &str,
+    create_sql: &str,
+    columns: &str,
+    select_sql: &str,
+) -> Result<()> {
+    tx.execute_batch(create_sql)?;
+    tx.execute_batch(&format!(
+        "INSERT INTO {table}_new ({columns}) SELECT {select_sql} FROM {table};
+         DROP TABLE {table};
+         ALTER TABLE {table}_new RENAME TO {table};"
+    ))?;
+    Ok(())
+}
+
+/// v1: the original schema shipped with SubScript
+fn migrate_v1_initial_schema(tx: &Transaction) -> Result<()> {
+    // Create subscriptions table
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS subscriptions (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             name TEXT NOT NULL,
@@ -26,18 +219,18 @@ pub fn init_database(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_subscriptions_next_date ON subscriptions(next_date)",
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_subscriptions_periodicity ON subscriptions(periodicity)",
         [],
     )?;
 
     // Create domains table
-    conn.execute(
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS domains (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             name TEXT NOT NULL UNIQUE,
@@ -55,13 +248,13 @@ pub fn init_database(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_domains_expiry ON domains(expiry_date)",
         [],
     )?;
 
     // Create receipts table
-    conn.execute(
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS receipts (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             subscription_id INTEGER,
@@ -82,18 +275,18 @@ pub fn init_database(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_receipts_date ON receipts(email_date)",
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_receipts_created ON receipts(created_at)",
         [],
     )?;
 
     // Create pending_imports table
-    conn.execute(
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS pending_imports (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             email_subject TEXT,
@@ -110,18 +303,18 @@ pub fn init_database(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_pending_status ON pending_imports(status)",
         [],
     )?;
 
-    conn.execute(
+    tx.execute(
         "CREATE INDEX IF NOT EXISTS idx_pending_classification ON pending_imports(classification)",
         [],
     )?;
 
     // Create settings table (key-value store)
-    conn.execute(
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS settings (
             key TEXT PRIMARY KEY,
             value TEXT NOT NULL,
@@ -131,7 +324,7 @@ pub fn init_database(conn: &Connection) -> Result<()> {
     )?;
 
     // Create sync_log table
-    conn.execute(
+    tx.execute(
         "CREATE TABLE IF NOT EXISTS sync_log (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             sync_started_at DATETIME NOT NULL,
@@ -145,23 +338,346 @@ pub fn init_database(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    // Insert default settings if they don't exist
-    initialize_default_settings(conn)?;
+    Ok(())
+}
 
-    // Store schema version
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS schema_version (
-            version INTEGER PRIMARY KEY,
-            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+/// v2: `status` was nullable on subscriptions and domains, which made rows
+/// unreadable as `Subscription`/`Domain`. Backfill NULLs and add NOT NULL.
+fn migrate_v2_require_status(tx: &Transaction) -> Result<()> {
+    rebuild_table(
+        tx,
+        "subscriptions",
+        "CREATE TABLE subscriptions_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            cost REAL NOT NULL,
+            currency TEXT NOT NULL DEFAULT 'USD',
+            periodicity TEXT NOT NULL CHECK(periodicity IN ('monthly', 'yearly', 'one-time')),
+            next_date DATE,
+            category TEXT DEFAULT 'General',
+            status TEXT NOT NULL DEFAULT 'active' CHECK(status IN ('active', 'paused', 'cancelled')),
+            notes TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
         )",
+        "id, name, cost, currency, periodicity, next_date, category, status, notes, created_at, updated_at",
+        "id, name, cost, currency, periodicity, next_date, category, COALESCE(status, 'active'), notes, created_at, updated_at",
+    )?;
+
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_subscriptions_next_date ON subscriptions(next_date);
+         CREATE INDEX IF NOT EXISTS idx_subscriptions_periodicity ON subscriptions(periodicity);
+         CREATE INDEX IF NOT EXISTS idx_subscriptions_status ON subscriptions(status);",
+    )?;
+
+    rebuild_table(
+        tx,
+        "domains",
+        "CREATE TABLE domains_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            registrar TEXT,
+            cost REAL,
+            currency TEXT DEFAULT 'USD',
+            registration_date DATE,
+            expiry_date DATE NOT NULL,
+            auto_renew INTEGER NOT NULL DEFAULT 0,
+            status TEXT NOT NULL DEFAULT 'active' CHECK(status IN ('active', 'expired', 'pending-renewal')),
+            notes TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        "id, name, registrar, cost, currency, registration_date, expiry_date, auto_renew, status, notes, created_at, updated_at",
+        "id, name, registrar, cost, currency, registration_date, expiry_date, COALESCE(auto_renew, 0), COALESCE(status, 'active'), notes, created_at, updated_at",
+    )?;
+
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_domains_expiry ON domains(expiry_date)",
         [],
     )?;
 
-    conn.execute(
-        "INSERT OR IGNORE INTO schema_version (version) VALUES (?1)",
-        [SCHEMA_VERSION],
+    Ok(())
+}
+
+/// v3: receipts keep only a SHA-256 reference to their attachment. Inline
+/// base64 data is parked in `legacy_receipt_files` until the attachment store
+/// moves it to disk on the next startup; `import_error` marks data it could
+/// not decode.
+fn migrate_v3_external_attachments(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS legacy_receipt_files (
+            receipt_id INTEGER PRIMARY KEY,
+            file_data TEXT NOT NULL,
+            import_error TEXT
+        );
+         INSERT INTO legacy_receipt_files (receipt_id, file_data)
+            SELECT id, file_data FROM receipts WHERE file_data IS NOT NULL AND file_data != '';",
+    )?;
+
+    rebuild_table(
+        tx,
+        "receipts",
+        "CREATE TABLE receipts_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            subscription_id INTEGER,
+            domain_id INTEGER,
+            email_subject TEXT,
+            email_from TEXT,
+            email_date DATE NOT NULL,
+            file_type TEXT,
+            file_hash TEXT,
+            file_size INTEGER,
+            raw_email_body TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(subscription_id) REFERENCES subscriptions(id) ON DELETE SET NULL,
+            FOREIGN KEY(domain_id) REFERENCES domains(id) ON DELETE SET NULL,
+            CHECK((subscription_id IS NOT NULL AND domain_id IS NULL) OR
+                  (subscription_id IS NULL AND domain_id IS NOT NULL) OR
+                  (subscription_id IS NULL AND domain_id IS NULL))
+        )",
+        "id, subscription_id, domain_id, email_subject, email_from, email_date, file_type, raw_email_body, created_at",
+        "id, subscription_id, domain_id, email_subject, email_from, email_date, file_type, raw_email_body, created_at",
+    )?;
+
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_receipts_date ON receipts(email_date);
+         CREATE INDEX IF NOT EXISTS idx_receipts_created ON receipts(created_at);
+         CREATE INDEX IF NOT EXISTS idx_receipts_file_hash ON receipts(file_hash);",
+    )?;
+
+    Ok(())
+}
+
+/// v4: per-mailbox IMAP cursor so each sync only fetches new UIDs
+fn migrate_v4_mailbox_cursors(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS mailbox_cursors (
+            mailbox TEXT PRIMARY KEY,
+            uid_validity INTEGER NOT NULL,
+            last_uid INTEGER NOT NULL DEFAULT 0,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// v5: remember which email each receipt and import came from, so the same
+/// message is never imported twice
+fn migrate_v5_message_ids(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE receipts ADD COLUMN message_id TEXT;
+         ALTER TABLE pending_imports ADD COLUMN message_id TEXT;
+         ALTER TABLE sync_log ADD COLUMN emails_skipped INTEGER NOT NULL DEFAULT 0;
+         CREATE UNIQUE INDEX IF NOT EXISTS idx_receipts_message_id ON receipts(message_id);
+         CREATE UNIQUE INDEX IF NOT EXISTS idx_pending_message_id ON pending_imports(message_id);",
+    )?;
+
+    Ok(())
+}
+
+/// v6: `imap_use_ssl` was never honoured (TLS was always used), so keep TLS for
+/// `true` and move `false` to STARTTLS rather than to an unencrypted connection
+fn migrate_v6_imap_security(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "INSERT OR IGNORE INTO settings (key, value)
+            SELECT 'imap_security', CASE value WHEN 'false' THEN 'starttls' ELSE 'tls' END
+            FROM settings WHERE key = 'imap_use_ssl';
+         DELETE FROM settings WHERE key = 'imap_use_ssl';",
+    )?;
+
+    Ok(())
+}
+
+/// v7: IMAP configuration moves from settings into `email_accounts`. An
+/// existing configuration becomes account 1, noted in the
+/// `legacy_credentials_account` setting so it also inherits the old
+/// single-account keyring entries (see `services::credentials`). The old
+/// settings keys are left in place and no longer read.
+fn migrate_v7_email_accounts(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE email_accounts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            server TEXT NOT NULL,
+            port INTEGER NOT NULL DEFAULT 993,
+            username TEXT NOT NULL,
+            security TEXT NOT NULL DEFAULT 'tls' CHECK(security IN ('tls', 'starttls', 'plain')),
+            ca_certificate TEXT,
+            pinned_certificate TEXT,
+            auth_method TEXT NOT NULL DEFAULT 'password' CHECK(auth_method IN ('password', 'oauth2')),
+            oauth_provider TEXT NOT NULL DEFAULT 'google' CHECK(oauth_provider IN ('google', 'microsoft')),
+            oauth_client_id TEXT,
+            oauth_client_secret TEXT,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        INSERT INTO email_accounts (id, name, server, port, username, security, ca_certificate,
+                                    pinned_certificate, auth_method, oauth_provider, oauth_client_id, oauth_client_secret)
+        SELECT 1, username, server, port, username, security, ca_certificate,
+               pinned_certificate, auth_method, oauth_provider, oauth_client_id, oauth_client_secret
+        FROM (
+            SELECT
+                (SELECT value FROM settings WHERE key = 'imap_server') AS server,
+                COALESCE((SELECT CAST(value AS INTEGER) FROM settings WHERE key = 'imap_port'), 993) AS port,
+                COALESCE((SELECT value FROM settings WHERE key = 'imap_username'), '') AS username,
+                COALESCE((SELECT value FROM settings WHERE key = 'imap_security'), 'tls') AS security,
+                (SELECT NULLIF(value, '') FROM settings WHERE key = 'imap_ca_certificate') AS ca_certificate,
+                (SELECT NULLIF(value, '') FROM settings WHERE key = 'imap_pinned_certificate') AS pinned_certificate,
+                COALESCE((SELECT value FROM settings WHERE key = 'imap_auth_method'), 'password') AS auth_method,
+                COALESCE((SELECT value FROM settings WHERE key = 'imap_oauth_provider'), 'google') AS oauth_provider,
+                (SELECT NULLIF(value, '') FROM settings WHERE key = 'imap_oauth_client_id') AS oauth_client_id,
+                (SELECT NULLIF(value, '') FROM settings WHERE key = 'imap_oauth_client_secret') AS oauth_client_secret
+        )
+        WHERE server <> '';
+
+        INSERT INTO settings (key, value)
+        SELECT 'legacy_credentials_account', '1' WHERE EXISTS (SELECT 1 FROM email_accounts WHERE id = 1);
+
+        ALTER TABLE receipts ADD COLUMN account_id INTEGER REFERENCES email_accounts(id) ON DELETE SET NULL;
+        ALTER TABLE sync_log ADD COLUMN account_id INTEGER REFERENCES email_accounts(id) ON DELETE SET NULL;
+        CREATE INDEX IF NOT EXISTS idx_receipts_account ON receipts(account_id);",
+    )?;
+
+    // Cursors are per account now; the old ones belong to account 1, if it exists
+    rebuild_table(
+        tx,
+        "mailbox_cursors",
+        "CREATE TABLE mailbox_cursors_new (
+            account_id INTEGER NOT NULL REFERENCES email_accounts(id) ON DELETE CASCADE,
+            mailbox TEXT NOT NULL,
+            uid_validity INTEGER NOT NULL,
+            last_uid INTEGER NOT NULL DEFAULT 0,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (account_id, mailbox)
+        )",
+        "account_id, mailbox, uid_validity, last_uid, updated_at",
+        "1, mailbox, uid_validity, last_uid, updated_at",
+    )?;
+    tx.execute("DELETE FROM mailbox_cursors WHERE account_id NOT IN (SELECT id FROM email_accounts)", [])?;
+
+    Ok(())
+}
+
+/// v8: Each account lists the folders to scan (a JSON array, INBOX until
+/// changed) and optional IMAP SEARCH criteria. `search_from` is a JSON array.
+fn migrate_v8_account_folders(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE email_accounts ADD COLUMN folders TEXT NOT NULL DEFAULT '[\"INBOX\"]';
+         ALTER TABLE email_accounts ADD COLUMN search_since TEXT;
+         ALTER TABLE email_accounts ADD COLUMN search_from TEXT NOT NULL DEFAULT '[]';
+         ALTER TABLE email_accounts ADD COLUMN search_subject TEXT;",
+    )?;
+    Ok(())
+}
+
+/// v9: Per-account actions for imported and junk messages (JSON objects,
+/// empty meaning leave the message alone), and what was done on each receipt
+fn migrate_v9_message_actions(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE email_accounts ADD COLUMN imported_action TEXT NOT NULL DEFAULT '{}';
+         ALTER TABLE email_accounts ADD COLUMN junk_action TEXT NOT NULL DEFAULT '{}';
+         ALTER TABLE receipts ADD COLUMN imap_action TEXT;",
+    )?;
+    Ok(())
+}
+
+/// v10: A sync run can be cancelled from the UI
+fn migrate_v10_cancelled_sync(tx: &Transaction) -> Result<()> {
+    let columns = "id, sync_started_at, sync_completed_at, emails_processed, emails_imported, status, error_message, created_at, emails_skipped, account_id";
+    rebuild_table(
+        tx,
+        "sync_log",
+        "CREATE TABLE sync_log_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sync_started_at DATETIME NOT NULL,
+            sync_completed_at DATETIME,
+            emails_processed INTEGER DEFAULT 0,
+            emails_imported INTEGER DEFAULT 0,
+            status TEXT CHECK(status IN ('running', 'completed', 'failed', 'cancelled')),
+            error_message TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            emails_skipped INTEGER NOT NULL DEFAULT 0,
+            account_id INTEGER REFERENCES email_accounts(id) ON DELETE SET NULL
+        )",
+        columns,
+        columns,
+    )
+}
+
+/// v11: One row per message a sync run handled, so failures can be
+/// inspected and retried. `uid` is only valid with its `uid_validity`.
+fn migrate_v11_sync_log_items(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE sync_log_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sync_log_id INTEGER NOT NULL REFERENCES sync_log(id) ON DELETE CASCADE,
+            mailbox TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            uid_validity INTEGER NOT NULL,
+            message_id TEXT,
+            subject TEXT,
+            status TEXT NOT NULL CHECK(status IN ('imported', 'skipped', 'failed')),
+            stage TEXT,
+            duration_ms INTEGER NOT NULL DEFAULT 0,
+            classification TEXT,
+            error_message TEXT,
+            receipt_id INTEGER REFERENCES receipts(id) ON DELETE SET NULL,
+            attempts INTEGER NOT NULL DEFAULT 1,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_sync_log_items_sync_log ON sync_log_items(sync_log_id);",
+    )?;
+    Ok(())
+}
+
+/// v12: Receipts are stored before extraction so a failed extraction can be
+/// retried from the stored message, kept in the attachment store. Existing
+/// receipts were all extracted and have no stored message.
+fn migrate_v12_receipt_processing_state(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE receipts ADD COLUMN processing_state TEXT NOT NULL DEFAULT 'extracted'
+            CHECK(processing_state IN ('fetched', 'converted', 'extracted', 'failed'));
+         ALTER TABLE receipts ADD COLUMN processing_error TEXT;
+         ALTER TABLE receipts ADD COLUMN raw_message_hash TEXT;
+         CREATE INDEX idx_receipts_processing_state ON receipts(processing_state);",
+    )?;
+    Ok(())
+}
+
+/// v13: Text recognized in images and scanned PDFs, kept so reviewers see
+/// what the LLM was given
+fn migrate_v13_receipt_ocr(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE receipts ADD COLUMN ocr_text TEXT;
+         ALTER TABLE receipts ADD COLUMN ocr_confidence REAL;",
     )?;
+    Ok(())
+}
 
+/// v14: The attachments and body considered for each receipt, with their
+/// scores. Receipts from before keep their single attachment.
+fn migrate_v14_receipt_sources(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE receipt_sources (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            receipt_id INTEGER NOT NULL REFERENCES receipts(id) ON DELETE CASCADE,
+            position INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            file_hash TEXT,
+            file_size INTEGER NOT NULL DEFAULT 0,
+            inline INTEGER NOT NULL DEFAULT 0,
+            score INTEGER NOT NULL DEFAULT 0,
+            used INTEGER NOT NULL DEFAULT 0,
+            UNIQUE(receipt_id, position)
+        );
+        CREATE INDEX idx_receipt_sources_file_hash ON receipt_sources(file_hash);",
+    )?;
     Ok(())
 }
 
@@ -169,13 +685,15 @@ pub fn init_database(conn: &Connection) -> Result<()> {
 fn initialize_default_settings(conn: &Connection) -> Result<()> {
     let default_settings = vec![
         ("default_currency", "USD"),
-        ("imap_server", ""),
-        ("imap_port", "993"),
-        ("imap_username", ""),
-        ("imap_use_ssl", "true"),
         ("sync_interval_minutes", "30"),
+        ("push_sync", "false"),
+        ("extraction_concurrency", "2"),
+        ("llm_provider", "ollama"),
         ("ollama_endpoint", "http://localhost:11434"),
         ("ollama_model", "llama3"),
+        ("openai_endpoint", "http://localhost:1234/v1"),
+        ("openai_model", ""),
+        ("ocr_model", ""),
         ("theme", "light"),
     ];
 
@@ -191,10 +709,532 @@ fn initialize_default_settings(conn: &Connection) -> Result<()> {
 
 /// Clear all data from test database
 pub fn clear_test_database(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM sync_log_items", [])?;
     conn.execute("DELETE FROM pending_imports", [])?;
+    conn.execute("DELETE FROM receipt_sources", [])?;
     conn.execute("DELETE FROM receipts", [])?;
     conn.execute("DELETE FROM subscriptions", [])?;
     conn.execute("DELETE FROM domains", [])?;
     conn.execute("DELETE FROM sync_log", [])?;
+    conn.execute("DELETE FROM mailbox_cursors", [])?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::OptionalExtension;
+
+    /// Schema exactly as created by SubScript before migrations existed
+    const V1_FIXTURE: &str = "
+        CREATE TABLE subscriptions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            cost REAL NOT NULL,
+            currency TEXT NOT NULL DEFAULT 'USD',
+            periodicity TEXT NOT NULL CHECK(periodicity IN ('monthly', 'yearly', 'one-time')),
+            next_date DATE,
+            category TEXT DEFAULT 'General',
+            status TEXT DEFAULT 'active' CHECK(status IN ('active', 'paused', 'cancelled')),
+            notes TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_subscriptions_next_date ON subscriptions(next_date);
+        CREATE INDEX idx_subscriptions_periodicity ON subscriptions(periodicity);
+        CREATE TABLE domains (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            registrar TEXT,
+            cost REAL,
+            currency TEXT DEFAULT 'USD',
+            registration_date DATE,
+            expiry_date DATE NOT NULL,
+            auto_renew INTEGER DEFAULT 0,
+            status TEXT DEFAULT 'active' CHECK(status IN ('active', 'expired', 'pending-renewal')),
+            notes TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_domains_expiry ON domains(expiry_date);
+        CREATE TABLE receipts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            subscription_id INTEGER,
+            domain_id INTEGER,
+            email_subject TEXT,
+            email_from TEXT,
+            email_date DATE NOT NULL,
+            file_type TEXT,
+            file_data TEXT,
+            raw_email_body TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(subscription_id) REFERENCES subscriptions(id) ON DELETE SET NULL,
+            FOREIGN KEY(domain_id) REFERENCES domains(id) ON DELETE SET NULL,
+            CHECK((subscription_id IS NOT NULL AND domain_id IS NULL) OR
+                  (subscription_id IS NULL AND domain_id IS NOT NULL) OR
+                  (subscription_id IS NULL AND domain_id IS NULL))
+        );
+        CREATE INDEX idx_receipts_date ON receipts(email_date);
+        CREATE INDEX idx_receipts_created ON receipts(created_at);
+        CREATE TABLE pending_imports (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            email_subject TEXT,
+            email_from TEXT NOT NULL,
+            email_date DATE,
+            classification TEXT CHECK(classification IN ('subscription', 'domain', 'junk')),
+            confidence REAL CHECK(confidence >= 0.0 AND confidence <= 1.0),
+            extracted_data TEXT NOT NULL,
+            receipt_id INTEGER,
+            status TEXT DEFAULT 'pending' CHECK(status IN ('pending', 'approved', 'rejected')),
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(receipt_id) REFERENCES receipts(id) ON DELETE SET NULL
+        );
+        CREATE INDEX idx_pending_status ON pending_imports(status);
+        CREATE INDEX idx_pending_classification ON pending_imports(classification);
+        CREATE TABLE settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE sync_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sync_started_at DATETIME NOT NULL,
+            sync_completed_at DATETIME,
+            emails_processed INTEGER DEFAULT 0,
+            emails_imported INTEGER DEFAULT 0,
+            status TEXT CHECK(status IN ('running', 'completed', 'failed')),
+            error_message TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE schema_version (
+            version INTEGER PRIMARY KEY,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        INSERT INTO schema_version (version) VALUES (1);
+    ";
+
+    fn v1_fixture() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(V1_FIXTURE).unwrap();
+        conn.execute("PRAGMA foreign_keys = ON", []).unwrap();
+        conn
+    }
+
+    /// Apply migrations up to and including `version` only
+    fn migrate_to(conn: &Connection, version: i32) {
+        let current = current_schema_version(conn).unwrap();
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current && m.version <= version) {
+            apply_migration(conn, migration).unwrap();
+        }
+        assert_eq!(current_schema_version(conn).unwrap(), version);
+    }
+
+    #[test]
+    fn test_fresh_database_reaches_current_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+
+        assert_eq!(current_schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+        let currency: String = conn
+            .query_row("SELECT value FROM settings WHERE key = 'default_currency'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(currency, "USD");
+    }
+
+    #[test]
+    fn test_init_is_idempotent() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        init_database(&conn).unwrap();
+
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, SCHEMA_VERSION as i64);
+    }
+
+    #[test]
+    fn test_refuses_newer_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            [SCHEMA_VERSION + 1],
+        )
+        .unwrap();
+
+        let err = init_database(&conn).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let conn = v1_fixture();
+        // A row the v1 CHECK should have rejected makes the v2 table copy fail
+        conn.execute_batch(
+            "PRAGMA ignore_check_constraints = ON;
+             INSERT INTO subscriptions (name, cost, periodicity, status) VALUES ('Netflix', 9.99, 'weekly', NULL);
+             PRAGMA ignore_check_constraints = OFF;",
+        )
+        .unwrap();
+
+        let err = init_database(&conn).unwrap_err();
+        assert!(err.to_string().contains("Migration 2"));
+
+        assert_eq!(current_schema_version(&conn).unwrap(), 1);
+        let status: Option<String> = conn
+            .query_row("SELECT status FROM subscriptions", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(status, None);
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0)).unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[test]
+    fn test_v2_backfills_status_and_preserves_rows() {
+        let conn = v1_fixture();
+        conn.execute_batch(
+            "INSERT INTO subscriptions (id, name, cost, periodicity, status) VALUES (1, 'Netflix', 15.99, 'monthly', NULL);
+             INSERT INTO subscriptions (id, name, cost, periodicity, status) VALUES (2, 'Spotify', 9.99, 'monthly', 'paused');
+             INSERT INTO domains (id, name, expiry_date, auto_renew, status) VALUES (1, 'example.com', '2025-01-01', NULL, NULL);
+             INSERT INTO receipts (id, subscription_id, email_date) VALUES (1, 2, '2024-01-01');
+             INSERT INTO receipts (id, domain_id, email_date) VALUES (2, 1, '2024-01-01');",
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+        assert_eq!(current_schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+        let statuses: Vec<String> = conn
+            .prepare("SELECT status FROM subscriptions ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(statuses, vec!["active", "paused"]);
+
+        let (domain_status, auto_renew): (String, i64) = conn
+            .query_row("SELECT status, auto_renew FROM domains WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(domain_status, "active");
+        assert_eq!(auto_renew, 0);
+
+        // Receipts still point at the rebuilt tables, and cascades still work
+        conn.execute("DELETE FROM subscriptions WHERE id = 2", []).unwrap();
+        let sub_id: Option<i64> = conn
+            .query_row("SELECT subscription_id FROM receipts WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(sub_id, None);
+
+        // The new constraint is enforced
+        let result = conn.execute(
+            "INSERT INTO subscriptions (name, cost, periodicity, status) VALUES ('Bad', 1.0, 'monthly', NULL)",
+            [],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_v5_message_ids_are_unique() {
+        let conn = v1_fixture();
+        conn.execute_batch(
+            "INSERT INTO receipts (email_date) VALUES ('2024-01-01');
+             INSERT INTO receipts (email_date) VALUES ('2024-01-02');",
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+
+        // Rows from before v5 have no Message-ID and do not collide
+        let existing: i64 = conn
+            .query_row("SELECT COUNT(*) FROM receipts WHERE message_id IS NULL", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(existing, 2);
+
+        conn.execute(
+            "INSERT INTO pending_imports (email_from, extracted_data, message_id) VALUES ('a@b.com', '{}', '<1@b.com>')",
+            [],
+        )
+        .unwrap();
+        let duplicate = conn.execute(
+            "INSERT INTO pending_imports (email_from, extracted_data, message_id) VALUES ('a@b.com', '{}', '<1@b.com>')",
+            [],
+        );
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn test_v6_maps_imap_use_ssl() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO settings (key, value) VALUES ('imap_use_ssl', 'false')", [])
+            .unwrap();
+
+        init_database(&conn).unwrap();
+
+        let security: String = conn
+            .query_row("SELECT value FROM settings WHERE key = 'imap_security'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(security, "starttls");
+        let legacy: i64 = conn
+            .query_row("SELECT COUNT(*) FROM settings WHERE key = 'imap_use_ssl'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(legacy, 0);
+    }
+
+    #[test]
+    fn test_v7_moves_imap_settings_to_an_account() {
+        let conn = v1_fixture();
+        conn.execute_batch(
+            "INSERT INTO settings (key, value) VALUES ('imap_server', 'imap.example.com');
+             INSERT INTO settings (key, value) VALUES ('imap_port', '143');
+             INSERT INTO settings (key, value) VALUES ('imap_username', 'me@example.com');
+             INSERT INTO settings (key, value) VALUES ('imap_use_ssl', 'false');",
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+
+        let account: (i64, String, i64, String, String, String, i64) = conn
+            .query_row(
+                "SELECT id, server, port, username, security, auth_method, enabled FROM email_accounts",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            account,
+            (1, "imap.example.com".into(), 143, "me@example.com".into(), "starttls".into(), "password".into(), 1)
+        );
+        assert_eq!(legacy_credentials_account(&conn), Some("1".to_string()));
+    }
+
+    fn legacy_credentials_account(conn: &Connection) -> Option<String> {
+        conn.query_row("SELECT value FROM settings WHERE key = 'legacy_credentials_account'", [], |r| r.get(0))
+            .optional()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_v8_existing_accounts_scan_inbox() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO settings (key, value) VALUES ('imap_server', 'imap.example.com')", [])
+            .unwrap();
+
+        init_database(&conn).unwrap();
+
+        let (folders, search_from): (String, String) = conn
+            .query_row("SELECT folders, search_from FROM email_accounts WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(folders, r#"["INBOX"]"#);
+        assert_eq!(search_from, "[]");
+    }
+
+    #[test]
+    fn test_v9_existing_accounts_and_receipts_have_no_actions() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO settings (key, value) VALUES ('imap_server', 'imap.example.com')", [])
+            .unwrap();
+        conn.execute("INSERT INTO receipts (id, email_date) VALUES (1, '2024-01-01')", []).unwrap();
+        migrate_to(&conn, 8);
+
+        init_database(&conn).unwrap();
+
+        let (imported, junk): (String, String) = conn
+            .query_row("SELECT imported_action, junk_action FROM email_accounts WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        for action in [imported, junk] {
+            let action: crate::models::MessageAction = serde_json::from_str(&action).unwrap();
+            assert!(action.is_empty());
+        }
+        let imap_action: Option<String> = conn
+            .query_row("SELECT imap_action FROM receipts WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(imap_action, None);
+    }
+
+    #[test]
+    fn test_v10_keeps_sync_logs_and_allows_cancelled() {
+        let conn = v1_fixture();
+        conn.execute(
+            "INSERT INTO sync_log (id, sync_started_at, emails_processed, status) VALUES (1, '2024-01-01', 4, 'completed')",
+            [],
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+
+        let processed: i64 = conn
+            .query_row("SELECT emails_processed FROM sync_log WHERE id = 1", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(processed, 4);
+        conn.execute("UPDATE sync_log SET status = 'cancelled' WHERE id = 1", []).unwrap();
+        assert!(conn.execute("UPDATE sync_log SET status = 'paused' WHERE id = 1", []).is_err());
+    }
+
+    #[test]
+    fn test_v11_sync_log_items_belong_to_a_sync_log() {
+        let conn = v1_fixture();
+        conn.execute(
+            "INSERT INTO sync_log (id, sync_started_at, status) VALUES (1, '2024-01-01', 'completed')",
+            [],
+        )
+        .unwrap();
+        migrate_to(&conn, 10);
+
+        init_database(&conn).unwrap();
+
+        let items: i64 = conn.query_row("SELECT COUNT(*) FROM sync_log_items", [], |r| r.get(0)).unwrap();
+        assert_eq!(items, 0);
+        let insert = |sync_log_id: i64| {
+            conn.execute(
+                "INSERT INTO sync_log_items (sync_log_id, mailbox, uid, uid_validity, status) VALUES (?1, 'INBOX', 1, 7, 'failed')",
+                [sync_log_id],
+            )
+        };
+        insert(1).unwrap();
+        assert!(insert(2).is_err());
+
+        conn.execute("DELETE FROM sync_log WHERE id = 1", []).unwrap();
+        let items: i64 = conn.query_row("SELECT COUNT(*) FROM sync_log_items", [], |r| r.get(0)).unwrap();
+        assert_eq!(items, 0);
+    }
+
+    #[test]
+    fn test_v12_existing_receipts_are_extracted() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO receipts (id, email_date) VALUES (1, '2024-01-01')", []).unwrap();
+
+        init_database(&conn).unwrap();
+
+        let (state, raw_message_hash): (String, Option<String>) = conn
+            .query_row("SELECT processing_state, raw_message_hash FROM receipts WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(state, "extracted");
+        assert_eq!(raw_message_hash, None);
+        assert!(conn.execute("UPDATE receipts SET processing_state = 'lost' WHERE id = 1", []).is_err());
+    }
+
+    #[test]
+    fn test_v13_receipts_have_no_ocr_text() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO receipts (id, email_date) VALUES (1, '2024-01-01')", []).unwrap();
+
+        init_database(&conn).unwrap();
+
+        let ocr: (Option<String>, Option<f64>) = conn
+            .query_row("SELECT ocr_text, ocr_confidence FROM receipts WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(ocr, (None, None));
+    }
+
+    #[test]
+    fn test_v14_sources_go_with_their_receipt() {
+        let conn = v1_fixture();
+        init_database(&conn).unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             INSERT INTO receipts (id, email_date) VALUES (1, '2024-01-01');
+             INSERT INTO receipt_sources (receipt_id, position, name, content_type) VALUES (1, 0, 'body', 'text/plain');",
+        )
+        .unwrap();
+        assert!(conn
+            .execute("INSERT INTO receipt_sources (receipt_id, position, name, content_type) VALUES (1, 0, 'x', 'y')", [])
+            .is_err());
+
+        conn.execute("DELETE FROM receipts WHERE id = 1", []).unwrap();
+        let sources: i64 = conn.query_row("SELECT COUNT(*) FROM receipt_sources", [], |r| r.get(0)).unwrap();
+        assert_eq!(sources, 0);
+    }
+
+    #[test]
+    fn test_v7_without_imap_settings_creates_no_account() {
+        let conn = v1_fixture();
+        init_database(&conn).unwrap();
+
+        let accounts: i64 = conn
+            .query_row("SELECT COUNT(*) FROM email_accounts", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(accounts, 0);
+        assert_eq!(legacy_credentials_account(&conn), None);
+    }
+
+    #[test]
+    fn test_v3_parks_inline_attachments() {
+        let conn = v1_fixture();
+        conn.execute_batch(
+            "INSERT INTO receipts (id, email_date, file_type, file_data) VALUES (1, '2024-01-01', 'application/pdf', 'UERG');
+             INSERT INTO receipts (id, email_date, raw_email_body) VALUES (2, '2024-02-01', 'Thanks');
+             INSERT INTO pending_imports (email_from, extracted_data, receipt_id) VALUES ('a@b.com', '{}', 1);",
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+
+        let parked: (i64, String) = conn
+            .query_row("SELECT receipt_id, file_data FROM legacy_receipt_files", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(parked, (1, "UERG".to_string()));
+
+        let (file_type, file_hash): (Option<String>, Option<String>) = conn
+            .query_row("SELECT file_type, file_hash FROM receipts WHERE id = 1", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(file_type, Some("application/pdf".to_string()));
+        assert_eq!(file_hash, None);
+
+        let has_file_data: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('receipts') WHERE name = 'file_data'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(has_file_data, 0);
+
+        // Pending imports still reference the rebuilt receipts table
+        conn.execute("DELETE FROM receipts WHERE id = 1", []).unwrap();
+        let receipt_id: Option<i64> = conn
+            .query_row("SELECT receipt_id FROM pending_imports", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(receipt_id, None);
+    }
+
+    #[test]
+    fn test_v4_adds_mailbox_cursors_that_later_move_to_the_account() {
+        let conn = v1_fixture();
+        conn.execute("INSERT INTO settings (key, value) VALUES ('imap_server', 'imap.example.com')", [])
+            .unwrap();
+        migrate_to(&conn, 4);
+
+        let cursors: i64 = conn.query_row("SELECT COUNT(*) FROM mailbox_cursors", [], |r| r.get(0)).unwrap();
+        assert_eq!(cursors, 0);
+        conn.execute("INSERT INTO mailbox_cursors (mailbox, uid_validity, last_uid) VALUES ('INBOX', 7, 42)", [])
+            .unwrap();
+        assert!(conn.execute("INSERT INTO mailbox_cursors (mailbox, uid_validity) VALUES ('INBOX', 8)", []).is_err());
+        assert!(conn.execute("INSERT INTO mailbox_cursors (mailbox) VALUES ('Receipts')", []).is_err());
+
+        init_database(&conn).unwrap();
+
+        let cursor: (i64, i64, i64) = conn
+            .query_row("SELECT account_id, uid_validity, last_uid FROM mailbox_cursors WHERE mailbox = 'INBOX'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(cursor, (1, 7, 42));
+    }
+}
@@ -0,0 +1,153 @@
+// Managed database state
+// Owns one connection pool and attachment store per database and runs queries off the async runtime
+
+use super::attachments::AttachmentStore;
+use super::connection::{create_pool, DbPool, DatabaseType};
+use super::schema::init_database;
+use crate::utils::{AppError, AppResult};
+use rusqlite::Connection;
+#[cfg(test)]
+use std::sync::Arc;
+
+/// Database handle stored in Tauri state and shared with background services.
+/// Cloning is cheap: pools are reference counted.
+#[derive(Clone)]
+pub struct DbState {
+    production: DbPool,
+    test: DbPool,
+    production_attachments: AttachmentStore,
+    test_attachments: AttachmentStore,
+    /// Where `in_memory` keeps attachments; removed once the last clone is dropped
+    #[cfg(test)]
+    _files: Option<Arc<tempfile::TempDir>>,
+}
+
+impl DbState {
+    /// Open both databases and bring their schema up to date
+    pub fn initialize() -> anyhow::Result<Self> {
+        let state = Self {
+            production: create_pool(DatabaseType::Production)?,
+            test: create_pool(DatabaseType::Test)?,
+            production_attachments: AttachmentStore::for_database(DatabaseType::Production)?,
+            test_attachments: AttachmentStore::for_database(DatabaseType::Test)?,
+            #[cfg(test)]
+            _files: None,
+        };
+
+        for db_type in [DatabaseType::Production, DatabaseType::Test] {
+            let conn = state.pool(db_type).get()?;
+            init_database(&conn)?;
+            state.attachments(db_type).import_legacy(&conn)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Fresh in-memory databases with attachment stores in a temporary directory, for tests
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        use super::connection::create_memory_pool;
+
+        let files = tempfile::tempdir().unwrap();
+        let state = Self {
+            production: create_memory_pool().unwrap(),
+            test: create_memory_pool().unwrap(),
+            production_attachments: AttachmentStore::new(files.path().join("attachments")),
+            test_attachments: AttachmentStore::new(files.path().join("attachments_test")),
+            _files: Some(Arc::new(files)),
+        };
+
+        init_database(&state.production.get().unwrap()).unwrap();
+        init_database(&state.test.get().unwrap()).unwrap();
+
+        state
+    }
+
+    pub fn pool(&self, db_type: DatabaseType) -> &DbPool {
+        match db_type {
+            DatabaseType::Production => &self.production,
+            DatabaseType::Test => &self.test,
+        }
+    }
+
+    pub fn attachments(&self, db_type: DatabaseType) -> &AttachmentStore {
+        match db_type {
+            DatabaseType::Production => &self.production_attachments,
+            DatabaseType::Test => &self.test_attachments,
+        }
+    }
+
+    /// Check out a connection on the current thread, for tests
+    #[cfg(test)]
+    pub fn get(&self, db_type: DatabaseType) -> AppResult<super::DbConnection> {
+        Ok(self.pool(db_type).get()?)
+    }
+
+    /// Run blocking SQLite work on the blocking thread pool
+    pub async fn run<F, T>(&self, db_type: DatabaseType, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&Connection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool(db_type).clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get()?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Database task failed: {}", e)))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_files_are_removed_with_the_last_clone() {
+        let db = DbState::in_memory();
+        let stored = db.attachments(DatabaseType::Test).put(b"receipt").unwrap();
+        let path = db.attachments(DatabaseType::Test).path(&stored.hash).unwrap();
+
+        let clone = db.clone();
+        drop(db);
+        assert!(path.exists());
+
+        drop(clone);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_uses_the_blocking_pool() {
+        let db = DbState::in_memory();
+
+        // The test runtime has one thread, so another thread means spawn_blocking
+        let caller = std::thread::current().id();
+        let (thread, answer) = db
+            .run(DatabaseType::Test, |conn| {
+                let answer: i64 = conn.query_row("SELECT 42", [], |row| row.get(0))?;
+                Ok((std::thread::current().id(), answer))
+            })
+            .await
+            .unwrap();
+
+        assert_ne!(thread, caller);
+        assert_eq!(answer, 42);
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_errors_unchanged() {
+        let db = DbState::in_memory();
+
+        let result: AppResult<()> = db
+            .run(DatabaseType::Test, |_| Err(AppError::Validation("Nothing to do".to_string())))
+            .await;
+        assert!(matches!(result, Err(AppError::Validation(message)) if message == "Nothing to do"));
+
+        let result = db
+            .run(DatabaseType::Test, |conn| Ok(conn.execute("INSERT INTO missing VALUES (1)", [])?))
+            .await;
+        assert!(matches!(result, Err(AppError::Database(_))));
+    }
+}
@@ -7,60 +7,65 @@ mod models;
 mod services;
 mod utils;
 
-use db::{get_db_connection, init_database, DatabaseType};
-
-// Initialize databases on app startup
-fn initialize_databases() -> Result<(), String> {
-    // Initialize production database
-    let prod_conn = get_db_connection(DatabaseType::Production).map_err(|e| e.to_string())?;
-    init_database(&prod_conn).map_err(|e| e.to_string())?;
-
-    // Initialize test database
-    let test_conn = get_db_connection(DatabaseType::Test).map_err(|e| e.to_string())?;
-    init_database(&test_conn).map_err(|e| e.to_string())?;
-
-    Ok(())
-}
+use db::{DatabaseType, DbState};
+use services::scheduler::SyncScheduler;
+use std::sync::Arc;
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize databases before starting the app
-    if let Err(e) = initialize_databases() {
-        eprintln!("Failed to initialize databases: {}", e);
-        std::process::exit(1);
+    // Open connection pools and migrate both databases before starting the app
+    let db_state = match DbState::initialize() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to initialize databases: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Hand the old single-account keyring entries to the account made from them
+    let adopted = db_state
+        .pool(DatabaseType::Production)
+        .get()
+        .map_err(Into::into)
+        .and_then(|conn| services::credentials::adopt_legacy(&conn));
+    if let Err(e) = adopted {
+        eprintln!("Failed to move the old IMAP credentials: {}", e);
     }
 
+    let scheduler = SyncScheduler::new(db_state.clone());
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(db_state)
+        .manage(scheduler)
         .setup(|app| {
-            let _handle = app.handle().clone();
-            tauri::async_runtime::spawn(async move {
-                loop {
-                    // Run sync every 30 minutes
-                    // Note: In a real app, we might want to check settings for the interval
-                    if let Err(e) = services::sync::SyncService::run_sync(false).await {
-                        eprintln!("Background sync error: {}", e);
-                    }
-                    tokio::time::sleep(std::time::Duration::from_secs(30 * 60)).await;
+            let handle = app.handle().clone();
+            app.state::<SyncScheduler>().start(Arc::new(move |progress| {
+                if let Err(e) = handle.emit("sync-progress", progress) {
+                    eprintln!("Failed to emit sync progress: {}", e);
                 }
-            });
+            }));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Subscription commands
             commands::subscriptions::get_subscriptions,
+            commands::subscriptions::count_subscriptions,
             commands::subscriptions::get_subscription_by_id,
             commands::subscriptions::create_subscription,
             commands::subscriptions::update_subscription,
             commands::subscriptions::delete_subscription,
             // Domain commands
             commands::domains::get_domains,
+            commands::domains::count_domains,
             commands::domains::get_domain_by_id,
             commands::domains::create_domain,
             commands::domains::update_domain,
             commands::domains::delete_domain,
             // Pending import commands
             commands::pending_imports::get_pending_imports,
+            commands::pending_imports::count_pending_imports,
             commands::pending_imports::create_pending_import,
             commands::pending_imports::approve_pending_import,
             commands::pending_imports::reject_pending_import,
@@ -69,16 +74,40 @@ pub fn run() {
             // Settings commands
             commands::settings::get_settings,
             commands::settings::update_settings,
-            commands::settings::get_ollama_models,
+            commands::settings::get_llm_models,
             commands::settings::test_imap_connection,
-            commands::settings::save_imap_password,
-            commands::settings::get_imap_password,
+            // Email account commands
+            commands::accounts::get_email_accounts,
+            commands::accounts::get_email_account_by_id,
+            commands::accounts::create_email_account,
+            commands::accounts::update_email_account,
+            commands::accounts::delete_email_account,
+            commands::accounts::save_account_password,
+            commands::accounts::authorize_account_oauth,
+            commands::accounts::is_account_oauth_authorized,
+            commands::accounts::disconnect_account_oauth,
             // Receipt commands
+            commands::receipts::get_receipts,
             commands::receipts::get_receipt_by_id,
+            commands::receipts::get_receipt_sources,
+            commands::receipts::get_receipt_attachment,
+            commands::receipts::get_receipt_attachment_path,
+            commands::receipts::delete_receipt,
             commands::receipts::delete_old_receipts,
+            commands::receipts::reprocess_receipt,
+            commands::receipts::reprocess_failed_receipts,
             // Sync commands
             commands::sync::trigger_email_sync,
+            commands::sync::cancel_email_sync,
             commands::sync::get_last_sync_time,
+            commands::sync::get_sync_logs,
+            commands::sync::get_sync_log_details,
+            commands::sync::retry_sync_log_item,
+            commands::sync::get_sync_status,
+            commands::sync::sync_now,
+            commands::sync::pause_sync,
+            commands::sync::resume_sync,
+            commands::sync::import_local_mail,
             // Database management commands
             commands::database::clear_test_db,
             commands::database::export_database,
@@ -1,6 +1,7 @@
 // Data models
 // This module contains all data structures used throughout the application
 
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,6 +85,9 @@ pub struct PendingImport {
     pub receipt_id: Option<i64>,
     pub status: String, // "pending", "approved", "rejected"
     pub created_at: String,
+    /// Identifies the source email, see `EmailContent::message_id`
+    #[serde(default)]
+    pub message_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,20 +101,98 @@ pub struct Receipt {
     pub email_date: String,
     #[serde(rename = "attachmentMimeType")]
     pub file_type: Option<String>,
-    #[serde(rename = "attachmentData")]
-    pub file_data: Option<String>, // Base64 encoded
+    /// SHA-256 of the attachment in the attachment store
+    #[serde(rename = "attachmentHash")]
+    pub file_hash: Option<String>,
+    #[serde(rename = "attachmentSize")]
+    pub file_size: Option<i64>,
     pub raw_email_body: Option<String>,
     pub created_at: String,
+    /// Identifies the source email, see `EmailContent::message_id`
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// Email account the receipt was imported from
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    /// What was done to the source message on the server, see `MessageAction::describe`
+    #[serde(default)]
+    pub imap_action: Option<String>,
+    /// "fetched", "converted", "extracted" or "failed"
+    #[serde(default = "default_processing_state")]
+    pub processing_state: String,
+    /// Why extraction failed, for a "failed" receipt
+    #[serde(default)]
+    pub processing_error: Option<String>,
+    /// SHA-256 of the source message in the attachment store
+    #[serde(default)]
+    pub raw_message_hash: Option<String>,
+    /// Text recognized in an image or scanned PDF, which is what extraction read
+    #[serde(default)]
+    pub ocr_text: Option<String>,
+    /// How sure the OCR model was of `ocr_text`, 0 to 1
+    #[serde(default)]
+    pub ocr_confidence: Option<f64>,
+}
+
+/// A part of the source email that was considered for extraction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptSource {
+    /// The attachment's filename, or "body"
+    pub name: String,
+    pub content_type: String,
+    /// SHA-256 of the attachment in the attachment store; None for the body
+    pub file_hash: Option<String>,
+    pub file_size: i64,
+    pub inline: bool,
+    /// How much it looks like the receipt; the best scoring parts are read
+    pub score: i64,
+    /// Whether its text was part of what the LLM read
+    pub used: bool,
+}
+
+fn default_processing_state() -> String {
+    "extracted".to_string()
+}
+
+/// Result of retrying every failed receipt
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReprocessSummary {
+    pub extracted: usize,
+    pub failed: usize,
+    /// Extracted meanwhile by a sync
+    pub skipped: usize,
+    /// Stopped before every failed receipt was retried
+    pub cancelled: bool,
+}
+
+/// Result of importing messages from an mbox file, a Maildir or `.eml` files
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// Messages found in the files, including unreadable ones
+    pub found: usize,
+    pub imported: usize,
+    /// Stored, but conversion or extraction failed; retry with reprocessing
+    pub failed: usize,
+    /// Already imported before
+    pub skipped: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EmailContent {
+    /// The Message-ID header, or `sha256:<hex>` of the raw message when it has none
+    pub message_id: String,
     pub subject: String,
     pub from: String,
     pub date: String,
     pub body: String,
     pub attachments: Vec<Attachment>,
+    /// The message as received, so reprocessing can parse it again
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,22 +201,258 @@ pub struct Attachment {
     pub filename: String,
     pub content_type: String,
     pub data: Vec<u8>,
+    /// Shown within the message, like an embedded image, rather than attached
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// How the IMAP connection is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionSecurity {
+    /// TLS from the first byte, usually port 993
+    #[default]
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually port 143
+    StartTls,
+    /// No encryption; only allowed for servers on localhost
+    Plain,
+}
+
+impl ConnectionSecurity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionSecurity::Tls => "tls",
+            ConnectionSecurity::StartTls => "starttls",
+            ConnectionSecurity::Plain => "plain",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tls" => Some(ConnectionSecurity::Tls),
+            "starttls" => Some(ConnectionSecurity::StartTls),
+            "plain" => Some(ConnectionSecurity::Plain),
+            _ => None,
+        }
+    }
+}
+
+/// How the app signs in to the IMAP server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImapAuthMethod {
+    /// LOGIN with the password kept in the keyring
+    #[default]
+    Password,
+    /// SASL XOAUTH2 with an access token from the OAuth provider
+    OAuth2,
+}
+
+impl ImapAuthMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImapAuthMethod::Password => "password",
+            ImapAuthMethod::OAuth2 => "oauth2",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "password" => Some(ImapAuthMethod::Password),
+            "oauth2" => Some(ImapAuthMethod::OAuth2),
+            _ => None,
+        }
+    }
+}
+
+/// Mail providers with a known OAuth2 setup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthProvider {
+    #[default]
+    Google,
+    Microsoft,
+}
+
+impl OAuthProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OAuthProvider::Google => "google",
+            OAuthProvider::Microsoft => "microsoft",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "google" => Some(OAuthProvider::Google),
+            "microsoft" => Some(OAuthProvider::Microsoft),
+            _ => None,
+        }
+    }
+}
+
+/// Server-side filters for the IMAP SEARCH run on every synced folder.
+/// All set criteria must match; any one of the `from` patterns is enough.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchCriteria {
+    /// Ignore mail older than this. Also where the first sync starts.
+    pub since: Option<NaiveDate>,
+    /// Substrings of the sender, e.g. "@stripe.com"
+    pub from: Vec<String>,
+    /// Substring of the subject, e.g. "receipt"
+    pub subject: Option<String>,
+}
+
+/// What to do on the server with a message once it has been imported.
+/// Flags are set first, then the copy, then the move.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MessageAction {
+    /// Set the \Seen flag
+    pub mark_seen: bool,
+    /// Custom keyword to add, e.g. "$SubScriptImported"
+    pub keyword: Option<String>,
+    pub copy_to: Option<String>,
+    pub move_to: Option<String>,
+}
+
+impl MessageAction {
+    pub fn is_empty(&self) -> bool {
+        self.describe().is_none()
+    }
+
+    /// Summary kept on the receipt, e.g. "seen, keyword $SubScriptImported, moved to Receipts"
+    pub fn describe(&self) -> Option<String> {
+        let set = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+
+        let mut steps = Vec::new();
+        if self.mark_seen {
+            steps.push("seen".to_string());
+        }
+        if let Some(keyword) = set(&self.keyword) {
+            steps.push(format!("keyword {}", keyword));
+        }
+        if let Some(folder) = set(&self.copy_to) {
+            steps.push(format!("copied to {}", folder));
+        }
+        if let Some(folder) = set(&self.move_to) {
+            steps.push(format!("moved to {}", folder));
+        }
+
+        (!steps.is_empty()).then(|| steps.join(", "))
+    }
+}
+
+/// A mailbox to import receipts from. Its password or OAuth refresh token
+/// is kept in the keyring, see `services::credentials`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailAccount {
+    pub id: Option<i64>,
+    /// Label shown in the app, e.g. "Work"
+    pub name: String,
+    pub server: String,
+    pub port: i32,
+    pub username: String,
+    #[serde(default)]
+    pub security: ConnectionSecurity,
+    /// Path to a PEM file with an extra CA to trust, for self-hosted servers
+    pub ca_certificate: Option<String>,
+    /// SHA-256 fingerprint of the exact server certificate to accept
+    pub pinned_certificate: Option<String>,
+    #[serde(default)]
+    pub auth_method: ImapAuthMethod,
+    #[serde(default)]
+    pub oauth_provider: OAuthProvider,
+    /// Client ID of the OAuth app registered with the provider
+    pub oauth_client_id: Option<String>,
+    /// Only for providers that want one from desktop apps; it is not a real secret there
+    pub oauth_client_secret: Option<String>,
+    /// Folders to scan, e.g. "INBOX" or "[Gmail]/All Mail"
+    #[serde(default = "default_folders")]
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub search: SearchCriteria,
+    /// Applied to messages imported as a subscription or domain receipt
+    #[serde(default)]
+    pub imported_action: MessageAction,
+    /// Applied to messages classified as junk
+    #[serde(default)]
+    pub junk_action: MessageAction,
+    pub enabled: bool,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+fn default_folders() -> Vec<String> {
+    vec!["INBOX".to_string()]
+}
+
+/// What reads receipts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmProviderKind {
+    #[default]
+    Ollama,
+    /// Any server with `/v1/chat/completions`: LM Studio, vLLM, llama.cpp's server
+    OpenAi,
+    /// No model; fixed parsers only
+    Rules,
+}
+
+impl LlmProviderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LlmProviderKind::Ollama => "ollama",
+            LlmProviderKind::OpenAi => "openai",
+            LlmProviderKind::Rules => "rules",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ollama" => Some(LlmProviderKind::Ollama),
+            "openai" => Some(LlmProviderKind::OpenAi),
+            "rules" => Some(LlmProviderKind::Rules),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AppSettings {
-    pub imap_server: String,
-    pub imap_port: i32,
-    pub imap_username: String,
-    pub imap_use_ssl: bool,
+    #[serde(default)]
+    pub llm_provider: LlmProviderKind,
     pub ollama_endpoint: String,
     pub ollama_model: String,
+    /// Base URL of an OpenAI-compatible server, with or without `/v1`
+    #[serde(default)]
+    pub openai_endpoint: String,
+    #[serde(default)]
+    pub openai_model: String,
     pub default_currency: String,
     pub sync_interval_minutes: i32,
+    /// Hold IMAP IDLE sessions to sync as soon as mail arrives
+    #[serde(default)]
+    pub push_sync: bool,
+    /// How many emails are sent to the model at once during sync
+    #[serde(default = "default_extraction_concurrency")]
+    pub extraction_concurrency: u32,
+    /// Vision model that reads images and scanned PDFs; empty turns OCR off
+    #[serde(default)]
+    pub ocr_model: String,
     pub theme: String,
 }
 
+fn default_extraction_concurrency() -> u32 {
+    2
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncLog {
@@ -143,9 +461,126 @@ pub struct SyncLog {
     pub sync_completed_at: Option<String>,
     pub emails_processed: i32,
     pub emails_imported: i32,
-    pub status: String, // "running", "completed", "failed"
+    /// Emails that had already been imported by an earlier or concurrent sync
+    pub emails_skipped: i32,
+    pub status: String, // "running", "completed", "failed", "cancelled"
     pub error_message: Option<String>,
     pub created_at: String,
+    /// Email account this run synced
+    pub account_id: Option<i64>,
+}
+
+/// What happened to one message during a sync run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncLogItem {
+    pub id: Option<i64>,
+    pub sync_log_id: i64,
+    pub mailbox: String,
+    pub uid: u32,
+    pub uid_validity: u32,
+    pub message_id: Option<String>,
+    pub subject: Option<String>,
+    pub status: String, // "imported", "skipped", "failed"
+    /// Last stage the message reached, e.g. "extracting" when the LLM call failed
+    pub stage: Option<String>,
+    pub duration_ms: i64,
+    pub classification: Option<String>,
+    pub error_message: Option<String>,
+    /// Receipt created for the message, when it lives in the same database as the run
+    pub receipt_id: Option<i64>,
+    pub attempts: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A sync run with the messages it handled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncLogDetails {
+    pub log: SyncLog,
+    pub items: Vec<SyncLogItem>,
+}
+
+/// What a sync run is doing, reported as it goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncStage {
+    /// Logged in to the account's IMAP server
+    Connected,
+    /// New messages were fetched from a folder; `total` says how many
+    MessagesFound,
+    Converting,
+    /// Reading an image or scanned PDF with the OCR model
+    Recognizing,
+    Extracting,
+    Saved,
+    /// Already imported by an earlier or concurrent sync
+    Skipped,
+    Failed,
+    Cancelled,
+    Finished,
+}
+
+/// Payload of the `sync-progress` event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub stage: SyncStage,
+    pub account_id: Option<i64>,
+    pub mailbox: Option<String>,
+    /// 1-based position of the message within its folder's batch
+    pub index: Option<usize>,
+    pub total: Option<usize>,
+    /// Subject of the message, or the error when a stage failed
+    pub detail: Option<String>,
+}
+
+impl SyncStage {
+    /// Same name as in the event payload
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStage::Connected => "connected",
+            SyncStage::MessagesFound => "messagesFound",
+            SyncStage::Converting => "converting",
+            SyncStage::Recognizing => "recognizing",
+            SyncStage::Extracting => "extracting",
+            SyncStage::Saved => "saved",
+            SyncStage::Skipped => "skipped",
+            SyncStage::Failed => "failed",
+            SyncStage::Cancelled => "cancelled",
+            SyncStage::Finished => "finished",
+        }
+    }
+}
+
+impl SyncProgress {
+    pub fn new(stage: SyncStage) -> Self {
+        Self { stage, account_id: None, mailbox: None, index: None, total: None, detail: None }
+    }
+}
+
+/// State of the background sync scheduler
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulerStatus {
+    pub paused: bool,
+    /// A sync, scheduled or manual, is in progress
+    pub running: bool,
+    /// When the next scheduled sync is due; None while paused or running
+    pub next_run_at: Option<String>,
+}
+
+/// How far sync has got in one IMAP mailbox. UIDs are only meaningful while
+/// the server's UIDVALIDITY for the mailbox stays the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MailboxCursor {
+    pub account_id: i64,
+    pub mailbox: String,
+    pub uid_validity: u32,
+    /// Highest UID that has been processed
+    pub last_uid: u32,
 }
 
 #[cfg(test)]
@@ -215,6 +650,7 @@ mod tests {
             receipt_id: None,
             status: "pending".to_string(),
             created_at: "2024-01-01T10:00:00Z".to_string(),
+            message_id: Some("<abc@netflix.com>".to_string()),
         };
 
         let serialized = serde_json::to_value(&import).unwrap();
@@ -222,5 +658,6 @@ mod tests {
         assert_eq!(serialized["emailSubject"], "Receipt");
         assert_eq!(serialized["emailFrom"], "info@netflix.com");
         assert_eq!(serialized["confidence"], 0.95);
+        assert_eq!(serialized["messageId"], "<abc@netflix.com>");
     }
 }
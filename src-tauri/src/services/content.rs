@@ -0,0 +1,304 @@
+// Content selection
+// Scores an email's attachments and body to decide what receipt extraction reads
+
+use crate::models::{Attachment, EmailContent};
+use crate::utils::{AppError, AppResult};
+
+/// Attachments converted for one email; the body comes on top
+pub const MAX_ATTACHMENTS: usize = 3;
+/// Inline images smaller than this are logos, icons and tracking pixels
+const MIN_INLINE_IMAGE_BYTES: usize = 8 * 1024;
+/// Larger files are catalogues and brochures rather than receipts
+const MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+/// Parts with this many points for billing words are read along with the best one
+const BILLING_BONUS: i64 = 20;
+/// Keeps the combined text within what a local model reads well
+const MAX_CONTEXT_CHARS: usize = 24_000;
+
+const INVOICE_HINTS: &[&str] = &[
+    "invoice", "receipt", "bill", "payment", "order", "statement", "factura", "facture", "rechnung", "recibo",
+];
+const MARKETING_HINTS: &[&str] = &[
+    "newsletter", "brochure", "catalog", "promo", "flyer", "offer", "terms", "conditions", "agb", "logo", "banner",
+];
+const BILLING_WORDS: &[&str] = &[
+    "invoice", "receipt", "total", "amount", "paid", "payment", "charged", "tax", "vat", "billing", "billed",
+    "due", "renew", "subscription", "plan", "domain", "expir", "$", "€", "£",
+];
+
+/// A part of an email that may hold the receipt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// Index into `EmailContent::attachments`
+    Attachment(usize),
+    Body,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub part: Part,
+    /// Extension to convert it as
+    pub extension: &'static str,
+    pub score: i64,
+}
+
+/// A candidate after conversion
+pub struct Converted {
+    pub candidate: Candidate,
+    pub markdown: AppResult<String>,
+}
+
+/// What extraction reads
+#[derive(Debug)]
+pub struct Selection {
+    pub markdown: String,
+    /// Every candidate with its final score and whether it was read
+    pub parts: Vec<(Part, i64, bool)>,
+}
+
+/// Parts worth converting: the best attachments by type, filename and
+/// placement, then the body
+pub fn candidates(email: &EmailContent) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = email
+        .attachments
+        .iter()
+        .enumerate()
+        .filter_map(|(index, attachment)| {
+            let extension = extension(attachment)?;
+            let size = attachment.data.len();
+            let logo = attachment.inline && is_image(extension) && size < MIN_INLINE_IMAGE_BYTES;
+            if size == 0 || size > MAX_ATTACHMENT_BYTES || logo {
+                return None;
+            }
+            Some(Candidate { part: Part::Attachment(index), extension, score: attachment_score(attachment, extension) })
+        })
+        .collect();
+
+    // Stable, so equal scores keep the order of the message
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.score));
+    candidates.truncate(MAX_ATTACHMENTS);
+
+    if !email.body.trim().is_empty() {
+        candidates.push(Candidate { part: Part::Body, extension: ".html", score: 0 });
+    }
+    candidates
+}
+
+/// The best part by score once billing words are counted, along with any
+/// other part full of billing words. Fails only when no part could be converted.
+pub fn select(email: &EmailContent, converted: Vec<Converted>) -> AppResult<Selection> {
+    let mut first_error = None;
+    // (part, score, points for billing words, text)
+    let mut scored = Vec::new();
+    for Converted { candidate, markdown } in converted {
+        match markdown {
+            Ok(markdown) => {
+                let bonus = keyword_bonus(&markdown);
+                scored.push((candidate.part, candidate.score + bonus, bonus, Some(markdown)));
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+                scored.push((candidate.part, candidate.score, 0, None));
+            }
+        }
+    }
+    if scored.iter().all(|(_, _, _, markdown)| markdown.is_none()) {
+        return Err(first_error.unwrap_or_else(|| AppError::Validation("The email has no content".to_string())));
+    }
+
+    let readable: Vec<usize> = (0..scored.len())
+        .filter(|&i| scored[i].3.as_ref().is_some_and(|markdown| !markdown.trim().is_empty()))
+        .collect();
+    // Ties go to the earlier, better ranked candidate
+    let best = readable.iter().copied().reduce(|best, i| if scored[i].1 > scored[best].1 { i } else { best });
+    let mut chosen: Vec<usize> = readable
+        .iter()
+        .copied()
+        .filter(|&i| Some(i) == best || scored[i].2 >= BILLING_BONUS)
+        .collect();
+    chosen.sort_by_key(|&i| std::cmp::Reverse(scored[i].1));
+
+    let markdown = match chosen.as_slice() {
+        [] => scored.iter().find_map(|(_, _, _, markdown)| markdown.clone()).unwrap_or_default(),
+        [only] => scored[*only].3.clone().unwrap_or_default(),
+        _ => chosen
+            .iter()
+            .map(|&i| format!("## {}\n\n{}", name(email, scored[i].0), scored[i].3.as_deref().unwrap_or_default().trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    };
+    let markdown = match markdown.char_indices().nth(MAX_CONTEXT_CHARS) {
+        Some((end, _)) => markdown[..end].to_string(),
+        None => markdown,
+    };
+
+    let parts = scored
+        .iter()
+        .enumerate()
+        .map(|(i, (part, score, _, _))| (*part, *score, chosen.contains(&i)))
+        .collect();
+    Ok(Selection { markdown, parts })
+}
+
+/// How a part is labelled in the combined text and in the recorded sources
+pub fn name(email: &EmailContent, part: Part) -> String {
+    match part {
+        Part::Attachment(index) => match email.attachments[index].filename.as_str() {
+            "" => format!("attachment {}", index + 1),
+            filename => filename.to_string(),
+        },
+        Part::Body => "body".to_string(),
+    }
+}
+
+fn extension(attachment: &Attachment) -> Option<&'static str> {
+    let content_type = attachment.content_type.to_lowercase();
+    let filename = attachment.filename.to_lowercase();
+    let is = |mime: &str, suffixes: &[&str]| {
+        content_type.contains(mime) || suffixes.iter().any(|suffix| filename.ends_with(suffix))
+    };
+
+    if is("pdf", &[".pdf"]) {
+        Some(".pdf")
+    } else if is("image/jpeg", &[".jpg", ".jpeg"]) || content_type.contains("image/jpg") {
+        Some(".jpg")
+    } else if is("image/png", &[".png"]) {
+        Some(".png")
+    } else if is("text/html", &[".html", ".htm"]) {
+        Some(".html")
+    } else if is("text/csv", &[".csv"]) {
+        Some(".csv")
+    } else if is("text/plain", &[".txt"]) {
+        Some(".txt")
+    } else {
+        None
+    }
+}
+
+fn is_image(extension: &str) -> bool {
+    matches!(extension, ".jpg" | ".png")
+}
+
+fn attachment_score(attachment: &Attachment, extension: &str) -> i64 {
+    let mut score = match extension {
+        ".pdf" => 30,
+        ".jpg" | ".png" => 20,
+        _ => 10,
+    };
+    score += if attachment.inline { -10 } else { 10 };
+
+    let filename = attachment.filename.to_lowercase();
+    if INVOICE_HINTS.iter().any(|hint| filename.contains(hint)) {
+        score += 40;
+    }
+    if MARKETING_HINTS.iter().any(|hint| filename.contains(hint)) {
+        score -= 40;
+    }
+    score
+}
+
+/// Up to 40 points for billing words per hundred words, so a long
+/// newsletter with one price in it doesn't beat a short invoice
+fn keyword_bonus(text: &str) -> i64 {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return 0;
+    }
+    let hits = words.iter().filter(|word| BILLING_WORDS.iter().any(|k| word.contains(k))).count();
+    let density = hits as f64 * 100.0 / words.len() as f64;
+    (density * 4.0).min(40.0) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(filename: &str, content_type: &str, size: usize, inline: bool) -> Attachment {
+        Attachment { filename: filename.to_string(), content_type: content_type.to_string(), data: vec![b'x'; size], inline }
+    }
+
+    fn email(body: &str, attachments: Vec<Attachment>) -> EmailContent {
+        EmailContent {
+            message_id: "<test@example.com>".to_string(),
+            subject: "Your order".to_string(),
+            from: "shop@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: body.to_string(),
+            attachments,
+            raw: Vec::new(),
+        }
+    }
+
+    fn converted(email: &EmailContent, texts: &[&str]) -> Vec<Converted> {
+        candidates(email)
+            .into_iter()
+            .zip(texts)
+            .map(|(candidate, text)| Converted { candidate, markdown: Ok(text.to_string()) })
+            .collect()
+    }
+
+    #[test]
+    fn test_ranks_invoices_over_marketing_and_logos() {
+        let email = email(
+            "See attached",
+            vec![
+                attachment("Spring-Catalog.pdf", "application/pdf", 2048, false),
+                attachment("logo.png", "image/png", 900, true),
+                attachment("scan.jpg", "image/jpeg", 50_000, true),
+                attachment("invoice-1042.pdf", "application/octet-stream", 2048, false),
+                attachment("data.bin", "application/octet-stream", 2048, false),
+            ],
+        );
+
+        let ranked: Vec<_> = candidates(&email).into_iter().map(|c| (c.part, c.extension)).collect();
+        assert_eq!(
+            ranked,
+            vec![
+                (Part::Attachment(3), ".pdf"),
+                (Part::Attachment(2), ".jpg"),
+                (Part::Attachment(0), ".pdf"),
+                (Part::Body, ".html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_combines_parts_that_read_like_billing() {
+        let email = email(
+            "Thanks for your order. Total charged: $15.99, next payment due on 1 February.",
+            vec![attachment("receipt.pdf", "application/pdf", 2048, false)],
+        );
+
+        let selection = select(&email, converted(&email, &["Receipt #1042, amount paid $15.99", &email.body])).unwrap();
+        assert_eq!(selection.markdown.matches("## ").count(), 2, "{}", selection.markdown);
+        assert!(selection.markdown.starts_with("## receipt.pdf"));
+        assert!(selection.parts.iter().all(|(_, _, used)| *used));
+
+        // A newsletter body isn't read along with the receipt
+        let newsletter = "Our spring collection is here! Discover new colours and styles for every \
+                          occasion, from garden parties to weekend trips, only at our stores.";
+        let selection = select(&email, converted(&email, &["Receipt #1042, amount paid $15.99", newsletter])).unwrap();
+        assert_eq!(selection.markdown, "Receipt #1042, amount paid $15.99");
+        assert_eq!(selection.parts[1], (Part::Body, 0, false));
+    }
+
+    #[test]
+    fn test_falls_back_when_a_part_fails() {
+        let email = email("Invoice total: 12.00 EUR", vec![attachment("invoice.pdf", "application/pdf", 10, false)]);
+        let candidates = candidates(&email);
+        let failing = vec![
+            Converted { candidate: candidates[0].clone(), markdown: Err(AppError::Validation("PDF has no text layer".to_string())) },
+            Converted { candidate: candidates[1].clone(), markdown: Ok("Invoice total: 12.00 EUR".to_string()) },
+        ];
+
+        let selection = select(&email, failing).unwrap();
+        assert_eq!(selection.markdown, "Invoice total: 12.00 EUR");
+        assert_eq!(selection.parts[0], (Part::Attachment(0), 80, false));
+
+        let nothing = vec![Converted {
+            candidate: candidates[0].clone(),
+            markdown: Err(AppError::Validation("PDF has no text layer".to_string())),
+        }];
+        assert!(matches!(select(&email, nothing), Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,196 @@
+// Document conversion
+// Turns PDFs, HTML and plain text into markdown in process, with MarkItDown as an optional fallback
+
+use crate::services::markitdown::MarkItDownService;
+use crate::utils::{AppError, AppResult};
+use std::sync::OnceLock;
+
+/// Converts in process. Formats it can't read, and PDFs without a text
+/// layer, go to MarkItDown when it happens to be installed.
+#[derive(Clone)]
+pub struct NativeConverter {
+    markitdown: bool,
+}
+
+impl NativeConverter {
+    /// Looks for MarkItDown once per run of the app; it is never installed
+    pub fn detect() -> Self {
+        static MARKITDOWN: OnceLock<bool> = OnceLock::new();
+        Self { markitdown: *MARKITDOWN.get_or_init(MarkItDownService::is_installed) }
+    }
+
+    pub fn convert(&self, data: &[u8], extension: &str) -> AppResult<String> {
+        match Self::convert_natively(data, extension) {
+            Ok(markdown) => Ok(markdown),
+            Err(e) if self.markitdown => {
+                eprintln!("Falling back to MarkItDown for a {} file: {}", extension, e);
+                MarkItDownService::convert_data_to_markdown(data, extension)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn convert_natively(data: &[u8], extension: &str) -> AppResult<String> {
+        match extension.to_ascii_lowercase().as_str() {
+            ".pdf" => pdf_text(data),
+            ".html" | ".htm" => {
+                let text = String::from_utf8_lossy(data);
+                // Email bodies come here too, and most are plain text
+                if looks_like_html(&text) {
+                    Ok(html2md::parse_html(&text))
+                } else {
+                    Ok(text.into_owned())
+                }
+            }
+            ".txt" | ".md" | ".csv" => Ok(String::from_utf8_lossy(data).into_owned()),
+            _ => Err(AppError::Validation(format!(
+                "{} files can't be converted without MarkItDown",
+                extension
+            ))),
+        }
+    }
+}
+
+/// The PDF's text layer. pdf-extract panics on some malformed files, which
+/// is reported as an error like any other.
+fn pdf_text(data: &[u8]) -> AppResult<String> {
+    let text = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(data))
+        .map_err(|_| AppError::Internal("Failed to read PDF".to_string()))?
+        .map_err(|e| AppError::Internal(format!("Failed to read PDF: {}", e)))?;
+
+    if text.trim().is_empty() {
+        return Err(AppError::Validation("PDF has no text layer".to_string()));
+    }
+    Ok(text)
+}
+
+/// What OCR can read when conversion finds no text: an image attachment
+/// itself, or the JPEG scans in a PDF. Other image encodings in a PDF are raw
+/// pixels that vision models don't take, so they are left out.
+pub fn scanned_images(data: &[u8], extension: &str) -> Vec<Vec<u8>> {
+    match extension.to_ascii_lowercase().as_str() {
+        ".jpg" | ".jpeg" | ".png" => vec![data.to_vec()],
+        ".pdf" => std::panic::catch_unwind(|| pdf_jpegs(data)).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn pdf_jpegs(data: &[u8]) -> Vec<Vec<u8>> {
+    let Ok(document) = lopdf::Document::load_mem(data) else {
+        return Vec::new();
+    };
+
+    document
+        .get_pages()
+        .into_values()
+        .flat_map(|page| document.get_page_images(page).unwrap_or_default())
+        .filter(|image| image.filters.as_deref() == Some(&["DCTDecode".to_string()]))
+        .map(|image| image.content.to_vec())
+        .collect()
+}
+
+pub fn looks_like_html(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    ["<html", "<body", "<div", "<table", "<p>", "<p ", "<br", "<span"]
+        .iter()
+        .any(|tag| lower.contains(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one-page PDF showing `text` in Helvetica
+    fn pdf_with_text(text: &str) -> Vec<u8> {
+        let content = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+        pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>"
+                .to_string(),
+            format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ])
+    }
+
+    /// A one-page PDF that is nothing but a JPEG, as scanners make them
+    fn scanned_pdf(jpeg: &str) -> Vec<u8> {
+        let content = "q 612 0 0 792 0 0 cm /Im1 Do Q";
+        pdf(&[
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /Resources << /XObject << /Im1 5 0 R >> >> >>"
+                .to_string(),
+            format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+            format!(
+                "<< /Type /XObject /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n{}\nendstream",
+                jpeg.len(),
+                jpeg
+            ),
+        ])
+    }
+
+    fn pdf(objects: &[String]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes(),
+        );
+        pdf
+    }
+
+    #[test]
+    fn test_converts_pdf_text() {
+        let converter = NativeConverter { markitdown: false };
+        let markdown = converter.convert(&pdf_with_text("Netflix Total 15.99 USD"), ".pdf").unwrap();
+        assert!(markdown.contains("Netflix Total 15.99 USD"), "{:?}", markdown);
+
+        assert!(converter.convert(b"not a pdf", ".pdf").is_err());
+    }
+
+    #[test]
+    fn test_finds_scanned_images() {
+        let converter = NativeConverter { markitdown: false };
+        let scan = scanned_pdf("JPEG-SCAN");
+        assert!(matches!(converter.convert(&scan, ".pdf"), Err(AppError::Validation(_))));
+
+        assert_eq!(scanned_images(&scan, ".pdf"), vec![b"JPEG-SCAN".to_vec()]);
+        assert_eq!(scanned_images(b"PNG", ".png"), vec![b"PNG".to_vec()]);
+        assert!(scanned_images(&pdf_with_text("Netflix"), ".pdf").is_empty());
+        assert!(scanned_images(b"not a pdf", ".pdf").is_empty());
+    }
+
+    #[test]
+    fn test_converts_html_keeping_tables() {
+        let converter = NativeConverter { markitdown: false };
+        let html = "<html><body><h1>Receipt</h1><table>\
+                    <tr><th>Item</th><th>Price</th></tr>\
+                    <tr><td>Premium plan</td><td>$15.99</td></tr>\
+                    </table></body></html>";
+
+        let markdown = converter.convert(html.as_bytes(), ".html").unwrap();
+        assert!(markdown.contains("Receipt"));
+        assert!(markdown.contains('|'), "{}", markdown);
+        assert!(markdown.contains("Premium plan"));
+        assert!(!markdown.contains("<td>"));
+    }
+
+    #[test]
+    fn test_passes_plain_text_through() {
+        let converter = NativeConverter { markitdown: false };
+        let body = "Thanks for your payment of $15.99.\nSee you next month <billing@netflix.com>";
+
+        assert_eq!(converter.convert(body.as_bytes(), ".html").unwrap(), body);
+        assert_eq!(converter.convert(body.as_bytes(), ".txt").unwrap(), body);
+        assert!(matches!(converter.convert(b"JPEG", ".jpg"), Err(AppError::Validation(_))));
+    }
+}
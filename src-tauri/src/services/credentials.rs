@@ -0,0 +1,118 @@
+// Account credentials
+// Passwords and OAuth refresh tokens in the OS keyring, one entry per account
+
+use crate::db::DatabaseType;
+use crate::utils::{AppError, AppResult};
+use keyring::Entry;
+use rusqlite::{Connection, OptionalExtension};
+
+const SERVICE: &str = "subscript";
+
+/// Settings key the v7 migration leaves when it turned the single-account
+/// settings into an account; its value is that account's ID
+const LEGACY_ACCOUNT_KEY: &str = "legacy_credentials_account";
+
+/// Kind of secret stored for an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secret {
+    Password,
+    OAuthRefreshToken,
+}
+
+impl Secret {
+    fn name(&self) -> &'static str {
+        match self {
+            Secret::Password => "password",
+            Secret::OAuthRefreshToken => "oauth_refresh_token",
+        }
+    }
+
+    /// Entry used before there were multiple accounts
+    fn legacy_entry(&self) -> &'static str {
+        match self {
+            Secret::Password => "imap_password",
+            Secret::OAuthRefreshToken => "imap_oauth_refresh_token",
+        }
+    }
+}
+
+/// Account IDs restart in each database, so test accounts get their own entries
+fn entry_name(db_type: DatabaseType, account_id: i64, secret: Secret) -> String {
+    match db_type {
+        DatabaseType::Production => format!("account:{}:{}", account_id, secret.name()),
+        DatabaseType::Test => format!("test:account:{}:{}", account_id, secret.name()),
+    }
+}
+
+fn entry(name: &str) -> AppResult<Entry> {
+    Entry::new(SERVICE, name).map_err(|e| AppError::Internal(format!("Keyring error: {}", e)))
+}
+
+fn read(name: &str) -> AppResult<Option<String>> {
+    match entry(name)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(AppError::Internal(format!("Failed to read credentials: {}", e))),
+    }
+}
+
+fn remove(name: &str) -> AppResult<()> {
+    match entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(AppError::Internal(format!("Failed to remove credentials: {}", e))),
+    }
+}
+
+pub fn get(db_type: DatabaseType, account_id: i64, secret: Secret) -> AppResult<Option<String>> {
+    read(&entry_name(db_type, account_id, secret))
+}
+
+/// Move the old single-account entries to the account the v7 migration made
+/// of that configuration, once. They are only dropped if it has been deleted
+/// since, and nothing happens when the migration made no account.
+pub fn adopt_legacy(conn: &Connection) -> AppResult<()> {
+    let account_id: Option<i64> = conn
+        .query_row("SELECT CAST(value AS INTEGER) FROM settings WHERE key = ?1", [LEGACY_ACCOUNT_KEY], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    let Some(account_id) = account_id else {
+        return Ok(());
+    };
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM email_accounts WHERE id = ?1)",
+        [account_id],
+        |row| row.get(0),
+    )?;
+
+    for secret in [Secret::Password, Secret::OAuthRefreshToken] {
+        if let Some(value) = read(secret.legacy_entry())? {
+            let name = entry_name(DatabaseType::Production, account_id, secret);
+            if exists && read(&name)?.is_none() {
+                save(DatabaseType::Production, account_id, secret, &value)?;
+            }
+            remove(secret.legacy_entry())?;
+        }
+    }
+
+    conn.execute("DELETE FROM settings WHERE key = ?1", [LEGACY_ACCOUNT_KEY])?;
+    Ok(())
+}
+
+pub fn save(db_type: DatabaseType, account_id: i64, secret: Secret, value: &str) -> AppResult<()> {
+    entry(&entry_name(db_type, account_id, secret))?
+        .set_password(value)
+        .map_err(|e| AppError::Internal(format!("Failed to save credentials: {}", e)))
+}
+
+pub fn delete(db_type: DatabaseType, account_id: i64, secret: Secret) -> AppResult<()> {
+    remove(&entry_name(db_type, account_id, secret))
+}
+
+/// Remove every secret of a deleted account
+pub fn delete_all(db_type: DatabaseType, account_id: i64) -> AppResult<()> {
+    for secret in [Secret::Password, Secret::OAuthRefreshToken] {
+        delete(db_type, account_id, secret)?;
+    }
+    Ok(())
+}
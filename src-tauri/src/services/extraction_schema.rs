@@ -0,0 +1,318 @@
+// Extraction schema
+// The fields the LLM fills in: one list drives the JSON Schema, the prompt and validation
+
+use crate::models::{DomainExtraction, SubscriptionExtraction};
+use crate::services::llm::LlmExtractionResult;
+use chrono::NaiveDate;
+use serde_json::{json, Map, Value};
+
+pub const CLASSIFICATIONS: &[&str] = &["subscription", "domain", "junk"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Text,
+    /// Zero or more
+    Number,
+    Boolean,
+    /// YYYY-MM-DD
+    Date,
+    /// 3-letter ISO code
+    Currency,
+    OneOf(&'static [&'static str]),
+}
+
+pub struct Field {
+    /// As serialized, e.g. "billingCycle"
+    pub name: &'static str,
+    pub kind: Kind,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// `SubscriptionExtraction`, field for field
+pub const SUBSCRIPTION_FIELDS: &[Field] = &[
+    Field { name: "name", kind: Kind::Text, required: true, description: "vendor or service name" },
+    Field { name: "cost", kind: Kind::Number, required: true, description: "amount charged per billing cycle" },
+    Field { name: "currency", kind: Kind::Currency, required: true, description: "currency of the cost" },
+    Field {
+        name: "billingCycle",
+        kind: Kind::OneOf(&["monthly", "yearly", "one-time"]),
+        required: true,
+        description: "how often it is charged",
+    },
+    Field { name: "nextBillingDate", kind: Kind::Date, required: false, description: "date of the next charge" },
+    Field { name: "category", kind: Kind::Text, required: false, description: "e.g. Streaming, Software, Cloud" },
+];
+
+/// `DomainExtraction`, field for field
+pub const DOMAIN_FIELDS: &[Field] = &[
+    Field { name: "domainName", kind: Kind::Text, required: true, description: "the domain, e.g. example.com" },
+    Field { name: "registrar", kind: Kind::Text, required: false, description: "company the domain is registered with" },
+    Field { name: "cost", kind: Kind::Number, required: false, description: "amount charged" },
+    Field { name: "currency", kind: Kind::Currency, required: false, description: "currency of the cost" },
+    Field { name: "registrationDate", kind: Kind::Date, required: false, description: "date it was registered" },
+    Field { name: "expiryDate", kind: Kind::Date, required: true, description: "date it expires unless renewed" },
+    Field { name: "autoRenew", kind: Kind::Boolean, required: false, description: "whether it renews automatically" },
+];
+
+fn fields(classification: &str) -> &'static [Field] {
+    match classification {
+        "subscription" => SUBSCRIPTION_FIELDS,
+        "domain" => DOMAIN_FIELDS,
+        _ => &[],
+    }
+}
+
+/// JSON Schema for the whole answer, for Ollama's `format` parameter
+pub fn response_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "type": { "type": "string", "enum": CLASSIFICATIONS },
+            "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+            "data": {
+                "anyOf": [
+                    object_schema(SUBSCRIPTION_FIELDS),
+                    object_schema(DOMAIN_FIELDS),
+                    object_schema(&[]),
+                ]
+            }
+        },
+        "required": ["type", "confidence", "data"]
+    })
+}
+
+fn object_schema(fields: &[Field]) -> Value {
+    let properties: Map<String, Value> = fields
+        .iter()
+        .map(|field| {
+            let mut schema = match field.kind {
+                Kind::Text => json!({ "type": "string" }),
+                Kind::Number => json!({ "type": "number", "minimum": 0 }),
+                Kind::Boolean => json!({ "type": "boolean" }),
+                Kind::Date => json!({ "type": "string", "pattern": "^[0-9]{4}-[0-9]{2}-[0-9]{2}$" }),
+                Kind::Currency => json!({ "type": "string", "pattern": "^[A-Z]{3}$" }),
+                Kind::OneOf(values) => json!({ "type": "string", "enum": values }),
+            };
+            schema["description"] = json!(field.description);
+            (field.name.to_string(), schema)
+        })
+        .collect();
+    let required: Vec<&str> = fields.iter().filter(|field| field.required).map(|field| field.name).collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false
+    })
+}
+
+/// The fields of each classification, as the prompt lists them
+fn describe() -> String {
+    let mut text = String::new();
+    for classification in CLASSIFICATIONS {
+        let fields = fields(classification);
+        if fields.is_empty() {
+            text.push_str(&format!("For \"{}\", data is {{}}.\n", classification));
+            continue;
+        }
+
+        text.push_str(&format!("For \"{}\", data has:\n", classification));
+        for field in fields {
+            let kind = match field.kind {
+                Kind::Text => "string".to_string(),
+                Kind::Number => "number".to_string(),
+                Kind::Boolean => "boolean".to_string(),
+                Kind::Date => "date, YYYY-MM-DD".to_string(),
+                Kind::Currency => "3-letter ISO currency code, e.g. USD".to_string(),
+                Kind::OneOf(values) => {
+                    let values: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+                    format!("one of {}", values.join(", "))
+                }
+            };
+            let required = if field.required { "required" } else { "optional, leave out if unknown" };
+            text.push_str(&format!("- {} ({}; {}): {}\n", field.name, kind, required, field.description));
+        }
+    }
+    text
+}
+
+pub fn prompt(markdown: &str) -> String {
+    format!(
+        r#"You are a receipt and billing document analyzer. Your task is to classify and extract data from receipts.
+
+CLASSIFICATION OPTIONS:
+- "subscription": Recurring payment (monthly/yearly service)
+- "domain": Domain registration or renewal
+- "junk": Spam, promotional email, or irrelevant content
+
+CONFIDENCE: Return a value from 0.0 to 1.0 indicating how confident you are.
+
+Answer with a JSON object with "type", "confidence" and "data".
+{}
+RECEIPT CONTENT:
+{}
+"#,
+        describe(),
+        markdown
+    )
+}
+
+/// Asks again after an answer failed validation, saying what was wrong
+pub fn repair_prompt(markdown: &str, answer: &str, problem: &str) -> String {
+    format!(
+        "Your previous answer did not follow the required format.\n\n\
+         PROBLEM: {}\n\n\
+         PREVIOUS ANSWER:\n{}\n\n\
+         Correct it and answer again.\n\n{}",
+        problem,
+        answer,
+        prompt(markdown)
+    )
+}
+
+/// Check an answer against the schema, and that its data reads as the
+/// model it will be saved as. The error says what to fix.
+pub fn validate(result: &LlmExtractionResult) -> Result<(), String> {
+    if !CLASSIFICATIONS.contains(&result.classification.as_str()) {
+        return Err(format!("\"type\" must be one of {:?}", CLASSIFICATIONS));
+    }
+    if !(0.0..=1.0).contains(&result.confidence) {
+        return Err("\"confidence\" must be between 0 and 1".to_string());
+    }
+    if result.classification == "junk" {
+        return Ok(());
+    }
+
+    let data = result.data.as_object().ok_or("\"data\" must be an object")?;
+    for field in fields(&result.classification) {
+        match data.get(field.name) {
+            None | Some(Value::Null) if field.required => {
+                return Err(format!("\"{}\" is required", field.name));
+            }
+            None | Some(Value::Null) => {}
+            Some(value) => check(field, value)?,
+        }
+    }
+
+    let parsed = match result.classification.as_str() {
+        "subscription" => serde_json::from_value::<SubscriptionExtraction>(result.data.clone()).map(|_| ()),
+        _ => serde_json::from_value::<DomainExtraction>(result.data.clone()).map(|_| ()),
+    };
+    parsed.map_err(|e| format!("\"data\" is not a valid {}: {}", result.classification, e))
+}
+
+fn check(field: &Field, value: &Value) -> Result<(), String> {
+    let valid = match field.kind {
+        Kind::Text => value.as_str().is_some_and(|s| !s.trim().is_empty()),
+        Kind::Number => value.as_f64().is_some_and(|n| n >= 0.0),
+        Kind::Boolean => value.is_boolean(),
+        Kind::Date => value.as_str().is_some_and(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()),
+        Kind::Currency => value.as_str().is_some_and(|s| s.len() == 3 && s.chars().all(|c| c.is_ascii_uppercase())),
+        Kind::OneOf(values) => value.as_str().is_some_and(|s| values.contains(&s)),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("\"{}\" is {}, which is not a valid {:?}", field.name, value, field.kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn names(fields: &[Field]) -> BTreeSet<&str> {
+        fields.iter().map(|field| field.name).collect()
+    }
+
+    fn keys(value: Value) -> BTreeSet<String> {
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    fn answer(classification: &str, data: Value) -> LlmExtractionResult {
+        LlmExtractionResult { classification: classification.to_string(), confidence: 0.9, data }
+    }
+
+    #[test]
+    fn test_fields_match_the_models() {
+        let subscription = SubscriptionExtraction {
+            name: "Netflix".to_string(),
+            cost: 15.99,
+            currency: "USD".to_string(),
+            periodicity: "monthly".to_string(),
+            next_date: Some("2024-02-01".to_string()),
+            category: Some("Streaming".to_string()),
+        };
+        let subscription = keys(serde_json::to_value(subscription).unwrap());
+        assert_eq!(subscription.iter().map(String::as_str).collect::<BTreeSet<_>>(), names(SUBSCRIPTION_FIELDS));
+
+        let domain = DomainExtraction {
+            name: "example.com".to_string(),
+            registrar: None,
+            cost: None,
+            currency: None,
+            registration_date: None,
+            expiry_date: "2025-01-01".to_string(),
+            auto_renew: None,
+        };
+        let domain = keys(serde_json::to_value(domain).unwrap());
+        assert_eq!(domain.iter().map(String::as_str).collect::<BTreeSet<_>>(), names(DOMAIN_FIELDS));
+
+        // Required fields are exactly the ones the models can't do without
+        for (classification, fields) in [("subscription", SUBSCRIPTION_FIELDS), ("domain", DOMAIN_FIELDS)] {
+            let sample: Map<String, Value> = fields
+                .iter()
+                .filter(|field| field.required)
+                .map(|field| {
+                    let value = match field.kind {
+                        Kind::Number => json!(1.0),
+                        Kind::Date => json!("2024-01-01"),
+                        Kind::Currency => json!("USD"),
+                        Kind::OneOf(values) => json!(values[0]),
+                        _ => json!("x"),
+                    };
+                    (field.name.to_string(), value)
+                })
+                .collect();
+            assert_eq!(validate(&answer(classification, Value::Object(sample))), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_schema_and_prompt_share_the_fields() {
+        let schema = response_schema();
+        let data = &schema["properties"]["data"]["anyOf"];
+        assert_eq!(data[0]["required"], json!(["name", "cost", "currency", "billingCycle"]));
+        assert_eq!(data[1]["properties"]["expiryDate"]["pattern"], json!("^[0-9]{4}-[0-9]{2}-[0-9]{2}$"));
+
+        let prompt = prompt("Netflix 15.99 USD");
+        for field in SUBSCRIPTION_FIELDS.iter().chain(DOMAIN_FIELDS) {
+            assert!(prompt.contains(&format!("- {} (", field.name)), "{} missing from prompt", field.name);
+        }
+        assert!(prompt.ends_with("Netflix 15.99 USD\n"));
+    }
+
+    #[test]
+    fn test_validate_explains_what_is_wrong() {
+        let valid = json!({ "name": "Netflix", "cost": 15.99, "currency": "USD", "billingCycle": "monthly" });
+        assert_eq!(validate(&answer("subscription", valid.clone())), Ok(()));
+        assert_eq!(validate(&answer("junk", json!({}))), Ok(()));
+
+        let mut weekly = valid.clone();
+        weekly["billingCycle"] = json!("weekly");
+        assert!(validate(&answer("subscription", weekly)).unwrap_err().contains("billingCycle"));
+
+        let mut no_cost = valid.clone();
+        no_cost["cost"] = Value::Null;
+        assert_eq!(validate(&answer("subscription", no_cost)), Err("\"cost\" is required".to_string()));
+
+        let domain = json!({ "domainName": "example.com", "expiryDate": "01/02/2025" });
+        assert!(validate(&answer("domain", domain)).unwrap_err().contains("expiryDate"));
+
+        assert!(validate(&answer("invoice", valid.clone())).is_err());
+        assert!(validate(&LlmExtractionResult { confidence: 1.5, ..answer("subscription", valid) }).is_err());
+    }
+}
@@ -1,68 +1,256 @@
-use async_imap::Client;
-use tokio::net::TcpStream;
-use native_tls::TlsConnector as NativeTlsConnector;
-use tokio_native_tls::TlsConnector as TokioTlsConnector;
-use crate::utils::{AppResult, AppError};
-use crate::models::{EmailContent, Attachment};
-use futures::StreamExt;
+use async_imap::extensions::idle::IdleResponse;
+use async_imap::imap_proto::{MailboxDatum, Response};
+use async_imap::types::UnsolicitedResponse;
+use async_imap::Session;
+use chrono::{Duration, NaiveDate, Utc};
+use std::collections::HashSet;
+use crate::services::imap_connection::{ImapConnector, ImapStream};
+use crate::services::pipeline::{FetchedEmail, NewMail};
+use crate::services::oauth::XOAuth2;
+use crate::utils::{sha256_hex, AppResult, AppError};
+use crate::models::{EmailContent, Attachment, MailboxCursor, MessageAction, SearchCriteria};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
 use mailparse::MailHeaderMap;
 
+/// How far back the first sync of a mailbox (or one after UIDVALIDITY changed) looks
+const INITIAL_LOOKBACK_DAYS: i64 = 90;
+
+/// Upper bound on messages downloaded per run; the rest follow on the next run
+const MAX_MESSAGES_PER_RUN: usize = 100;
+
+/// Servers may end IDLE after 30 minutes (RFC 2177), so it is re-issued sooner
+const IDLE_RENEWAL: std::time::Duration = std::time::Duration::from_secs(25 * 60);
+
+/// What `ImapService::watch` reports while it holds IDLE on a mailbox
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdleEvent {
+    /// IDLE is established; new mail from here on is reported
+    Watching,
+    /// The mailbox gained messages
+    NewMail,
+}
+
+/// How watching a mailbox ended, short of an error
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdleOutcome {
+    /// The server does not support IDLE; polling has to do
+    Unsupported,
+}
+
+/// Which messages to ask the server for
+#[derive(Debug, PartialEq)]
+enum SearchPlan {
+    /// No usable cursor: take recent mail only
+    Since(NaiveDate),
+    /// Everything after the last processed UID
+    AfterUid(u32),
+}
+
+impl SearchPlan {
+    /// `since` replaces the default lookback when the account sets one
+    fn new(cursor: Option<&MailboxCursor>, uid_validity: u32, today: NaiveDate, since: Option<NaiveDate>) -> Self {
+        match cursor {
+            // UIDs from another UIDVALIDITY epoch say nothing about the current mailbox
+            Some(cursor) if cursor.uid_validity == uid_validity => SearchPlan::AfterUid(cursor.last_uid),
+            _ => SearchPlan::Since(since.unwrap_or(today - Duration::days(INITIAL_LOOKBACK_DAYS))),
+        }
+    }
+
+    /// The UID SEARCH arguments, narrowed by the account's criteria so the
+    /// server does the filtering
+    fn query(&self, criteria: &SearchCriteria) -> String {
+        let mut keys = vec![match self {
+            SearchPlan::Since(date) => format!("SINCE {}", imap_date(*date)),
+            SearchPlan::AfterUid(uid) => format!("UID {}:*", uid.saturating_add(1)),
+        }];
+
+        if let (SearchPlan::AfterUid(_), Some(since)) = (self, criteria.since) {
+            keys.push(format!("SINCE {}", imap_date(since)));
+        }
+
+        // `OR a OR b c`: any one sender is enough
+        let senders: Vec<String> = criteria
+            .from
+            .iter()
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| format!("FROM {}", quote(pattern)))
+            .collect();
+        if let Some((last, rest)) = senders.split_last() {
+            keys.push(rest.iter().rev().fold(last.clone(), |acc, key| format!("OR {} {}", key, acc)));
+        }
+
+        if let Some(subject) = criteria.subject.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+            keys.push(format!("SUBJECT {}", quote(subject)));
+        }
+
+        let query = keys.join(" ");
+        // Quoted strings are 7-bit; servers take UTF-8 ones once the charset is named
+        if query.is_ascii() {
+            query
+        } else {
+            format!("CHARSET UTF-8 {}", query)
+        }
+    }
+
+    /// Pick the UIDs to fetch this run, ascending, and the UID the cursor can
+    /// move to once they are all processed
+    fn select_uids(&self, found: HashSet<u32>, uid_next: Option<u32>) -> (Vec<u32>, u32) {
+        let floor = match self {
+            SearchPlan::Since(_) => 0,
+            SearchPlan::AfterUid(uid) => *uid,
+        };
+
+        // `n:*` always matches the highest UID, even when it is below n
+        let mut uids: Vec<u32> = found.into_iter().filter(|uid| *uid > floor).collect();
+        uids.sort_unstable();
+
+        if uids.len() > MAX_MESSAGES_PER_RUN {
+            uids.truncate(MAX_MESSAGES_PER_RUN);
+            let last_uid = uids[uids.len() - 1];
+            return (uids, last_uid);
+        }
+
+        // Everything below UIDNEXT has now been considered
+        let last_uid = uid_next
+            .map(|next| next.saturating_sub(1))
+            .into_iter()
+            .chain(uids.last().copied())
+            .fold(floor, u32::max);
+
+        (uids, last_uid)
+    }
+}
+
+fn imap_date(date: NaiveDate) -> String {
+    date.format("%d-%b-%Y").to_string()
+}
+
+/// An IMAP quoted string
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// STORE arguments adding the action's flags, if it sets any
+fn store_flags(action: &MessageAction) -> Option<String> {
+    let keyword = action.keyword.as_deref().map(str::trim).filter(|k| !k.is_empty());
+    let flags: Vec<&str> = action.mark_seen.then_some("\\Seen").into_iter().chain(keyword).collect();
+
+    (!flags.is_empty()).then(|| format!("+FLAGS.SILENT ({})", flags.join(" ")))
+}
+
+fn folder(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|f| !f.is_empty())
+}
+
+/// Secret used to sign in as the configured user
+pub enum ImapCredentials {
+    Password(String),
+    /// A current OAuth2 access token, sent with SASL XOAUTH2
+    OAuth2(String),
+}
+
 pub struct ImapService {
-    server: String,
-    port: u16,
+    connector: ImapConnector,
     username: String,
-    password: String,
-    #[allow(dead_code)]
-    use_ssl: bool,
+    credentials: ImapCredentials,
+    criteria: SearchCriteria,
 }
 
 impl ImapService {
-    pub fn new(
-        server: String,
-        port: u16,
-        username: String,
-        password: String,
-        use_ssl: bool,
-    ) -> Self {
+    pub fn new(connector: ImapConnector, username: String, credentials: ImapCredentials) -> Self {
         Self {
-            server,
-            port,
+            connector,
             username,
-            password,
-            use_ssl,
+            credentials,
+            criteria: SearchCriteria::default(),
+        }
+    }
+
+    /// Only fetch messages matching `criteria`
+    pub fn with_criteria(mut self, criteria: SearchCriteria) -> Self {
+        self.criteria = criteria;
+        self
+    }
+
+    /// Connect and log in
+    async fn connect(&self) -> AppResult<Session<ImapStream>> {
+        let client = self.connector.connect().await?;
+
+        let session = match &self.credentials {
+            ImapCredentials::Password(password) => client.login(&self.username, password).await,
+            ImapCredentials::OAuth2(access_token) => {
+                let auth = XOAuth2::new(self.username.clone(), access_token.clone());
+                client.authenticate("XOAUTH2", auth).await
+            }
         }
+        .map_err(|e| AppError::Internal(format!("Failed to login to IMAP server: {}", e.0)))?;
+
+        Ok(session)
     }
 
     pub async fn test_connection(&self) -> AppResult<()> {
-        // 1. Create the TCP connection
-        let tcp_stream = TcpStream::connect((self.server.as_str(), self.port))
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to connect to IMAP server: {}", e)))?;
-
-        // 2. Set up TLS
-        let native_tls_connector = NativeTlsConnector::builder()
-            .build()
-            .map_err(|e| AppError::Internal(format!("Failed to build TLS connector: {}", e)))?;
-        let tokio_tls_connector = TokioTlsConnector::from(native_tls_connector);
-        
-        let tls_stream = tokio_tls_connector
-            .connect(&self.server, tcp_stream)
-            .await
-            .map_err(|e| AppError::Internal(format!("TLS connection error: {}", e)))?;
+        let mut session = self.connect().await?;
 
-        // 3. Create the IMAP client
-        let mut client = Client::new(tls_stream);
+        session.logout().await.map_err(|e| {
+            AppError::Internal(format!("Failed to logout from IMAP server: {}", e))
+        })?;
+
+        Ok(())
+    }
 
-        // 4. Read the server greeting (REQUIRED for Client::new)
-        client.read_response().await.map_err(|e| {
-            AppError::Internal(format!("Failed to read server greeting: {}", e))
+    /// Find messages that arrived in `mailbox` after `cursor`, regardless of
+    /// their read state. The session stays open for `download`.
+    pub async fn find_new_emails(&self, mailbox: &str, cursor: Option<&MailboxCursor>) -> AppResult<NewMail<Session<ImapStream>>> {
+        let mut session = self.connect().await?;
+
+        let selected = session.select(mailbox).await.map_err(|e| {
+            AppError::Internal(format!("Failed to select {}: {}", mailbox, e))
+        })?;
+        let uid_validity = selected.uid_validity.ok_or_else(|| {
+            AppError::Internal(format!("Server did not report UIDVALIDITY for {}", mailbox))
+        })?;
+
+        let plan = SearchPlan::new(cursor, uid_validity, Utc::now().date_naive(), self.criteria.since);
+        let found = session.uid_search(plan.query(&self.criteria)).await.map_err(|e| {
+            AppError::Internal(format!("Failed to search {}: {}", mailbox, e))
         })?;
+        let (uids, last_uid) = plan.select_uids(found, selected.uid_next);
+
+        Ok(NewMail {
+            session,
+            mailbox: mailbox.to_string(),
+            uid_validity,
+            last_uid,
+            uids,
+        })
+    }
 
-        // 5. Login
-        let mut session = client
-            .login(&self.username, &self.password)
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to login to IMAP server: {}", e.0)))?;
+    /// Download the messages one at a time, oldest first, into `tx`. Messages
+    /// are fetched with `BODY.PEEK[]` so their \Seen flag is left alone. A full
+    /// channel pauses the download; a closed one ends it.
+    pub async fn download(&self, new_mail: NewMail<Session<ImapStream>>, mut tx: mpsc::Sender<FetchedEmail>) -> AppResult<()> {
+        let NewMail { mut session, mailbox, uids, .. } = new_mail;
+
+        for uid in uids {
+            if tx.is_closed() {
+                break;
+            }
+
+            // An unparseable or vanished message is skipped rather than blocking the cursor forever
+            let email = match self.fetch_one(&mut session, uid).await? {
+                Some(Ok(email)) => email,
+                Some(Err(e)) => {
+                    eprintln!("Skipping message {} in {}: {}", uid, mailbox, e);
+                    continue;
+                }
+                None => continue,
+            };
+            if tx.send(FetchedEmail { uid, email }).await.is_err() {
+                break;
+            }
+        }
 
         session.logout().await.map_err(|e| {
             AppError::Internal(format!("Failed to logout from IMAP server: {}", e))
@@ -71,59 +259,92 @@ impl ImapService {
         Ok(())
     }
 
-    pub async fn fetch_unread_emails(&self) -> AppResult<Vec<EmailContent>> {
-        // 1. Create the TCP connection
-        let tcp_stream = TcpStream::connect((self.server.as_str(), self.port))
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to connect to IMAP server: {}", e)))?;
+    /// Fetch and parse one message of the selected mailbox; None when it is gone
+    async fn fetch_one(&self, session: &mut Session<ImapStream>, uid: u32) -> AppResult<Option<AppResult<EmailContent>>> {
+        let mut fetch = session.uid_fetch(uid.to_string(), "BODY.PEEK[]").await.map_err(|e| {
+            AppError::Internal(format!("Failed to fetch email: {}", e))
+        })?;
 
-        // 2. Set up TLS
-        let native_tls_connector = NativeTlsConnector::builder()
-            .build()
-            .map_err(|e| AppError::Internal(format!("Failed to build TLS connector: {}", e)))?;
-        let tokio_tls_connector = TokioTlsConnector::from(native_tls_connector);
-        
-        let tls_stream = tokio_tls_connector
-            .connect(&self.server, tcp_stream)
-            .await
-            .map_err(|e| AppError::Internal(format!("TLS connection error: {}", e)))?;
+        let mut email = None;
+        while let Some(msg_res) = fetch.next().await {
+            let msg = msg_res.map_err(|e| AppError::Internal(format!("Fetch error: {}", e)))?;
+            if let (Some(found), Some(body)) = (msg.uid, msg.body()) {
+                if found == uid {
+                    email = Some(parse_email(body));
+                }
+            }
+        }
 
-        // 3. Create the IMAP client
-        let mut client = Client::new(tls_stream);
+        Ok(email)
+    }
+
+    /// Fetch a single message again, e.g. to retry it. Fails when the
+    /// mailbox's UIDVALIDITY changed, as `uid` then names another message.
+    pub async fn fetch_message(&self, mailbox: &str, uid_validity: u32, uid: u32) -> AppResult<EmailContent> {
+        let mut session = self.connect().await?;
 
-        // 4. Read the server greeting
-        client.read_response().await.map_err(|e| {
-            AppError::Internal(format!("Failed to read server greeting: {}", e))
+        let selected = session.select(mailbox).await.map_err(|e| {
+            AppError::Internal(format!("Failed to select {}: {}", mailbox, e))
         })?;
+        if selected.uid_validity != Some(uid_validity) {
+            return Err(AppError::NotFound(format!(
+                "{} was renumbered on the server; message {} can no longer be found",
+                mailbox, uid
+            )));
+        }
 
-        // 5. Login
-        let mut session = client
-            .login(&self.username, &self.password)
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to login to IMAP server: {}", e.0)))?;
+        let email = self.fetch_one(&mut session, uid).await?.transpose()?;
 
-        // 6. Select INBOX
-        session.select("INBOX").await.map_err(|e| {
-            AppError::Internal(format!("Failed to select INBOX: {}", e))
+        session.logout().await.map_err(|e| {
+            AppError::Internal(format!("Failed to logout from IMAP server: {}", e))
         })?;
 
-        // 7. Search for unread emails
-        let uids = session.uid_search("UNSEEN").await.map_err(|e| {
-            AppError::Internal(format!("Failed to search for unread emails: {}", e))
+        email.ok_or_else(|| AppError::NotFound(format!("Message {} is no longer in {}", uid, mailbox)))
+    }
+
+    /// Apply `action` to `uids` in `mailbox`. Without the MOVE extension a
+    /// move is a copy plus \Deleted, expunged right away only with UIDPLUS.
+    pub async fn apply_action(&self, mailbox: &str, uids: &[u32], action: &MessageAction) -> AppResult<()> {
+        if uids.is_empty() || action.is_empty() {
+            return Ok(());
+        }
+
+        let mut session = self.connect().await?;
+        session.select(mailbox).await.map_err(|e| {
+            AppError::Internal(format!("Failed to select {}: {}", mailbox, e))
         })?;
 
-        let mut emails = Vec::new();
+        let uid_set = uids.iter().map(|uid| uid.to_string()).collect::<Vec<_>>().join(",");
+        let store_error = |e: async_imap::error::Error| AppError::Internal(format!("Failed to flag messages: {}", e));
 
-        for uid in uids {
-            let mut fetch = session.uid_fetch(uid.to_string(), "RFC822").await.map_err(|e| {
-                AppError::Internal(format!("Failed to fetch email {}: {}", uid, e))
+        if let Some(flags) = store_flags(action) {
+            let updates = session.uid_store(&uid_set, &flags).await.map_err(store_error)?;
+            updates.collect::<Vec<_>>().await;
+        }
+
+        if let Some(target) = folder(&action.copy_to) {
+            session.uid_copy(&uid_set, target).await.map_err(|e| {
+                AppError::Internal(format!("Failed to copy messages to {}: {}", target, e))
             })?;
+        }
 
-            while let Some(msg_res) = fetch.next().await {
-                let msg = msg_res.map_err(|e| AppError::Internal(format!("Fetch error: {}", e)))?;
-                if let Some(body) = msg.body() {
-                    let email_content = self.parse_email(body)?;
-                    emails.push(email_content);
+        if let Some(target) = folder(&action.move_to) {
+            let capabilities = session.capabilities().await.map_err(|e| {
+                AppError::Internal(format!("Failed to read server capabilities: {}", e))
+            })?;
+            let move_error = |e: async_imap::error::Error| {
+                AppError::Internal(format!("Failed to move messages to {}: {}", target, e))
+            };
+
+            if capabilities.has_str("MOVE") {
+                session.uid_mv(&uid_set, target).await.map_err(move_error)?;
+            } else {
+                session.uid_copy(&uid_set, target).await.map_err(move_error)?;
+                let updates = session.uid_store(&uid_set, "+FLAGS.SILENT (\\Deleted)").await.map_err(store_error)?;
+                updates.collect::<Vec<_>>().await;
+                if capabilities.has_str("UIDPLUS") {
+                    let expunged = session.uid_expunge(&uid_set).await.map_err(move_error)?;
+                    expunged.collect::<Vec<_>>().await;
                 }
             }
         }
@@ -132,89 +353,312 @@ impl ImapService {
             AppError::Internal(format!("Failed to logout from IMAP server: {}", e))
         })?;
 
-        Ok(emails)
+        Ok(())
     }
 
-    fn parse_email(&self, raw_body: &[u8]) -> AppResult<EmailContent> {
-        let parsed = mailparse::parse_mail(raw_body).map_err(|e| {
-            AppError::Internal(format!("Failed to parse email: {}", e))
+    /// Hold IDLE on `mailbox` until the connection fails, calling `on_event`
+    /// once it is established and whenever the mailbox gains messages. Only a
+    /// growing EXISTS count is new mail; flag changes and expunges elsewhere
+    /// don't wake sync.
+    pub async fn watch(&self, mailbox: &str, mut on_event: impl FnMut(IdleEvent)) -> AppResult<IdleOutcome> {
+        let mut session = self.connect().await?;
+
+        let capabilities = session.capabilities().await.map_err(|e| {
+            AppError::Internal(format!("Failed to read server capabilities: {}", e))
         })?;
+        if !capabilities.has_str("IDLE") {
+            session.logout().await.map_err(|e| {
+                AppError::Internal(format!("Failed to logout from IMAP server: {}", e))
+            })?;
+            return Ok(IdleOutcome::Unsupported);
+        }
 
-        let mut subject = String::new();
-        let mut from = String::new();
-        let mut date = String::new();
+        let selected = session.select(mailbox).await.map_err(|e| {
+            AppError::Internal(format!("Failed to select {}: {}", mailbox, e))
+        })?;
+        let mut exists = selected.exists;
+
+        let idle_error = |e: async_imap::error::Error| AppError::Internal(format!("IDLE failed on {}: {}", mailbox, e));
+        let mut watching = false;
+        loop {
+            // Responses that arrived outside IDLE, e.g. while ending the last one
+            let mut grew = false;
+            while let Ok(update) = session.unsolicited_responses.try_recv() {
+                grew |= count_grew(&mut exists, &update);
+            }
+            if grew {
+                on_event(IdleEvent::NewMail);
+            }
 
-        for header in &parsed.headers {
-            match header.get_key().to_lowercase().as_str() {
-                "subject" => subject = header.get_value(),
-                "from" => from = header.get_value(),
-                "date" => date = header.get_value(),
-                _ => {}
+            let mut idle = session.idle();
+            idle.init().await.map_err(idle_error)?;
+            if !watching {
+                watching = true;
+                on_event(IdleEvent::Watching);
+            }
+            let response = {
+                // Dropping the stop source would interrupt the wait
+                let (wait, _stop) = idle.wait_with_timeout(IDLE_RENEWAL);
+                wait.await
+            }
+            .map_err(idle_error)?;
+            session = idle.done().await.map_err(idle_error)?;
+
+            if let IdleResponse::NewData(data) = response {
+                let update = match data.parsed() {
+                    Response::MailboxData(MailboxDatum::Exists(count)) => UnsolicitedResponse::Exists(*count),
+                    Response::Expunge(seq) => UnsolicitedResponse::Expunge(*seq),
+                    _ => continue,
+                };
+                if count_grew(&mut exists, &update) {
+                    on_event(IdleEvent::NewMail);
+                }
             }
         }
+    }
+}
 
-        let mut body = String::new();
-        let mut attachments = Vec::new();
-
-        self.extract_parts(&parsed, &mut body, &mut attachments)?;
-
-        Ok(EmailContent {
-            subject,
-            from,
-            date,
-            body,
-            attachments,
-        })
+/// Follow a mailbox's message count through EXISTS and EXPUNGE; true when it grew
+fn count_grew(exists: &mut u32, update: &UnsolicitedResponse) -> bool {
+    match update {
+        UnsolicitedResponse::Exists(count) => {
+            let grew = *count > *exists;
+            *exists = *count;
+            grew
+        }
+        UnsolicitedResponse::Expunge(_) => {
+            *exists = exists.saturating_sub(1);
+            false
+        }
+        _ => false,
     }
+}
 
-    fn extract_parts(
-        &self,
-        part: &mailparse::ParsedMail,
-        body: &mut String,
-        attachments: &mut Vec<Attachment>,
-    ) -> AppResult<()> {
-        let content_type = part.get_headers().get_first_value("Content-Type").unwrap_or_default();
-        let disposition = part.get_headers().get_first_value("Content-Disposition").unwrap_or_default();
-
-        if disposition.contains("attachment") {
-            let filename = part
-                .get_content_disposition()
-                .params
-                .get("filename")
-                .cloned()
-                .unwrap_or_else(|| "unnamed_attachment".to_string());
-
-            attachments.push(Attachment {
-                filename,
-                content_type,
-                data: part.get_body_raw().map_err(|e| {
-                    AppError::Internal(format!("Failed to get attachment body: {}", e))
-                })?,
-            });
-        } else if content_type.contains("text/plain") {
-            let text = part.get_body().map_err(|e| {
-                AppError::Internal(format!("Failed to get email body: {}", e))
-            })?;
-            body.push_str(&text);
-        } else if content_type.contains("text/html") && body.is_empty() {
-            // Prefer plain text, but take HTML if no plain text yet
-            let html = part.get_body().map_err(|e| {
-                AppError::Internal(format!("Failed to get email body: {}", e))
-            })?;
-            body.push_str(&html);
+/// Parse a raw RFC 822 message, as fetched from IMAP or read from a file
+pub fn parse_email(raw_body: &[u8]) -> AppResult<EmailContent> {
+    let parsed = mailparse::parse_mail(raw_body).map_err(|e| {
+        AppError::Internal(format!("Failed to parse email: {}", e))
+    })?;
+
+    let mut subject = String::new();
+    let mut from = String::new();
+    let mut date = String::new();
+    let mut message_id = None;
+
+    for header in &parsed.headers {
+        match header.get_key().to_lowercase().as_str() {
+            "subject" => subject = header.get_value(),
+            "from" => from = header.get_value(),
+            "date" => date = header.get_value(),
+            "message-id" => message_id = Some(header.get_value().trim().to_string()),
+            _ => {}
         }
+    }
 
+    // Without a Message-ID the raw bytes are the best identity we have
+    let message_id = message_id
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| format!("sha256:{}", sha256_hex(raw_body)));
+
+    let mut text = Vec::new();
+    let mut html = Vec::new();
+    let mut attachments = Vec::new();
+
+    extract_parts(&parsed, &mut text, &mut html, &mut attachments)?;
+
+    // Plain text converts most faithfully; HTML is all some senders include
+    let body = if text.iter().any(|part| !part.trim().is_empty()) { text } else { html }.join("\n\n");
+
+    Ok(EmailContent {
+        message_id,
+        subject,
+        from,
+        date,
+        body,
+        attachments,
+        raw: raw_body.to_vec(),
+    })
+}
+
+/// Sort the message's parts into plain text, HTML and files. Files include
+/// inline ones, such as an invoice image embedded in the HTML.
+fn extract_parts(
+    part: &mailparse::ParsedMail,
+    text: &mut Vec<String>,
+    html: &mut Vec<String>,
+    attachments: &mut Vec<Attachment>,
+) -> AppResult<()> {
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    let disposition = part.get_content_disposition();
+    let attached = disposition.disposition == mailparse::DispositionType::Attachment;
+
+    if mimetype.starts_with("multipart/") {
         for subpart in &part.subparts {
-            self.extract_parts(subpart, body, attachments)?;
+            extract_parts(subpart, text, html, attachments)?;
         }
-
-        Ok(())
+    } else if !attached && mimetype == "text/plain" {
+        text.push(part.get_body().map_err(|e| {
+            AppError::Internal(format!("Failed to get email body: {}", e))
+        })?);
+    } else if !attached && mimetype == "text/html" {
+        html.push(part.get_body().map_err(|e| {
+            AppError::Internal(format!("Failed to get email body: {}", e))
+        })?);
+    } else {
+        let filename = disposition
+            .params
+            .get("filename")
+            .or_else(|| part.ctype.params.get("name"))
+            .cloned()
+            .unwrap_or_else(|| "unnamed_attachment".to_string());
+
+        attachments.push(Attachment {
+            filename,
+            content_type: part.get_headers().get_first_value("Content-Type").unwrap_or(mimetype),
+            data: part.get_body_raw().map_err(|e| {
+                AppError::Internal(format!("Failed to get attachment body: {}", e))
+            })?,
+            inline: !attached,
+        });
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::{ConnectionSecurity, OAuthProvider};
+    use crate::services::oauth::{decode_xoauth2, fake_token_endpoint, OAuthClient, OAuthConfig};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn cursor(uid_validity: u32, last_uid: u32) -> MailboxCursor {
+        MailboxCursor {
+            account_id: 1,
+            mailbox: "INBOX".to_string(),
+            uid_validity,
+            last_uid,
+        }
+    }
+
+    #[test]
+    fn test_search_plan_follows_cursor() {
+        let today = date("2024-06-30");
+
+        let plan = SearchPlan::new(Some(&cursor(7, 120)), 7, today, None);
+        assert_eq!(plan, SearchPlan::AfterUid(120));
+        assert_eq!(plan.query(&SearchCriteria::default()), "UID 121:*");
+
+        let first_run = SearchPlan::new(None, 7, today, None);
+        assert_eq!(first_run, SearchPlan::Since(date("2024-04-01")));
+        assert_eq!(first_run.query(&SearchCriteria::default()), "SINCE 01-Apr-2024");
+    }
+
+    #[test]
+    fn test_search_query_applies_criteria() {
+        let criteria = SearchCriteria {
+            since: Some(date("2023-01-15")),
+            from: vec!["@stripe.com".to_string(), " ".to_string(), "billing@".to_string(), "paypal".to_string()],
+            subject: Some("Your \"receipt\"".to_string()),
+        };
+
+        // A configured date replaces the default lookback on the first run
+        let first_run = SearchPlan::new(None, 7, date("2024-06-30"), criteria.since);
+        assert_eq!(
+            first_run.query(&criteria),
+            "SINCE 15-Jan-2023 OR FROM \"@stripe.com\" OR FROM \"billing@\" FROM \"paypal\" SUBJECT \"Your \\\"receipt\\\"\""
+        );
+
+        let single_sender = SearchCriteria {
+            from: vec!["@stripe.com".to_string()],
+            ..criteria.clone()
+        };
+        assert_eq!(
+            SearchPlan::AfterUid(120).query(&single_sender),
+            "UID 121:* SINCE 15-Jan-2023 FROM \"@stripe.com\" SUBJECT \"Your \\\"receipt\\\"\""
+        );
+
+        let unicode = SearchCriteria {
+            subject: Some("Quittung für".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            SearchPlan::AfterUid(0).query(&unicode),
+            "CHARSET UTF-8 UID 1:* SUBJECT \"Quittung für\""
+        );
+    }
+
+    #[test]
+    fn test_search_plan_resets_on_uidvalidity_change() {
+        let plan = SearchPlan::new(Some(&cursor(7, 120)), 8, date("2024-06-30"), None);
+        assert!(matches!(plan, SearchPlan::Since(_)));
+
+        // Old UIDs must not be used as a floor in the new epoch
+        let (uids, last_uid) = plan.select_uids(HashSet::from([3, 5]), Some(10));
+        assert_eq!(uids, vec![3, 5]);
+        assert_eq!(last_uid, 9);
+    }
+
+    #[test]
+    fn test_select_uids_skips_already_processed() {
+        let plan = SearchPlan::AfterUid(120);
+
+        // `121:*` with nothing new still returns the newest message
+        let (uids, last_uid) = plan.select_uids(HashSet::from([120]), Some(121));
+        assert!(uids.is_empty());
+        assert_eq!(last_uid, 120);
+
+        let (uids, last_uid) = plan.select_uids(HashSet::from([125, 121, 130]), Some(131));
+        assert_eq!(uids, vec![121, 125, 130]);
+        assert_eq!(last_uid, 130);
+    }
+
+    #[test]
+    fn test_select_uids_caps_batch_size() {
+        let plan = SearchPlan::AfterUid(0);
+        let found: HashSet<u32> = (1..=250).collect();
+
+        let (uids, last_uid) = plan.select_uids(found, Some(251));
+        assert_eq!(uids.len(), MAX_MESSAGES_PER_RUN);
+        assert_eq!(last_uid, MAX_MESSAGES_PER_RUN as u32);
+    }
+
+    #[test]
+    fn test_store_flags() {
+        let mut action = MessageAction::default();
+        assert_eq!(store_flags(&action), None);
+
+        action.move_to = Some("Receipts".to_string());
+        assert_eq!(store_flags(&action), None);
+
+        action.mark_seen = true;
+        assert_eq!(store_flags(&action).as_deref(), Some("+FLAGS.SILENT (\\Seen)"));
+
+        action.keyword = Some(" $SubScriptImported ".to_string());
+        assert_eq!(store_flags(&action).as_deref(), Some("+FLAGS.SILENT (\\Seen $SubScriptImported)"));
+        assert_eq!(
+            action.describe().as_deref(),
+            Some("seen, keyword $SubScriptImported, moved to Receipts")
+        );
+    }
+
+    #[test]
+    fn test_count_grew_only_on_new_messages() {
+        let mut exists = 10;
+        assert!(!count_grew(&mut exists, &UnsolicitedResponse::Exists(10)));
+        assert!(count_grew(&mut exists, &UnsolicitedResponse::Exists(11)));
+        // An expunge followed by the lower count is not new mail
+        assert!(!count_grew(&mut exists, &UnsolicitedResponse::Expunge(3)));
+        assert!(!count_grew(&mut exists, &UnsolicitedResponse::Exists(10)));
+        assert!(!count_grew(&mut exists, &UnsolicitedResponse::Recent(1)));
+        assert!(count_grew(&mut exists, &UnsolicitedResponse::Exists(12)));
+        assert_eq!(exists, 12);
+    }
 
     #[test]
     fn test_parse_simple_email() {
@@ -224,16 +668,8 @@ mod tests {
                           Content-Type: text/plain\r\n\
                           \r\n\
                           This is the email body.";
-        
-        let service = ImapService::new(
-            "localhost".to_string(),
-            993,
-            "user".to_string(),
-            "pass".to_string(),
-            true,
-        );
-        
-        let parsed = service.parse_email(raw_email).unwrap();
+
+        let parsed = parse_email(raw_email).unwrap();
         
         assert_eq!(parsed.from, "sender@example.com");
         assert_eq!(parsed.subject, "Test Subject");
@@ -241,6 +677,24 @@ mod tests {
         assert_eq!(parsed.attachments.len(), 0);
     }
 
+    #[test]
+    fn test_parse_message_id() {
+        let with_id = b"From: sender@example.com\r\n\
+                        Message-ID: <invoice-42@example.com>\r\n\
+                        Subject: Invoice\r\n\
+                        \r\n\
+                        Total: $10";
+        let parsed = parse_email(with_id).unwrap();
+        assert_eq!(parsed.message_id, "<invoice-42@example.com>");
+
+        // Without a Message-ID, identical messages still map to the same key
+        let without_id = b"From: sender@example.com\r\nSubject: Invoice\r\n\r\nTotal: $10";
+        let first = parse_email(without_id).unwrap();
+        let second = parse_email(without_id).unwrap();
+        assert!(first.message_id.starts_with("sha256:"));
+        assert_eq!(first.message_id, second.message_id);
+    }
+
     #[test]
     fn test_parse_multipart_email() {
         let raw_email = b"From: sender@example.com\r\n\
@@ -258,16 +712,8 @@ mod tests {
                           \r\n\
                           PDFDATA\r\n\
                           --boundary--";
-        
-        let service = ImapService::new(
-            "localhost".to_string(),
-            993,
-            "user".to_string(),
-            "pass".to_string(),
-            true,
-        );
-        
-        let parsed = service.parse_email(raw_email).unwrap();
+
+        let parsed = parse_email(raw_email).unwrap();
         
         assert_eq!(parsed.subject, "Multipart Test");
         assert_eq!(parsed.body.trim(), "Body text.");
@@ -276,4 +722,115 @@ mod tests {
         assert_eq!(parsed.attachments[0].content_type, "application/pdf");
         assert!(parsed.attachments[0].data.starts_with(b"PDFDATA"));
     }
+
+    #[test]
+    fn test_parse_inline_parts_and_html_only_email() {
+        let raw_email = b"From: billing@example.com\r\n\
+                          Subject: Your invoice\r\n\
+                          Content-Type: multipart/related; boundary=\"related\"\r\n\
+                          \r\n\
+                          --related\r\n\
+                          Content-Type: text/html\r\n\
+                          \r\n\
+                          <p>Invoice attached below</p>\r\n\
+                          --related\r\n\
+                          Content-Type: image/png; name=\"invoice.png\"\r\n\
+                          Content-Disposition: inline\r\n\
+                          Content-ID: <invoice>\r\n\
+                          \r\n\
+                          PNGDATA\r\n\
+                          --related\r\n\
+                          Content-Type: text/html\r\n\
+                          \r\n\
+                          <p>Total: $10</p>\r\n\
+                          --related--";
+
+        let parsed = parse_email(raw_email).unwrap();
+
+        assert!(parsed.body.contains("Invoice attached below"));
+        assert!(parsed.body.contains("Total: $10"));
+        assert_eq!(parsed.attachments.len(), 1);
+        assert_eq!(parsed.attachments[0].filename, "invoice.png");
+        assert!(parsed.attachments[0].inline);
+    }
+
+    /// Plaintext IMAP server on localhost that accepts XOAUTH2 for one user
+    /// and token. It doesn't support IDLE.
+    async fn fake_imap_server(user: &'static str, token: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"* OK [CAPABILITY IMAP4rev1 AUTH=XOAUTH2] ready\r\n").await.unwrap();
+
+            let mut authenticating: Option<String> = None;
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = if let Some(tag) = authenticating.take() {
+                    match decode_xoauth2(&line) {
+                        Some((u, t)) if u == user && t == token => format!("{} OK Authenticated\r\n", tag),
+                        _ => format!("{} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n", tag),
+                    }
+                } else {
+                    let mut parts = line.splitn(3, ' ');
+                    let tag = parts.next().unwrap_or_default().to_string();
+                    match parts.next().map(|c| c.to_ascii_uppercase()).as_deref() {
+                        Some("AUTHENTICATE") => {
+                            authenticating = Some(tag);
+                            "+ \r\n".to_string()
+                        }
+                        Some("CAPABILITY") => {
+                            format!("* CAPABILITY IMAP4rev1 AUTH=XOAUTH2\r\n{} OK Capability completed\r\n", tag)
+                        }
+                        Some("LOGOUT") => format!("* BYE\r\n{} OK Logged out\r\n", tag),
+                        _ => format!("{} BAD Unsupported\r\n", tag),
+                    }
+                };
+                write.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn test_xoauth2_login_with_refreshed_token() {
+        let (token_url, _) =
+            fake_token_endpoint(vec![(200, serde_json::json!({ "access_token": "fresh-token" }))]).await;
+        let mut config = OAuthConfig::for_provider(OAuthProvider::Google, "client".to_string(), None);
+        config.token_url = token_url;
+        let tokens = OAuthClient::new(config).refresh("refresh-token").await.unwrap();
+
+        let port = fake_imap_server("me@example.com", "fresh-token").await;
+        let service = ImapService::new(
+            ImapConnector::new("127.0.0.1", port, ConnectionSecurity::Plain),
+            "me@example.com".to_string(),
+            ImapCredentials::OAuth2(tokens.access_token),
+        );
+        service.test_connection().await.unwrap();
+
+        let port = fake_imap_server("me@example.com", "fresh-token").await;
+        let stale = ImapService::new(
+            ImapConnector::new("127.0.0.1", port, ConnectionSecurity::Plain),
+            "me@example.com".to_string(),
+            ImapCredentials::OAuth2("expired-token".to_string()),
+        );
+        assert!(stale.test_connection().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_without_idle() {
+        let port = fake_imap_server("me@example.com", "token").await;
+        let service = ImapService::new(
+            ImapConnector::new("127.0.0.1", port, ConnectionSecurity::Plain),
+            "me@example.com".to_string(),
+            ImapCredentials::OAuth2("token".to_string()),
+        );
+        let mut events = Vec::new();
+        let outcome = service.watch("INBOX", |event| events.push(event)).await.unwrap();
+        assert_eq!(outcome, IdleOutcome::Unsupported);
+        assert!(events.is_empty());
+    }
 }
@@ -0,0 +1,345 @@
+// IMAP connection builder
+// Opens the transport for every IMAP call: implicit TLS, STARTTLS or localhost-only plaintext
+
+use crate::models::{ConnectionSecurity, EmailAccount};
+use crate::utils::{sha256_hex, AppError, AppResult};
+use async_imap::Client;
+use native_tls::{Certificate, TlsConnector as NativeTlsConnector};
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsConnector as TokioTlsConnector, TlsStream};
+
+/// Transport under an IMAP client, encrypted or not
+#[derive(Debug)]
+pub enum ImapStream {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl AsyncRead for ImapStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ImapStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            ImapStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ImapStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ImapStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            ImapStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ImapStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            ImapStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ImapStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            ImapStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Everything needed to reach an IMAP server, up to (not including) login
+#[derive(Debug, Clone)]
+pub struct ImapConnector {
+    server: String,
+    port: u16,
+    security: ConnectionSecurity,
+    /// Extra trusted root, PEM encoded
+    ca_certificate: Option<Vec<u8>>,
+    /// Normalised SHA-256 fingerprint of the only certificate to accept
+    pinned_certificate: Option<String>,
+}
+
+impl ImapConnector {
+    pub fn new(server: impl Into<String>, port: u16, security: ConnectionSecurity) -> Self {
+        Self {
+            server: server.into(),
+            port,
+            security,
+            ca_certificate: None,
+            pinned_certificate: None,
+        }
+    }
+
+    /// Trust certificates issued by this CA in addition to the system roots
+    pub fn with_ca_certificate(mut self, pem: Vec<u8>) -> AppResult<Self> {
+        Certificate::from_pem(&pem)
+            .map_err(|e| AppError::Validation(format!("Invalid CA certificate: {}", e)))?;
+        self.ca_certificate = Some(pem);
+        Ok(self)
+    }
+
+    /// Accept exactly the certificate with this SHA-256 fingerprint, whoever issued it
+    pub fn with_pinned_certificate(mut self, fingerprint: &str) -> AppResult<Self> {
+        self.pinned_certificate = Some(normalize_fingerprint(fingerprint)?);
+        Ok(self)
+    }
+
+    /// Build a connector from user-facing values; `ca_certificate` is a path to a PEM file
+    pub fn from_config(
+        server: String,
+        port: u16,
+        security: ConnectionSecurity,
+        ca_certificate: Option<&str>,
+        pinned_certificate: Option<&str>,
+    ) -> AppResult<Self> {
+        let mut connector = Self::new(server, port, security);
+
+        if let Some(path) = ca_certificate.filter(|p| !p.trim().is_empty()) {
+            let pem = std::fs::read(path.trim()).map_err(|e| {
+                AppError::Validation(format!("Failed to read CA certificate {}: {}", path, e))
+            })?;
+            connector = connector.with_ca_certificate(pem)?;
+        }
+        if let Some(fingerprint) = pinned_certificate.filter(|f| !f.trim().is_empty()) {
+            connector = connector.with_pinned_certificate(fingerprint)?;
+        }
+
+        Ok(connector)
+    }
+
+    pub fn from_account(account: &EmailAccount) -> AppResult<Self> {
+        Self::from_config(
+            account.server.clone(),
+            account.port as u16,
+            account.security,
+            account.ca_certificate.as_deref(),
+            account.pinned_certificate.as_deref(),
+        )
+    }
+
+    /// Open the connection and read the server greeting, ready for login
+    pub async fn connect(&self) -> AppResult<Client<ImapStream>> {
+        if self.security == ConnectionSecurity::Plain && !is_local_host(&self.server) {
+            return Err(AppError::Validation(format!(
+                "Unencrypted IMAP is only allowed for localhost, not {}",
+                self.server
+            )));
+        }
+
+        let tcp_stream = TcpStream::connect((self.server.as_str(), self.port))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to connect to IMAP server: {}", e)))?;
+
+        match self.security {
+            ConnectionSecurity::Tls => {
+                let tls_stream = self.upgrade(tcp_stream).await?;
+                let mut client = Client::new(ImapStream::Tls(tls_stream));
+                read_greeting(&mut client).await?;
+                Ok(client)
+            }
+            ConnectionSecurity::StartTls => {
+                let mut client = Client::new(ImapStream::Plain(tcp_stream));
+                read_greeting(&mut client).await?;
+                client
+                    .run_command_and_check_ok("STARTTLS", None)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("STARTTLS failed: {}", e)))?;
+
+                let ImapStream::Plain(tcp_stream) = client.into_inner() else {
+                    unreachable!("STARTTLS client was created over a plain stream");
+                };
+                // No second greeting after STARTTLS (RFC 3501 6.2.1)
+                let tls_stream = self.upgrade(tcp_stream).await?;
+                Ok(Client::new(ImapStream::Tls(tls_stream)))
+            }
+            ConnectionSecurity::Plain => {
+                let mut client = Client::new(ImapStream::Plain(tcp_stream));
+                read_greeting(&mut client).await?;
+                Ok(client)
+            }
+        }
+    }
+
+    /// Run the TLS handshake and apply the custom trust settings
+    async fn upgrade(&self, tcp_stream: TcpStream) -> AppResult<TlsStream<TcpStream>> {
+        let tls_stream = self
+            .tls_connector()?
+            .connect(&self.server, tcp_stream)
+            .await
+            .map_err(|e| AppError::Internal(format!("TLS connection error: {}", e)))?;
+
+        if let Some(pinned) = &self.pinned_certificate {
+            let certificate = tls_stream
+                .get_ref()
+                .peer_certificate()
+                .map_err(|e| AppError::Internal(format!("Failed to read server certificate: {}", e)))?
+                .ok_or_else(|| AppError::Internal("Server sent no certificate".to_string()))?;
+            let der = certificate
+                .to_der()
+                .map_err(|e| AppError::Internal(format!("Failed to read server certificate: {}", e)))?;
+
+            let actual = sha256_hex(&der);
+            if &actual != pinned {
+                return Err(AppError::Validation(format!(
+                    "Server certificate fingerprint {} does not match the pinned certificate",
+                    actual
+                )));
+            }
+        }
+
+        Ok(tls_stream)
+    }
+
+    fn tls_connector(&self) -> AppResult<TokioTlsConnector> {
+        let mut builder = NativeTlsConnector::builder();
+
+        if let Some(pem) = &self.ca_certificate {
+            let certificate = Certificate::from_pem(pem)
+                .map_err(|e| AppError::Validation(format!("Invalid CA certificate: {}", e)))?;
+            builder.add_root_certificate(certificate);
+        }
+        if self.pinned_certificate.is_some() {
+            // The fingerprint check after the handshake replaces chain and name validation
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+
+        let connector = builder
+            .build()
+            .map_err(|e| AppError::Internal(format!("Failed to build TLS connector: {}", e)))?;
+        Ok(TokioTlsConnector::from(connector))
+    }
+}
+
+async fn read_greeting(client: &mut Client<ImapStream>) -> AppResult<()> {
+    client
+        .read_response()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read server greeting: {}", e)))?;
+    Ok(())
+}
+
+/// Lowercase hex without separators, accepting the `AB:CD:...` form browsers and openssl show
+fn normalize_fingerprint(fingerprint: &str) -> AppResult<String> {
+    let hex: String = fingerprint
+        .chars()
+        .filter(|c| !matches!(c, ':' | ' '))
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(AppError::Validation(format!(
+            "Pinned certificate must be a SHA-256 fingerprint, got {}",
+            fingerprint
+        )));
+    }
+
+    Ok(hex)
+}
+
+fn is_local_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_tls::{Identity, TlsAcceptor};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    const CERT: &[u8] = include_bytes!("../../tests/fixtures/localhost.crt");
+    const KEY: &[u8] = include_bytes!("../../tests/fixtures/localhost.key");
+    const FINGERPRINT: &str =
+        "49:77:8E:0C:59:48:F4:B6:FF:14:26:69:AC:79:69:A0:35:D4:12:7A:83:03:EE:74:B8:F8:A6:56:1B:32:FD:DC";
+
+    /// Accept one connection on a local port, complete the handshake with the fixture certificate
+    async fn tls_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = tokio_native_tls::TlsAcceptor::from(
+            TlsAcceptor::new(Identity::from_pkcs8(CERT, KEY).unwrap()).unwrap(),
+        );
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            if let Ok(mut tls) = acceptor.accept(socket).await {
+                let _ = tls.shutdown().await;
+            }
+        });
+
+        port
+    }
+
+    async fn handshake(connector: ImapConnector) -> AppResult<()> {
+        let tcp_stream = TcpStream::connect(("127.0.0.1", connector.port)).await.unwrap();
+        connector.upgrade(tcp_stream).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_certificate_is_rejected() {
+        let port = tls_server().await;
+        let connector = ImapConnector::new("localhost", port, ConnectionSecurity::Tls);
+
+        assert!(handshake(connector).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_custom_ca_is_trusted() {
+        let port = tls_server().await;
+        let connector = ImapConnector::new("localhost", port, ConnectionSecurity::Tls)
+            .with_ca_certificate(CERT.to_vec())
+            .unwrap();
+
+        handshake(connector).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pinned_certificate() {
+        let port = tls_server().await;
+        // The pin alone is enough, even when the name does not match
+        let connector = ImapConnector::new("mail.example.com", port, ConnectionSecurity::Tls)
+            .with_pinned_certificate(FINGERPRINT)
+            .unwrap();
+        handshake(connector).await.unwrap();
+
+        let port = tls_server().await;
+        let connector = ImapConnector::new("localhost", port, ConnectionSecurity::Tls)
+            .with_pinned_certificate(&"ab".repeat(32))
+            .unwrap();
+        assert!(matches!(handshake(connector).await, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_is_refused_for_remote_hosts() {
+        let connector = ImapConnector::new("imap.example.com", 143, ConnectionSecurity::Plain);
+
+        assert!(matches!(connector.connect().await, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn test_local_hosts() {
+        assert!(is_local_host("localhost"));
+        assert!(is_local_host("127.0.0.1"));
+        assert!(is_local_host("[::1]"));
+        assert!(!is_local_host("192.168.1.10"));
+        assert!(!is_local_host("localhost.example.com"));
+    }
+
+    #[test]
+    fn test_normalize_fingerprint() {
+        assert_eq!(
+            normalize_fingerprint(FINGERPRINT).unwrap(),
+            "49778e0c5948f4b6ff142669ac7969a035d4127a8303ee74b8f8a6561b32fddc"
+        );
+        assert!(normalize_fingerprint("49:77:8E").is_err());
+        assert!(normalize_fingerprint(&"zz".repeat(32)).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+// LLM providers
+// The servers prompts can go to, and schema-guided receipt extraction on top of any of them
+
+use crate::models::{AppSettings, LlmProviderKind};
+use crate::services::extraction_schema;
+use crate::services::ollama::OllamaService;
+use crate::services::openai::OpenAiService;
+use crate::services::rules::RuleExtractor;
+use crate::utils::{AppError, AppResult};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::sync::Semaphore;
+
+/// Sampling is fixed so the same receipt gets the same answer
+pub const TEMPERATURE: f64 = 0.0;
+pub const SEED: i64 = 42;
+/// Room for `content::MAX_CONTEXT_CHARS` of receipt plus the prompt
+pub const CONTEXT_TOKENS: u32 = 16_384;
+/// Most extractions a sync runs at once, whatever the settings say
+pub const MAX_CONCURRENCY: u32 = 16;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LlmExtractionResult {
+    #[serde(rename = "type")]
+    pub classification: String,
+    pub confidence: f64,
+    /// Left out for junk
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+/// Text read from an image by the OCR model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcrText {
+    pub text: String,
+    pub confidence: f64,
+}
+
+/// One prompt for a provider
+pub struct Completion {
+    pub model: String,
+    pub prompt: String,
+    /// Base64 encoded, for vision models
+    pub images: Vec<String>,
+    /// JSON Schema the answer must follow
+    pub format: Option<serde_json::Value>,
+}
+
+/// A server that answers prompts
+pub trait LlmProvider: Send + Sync {
+    /// The answer's text, sampled with `TEMPERATURE` and `SEED`
+    fn complete(&self, completion: Completion) -> impl Future<Output = AppResult<String>> + Send;
+
+    fn list_models(&self) -> impl Future<Output = AppResult<Vec<String>>> + Send;
+}
+
+/// Reads receipts with a model served by `P`
+pub struct LlmExtractor<P> {
+    provider: P,
+    model: String,
+    /// Vision model for OCR; without one images aren't read
+    ocr_model: Option<String>,
+    /// Bounds how many completions run at once
+    limit: Semaphore,
+}
+
+impl<P: LlmProvider> LlmExtractor<P> {
+    pub fn new(provider: P, model: String) -> Self {
+        Self { provider, model, ocr_model: None, limit: Semaphore::new(1) }
+    }
+
+    /// Read images with `model`; an empty name leaves OCR off
+    pub fn with_ocr_model(mut self, model: &str) -> Self {
+        self.ocr_model = Some(model.trim().to_string()).filter(|m| !m.is_empty());
+        self
+    }
+
+    /// Allow up to `concurrency` completions in flight; further calls wait their turn
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.limit = Semaphore::new(concurrency.max(1));
+        self
+    }
+
+    pub fn reads_images(&self) -> bool {
+        self.ocr_model.is_some()
+    }
+
+    async fn complete(&self, completion: Completion) -> AppResult<String> {
+        let _permit = self
+            .limit
+            .acquire()
+            .await
+            .map_err(|e| AppError::Internal(format!("LLM request failed: {}", e)))?;
+        self.provider.complete(completion).await
+    }
+
+    /// Classify and extract a receipt. An answer that doesn't match the
+    /// schema is sent back once with what was wrong before giving up.
+    pub async fn extract_receipt_data(&self, markdown_content: &str) -> AppResult<LlmExtractionResult> {
+        let schema = extraction_schema::response_schema();
+        let completion = |prompt| Completion {
+            model: self.model.clone(),
+            prompt,
+            images: Vec::new(),
+            format: Some(schema.clone()),
+        };
+        let response = self.complete(completion(extraction_schema::prompt(markdown_content))).await?;
+
+        let problem = match parse_extraction(&response) {
+            Ok(result) => return Ok(result),
+            Err(problem) => problem,
+        };
+        eprintln!("LLM answer did not match the extraction schema, asking again: {}", problem);
+
+        let prompt = extraction_schema::repair_prompt(markdown_content, &response, &problem);
+        let response = self.complete(completion(prompt)).await?;
+        parse_extraction(&response)
+            .map_err(|problem| AppError::Internal(format!("LLM answer does not match the extraction schema: {}", problem)))
+    }
+
+    /// The text in a photographed receipt or scanned page
+    pub async fn recognize_text(&self, image: &[u8]) -> AppResult<OcrText> {
+        let model = self
+            .ocr_model
+            .clone()
+            .ok_or_else(|| AppError::Validation("No OCR model is configured".to_string()))?;
+
+        let prompt = r#"Transcribe all text in this image of a receipt or invoice, line by line, keeping amounts, dates and currency symbols exactly as printed.
+
+CONFIDENCE: Return a value from 0.0 to 1.0 indicating how legible the text was.
+
+Answer with a JSON object with "text" and "confidence".
+"#;
+        let format = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "text": { "type": "string" },
+                "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+            },
+            "required": ["text", "confidence"]
+        });
+        let image = base64::engine::general_purpose::STANDARD.encode(image);
+        let completion = Completion { model, prompt: prompt.to_string(), images: vec![image], format: Some(format) };
+        let response = self.complete(completion).await?;
+        serde_json::from_str(response.trim()).map_err(|e| AppError::Internal(format!("Failed to parse OCR JSON: {}", e)))
+    }
+}
+
+/// Structured output comes back as bare JSON, so anything else is a problem to report
+fn parse_extraction(response: &str) -> Result<LlmExtractionResult, String> {
+    let result: LlmExtractionResult =
+        serde_json::from_str(response.trim()).map_err(|e| format!("the answer is not valid JSON: {}", e))?;
+    extraction_schema::validate(&result)?;
+    Ok(result)
+}
+
+/// The extractor chosen in the settings
+pub enum Extractor {
+    Ollama(LlmExtractor<OllamaService>),
+    OpenAi(LlmExtractor<OpenAiService>),
+    Rules(RuleExtractor),
+}
+
+/// Parallel extractions allowed by the settings. Values saved before the
+/// limit existed are clamped into range.
+pub fn extraction_concurrency(settings: &AppSettings) -> usize {
+    settings.extraction_concurrency.clamp(1, MAX_CONCURRENCY) as usize
+}
+
+impl Extractor {
+    pub fn from_settings(settings: &AppSettings) -> Self {
+        let concurrency = extraction_concurrency(settings);
+        match settings.llm_provider {
+            LlmProviderKind::Ollama => Extractor::Ollama(
+                LlmExtractor::new(OllamaService::new(settings.ollama_endpoint.clone()), settings.ollama_model.clone())
+                    .with_concurrency(concurrency)
+                    .with_ocr_model(&settings.ocr_model),
+            ),
+            LlmProviderKind::OpenAi => Extractor::OpenAi(
+                LlmExtractor::new(OpenAiService::new(settings.openai_endpoint.clone()), settings.openai_model.clone())
+                    .with_concurrency(concurrency)
+                    .with_ocr_model(&settings.ocr_model),
+            ),
+            LlmProviderKind::Rules => Extractor::Rules(RuleExtractor),
+        }
+    }
+}
+
+/// Models the server at `endpoint` offers
+pub async fn list_models(provider: LlmProviderKind, endpoint: &str) -> AppResult<Vec<String>> {
+    match provider {
+        LlmProviderKind::Ollama => OllamaService::new(endpoint.to_string()).list_models().await,
+        LlmProviderKind::OpenAi => OpenAiService::new(endpoint.to_string()).list_models().await,
+        LlmProviderKind::Rules => Ok(Vec::new()),
+    }
+}
@@ -0,0 +1,282 @@
+// Local mail import
+// Reads messages from an mbox file, a Maildir or `.eml` files, for mail that never was on IMAP
+
+use crate::models::{EmailContent, MailboxCursor, MessageAction};
+use crate::services::imap::parse_email;
+use crate::services::pipeline::{FetchedEmail, MailSource, NewMail};
+use crate::utils::{AppError, AppResult};
+use futures::channel::mpsc;
+use futures::SinkExt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Where one message is kept
+#[derive(Debug, PartialEq)]
+enum Location {
+    /// A whole file: an `.eml` file or a Maildir entry
+    File(PathBuf),
+    /// A slice of an mbox file, after its "From " separator line
+    Mbox { offset: u64, len: u64 },
+}
+
+/// The messages of an mbox file, a Maildir directory, a directory of `.eml`
+/// files or a single `.eml` file. Message N is UID N, in file order.
+pub struct LocalMailSource {
+    path: PathBuf,
+    messages: Vec<Location>,
+}
+
+impl LocalMailSource {
+    /// Find the messages at `path`. Scanning reads a whole mbox file, so
+    /// call this on a blocking thread.
+    pub fn open(path: &Path) -> AppResult<Self> {
+        let messages = if path.is_dir() {
+            if path.join("cur").is_dir() || path.join("new").is_dir() {
+                Self::maildir_files(path)?
+            } else {
+                Self::eml_files(path)?
+            }
+        } else if path.is_file() {
+            if has_eml_extension(path) {
+                vec![Location::File(path.to_path_buf())]
+            } else {
+                Self::scan_mbox(path)?
+            }
+        } else {
+            return Err(AppError::NotFound(format!("{} does not exist", path.display())));
+        };
+
+        Ok(Self { path: path.to_path_buf(), messages })
+    }
+
+    /// Delivered (`new`) and seen (`cur`) messages; `tmp` holds unfinished deliveries
+    fn maildir_files(path: &Path) -> AppResult<Vec<Location>> {
+        let mut files = Vec::new();
+        for folder in ["cur", "new"] {
+            let folder = path.join(folder);
+            if folder.is_dir() {
+                files.extend(sorted_files(&folder)?);
+            }
+        }
+        Ok(files.into_iter().map(Location::File).collect())
+    }
+
+    fn eml_files(path: &Path) -> AppResult<Vec<Location>> {
+        let files = sorted_files(path)?.into_iter().filter(|file| has_eml_extension(file));
+        Ok(files.map(Location::File).collect())
+    }
+
+    /// A message starts after each "From " line that opens the file or follows a blank line
+    fn scan_mbox(path: &Path) -> AppResult<Vec<Location>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut messages = Vec::new();
+        let mut line = Vec::new();
+        let mut offset = 0u64;
+        let mut start = None;
+        let mut after_blank = true;
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            if after_blank && line.starts_with(b"From ") {
+                if let Some(start) = start {
+                    messages.push(Location::Mbox { offset: start, len: offset - start });
+                }
+                start = Some(offset + read);
+            }
+            after_blank = line == b"\n" || line == b"\r\n";
+            offset += read;
+        }
+        if let Some(start) = start {
+            messages.push(Location::Mbox { offset: start, len: offset - start });
+        }
+
+        if messages.is_empty() && offset > 0 {
+            return Err(AppError::Validation(format!(
+                "{} is not an mbox file, a Maildir or an .eml file",
+                path.display()
+            )));
+        }
+        Ok(messages)
+    }
+
+    async fn read(&self, uid: u32) -> AppResult<Vec<u8>> {
+        let location = (uid as usize)
+            .checked_sub(1)
+            .and_then(|index| self.messages.get(index))
+            .ok_or_else(|| AppError::NotFound(format!("Message {} is not in {}", uid, self.path.display())))?;
+
+        match location {
+            Location::File(file) => Ok(tokio::fs::read(file).await?),
+            Location::Mbox { offset, len } => {
+                let mut file = tokio::fs::File::open(&self.path).await?;
+                file.seek(SeekFrom::Start(*offset)).await?;
+                let mut raw = vec![0; *len as usize];
+                file.read_exact(&mut raw).await?;
+                // Quoted ">From " lines stay as they are: mboxo, which Thunderbird
+                // writes, can't be unquoted safely and the file doesn't say which
+                // flavour it is
+                Ok(raw)
+            }
+        }
+    }
+
+    async fn read_email(&self, uid: u32) -> AppResult<EmailContent> {
+        parse_email(&self.read(uid).await?)
+    }
+}
+
+impl MailSource for LocalMailSource {
+    type Session = ();
+
+    async fn test_connection(&self) -> AppResult<()> {
+        Ok(())
+    }
+
+    /// Every message is new; local files have no cursor
+    async fn find_new_emails(&self, mailbox: &str, _cursor: Option<&MailboxCursor>) -> AppResult<NewMail<()>> {
+        let last_uid = self.messages.len() as u32;
+        Ok(NewMail {
+            session: (),
+            mailbox: mailbox.to_string(),
+            uid_validity: 0,
+            last_uid,
+            uids: (1..=last_uid).collect(),
+        })
+    }
+
+    async fn download(&self, new_mail: NewMail<()>, mut tx: mpsc::Sender<FetchedEmail>) -> AppResult<()> {
+        for uid in new_mail.uids {
+            if tx.is_closed() {
+                break;
+            }
+
+            let email = match self.read_email(uid).await {
+                Ok(email) => email,
+                Err(e) => {
+                    eprintln!("Skipping message {} in {}: {}", uid, self.path.display(), e);
+                    continue;
+                }
+            };
+            if tx.send(FetchedEmail { uid, email }).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch_message(&self, _mailbox: &str, _uid_validity: u32, uid: u32) -> AppResult<EmailContent> {
+        self.read_email(uid).await
+    }
+
+    /// Files are left as they are
+    async fn apply_action(&self, _mailbox: &str, _uids: &[u32], _action: &MessageAction) -> AppResult<()> {
+        Ok(())
+    }
+}
+
+fn has_eml_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("eml"))
+}
+
+fn sorted_files(dir: &Path) -> AppResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    const MBOX: &str = "From billing@netflix.com Mon Jan  1 10:00:00 2024\n\
+                        From: billing@netflix.com\n\
+                        Subject: Your receipt\n\
+                        Content-Type: text/plain\n\
+                        \n\
+                        Thanks for your payment\n\
+                        >From now on, you pay yearly\n\
+                        \n\
+                        From shop@example.com Tue Jan  2 10:00:00 2024\n\
+                        From: shop@example.com\n\
+                        Subject: Sale\n\
+                        \n\
+                        Everything must go\n";
+
+    async fn download_all(source: &LocalMailSource) -> Vec<(u32, EmailContent)> {
+        let new_mail = source.find_new_emails("import", None).await.unwrap();
+        let (tx, rx) = mpsc::channel(1);
+        let (downloaded, emails) = tokio::join!(source.download(new_mail, tx), rx.collect::<Vec<_>>());
+        downloaded.unwrap();
+        emails.into_iter().map(|fetched| (fetched.uid, fetched.email)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_reads_mbox_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Invoices");
+        std::fs::write(&path, MBOX).unwrap();
+
+        let source = LocalMailSource::open(&path).unwrap();
+        assert_eq!(source.messages.len(), 2);
+
+        let emails = download_all(&source).await;
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].0, 1);
+        assert_eq!(emails[0].1.subject, "Your receipt");
+        assert!(emails[0].1.body.contains("\n>From now on"));
+        assert_eq!(emails[1].1.subject, "Sale");
+        assert_eq!(source.fetch_message("import", 0, 2).await.unwrap().subject, "Sale");
+        assert!(matches!(source.fetch_message("import", 0, 3).await, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reads_maildir_and_eml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let maildir = dir.path().join("Archive");
+        for folder in ["cur", "new", "tmp"] {
+            std::fs::create_dir_all(maildir.join(folder)).unwrap();
+        }
+        std::fs::write(maildir.join("cur/1.host:2,S"), "Subject: Seen\n\nOld").unwrap();
+        std::fs::write(maildir.join("new/2.host"), "Subject: Unseen\n\nNew").unwrap();
+        std::fs::write(maildir.join("tmp/3.host"), "Subject: Half delivered\n\n").unwrap();
+
+        let subjects = |emails: Vec<(u32, EmailContent)>| emails.into_iter().map(|(_, e)| e.subject).collect::<Vec<_>>();
+        let source = LocalMailSource::open(&maildir).unwrap();
+        assert_eq!(subjects(download_all(&source).await), vec!["Seen", "Unseen"]);
+
+        let exports = dir.path().join("exports");
+        std::fs::create_dir(&exports).unwrap();
+        std::fs::write(exports.join("b.eml"), "Subject: Second\n\nB").unwrap();
+        std::fs::write(exports.join("a.EML"), "Subject: First\n\nA").unwrap();
+        std::fs::write(exports.join("notes.txt"), "not mail").unwrap();
+
+        let source = LocalMailSource::open(&exports).unwrap();
+        assert_eq!(subjects(download_all(&source).await), vec!["First", "Second"]);
+
+        let single = LocalMailSource::open(&exports.join("b.eml")).unwrap();
+        assert_eq!(subjects(download_all(&single).await), vec!["Second"]);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "Just some notes\n").unwrap();
+
+        assert!(matches!(LocalMailSource::open(&path), Err(AppError::Validation(_))));
+        assert!(matches!(LocalMailSource::open(&dir.path().join("missing")), Err(AppError::NotFound(_))));
+    }
+}
@@ -2,35 +2,18 @@ use std::process::Command;
 use std::io::Write;
 use crate::utils::AppResult;
 
+#[derive(Clone)]
 pub struct MarkItDownService;
 
 impl MarkItDownService {
-    pub fn ensure_markitdown() -> AppResult<()> {
-        let output = Command::new("python3")
+    /// Whether `python3 -m markitdown` runs. It is never installed for the user.
+    pub fn is_installed() -> bool {
+        Command::new("python3")
             .arg("-m")
             .arg("markitdown")
             .arg("--version")
-            .output();
-
-        match output {
-            Ok(out) if out.status.success() => Ok(()),
-            _ => {
-                // Try to install it
-                let install_output = Command::new("python3")
-                    .arg("-m")
-                    .arg("pip")
-                    .arg("install")
-                    .arg("markitdown")
-                    .output()
-                    .map_err(|e| crate::utils::AppError::Internal(format!("Failed to install markitdown: {}", e)))?;
-
-                if !install_output.status.success() {
-                    let error = String::from_utf8_lossy(&install_output.stderr);
-                    return Err(crate::utils::AppError::Internal(format!("Failed to install markitdown: {}", error)));
-                }
-                Ok(())
-            }
-        }
+            .output()
+            .is_ok_and(|out| out.status.success())
     }
 
     pub fn convert_to_markdown(file_path: &str) -> AppResult<String> {
@@ -1,8 +1,20 @@
 // Business logic services
 // This module contains core business logic separated from command handlers
 
+pub mod content;
+pub mod converter;
+pub mod credentials;
+pub mod extraction_schema;
 pub mod imap;
+pub mod imap_connection;
+pub mod llm;
+pub mod local_mail;
+pub mod oauth;
 pub mod ollama;
+pub mod openai;
 pub mod markitdown;
+pub mod pipeline;
+pub mod rules;
+pub mod scheduler;
 pub mod sync;
 
@@ -0,0 +1,525 @@
+// OAuth2 for IMAP
+// Authorization-code flow with PKCE over a loopback redirect, token refresh and the XOAUTH2 SASL mechanism
+
+use crate::models::{EmailAccount, OAuthProvider};
+use crate::utils::{AppError, AppResult};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How long the user has to finish signing in through the browser
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Endpoints and scope of an OAuth2 provider, plus our client registration
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub auth_url: String,
+    pub token_url: String,
+    pub scope: String,
+    /// Extra authorization parameters the provider needs to issue a refresh token
+    pub extra_auth_params: Vec<(String, String)>,
+}
+
+impl OAuthConfig {
+    pub fn for_provider(provider: OAuthProvider, client_id: String, client_secret: Option<String>) -> Self {
+        let (auth_url, token_url, scope, extra_auth_params) = match provider {
+            OAuthProvider::Google => (
+                "https://accounts.google.com/o/oauth2/v2/auth",
+                "https://oauth2.googleapis.com/token",
+                "https://mail.google.com/",
+                vec![("access_type", "offline"), ("prompt", "consent")],
+            ),
+            OAuthProvider::Microsoft => (
+                "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+                "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+                "https://outlook.office.com/IMAP.AccessAsUser.All offline_access",
+                vec![],
+            ),
+        };
+
+        Self {
+            client_id,
+            client_secret,
+            auth_url: auth_url.to_string(),
+            token_url: token_url.to_string(),
+            scope: scope.to_string(),
+            extra_auth_params: extra_auth_params
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn from_account(account: &EmailAccount) -> AppResult<Self> {
+        let client_id = account
+            .oauth_client_id
+            .clone()
+            .filter(|id| !id.trim().is_empty())
+            .ok_or_else(|| {
+                AppError::Validation(format!("OAuth client ID is not configured for {}", account.name))
+            })?;
+
+        Ok(Self::for_provider(
+            account.oauth_provider,
+            client_id.trim().to_string(),
+            account.oauth_client_secret.clone().filter(|s| !s.is_empty()),
+        ))
+    }
+}
+
+/// Proof key for the authorization-code exchange (RFC 7636, S256)
+#[derive(Debug, Clone)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> AppResult<Self> {
+        Ok(Self::from_verifier(random_token(32)?))
+    }
+
+    fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+/// URL-safe random string from `len` random bytes
+fn random_token(len: usize) -> AppResult<String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| AppError::Internal(format!("Failed to generate random data: {}", e)))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    /// Only sent on the first exchange, or when the provider rotates it
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+pub struct OAuthClient {
+    config: OAuthConfig,
+    http: reqwest::Client,
+}
+
+impl OAuthClient {
+    pub fn new(config: OAuthConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Where to send the user's browser to sign in
+    pub fn authorization_url(&self, redirect_uri: &str, pkce: &Pkce, state: &str) -> AppResult<String> {
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.config.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("scope", self.config.scope.as_str()),
+            ("state", state),
+            ("code_challenge", pkce.challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+        params.extend(self.config.extra_auth_params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+        let url = Url::parse_with_params(&self.config.auth_url, &params)
+            .map_err(|e| AppError::Validation(format!("Invalid authorization URL: {}", e)))?;
+        Ok(url.into())
+    }
+
+    /// Run the whole sign-in: listen on a loopback port, hand the authorization
+    /// URL to `open`, wait for the redirect and exchange the code for tokens
+    pub async fn authorize<F>(&self, open: F) -> AppResult<TokenResponse>
+    where
+        F: FnOnce(&str) -> AppResult<()>,
+    {
+        let redirect = LoopbackRedirect::bind().await?;
+        let redirect_uri = redirect.redirect_uri()?;
+        let pkce = Pkce::generate()?;
+        let state = random_token(16)?;
+
+        open(&self.authorization_url(&redirect_uri, &pkce, &state)?)?;
+
+        let code = tokio::time::timeout(AUTHORIZE_TIMEOUT, redirect.wait_for_code(&state))
+            .await
+            .map_err(|_| AppError::Validation("Timed out waiting for sign-in".to_string()))??;
+
+        self.exchange_code(&code, &pkce, &redirect_uri).await
+    }
+
+    pub async fn exchange_code(&self, code: &str, pkce: &Pkce, redirect_uri: &str) -> AppResult<TokenResponse> {
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", pkce.verifier.as_str()),
+        ])
+        .await
+    }
+
+    /// Get a fresh access token. The response may carry a rotated refresh token.
+    pub async fn refresh(&self, refresh_token: &str) -> AppResult<TokenResponse> {
+        self.request_token(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+            .await
+    }
+
+    async fn request_token(&self, grant: &[(&str, &str)]) -> AppResult<TokenResponse> {
+        let mut form = vec![("client_id", self.config.client_id.as_str())];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        form.extend_from_slice(grant);
+
+        let response = self
+            .http
+            .post(&self.config.token_url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Token request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(match serde_json::from_str::<TokenError>(&body) {
+                // The grant was revoked or expired; only signing in again helps
+                Ok(err) if err.error == "invalid_grant" => AppError::Validation(format!(
+                    "OAuth sign-in is no longer valid, sign in again: {}",
+                    err.error_description.unwrap_or(err.error)
+                )),
+                Ok(err) => AppError::Internal(format!(
+                    "Token request failed: {}",
+                    err.error_description.unwrap_or(err.error)
+                )),
+                Err(_) => AppError::Internal(format!("Token request failed with status {}", status)),
+            });
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse token response: {}", e)))
+    }
+}
+
+/// One-shot HTTP listener on 127.0.0.1 that receives the authorization redirect
+pub struct LoopbackRedirect {
+    listener: TcpListener,
+}
+
+impl LoopbackRedirect {
+    pub async fn bind() -> AppResult<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        Ok(Self { listener })
+    }
+
+    pub fn redirect_uri(&self) -> AppResult<String> {
+        Ok(format!("http://{}/", self.listener.local_addr()?))
+    }
+
+    /// Wait for the browser to come back with an authorization code. Requests
+    /// without one, such as a favicon fetch, are answered and ignored.
+    pub async fn wait_for_code(self, state: &str) -> AppResult<String> {
+        loop {
+            let (mut stream, _) = self.listener.accept().await?;
+            let Some(target) = read_request_target(&mut stream).await? else {
+                continue;
+            };
+
+            let url = Url::parse(&format!("http://localhost{}", target))
+                .map_err(|e| AppError::Validation(format!("Invalid redirect request: {}", e)))?;
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+
+            if let Some(error) = param("error") {
+                respond(&mut stream, "200 OK", "Sign-in was cancelled. You can close this window.").await;
+                return Err(AppError::Validation(format!("Sign-in failed: {}", error)));
+            }
+            let Some(code) = param("code") else {
+                respond(&mut stream, "404 Not Found", "Not found").await;
+                continue;
+            };
+            if param("state").as_deref() != Some(state) {
+                respond(&mut stream, "400 Bad Request", "Sign-in request did not match. Please try again.").await;
+                return Err(AppError::Validation("OAuth state mismatch".to_string()));
+            }
+
+            respond(&mut stream, "200 OK", "Signed in. You can close this window and return to Subscript.").await;
+            return Ok(code);
+        }
+    }
+}
+
+/// Read an HTTP request head and return the target of a GET request
+async fn read_request_target(stream: &mut TcpStream) -> AppResult<Option<String>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() > 16 * 1024 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&request);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    Ok(match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    })
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!("<!doctype html><html><body><p>{}</p></body></html>", message);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    // The browser going away early is not our problem
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// SASL XOAUTH2 as used by Gmail and Outlook
+pub struct XOAuth2 {
+    user: String,
+    access_token: String,
+    sent: bool,
+}
+
+impl XOAuth2 {
+    pub fn new(user: String, access_token: String) -> Self {
+        Self {
+            user,
+            access_token,
+            sent: false,
+        }
+    }
+}
+
+impl async_imap::Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&mut self, _challenge: &[u8]) -> String {
+        // A second challenge carries the server's error details and wants an
+        // empty response, after which the command fails with NO
+        if std::mem::replace(&mut self.sent, true) {
+            return String::new();
+        }
+        format!("user={}\x01auth=Bearer {}\x01\x01", self.user, self.access_token)
+    }
+}
+
+/// Decode an XOAUTH2 initial response into (user, access token), for stand-in servers in tests
+#[cfg(test)]
+pub(crate) fn decode_xoauth2(encoded: &str) -> Option<(String, String)> {
+    let decoded = String::from_utf8(base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let mut fields = decoded.split('\x01');
+    let user = fields.next()?.strip_prefix("user=")?.to_string();
+    let token = fields.next()?.strip_prefix("auth=Bearer ")?.to_string();
+    Some((user, token))
+}
+
+/// Serve one token endpoint response per connection, in order, and hand back
+/// the form bodies that were posted
+#[cfg(test)]
+pub(crate) async fn fake_token_endpoint(
+    responses: Vec<(u16, serde_json::Value)>,
+) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/token", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let mut bodies = Vec::new();
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let body_start = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|line| {
+                            let line = line.to_ascii_lowercase();
+                            line.strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break header_end + 4;
+                    }
+                }
+                assert!(n > 0, "token request ended early");
+            };
+            bodies.push(String::from_utf8_lossy(&request[body_start..]).into_owned());
+
+            let body = body.to_string();
+            let response = format!(
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+        bodies
+    });
+
+    (url, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+
+    fn test_config(token_url: &str) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "subscript-desktop".to_string(),
+            client_secret: None,
+            auth_url: "https://auth.example.com/authorize".to_string(),
+            token_url: token_url.to_string(),
+            scope: "mail".to_string(),
+            extra_auth_params: vec![],
+        }
+    }
+
+    fn form_value(body: &str, name: &str) -> Option<String> {
+        Url::parse(&format!("http://localhost/?{}", body))
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // Example from RFC 7636 appendix B
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(pkce.challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+        let generated = Pkce::generate().unwrap();
+        assert_eq!(generated.verifier.len(), 43);
+        assert_ne!(generated.verifier, Pkce::generate().unwrap().verifier);
+    }
+
+    #[test]
+    fn test_xoauth2_response() {
+        let mut auth = XOAuth2::new("me@example.com".to_string(), "ya29.token".to_string());
+
+        let first = async_imap::Authenticator::process(&mut auth, b"");
+        assert_eq!(first, "user=me@example.com\x01auth=Bearer ya29.token\x01\x01");
+        assert_eq!(
+            decode_xoauth2(&STANDARD.encode(&first)),
+            Some(("me@example.com".to_string(), "ya29.token".to_string()))
+        );
+        assert_eq!(async_imap::Authenticator::process(&mut auth, b"{\"status\":\"400\"}"), "");
+    }
+
+    #[tokio::test]
+    async fn test_authorize_with_pkce_through_loopback() {
+        let (token_url, requests) = fake_token_endpoint(vec![(
+            200,
+            serde_json::json!({ "access_token": "access-1", "refresh_token": "refresh-1", "expires_in": 3600 }),
+        )])
+        .await;
+        let client = OAuthClient::new(test_config(&token_url));
+
+        let mut challenge = String::new();
+        let tokens = client
+            .authorize(|auth_url| {
+                // Play the browser: the provider redirects back with a code
+                let auth_url = Url::parse(auth_url).unwrap();
+                let param = |name: &str| {
+                    auth_url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned()).unwrap()
+                };
+                assert_eq!(param("code_challenge_method"), "S256");
+                challenge = param("code_challenge");
+
+                let favicon = format!("{}favicon.ico", param("redirect_uri"));
+                let mut redirect = Url::parse(&param("redirect_uri")).unwrap();
+                redirect
+                    .query_pairs_mut()
+                    .append_pair("code", "auth-code")
+                    .append_pair("state", &param("state"));
+                tokio::spawn(async move {
+                    let _ = reqwest::get(favicon).await;
+                    reqwest::get(redirect).await.unwrap();
+                });
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(tokens.access_token, "access-1");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh-1"));
+
+        let body = &requests.await.unwrap()[0];
+        assert_eq!(form_value(body, "grant_type").as_deref(), Some("authorization_code"));
+        assert_eq!(form_value(body, "code").as_deref(), Some("auth-code"));
+        let verifier = form_value(body, "code_verifier").unwrap();
+        assert_eq!(Pkce::from_verifier(verifier).challenge, challenge);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_rejects_state_mismatch() {
+        let redirect = LoopbackRedirect::bind().await.unwrap();
+        let uri = redirect.redirect_uri().unwrap();
+        tokio::spawn(async move {
+            let _ = reqwest::get(format!("{}?code=abc&state=forged", uri)).await;
+        });
+
+        assert!(matches!(redirect.wait_for_code("expected").await, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let (token_url, requests) = fake_token_endpoint(vec![
+            (200, serde_json::json!({ "access_token": "access-2", "expires_in": 3600 })),
+            (400, serde_json::json!({ "error": "invalid_grant", "error_description": "Token has been revoked" })),
+        ])
+        .await;
+        let client = OAuthClient::new(test_config(&token_url));
+
+        let tokens = client.refresh("refresh-1").await.unwrap();
+        assert_eq!(tokens.access_token, "access-2");
+        assert_eq!(tokens.refresh_token, None);
+
+        let err = client.refresh("refresh-1").await.unwrap_err();
+        assert!(matches!(err, AppError::Validation(ref msg) if msg.contains("revoked")));
+
+        let bodies = requests.await.unwrap();
+        assert_eq!(form_value(&bodies[0], "grant_type").as_deref(), Some("refresh_token"));
+        assert_eq!(form_value(&bodies[0], "refresh_token").as_deref(), Some("refresh-1"));
+        assert_eq!(form_value(&bodies[0], "client_id").as_deref(), Some("subscript-desktop"));
+    }
+}
@@ -1,18 +1,33 @@
+use crate::services::llm::{self, Completion, LlmProvider};
 use crate::utils::AppResult;
 use serde::{Deserialize, Serialize};
 
+/// Ollama's own API: `/api/generate` and `/api/tags`
 pub struct OllamaService {
     endpoint: String,
-    model: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OllamaRequest {
     model: String,
     prompt: String,
+    /// Base64 encoded, for vision models
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    images: Vec<String>,
+    /// JSON Schema for structured output
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    format: Option<serde_json::Value>,
+    options: OllamaOptions,
     stream: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaOptions {
+    temperature: f64,
+    seed: i64,
+    num_ctx: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct OllamaResponse {
     response: String,
@@ -28,120 +43,56 @@ struct OllamaTagsResponse {
     models: Vec<OllamaModel>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LlmExtractionResult {
-    #[serde(rename = "type")]
-    pub classification: String,
-    pub confidence: f64,
-    pub data: serde_json::Value,
-}
-
 impl OllamaService {
-    pub fn new(endpoint: String, model: String) -> Self {
-        Self { endpoint, model }
+    pub fn new(endpoint: String) -> Self {
+        Self { endpoint }
     }
+}
 
-    pub async fn list_models(endpoint: &str) -> AppResult<Vec<String>> {
+impl LlmProvider for OllamaService {
+    async fn complete(&self, completion: Completion) -> AppResult<String> {
         let client = reqwest::Client::new();
-        let url = format!("{}/api/tags", endpoint);
+        let url = format!("{}/api/generate", self.endpoint);
+
+        let request = OllamaRequest {
+            model: completion.model,
+            prompt: completion.prompt,
+            images: completion.images,
+            format: completion.format,
+            options: OllamaOptions { temperature: llm::TEMPERATURE, seed: llm::SEED, num_ctx: llm::CONTEXT_TOKENS },
+            stream: false,
+        };
 
         let response = client
-            .get(url)
+            .post(url)
+            .json(&request)
             .send()
             .await
             .map_err(|e| crate::utils::AppError::Internal(format!("Ollama request failed: {}", e)))?;
 
-        let result: OllamaTagsResponse = response
+        let result: OllamaResponse = response
             .json()
             .await
             .map_err(|e| crate::utils::AppError::Internal(format!("Failed to parse Ollama response: {}", e)))?;
 
-        Ok(result.models.into_iter().map(|m| m.name).collect())
+        Ok(result.response)
     }
 
-    pub async fn generate(&self, prompt: String) -> AppResult<String> {
+    async fn list_models(&self) -> AppResult<Vec<String>> {
         let client = reqwest::Client::new();
-        let url = format!("{}/api/generate", self.endpoint);
-
-        let request = OllamaRequest {
-            model: self.model.clone(),
-            prompt,
-            stream: false,
-        };
+        let url = format!("{}/api/tags", self.endpoint);
 
         let response = client
-            .post(url)
-            .json(&request)
+            .get(url)
             .send()
             .await
             .map_err(|e| crate::utils::AppError::Internal(format!("Ollama request failed: {}", e)))?;
 
-        let result: OllamaResponse = response
+        let result: OllamaTagsResponse = response
             .json()
             .await
             .map_err(|e| crate::utils::AppError::Internal(format!("Failed to parse Ollama response: {}", e)))?;
 
-        Ok(result.response)
-    }
-
-    pub async fn extract_receipt_data(&self, markdown_content: &str) -> AppResult<LlmExtractionResult> {
-        let prompt = format!(
-            r#"You are a receipt and billing document analyzer. Your task is to classify and extract data from receipts.
-
-CLASSIFICATION OPTIONS:
-- "subscription": Recurring payment (monthly/yearly service)
-- "domain": Domain registration or renewal
-- "junk": Spam, promotional email, or irrelevant content
-
-CONFIDENCE: Return a value from 0.0 to 1.0 indicating how confident you are.
-
-EXTRACTION RULES:
-- For subscriptions: Extract vendor name, cost, currency, billing cycle (monthly/yearly/one-time), next billing date, and category
-- For domains: Extract domain name, registrar, cost, currency, registration date, and expiry date
-- For junk: Only return type and confidence, no data field
-- All dates should be in ISO format (YYYY-MM-DD)
-- Currency codes should be 3-letter ISO codes (USD, EUR, GBP, etc.)
-
-Return ONLY valid JSON matching this structure:
-{{
-  "type": "subscription" | "domain" | "junk",
-  "confidence": 0.0-1.0,
-  "data": {{
-    // For subscriptions:
-    "name": "string",
-    "cost": number,
-    "currency": "string",
-    "billingCycle": "monthly" | "yearly" | "one-time",
-    "nextBillingDate": "YYYY-MM-DD" (optional),
-    "category": "string" (optional)
-    
-    // For domains:
-    "domainName": "string",
-    "registrar": "string" (optional),
-    "cost": number (optional),
-    "currency": "string" (optional),
-    "registrationDate": "YYYY-MM-DD" (optional),
-    "expiryDate": "YYYY-MM-DD",
-    "autoRenew": boolean (optional)
-  }}
-}}
-
-RECEIPT CONTENT:
-{}
-"#,
-            markdown_content
-        );
-
-        let response = self.generate(prompt).await?;
-        
-        // Try to extract JSON from the response (in case the LLM adds extra text)
-        let json_start = response.find('{').ok_or_else(|| crate::utils::AppError::Internal("No JSON found in Ollama response".to_string()))?;
-        let json_end = response.rfind('}').ok_or_else(|| crate::utils::AppError::Internal("No JSON found in Ollama response".to_string()))?;
-        let json_str = &response[json_start..=json_end];
-
-        let result: LlmExtractionResult = serde_json::from_str(json_str)
-            .map_err(|e| crate::utils::AppError::Internal(format!("Failed to parse LLM JSON: {}", e)))?;
-
-        Ok(result)
+        Ok(result.models.into_iter().map(|m| m.name).collect())
     }
 }
@@ -0,0 +1,161 @@
+// OpenAI-compatible provider
+// Chat completions for LM Studio, vLLM, llama.cpp's server and other servers with the OpenAI API
+
+use crate::services::llm::{self, Completion, LlmProvider};
+use crate::utils::{AppError, AppResult};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+pub struct OpenAiService {
+    endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+impl OpenAiService {
+    /// `endpoint` is the server's base URL, with or without `/v1`
+    pub fn new(endpoint: String) -> Self {
+        Self { endpoint }
+    }
+
+    fn url(&self, path: &str) -> String {
+        let base = self.endpoint.trim_end_matches('/');
+        let base = base.strip_suffix("/v1").unwrap_or(base);
+        format!("{}/v1/{}", base, path)
+    }
+
+    fn request_body(completion: Completion) -> Value {
+        let content = if completion.images.is_empty() {
+            json!(completion.prompt)
+        } else {
+            let mut parts = vec![json!({ "type": "text", "text": completion.prompt })];
+            for image in completion.images {
+                // PNGs start with "\x89PNG", which is "iVBOR" in base64
+                let mime = if image.starts_with("iVBOR") { "image/png" } else { "image/jpeg" };
+                parts.push(json!({
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", mime, image) }
+                }));
+            }
+            Value::Array(parts)
+        };
+
+        let mut body = json!({
+            "model": completion.model,
+            "messages": [{ "role": "user", "content": content }],
+            "temperature": llm::TEMPERATURE,
+            "seed": llm::SEED,
+            "stream": false,
+        });
+        if let Some(schema) = completion.format {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "answer", "schema": schema }
+            });
+        }
+        body
+    }
+}
+
+impl LlmProvider for OpenAiService {
+    async fn complete(&self, completion: Completion) -> AppResult<String> {
+        let response = reqwest::Client::new()
+            .post(self.url("chat/completions"))
+            .json(&Self::request_body(completion))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("LLM request failed: {}", e)))?;
+
+        let result: ChatResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse LLM response: {}", e)))?;
+
+        result
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AppError::Internal("The LLM returned no answer".to_string()))
+    }
+
+    async fn list_models(&self) -> AppResult<Vec<String>> {
+        let response = reqwest::Client::new()
+            .get(self.url("models"))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("LLM request failed: {}", e)))?;
+
+        let result: ModelsResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse LLM response: {}", e)))?;
+
+        Ok(result.data.into_iter().map(|model| model.id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls_with_or_without_v1() {
+        for endpoint in ["http://localhost:8080", "http://localhost:8080/", "http://localhost:8080/v1", "http://localhost:8080/v1/"] {
+            let service = OpenAiService::new(endpoint.to_string());
+            assert_eq!(service.url("chat/completions"), "http://localhost:8080/v1/chat/completions");
+        }
+    }
+
+    #[test]
+    fn test_request_body() {
+        let body = OpenAiService::request_body(Completion {
+            model: "qwen2.5-7b-instruct".to_string(),
+            prompt: "Read this".to_string(),
+            images: Vec::new(),
+            format: Some(json!({ "type": "object" })),
+        });
+        assert_eq!(body["messages"][0]["content"], "Read this");
+        assert_eq!(body["temperature"], 0.0);
+        assert_eq!(body["seed"], 42);
+        assert_eq!(body["response_format"]["json_schema"]["schema"], json!({ "type": "object" }));
+
+        let body = OpenAiService::request_body(Completion {
+            model: "llava".to_string(),
+            prompt: "Transcribe".to_string(),
+            images: vec!["iVBORw0KGgo=".to_string(), "/9j/4AAQ".to_string()],
+            format: None,
+        });
+        let content = &body["messages"][0]["content"];
+        assert_eq!(content[0]["text"], "Transcribe");
+        assert_eq!(content[1]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(content[2]["image_url"]["url"], "data:image/jpeg;base64,/9j/4AAQ");
+        assert!(body.get("response_format").is_none());
+    }
+}
@@ -0,0 +1,187 @@
+// Sync pipeline stages
+// Where mail comes from, how documents become markdown and how receipts are read from it
+
+use crate::models::{EmailContent, MailboxCursor, MessageAction};
+use crate::services::converter::NativeConverter;
+use crate::services::imap::ImapService;
+use crate::services::markitdown::MarkItDownService;
+use crate::services::rules::RuleExtractor;
+use crate::services::llm::{Extractor, LlmExtractionResult, LlmExtractor, LlmProvider, OcrText};
+use crate::utils::{AppError, AppResult};
+use futures::channel::mpsc;
+use std::future::Future;
+
+/// A parsed message together with its UID
+pub struct FetchedEmail {
+    pub uid: u32,
+    pub email: EmailContent,
+}
+
+/// New messages found in a mailbox, downloaded on demand by `MailSource::download`
+pub struct NewMail<S> {
+    /// Whatever the source needs to download them, e.g. an open session
+    pub session: S,
+    pub mailbox: String,
+    pub uid_validity: u32,
+    /// Highest UID covered once every message in `uids` is processed
+    pub last_uid: u32,
+    /// Oldest first
+    pub uids: Vec<u32>,
+}
+
+/// A mail server's folders, addressed by UID
+pub trait MailSource: Send + Sync {
+    type Session: Send;
+
+    /// Log in, so bad credentials fail before any folder is touched
+    fn test_connection(&self) -> impl Future<Output = AppResult<()>> + Send;
+
+    /// Messages that arrived in `mailbox` after `cursor`
+    fn find_new_emails(
+        &self,
+        mailbox: &str,
+        cursor: Option<&MailboxCursor>,
+    ) -> impl Future<Output = AppResult<NewMail<Self::Session>>> + Send;
+
+    /// Send the messages into `tx`, oldest first, skipping any that can't be
+    /// read. A full channel pauses the download; a closed one ends it.
+    fn download(
+        &self,
+        new_mail: NewMail<Self::Session>,
+        tx: mpsc::Sender<FetchedEmail>,
+    ) -> impl Future<Output = AppResult<()>> + Send;
+
+    /// One message again; NotFound once `uid` no longer names it
+    fn fetch_message(
+        &self,
+        mailbox: &str,
+        uid_validity: u32,
+        uid: u32,
+    ) -> impl Future<Output = AppResult<EmailContent>> + Send;
+
+    fn apply_action(
+        &self,
+        mailbox: &str,
+        uids: &[u32],
+        action: &MessageAction,
+    ) -> impl Future<Output = AppResult<()>> + Send;
+}
+
+/// Turns attachments and email bodies into markdown. Conversion blocks, so
+/// it runs on a blocking thread.
+pub trait DocumentConverter: Clone + Send + Sync + 'static {
+    /// Make sure the converter can run
+    fn ensure_ready(&self) -> AppResult<()>;
+
+    /// Convert `data`, a file with the given extension (e.g. ".pdf")
+    fn convert(&self, data: &[u8], extension: &str) -> AppResult<String>;
+}
+
+/// Classifies a document and pulls the billing details out of it
+pub trait ReceiptExtractor: Send + Sync {
+    fn extract_receipt_data(&self, markdown: &str) -> impl Future<Output = AppResult<LlmExtractionResult>> + Send;
+
+    /// Whether `recognize_text` can be used
+    fn reads_images(&self) -> bool {
+        false
+    }
+
+    /// OCR: the text in a JPEG or PNG image
+    fn recognize_text(&self, _image: &[u8]) -> impl Future<Output = AppResult<OcrText>> + Send {
+        async { Err(AppError::Validation("Text recognition is not supported".to_string())) }
+    }
+}
+
+impl MailSource for ImapService {
+    type Session = async_imap::Session<crate::services::imap_connection::ImapStream>;
+
+    async fn test_connection(&self) -> AppResult<()> {
+        ImapService::test_connection(self).await
+    }
+
+    async fn find_new_emails(&self, mailbox: &str, cursor: Option<&MailboxCursor>) -> AppResult<NewMail<Self::Session>> {
+        ImapService::find_new_emails(self, mailbox, cursor).await
+    }
+
+    async fn download(&self, new_mail: NewMail<Self::Session>, tx: mpsc::Sender<FetchedEmail>) -> AppResult<()> {
+        ImapService::download(self, new_mail, tx).await
+    }
+
+    async fn fetch_message(&self, mailbox: &str, uid_validity: u32, uid: u32) -> AppResult<EmailContent> {
+        ImapService::fetch_message(self, mailbox, uid_validity, uid).await
+    }
+
+    async fn apply_action(&self, mailbox: &str, uids: &[u32], action: &MessageAction) -> AppResult<()> {
+        ImapService::apply_action(self, mailbox, uids, action).await
+    }
+}
+
+impl DocumentConverter for NativeConverter {
+    fn ensure_ready(&self) -> AppResult<()> {
+        Ok(())
+    }
+
+    fn convert(&self, data: &[u8], extension: &str) -> AppResult<String> {
+        NativeConverter::convert(self, data, extension)
+    }
+}
+
+impl DocumentConverter for MarkItDownService {
+    fn ensure_ready(&self) -> AppResult<()> {
+        if MarkItDownService::is_installed() {
+            Ok(())
+        } else {
+            Err(AppError::Validation("MarkItDown is not installed (pip install markitdown)".to_string()))
+        }
+    }
+
+    fn convert(&self, data: &[u8], extension: &str) -> AppResult<String> {
+        MarkItDownService::convert_data_to_markdown(data, extension)
+    }
+}
+
+impl<P: LlmProvider> ReceiptExtractor for LlmExtractor<P> {
+    async fn extract_receipt_data(&self, markdown: &str) -> AppResult<LlmExtractionResult> {
+        LlmExtractor::extract_receipt_data(self, markdown).await
+    }
+
+    fn reads_images(&self) -> bool {
+        LlmExtractor::reads_images(self)
+    }
+
+    async fn recognize_text(&self, image: &[u8]) -> AppResult<OcrText> {
+        LlmExtractor::recognize_text(self, image).await
+    }
+}
+
+impl ReceiptExtractor for RuleExtractor {
+    async fn extract_receipt_data(&self, markdown: &str) -> AppResult<LlmExtractionResult> {
+        RuleExtractor::extract(self, markdown)
+    }
+}
+
+impl ReceiptExtractor for Extractor {
+    async fn extract_receipt_data(&self, markdown: &str) -> AppResult<LlmExtractionResult> {
+        match self {
+            Extractor::Ollama(extractor) => extractor.extract_receipt_data(markdown).await,
+            Extractor::OpenAi(extractor) => extractor.extract_receipt_data(markdown).await,
+            Extractor::Rules(extractor) => extractor.extract(markdown),
+        }
+    }
+
+    fn reads_images(&self) -> bool {
+        match self {
+            Extractor::Ollama(extractor) => extractor.reads_images(),
+            Extractor::OpenAi(extractor) => extractor.reads_images(),
+            Extractor::Rules(_) => false,
+        }
+    }
+
+    async fn recognize_text(&self, image: &[u8]) -> AppResult<OcrText> {
+        match self {
+            Extractor::Ollama(extractor) => extractor.recognize_text(image).await,
+            Extractor::OpenAi(extractor) => extractor.recognize_text(image).await,
+            Extractor::Rules(_) => Err(AppError::Validation("Text recognition needs a model".to_string())),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+// Rule-based extraction
+// Reads receipts with fixed patterns instead of a model, for machines that can't run one
+
+use crate::services::llm::LlmExtractionResult;
+use crate::utils::{AppError, AppResult};
+use chrono::NaiveDate;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::sync::OnceLock;
+
+/// Rules can't tell how sure they are, so everything they find is worth a look
+const CONFIDENCE: f64 = 0.6;
+
+const CURRENCY_CODES: &str = "USD|EUR|GBP|CAD|AUD|NZD|CHF|JPY|SEK|NOK|DKK|PLN|CZK|INR|BRL|MXN";
+const MONTHS: &str = "jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec";
+/// Lines whose amount is the one charged
+const TOTAL_WORDS: &[&str] = &["total", "amount", "charged", "paid", "price"];
+const EXPIRY_WORDS: &[&str] = &["expir", "valid until", "renewal date", "renews on", "renew by", "paid until"];
+const NEXT_WORDS: &[&str] = &["next", "renew", "due"];
+const MONTHLY_WORDS: &[&str] = &["per month", "/month", "/mo", "monthly", "a month", "every month"];
+const YEARLY_WORDS: &[&str] = &["per year", "/year", "/yr", "yearly", "annual", "every year", "12 months"];
+const MARKETING_WORDS: &[&str] = &["unsubscribe", "newsletter", "% off", "sale", "promo", "offer", "deal"];
+const REGISTRARS: &[&str] = &[
+    "Namecheap", "GoDaddy", "Gandi", "Porkbun", "Cloudflare", "Squarespace", "Hover", "Name.com", "IONOS", "OVHcloud",
+    "Dynadot", "Hostinger", "Network Solutions", "NameSilo", "Tucows",
+];
+/// Capitalized words that say what a message is rather than who sent it
+const GENERIC_NAMES: &[&str] = &[
+    "Order", "Receipt", "Invoice", "Payment", "Subscription", "Account", "Plan", "Bill", "Domain", "Purchase",
+];
+/// Lines that head a receipt without naming the vendor
+const GENERIC_LINES: &[&str] = &["receipt", "invoice", "order confirmation", "payment", "hello", "hi", "dear", "body"];
+const FILE_EXTENSIONS: &[&str] = &["pdf", "html", "htm", "jpg", "jpeg", "png", "txt", "csv"];
+
+struct Patterns {
+    /// "$15.99", "EUR 15,99"
+    amount_before: Regex,
+    /// "15.99 USD", "15,99 €"
+    amount_after: Regex,
+    iso_date: Regex,
+    /// "1 February 2025"
+    day_month_year: Regex,
+    /// "February 1, 2025"
+    month_day_year: Regex,
+    domain: Regex,
+    /// "Your Netflix receipt", "receipt from Apple"
+    vendor: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let number = r"(\d[\d.,]*\d|\d)";
+        Patterns {
+            amount_before: Regex::new(&format!(r"(?:(US\$|CA\$|A\$|[$€£¥])|\b({})\b)\s?{}", CURRENCY_CODES, number)).unwrap(),
+            amount_after: Regex::new(&format!(r"{}\s?(?:([$€£¥])|({})\b)", number, CURRENCY_CODES)).unwrap(),
+            iso_date: Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap(),
+            day_month_year: Regex::new(&format!(r"(?i)\b(\d{{1,2}})(?:st|nd|rd|th)?\s+({})[a-z]*\.?,?\s+(\d{{4}})\b", MONTHS))
+                .unwrap(),
+            month_day_year: Regex::new(&format!(r"(?i)\b({})[a-z]*\.?\s+(\d{{1,2}})(?:st|nd|rd|th)?,?\s+(\d{{4}})\b", MONTHS))
+                .unwrap(),
+            domain: Regex::new(r"(?i)(^|[^@\w.-])((?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,24})\b").unwrap(),
+            vendor: Regex::new(
+                r"(?:[Yy]our|[Tt]hanks for using|[Tt]hank you for (?:subscribing to|choosing|using)|[Ss]ubscription to|(?:[Rr]eceipt|[Ii]nvoice|[Oo]rder) from|[Pp]ayment to|[Ww]elcome to)\s+([A-Z][\w&+'.-]*(?:\s+[A-Z][\w&+'.-]*){0,2})",
+            )
+            .unwrap(),
+        }
+    })
+}
+
+/// Extracts receipts from text alone
+pub struct RuleExtractor;
+
+impl RuleExtractor {
+    /// A domain when a domain name and its expiry are found, a subscription
+    /// when an amount and a vendor are, and junk when a marketing message has
+    /// no amount. Anything else fails, to be retried once a model is set up.
+    pub fn extract(&self, markdown: &str) -> AppResult<LlmExtractionResult> {
+        let lines: Vec<&str> = markdown.lines().collect();
+        let text = markdown.to_lowercase();
+        let amount = amount(&lines);
+
+        if text.contains("domain") {
+            if let (Some(domain), Some(expiry)) = (domain_name(&lines), dated(&lines, EXPIRY_WORDS)) {
+                let mut data = Map::new();
+                data.insert("domainName".to_string(), json!(domain));
+                data.insert("expiryDate".to_string(), json!(expiry.to_string()));
+                if let Some(registrar) = REGISTRARS.iter().find(|r| text.contains(&r.to_lowercase())) {
+                    data.insert("registrar".to_string(), json!(registrar));
+                }
+                if let Some((cost, currency)) = amount {
+                    data.insert("cost".to_string(), json!(cost));
+                    data.insert("currency".to_string(), json!(currency));
+                }
+                if let Some(auto_renew) = auto_renew(&text) {
+                    data.insert("autoRenew".to_string(), json!(auto_renew));
+                }
+                return Ok(result("domain", Value::Object(data)));
+            }
+        }
+
+        match (amount, vendor(markdown, &lines)) {
+            (Some((cost, currency)), Some(name)) => {
+                let mut data = Map::new();
+                data.insert("name".to_string(), json!(name));
+                data.insert("cost".to_string(), json!(cost));
+                data.insert("currency".to_string(), json!(currency));
+                data.insert("billingCycle".to_string(), json!(billing_cycle(&text)));
+                if let Some(next) = dated(&lines, NEXT_WORDS) {
+                    data.insert("nextBillingDate".to_string(), json!(next.to_string()));
+                }
+                Ok(result("subscription", Value::Object(data)))
+            }
+            (None, _) if MARKETING_WORDS.iter().any(|word| text.contains(word)) => Ok(result("junk", json!({}))),
+            _ => Err(AppError::Validation("No billing details found by the extraction rules".to_string())),
+        }
+    }
+}
+
+fn result(classification: &str, data: Value) -> LlmExtractionResult {
+    LlmExtractionResult { classification: classification.to_string(), confidence: CONFIDENCE, data }
+}
+
+/// The amount on the first line about a total, or else the first amount
+fn amount(lines: &[&str]) -> Option<(f64, &'static str)> {
+    let amounts: Vec<(usize, f64, &'static str)> = lines
+        .iter()
+        .enumerate()
+        .flat_map(|(index, line)| amounts_in(line).into_iter().map(move |(cost, currency)| (index, cost, currency)))
+        .filter(|(_, cost, _)| *cost > 0.0)
+        .collect();
+
+    let is_total = |index: usize| {
+        let line = lines[index].to_lowercase();
+        !line.contains("subtotal") && TOTAL_WORDS.iter().any(|word| line.contains(word))
+    };
+    amounts
+        .iter()
+        .find(|(index, _, _)| is_total(*index))
+        .or(amounts.first())
+        .map(|&(_, cost, currency)| (cost, currency))
+}
+
+fn amounts_in(line: &str) -> Vec<(f64, &'static str)> {
+    let patterns = patterns();
+    let before = patterns.amount_before.captures_iter(line).map(|c| (c.get(3), c.get(1).or(c.get(2))));
+    let after = patterns.amount_after.captures_iter(line).map(|c| (c.get(1), c.get(2).or(c.get(3))));
+    before
+        .chain(after)
+        .filter_map(|(number, currency)| {
+            let currency = currency_code(currency?.as_str())?;
+            Some((parse_number(number?.as_str())?, currency))
+        })
+        .collect()
+}
+
+fn currency_code(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        "$" | "US$" => Some("USD"),
+        "CA$" => Some("CAD"),
+        "A$" => Some("AUD"),
+        "€" => Some("EUR"),
+        "£" => Some("GBP"),
+        "¥" => Some("JPY"),
+        code => CURRENCY_CODES.split('|').find(|c| *c == code),
+    }
+}
+
+/// "1,234.56", "1.234,56" and "15,99" alike: a last separator followed by
+/// one or two digits is the decimal point
+fn parse_number(number: &str) -> Option<f64> {
+    let (whole, fraction) = match number.rfind(['.', ',']) {
+        Some(i) if (1..=2).contains(&(number.len() - i - 1)) => (&number[..i], &number[i + 1..]),
+        _ => (number, "0"),
+    };
+    let whole: String = whole.chars().filter(char::is_ascii_digit).collect();
+    format!("{}.{}", whole, fraction).parse().ok()
+}
+
+fn dates_in(line: &str) -> Vec<NaiveDate> {
+    let patterns = patterns();
+    let month = |name: &str| MONTHS.split('|').position(|m| name.to_lowercase().starts_with(m)).map(|i| i as u32 + 1);
+    let number = |m: Option<regex::Match>| m.and_then(|m| m.as_str().parse::<u32>().ok());
+
+    let iso = patterns.iso_date.captures_iter(line).filter_map(|c| {
+        NaiveDate::from_ymd_opt(number(c.get(1))? as i32, number(c.get(2))?, number(c.get(3))?)
+    });
+    let day_first = patterns.day_month_year.captures_iter(line).filter_map(|c| {
+        NaiveDate::from_ymd_opt(number(c.get(3))? as i32, month(c.get(2)?.as_str())?, number(c.get(1))?)
+    });
+    let month_first = patterns.month_day_year.captures_iter(line).filter_map(|c| {
+        NaiveDate::from_ymd_opt(number(c.get(3))? as i32, month(c.get(1)?.as_str())?, number(c.get(2))?)
+    });
+    iso.chain(day_first).chain(month_first).collect()
+}
+
+/// The first date on a line, or just below a line, with one of `words`
+fn dated(lines: &[&str], words: &[&str]) -> Option<NaiveDate> {
+    let mentions = |index: usize| {
+        let line = lines[index].to_lowercase();
+        words.iter().any(|word| line.contains(word))
+    };
+    (0..lines.len())
+        .filter(|&index| mentions(index) || (index > 0 && mentions(index - 1)))
+        .find_map(|index| dates_in(lines[index]).into_iter().next())
+}
+
+/// The first domain name on a line about the domain, leaving out email
+/// addresses and attachment names
+fn domain_name(lines: &[&str]) -> Option<String> {
+    lines
+        .iter()
+        .filter(|line| {
+            let line = line.to_lowercase();
+            ["domain", "registration", "renew", "expir"].iter().any(|word| line.contains(word))
+        })
+        .flat_map(|line| patterns().domain.captures_iter(line).filter_map(|c| c.get(2)))
+        .map(|m| m.as_str().to_lowercase())
+        .map(|domain| domain.strip_prefix("www.").map(str::to_string).unwrap_or(domain))
+        .find(|domain| {
+            let tld = domain.rsplit('.').next().unwrap_or_default();
+            !FILE_EXTENSIONS.contains(&tld) && !tld.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+fn auto_renew(text: &str) -> Option<bool> {
+    if ["auto-renew is off", "auto-renewal is off", "auto-renew is disabled", "auto-renewal is disabled", "will not renew"]
+        .iter()
+        .any(|phrase| text.contains(phrase))
+    {
+        Some(false)
+    } else if ["auto-renew", "automatically renew", "auto renew"].iter().any(|phrase| text.contains(phrase)) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Whichever cycle is mentioned first; a one-off purchase mentions neither
+fn billing_cycle(text: &str) -> &'static str {
+    let first = |words: &[&str]| words.iter().filter_map(|word| text.find(word)).min();
+    match (first(MONTHLY_WORDS), first(YEARLY_WORDS)) {
+        (Some(monthly), Some(yearly)) if yearly < monthly => "yearly",
+        (Some(_), _) => "monthly",
+        (None, Some(_)) => "yearly",
+        (None, None) => "one-time",
+    }
+}
+
+/// Who was paid: a name after "Your" or "receipt from", or else the words
+/// that start the first line naming something
+fn vendor(markdown: &str, lines: &[&str]) -> Option<String> {
+    let named = patterns().vendor.captures_iter(markdown).filter_map(|c| c.get(1)).find_map(|m| {
+        let words: Vec<&str> = m
+            .as_str()
+            .split_whitespace()
+            .take_while(|word| !GENERIC_NAMES.contains(&word.trim_end_matches(['.', ','])))
+            .collect();
+        (!words.is_empty()).then(|| words.join(" ").trim_end_matches(['.', ',']).to_string())
+    });
+    named.or_else(|| lines.iter().find_map(|line| leading_name(line)))
+}
+
+fn leading_name(line: &str) -> Option<String> {
+    let line = line.trim_start_matches(['#', '*', '_', '>', '|', ' ', '-']).trim();
+    let lowered = line.to_lowercase();
+    // Section headings of combined parts name a file, not the vendor
+    let is_file = lowered.rsplit_once('.').is_some_and(|(_, ext)| FILE_EXTENSIONS.contains(&ext));
+    if line.is_empty() || is_file || lowered.starts_with("attachment ") {
+        return None;
+    }
+
+    let end = line.find(|c: char| c.is_ascii_digit() || "$€£¥:|,!(".contains(c)).unwrap_or(line.len());
+    let words: Vec<&str> = line[..end]
+        .split_whitespace()
+        .map(|word| word.trim_matches(['*', '_']))
+        .filter(|word| !word.is_empty())
+        .take(4)
+        .collect();
+    let name = words.join(" ");
+    let generic = GENERIC_LINES.iter().any(|generic| name.to_lowercase().starts_with(generic));
+    (name.chars().any(char::is_alphabetic) && !generic).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::extraction_schema;
+
+    fn extract(markdown: &str) -> LlmExtractionResult {
+        let result = RuleExtractor.extract(markdown).unwrap();
+        assert_eq!(extraction_schema::validate(&result), Ok(()), "{:?}", result);
+        result
+    }
+
+    #[test]
+    fn test_reads_subscription_receipts() {
+        let result = extract(
+            "# Your Spotify Premium receipt\n\nSubtotal: $9.17\nTotal charged: $9.99 per month\n\
+             Your next payment is due on February 1, 2024.",
+        );
+        assert_eq!(result.classification, "subscription");
+        assert_eq!(
+            result.data,
+            json!({ "name": "Spotify Premium", "cost": 9.99, "currency": "USD", "billingCycle": "monthly", "nextBillingDate": "2024-02-01" })
+        );
+
+        let result = extract("## invoice.pdf\n\nAcme Cloud GmbH\nRechnung 1042\nGesamtbetrag 1.234,50 EUR, annual plan");
+        assert_eq!(result.data["name"], "Acme Cloud GmbH");
+        assert_eq!(result.data["cost"], 1234.5);
+        assert_eq!(result.data["currency"], "EUR");
+        assert_eq!(result.data["billingCycle"], "yearly");
+
+        // OCR output of a photographed receipt
+        let result = extract("NETFLIX Premium 15.99 USD");
+        assert_eq!(result.data["name"], "NETFLIX Premium");
+        assert_eq!(result.data["billingCycle"], "one-time");
+    }
+
+    #[test]
+    fn test_reads_domain_renewals() {
+        let result = extract(
+            "Namecheap\n\nYour domain example.com has been renewed.\nNew expiry date: 12 March 2026\n\
+             Amount paid: $10.98\nAuto-renew is off.\nQuestions? support@namecheap.com",
+        );
+        assert_eq!(result.classification, "domain");
+        assert_eq!(
+            result.data,
+            json!({
+                "domainName": "example.com",
+                "expiryDate": "2026-03-12",
+                "registrar": "Namecheap",
+                "cost": 10.98,
+                "currency": "USD",
+                "autoRenew": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_junk_and_unreadable_messages() {
+        assert_eq!(extract("Spring sale! Everything 20% off this week. Unsubscribe").classification, "junk");
+        assert!(matches!(
+            RuleExtractor.extract("Thanks for reaching out, we'll get back to you soon."),
+            Err(AppError::Validation(_))
+        ));
+    }
+}
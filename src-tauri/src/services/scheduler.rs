@@ -0,0 +1,529 @@
+// Background sync scheduling
+// Syncs on the configured interval and, with push sync on, as soon as IMAP IDLE reports new mail
+
+use crate::commands::settings::load_settings;
+use crate::db::repo::EmailAccountRepo;
+use crate::db::{DatabaseType, DbState};
+use crate::models::{EmailAccount, ImportSummary, Receipt, ReprocessSummary, SchedulerStatus, SyncLogItem};
+use crate::services::imap::{IdleEvent, IdleOutcome, ImapService};
+use crate::services::sync::{CancelToken, ProgressSink, SyncOutcome, SyncRun, SyncService};
+use crate::utils::{AppError, AppResult};
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tauri::async_runtime::{self, JoinHandle};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Used when the settings cannot be read
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Exponential delay between reconnect attempts
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(5);
+    const MAX: Duration = Duration::from_secs(5 * 60);
+
+    fn new() -> Self {
+        Self { next: Self::INITIAL }
+    }
+
+    fn delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(Self::MAX);
+        delay
+    }
+}
+
+#[derive(Debug, Default)]
+struct Schedule {
+    paused: bool,
+    next_run_at: Option<DateTime<Utc>>,
+}
+
+/// The IDLE watchers, one per folder, and the accounts they were started for
+#[derive(Default)]
+struct Watchers {
+    accounts: Vec<EmailAccount>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+struct Inner {
+    db: DbState,
+    schedule: Mutex<Schedule>,
+    /// Run now: "sync now", or a watcher saw new mail. A wake during a sync
+    /// is kept and runs one more sync after it.
+    wake: Notify,
+    /// Settings changed or the scheduler was paused or resumed
+    reschedule: Notify,
+    /// Held for the whole of every sync, scheduled or manual, and every
+    /// import, retry or reprocessing
+    running: tokio::sync::Mutex<()>,
+    /// Syncs waiting for their turn or running; "sync now" has nothing to add to them
+    syncs: AtomicUsize,
+    /// Cancels the sync, import, retry or reprocessing in progress, if any
+    current: Mutex<Option<CancelToken>>,
+    /// Kept running across syncs so no new mail slips by between them
+    watchers: Mutex<Watchers>,
+    /// Where every sync reports its progress; set once the app is up
+    progress: OnceLock<ProgressSink>,
+}
+
+/// Runs sync in the background, shared through Tauri state
+#[derive(Clone)]
+pub struct SyncScheduler {
+    inner: Arc<Inner>,
+}
+
+impl SyncScheduler {
+    pub fn new(db: DbState) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                db,
+                schedule: Mutex::new(Schedule::default()),
+                wake: Notify::new(),
+                reschedule: Notify::new(),
+                running: tokio::sync::Mutex::new(()),
+                syncs: AtomicUsize::new(0),
+                current: Mutex::new(None),
+                watchers: Mutex::new(Watchers::default()),
+                progress: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Start the scheduling loop; the first sync runs right away.
+    /// Every sync from then on, scheduled or manual, reports to `progress`.
+    pub fn start(&self, progress: ProgressSink) {
+        let _ = self.inner.progress.set(progress);
+        let scheduler = self.clone();
+        async_runtime::spawn(async move { scheduler.run().await });
+    }
+
+    /// Sync now, waiting for a sync already in progress to finish first
+    pub async fn run_exclusive(&self, test_mode: bool) -> AppResult<SyncOutcome> {
+        self.inner.syncs.fetch_add(1, Ordering::SeqCst);
+        let db = &self.inner.db;
+        let result = self
+            .exclusive(self.inner.progress.get().cloned(), |run| async move {
+                SyncService::run_sync(db, test_mode, &run).await
+            })
+            .await;
+        self.inner.syncs.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    /// Import local mail, taking its turn like a sync: it never overlaps one
+    /// and `cancel` stops it
+    pub async fn import_local(&self, test_mode: bool, path: PathBuf, progress: ProgressSink) -> AppResult<ImportSummary> {
+        let db = &self.inner.db;
+        self.exclusive(Some(progress), |run| async move {
+            SyncService::import_local(db, test_mode, path, &run).await
+        })
+        .await
+    }
+
+    /// Process a failed message of an earlier sync again, taking its turn like
+    /// a sync; returns the updated item
+    pub async fn retry_item(&self, test_mode: bool, item_id: i64) -> AppResult<SyncLogItem> {
+        let db = &self.inner.db;
+        self.exclusive(None, |run| async move { SyncService::retry_item(db, test_mode, item_id, &run).await })
+            .await
+    }
+
+    /// Extract a stored receipt again, taking its turn like a sync
+    pub async fn reprocess_receipt(&self, test_mode: bool, receipt_id: i64) -> AppResult<Receipt> {
+        let db = &self.inner.db;
+        self.exclusive(None, |run| async move {
+            SyncService::reprocess_receipt(db, test_mode, receipt_id, &run).await
+        })
+        .await
+    }
+
+    /// Extract every receipt whose extraction failed again, taking its turn
+    /// like a sync
+    pub async fn reprocess_failed_receipts(&self, test_mode: bool) -> AppResult<ReprocessSummary> {
+        let db = &self.inner.db;
+        self.exclusive(None, |run| async move {
+            SyncService::reprocess_failed_receipts(db, test_mode, &run).await
+        })
+        .await
+    }
+
+    /// Hold `running` for the whole of `work`, with a token `cancel` reaches
+    async fn exclusive<T, F, Fut>(&self, progress: Option<ProgressSink>, work: F) -> AppResult<T>
+    where
+        F: FnOnce(SyncRun) -> Fut,
+        Fut: Future<Output = AppResult<T>>,
+    {
+        let _running = self.inner.running.lock().await;
+        let run = SyncRun { progress, cancel: CancelToken::new() };
+        *self.inner.current.lock().unwrap() = Some(run.cancel.clone());
+
+        let result = work(run).await;
+        *self.inner.current.lock().unwrap() = None;
+        result
+    }
+
+    /// Stop the sync, import, retry or reprocessing in progress. Messages it has not finished with
+    /// are left for the next run; a sync waiting for its turn still runs.
+    pub fn cancel(&self) {
+        if let Some(token) = self.inner.current.lock().unwrap().as_ref() {
+            token.cancel();
+        }
+    }
+
+    /// Run the scheduled sync now instead of at its time. Does nothing while
+    /// a sync is waiting or running, as that one already fetches everything.
+    pub fn sync_now(&self) {
+        if self.inner.syncs.load(Ordering::SeqCst) == 0 {
+            self.inner.wake.notify_one();
+        }
+    }
+
+    /// A watcher saw new mail. Unlike "sync now" this is kept through a
+    /// running sync, which may have searched that folder already.
+    fn new_mail(&self) {
+        self.inner.wake.notify_one();
+    }
+
+    /// Pick up changed settings
+    pub fn reschedule(&self) {
+        self.inner.reschedule.notify_one();
+    }
+
+    pub fn pause(&self) {
+        self.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.set_paused(false);
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let mut schedule = self.inner.schedule.lock().unwrap();
+        schedule.paused = paused;
+        if paused {
+            schedule.next_run_at = None;
+        }
+        drop(schedule);
+        self.reschedule();
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        let schedule = self.inner.schedule.lock().unwrap();
+        SchedulerStatus {
+            paused: schedule.paused,
+            running: self.inner.running.try_lock().is_err(),
+            next_run_at: schedule.next_run_at.map(|at| at.to_rfc3339()),
+        }
+    }
+
+    async fn run(&self) {
+        // Watch from the start so mail arriving during the first sync is caught
+        self.refresh_watchers().await;
+        loop {
+            let started = Instant::now();
+            if let Err(e) = self.run_exclusive(false).await {
+                eprintln!("Background sync error: {}", e);
+            }
+            self.wait_for_next_run(started).await;
+        }
+    }
+
+    /// Sleep until the interval since `last_run` is up or something asks for a
+    /// sync. Changed settings move the deadline without restarting the clock.
+    async fn wait_for_next_run(&self, last_run: Instant) {
+        loop {
+            let interval = self.refresh_watchers().await;
+            let paused = self.inner.schedule.lock().unwrap().paused;
+            let deadline = (!paused).then(|| last_run + interval);
+
+            self.inner.schedule.lock().unwrap().next_run_at = deadline.map(|at| {
+                let remaining = at.saturating_duration_since(Instant::now());
+                Utc::now() + chrono::Duration::from_std(remaining).unwrap_or_default()
+            });
+
+            let sleep = async {
+                match deadline {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            let due = tokio::select! {
+                _ = sleep => true,
+                _ = self.inner.wake.notified() => true,
+                _ = self.inner.reschedule.notified() => false,
+            };
+
+            if due {
+                self.inner.schedule.lock().unwrap().next_run_at = None;
+                return;
+            }
+        }
+    }
+
+    /// The polling interval, after matching the watchers to the settings: one
+    /// per folder of every enabled account while push sync is on and the
+    /// scheduler isn't paused. Watchers that still match keep running.
+    async fn refresh_watchers(&self) -> Duration {
+        let (interval, accounts) = match self.watched_accounts().await {
+            Ok(watched) => watched,
+            Err(e) => {
+                eprintln!("Failed to read sync settings: {}", e);
+                return DEFAULT_INTERVAL;
+            }
+        };
+
+        let mut watchers = self.inner.watchers.lock().unwrap();
+        if watchers.accounts != accounts {
+            for task in watchers.tasks.drain(..) {
+                task.abort();
+            }
+            for account in &accounts {
+                let client = Arc::new(AccountClient::new(account.clone()));
+                for mailbox in account.folders.iter().map(|f| f.trim()).filter(|f| !f.is_empty()) {
+                    let watch = watch_folder(client.clone(), mailbox.to_string(), self.clone());
+                    watchers.tasks.push(async_runtime::spawn(watch));
+                }
+            }
+            watchers.accounts = accounts;
+        }
+        interval
+    }
+
+    /// The polling interval and the accounts to watch with IDLE
+    async fn watched_accounts(&self) -> AppResult<(Duration, Vec<EmailAccount>)> {
+        let (settings, accounts) = self
+            .inner
+            .db
+            .run(DatabaseType::Production, |conn| {
+                Ok((load_settings(conn)?, EmailAccountRepo::new(conn).list_enabled()?))
+            })
+            .await?;
+
+        let interval = Duration::from_secs(settings.sync_interval_minutes.max(1) as u64 * 60);
+        if !settings.push_sync || self.inner.schedule.lock().unwrap().paused {
+            return Ok((interval, Vec::new()));
+        }
+
+        let accounts = accounts
+            .into_iter()
+            .filter(|account| SyncService::account_secret(DatabaseType::Production, account).is_some())
+            .filter(|account| account.folders.iter().any(|f| !f.trim().is_empty()))
+            .collect();
+        Ok((interval, accounts))
+    }
+}
+
+/// The IMAP client an account's watchers connect with. Shared by its
+/// folders, so an OAuth2 access token is refreshed once for all of them
+/// rather than raced for, and kept only while the token is surely current.
+struct AccountClient {
+    account: EmailAccount,
+    service: tokio::sync::Mutex<Option<(Arc<ImapService>, Instant)>>,
+}
+
+impl AccountClient {
+    /// Access tokens usually last an hour
+    const LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+    fn new(account: EmailAccount) -> Self {
+        Self { account, service: tokio::sync::Mutex::new(None) }
+    }
+
+    async fn get(&self) -> AppResult<Arc<ImapService>> {
+        let mut service = self.service.lock().await;
+        if let Some((current, built)) = service.as_ref() {
+            if built.elapsed() < Self::LIFETIME {
+                return Ok(current.clone());
+            }
+        }
+
+        let db_type = DatabaseType::Production;
+        let secret = SyncService::account_secret(db_type, &self.account)
+            .ok_or_else(|| AppError::Validation(format!("{} is not fully configured", self.account.name)))?;
+        let fresh = Arc::new(SyncService::imap_service(db_type, &self.account, secret).await?);
+        *service = Some((fresh.clone(), Instant::now()));
+        Ok(fresh)
+    }
+}
+
+/// Wake the scheduler on new mail in `mailbox`, reconnecting with backoff.
+/// Once back after a failure it wakes the scheduler too, for mail that came
+/// in while the connection was down. Returns when the server lacks IDLE,
+/// leaving the folder to polling.
+async fn watch_folder(client: Arc<AccountClient>, mailbox: String, scheduler: SyncScheduler) {
+    let account = &client.account;
+    let mut backoff = Backoff::new();
+    let mut reconnecting = false;
+
+    loop {
+        let watched = match client.get().await {
+            Ok(imap_service) => {
+                imap_service
+                    .watch(&mailbox, |event| match event {
+                        IdleEvent::Watching => {
+                            backoff = Backoff::new();
+                            if std::mem::take(&mut reconnecting) {
+                                scheduler.new_mail();
+                            }
+                        }
+                        IdleEvent::NewMail => scheduler.new_mail(),
+                    })
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match watched {
+            Ok(IdleOutcome::Unsupported) => {
+                eprintln!("{} does not support IDLE, polling {} instead", account.server, account.name);
+                return;
+            }
+            Err(e) => {
+                let delay = backoff.delay();
+                eprintln!("Watching {} of {} failed, retrying in {:?}: {}", mailbox, account.name, delay, e);
+                reconnecting = true;
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<u64> = (0..8).map(|_| backoff.delay().as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20, 40, 80, 160, 300, 300]);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_move_next_run() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        scheduler.start(Arc::new(|_| {}));
+
+        let status = wait_for(&scheduler, |s| s.next_run_at.is_some()).await;
+        assert!(!status.paused);
+
+        scheduler.pause();
+        let status = wait_for(&scheduler, |s| s.next_run_at.is_none()).await;
+        assert!(status.paused);
+
+        scheduler.resume();
+        wait_for(&scheduler, |s| !s.paused && s.next_run_at.is_some()).await;
+    }
+
+    #[tokio::test]
+    async fn test_manual_sync_waits_for_running_sync() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        let running = scheduler.inner.running.lock().await;
+        assert!(scheduler.status().running);
+
+        let manual = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.run_exclusive(true).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!manual.is_finished());
+
+        drop(running);
+        manual.await.unwrap().unwrap();
+        assert!(!scheduler.status().running);
+    }
+
+    #[tokio::test]
+    async fn test_import_waits_for_running_sync() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        let running = scheduler.inner.running.lock().await;
+
+        let import = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.import_local(true, PathBuf::from("/nonexistent/mail"), Arc::new(|_| {})).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!import.is_finished());
+
+        drop(running);
+        assert!(import.await.unwrap().is_err());
+        assert!(!scheduler.status().running);
+        assert!(scheduler.inner.current.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reprocessing_waits_for_running_sync() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        let running = scheduler.inner.running.lock().await;
+
+        let reprocess = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.reprocess_failed_receipts(true).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!reprocess.is_finished());
+
+        drop(running);
+        assert_eq!(reprocess.await.unwrap().unwrap(), ReprocessSummary::default());
+        assert!(scheduler.inner.current.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_sync_only() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        // Nothing running: nothing to cancel, and the next sync is unaffected
+        scheduler.cancel();
+        assert_eq!(scheduler.run_exclusive(true).await.unwrap(), SyncOutcome::Completed);
+
+        let token = CancelToken::new();
+        *scheduler.inner.current.lock().unwrap() = Some(token.clone());
+        scheduler.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_sync_now_adds_nothing_to_a_running_sync() {
+        let scheduler = SyncScheduler::new(DbState::in_memory());
+        let woken = |scheduler: &SyncScheduler| {
+            let scheduler = scheduler.clone();
+            async move {
+                tokio::time::timeout(Duration::from_millis(20), scheduler.inner.wake.notified()).await.is_ok()
+            }
+        };
+
+        scheduler.inner.syncs.fetch_add(1, Ordering::SeqCst);
+        scheduler.sync_now();
+        assert!(!woken(&scheduler).await);
+
+        // New mail may have missed the running sync, so it gets one more
+        scheduler.new_mail();
+        scheduler.new_mail();
+        assert!(woken(&scheduler).await);
+        assert!(!woken(&scheduler).await);
+
+        scheduler.inner.syncs.fetch_sub(1, Ordering::SeqCst);
+        scheduler.sync_now();
+        assert!(woken(&scheduler).await);
+    }
+
+    async fn wait_for(scheduler: &SyncScheduler, done: impl Fn(&SchedulerStatus) -> bool) -> SchedulerStatus {
+        for _ in 0..200 {
+            let status = scheduler.status();
+            if done(&status) {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("scheduler never reached the expected state: {:?}", scheduler.status());
+    }
+}
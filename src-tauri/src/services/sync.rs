@@ -1,200 +1,1451 @@
-use crate::db::{get_db_connection, DatabaseType};
-use crate::models::EmailContent;
-use crate::services::imap::ImapService;
-use crate::services::markitdown::MarkItDownService;
-use crate::services::ollama::OllamaService;
-use crate::utils::{get_current_timestamp, AppResult, is_test_email};
-use crate::commands::settings::{get_settings, get_imap_password};
+use crate::db::repo::{
+    EmailAccountRepo, MailboxCursorRepo, PendingImportRepo, ReceiptFilter, ReceiptRepo, ReceiptSourceRepo, SyncLogItemRepo,
+    SyncLogRepo,
+};
+use crate::db::attachments::AttachmentStore;
+use crate::db::{DatabaseType, DbState};
+use crate::models::{
+    Attachment, EmailAccount, EmailContent, ImapAuthMethod, ImportSummary, MailboxCursor, MessageAction, PendingImport,
+    Receipt, ReceiptSource, ReprocessSummary, SyncLogItem, SyncProgress, SyncStage,
+};
+use crate::services::content::{self, Converted, Part, Selection};
+use crate::services::converter::{looks_like_html, scanned_images, NativeConverter};
+use crate::services::credentials::{self, Secret};
+use crate::services::imap::{parse_email, ImapCredentials, ImapService};
+use crate::services::imap_connection::ImapConnector;
+use crate::services::local_mail::LocalMailSource;
+use crate::services::oauth::{OAuthClient, OAuthConfig};
+use crate::services::llm::{Extractor, LlmExtractionResult, OcrText};
+use crate::services::pipeline::{DocumentConverter, FetchedEmail, MailSource, NewMail, ReceiptExtractor};
+use crate::utils::{AppError, AppResult, is_test_email};
+use crate::commands::settings::load_settings;
+use futures::channel::mpsc;
+use futures::stream::{BoxStream, StreamExt};
+use rusqlite::Connection;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Semaphore};
+
+/// Messages downloaded ahead of processing, bounding how many sit in memory
+const FETCH_AHEAD: usize = 4;
+
+/// Most messages processed at once, enough to keep the most extractions
+/// allowed busy while others are converted or saved
+const MAX_IN_FLIGHT: usize = 32;
+
+/// Bytes of downloaded mail processed at once; a larger message goes alone
+const IN_FLIGHT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Converted text shorter than this means a photo or a scan, worth OCR
+const MIN_TEXT_CHARS: usize = 40;
+
+/// What happened to a single email during sync
+#[derive(Debug, PartialEq, Eq)]
+enum ProcessOutcome {
+    Imported { receipt_id: i64, junk: bool, classification: String },
+    /// Stored, but conversion or extraction failed; see `SyncService::reprocess_receipt`
+    Failed { receipt_id: i64, error: String },
+    /// Already imported by an earlier or concurrent sync
+    Skipped,
+}
+
+/// Email counts for one account's sync log row
+#[derive(Debug, Default, Clone, Copy)]
+struct SyncStats {
+    processed: i32,
+    imported: i32,
+    skipped: i32,
+}
+
+impl SyncStats {
+    fn add(&mut self, other: SyncStats) {
+        self.processed += other.processed;
+        self.imported += other.imported;
+        self.skipped += other.skipped;
+    }
+}
+
+/// Receives a run's progress, e.g. to forward it to the UI as an event
+pub type ProgressSink = Arc<dyn Fn(SyncProgress) + Send + Sync>;
+
+/// Asks a sync run to stop; clones share the same flag
+#[derive(Clone)]
+pub struct CancelToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self { cancelled: Arc::new(watch::Sender::new(false)) }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolves once the token is cancelled
+    pub async fn cancelled(&self) {
+        let _ = self.cancelled.subscribe().wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where a sync run reports progress and how it is told to stop
+#[derive(Clone, Default)]
+pub struct SyncRun {
+    pub progress: Option<ProgressSink>,
+    pub cancel: CancelToken,
+}
+
+impl SyncRun {
+    fn report(&self, progress: SyncProgress) {
+        if let Some(sink) = &self.progress {
+            sink(progress);
+        }
+    }
+
+    /// `next`, unless the run is cancelled first
+    async fn unless_cancelled<T>(&self, next: impl Future<Output = Option<T>>) -> Option<T> {
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => None,
+            next = next => next,
+        }
+    }
+}
+
+/// How a sync run ended
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyncOutcome {
+    Completed,
+    Cancelled,
+}
 
 pub struct SyncService;
 
 impl SyncService {
-    pub async fn run_sync(test_mode: bool) -> AppResult<()> {
-        // 1. Get settings
-        let settings = get_settings(test_mode)?;
-        let password = get_imap_password().unwrap_or_default();
+    /// Sync every enabled account. A failing account is logged and does not
+    /// stop the others; the error names every account that failed.
+    /// Once `run` is cancelled, accounts not yet started are left alone.
+    pub async fn run_sync(db: &DbState, test_mode: bool, run: &SyncRun) -> AppResult<SyncOutcome> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+
+        // 1. Get settings and accounts
+        let (settings, accounts) = db
+            .run(db_type, |conn| Ok((load_settings(conn)?, EmailAccountRepo::new(conn).list_enabled()?)))
+            .await?;
 
-        if settings.imap_server.is_empty() || settings.imap_username.is_empty() || password.is_empty() {
-            return Ok(()); // Skip sync if not configured
+        let converter = NativeConverter::detect();
+        let extractor = Extractor::from_settings(&settings);
+
+        let mut failed = Vec::new();
+        for account in accounts {
+            if run.cancel.is_cancelled() {
+                break;
+            }
+            if let Err(e) = Self::sync_account(db, &account, &converter, &extractor, test_mode, run).await {
+                eprintln!("Sync failed for {}: {}", account.name, e);
+                run.report(SyncProgress {
+                    account_id: account.id,
+                    detail: Some(e.to_string()),
+                    ..SyncProgress::new(SyncStage::Failed)
+                });
+                failed.push(account.name);
+            }
+        }
+
+        let error = (!failed.is_empty()).then(|| format!("Sync failed for {}", failed.join(", ")));
+        let outcome = if run.cancel.is_cancelled() { SyncOutcome::Cancelled } else { SyncOutcome::Completed };
+        let stage = match outcome {
+            SyncOutcome::Completed => SyncStage::Finished,
+            SyncOutcome::Cancelled => SyncStage::Cancelled,
+        };
+        run.report(SyncProgress { detail: error.clone(), ..SyncProgress::new(stage) });
+
+        match error {
+            Some(error) => Err(AppError::Internal(error)),
+            None => Ok(outcome),
         }
+    }
+
+    async fn sync_account<C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        account: &EmailAccount,
+        converter: &C,
+        extractor: &X,
+        test_mode: bool,
+        run: &SyncRun,
+    ) -> AppResult<()> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let account_id = account
+            .id
+            .ok_or_else(|| AppError::Validation("Email account has no ID".to_string()))?;
+
+        let Some(secret) = Self::account_secret(db_type, account) else {
+            return Ok(()); // Skip accounts that are not fully configured
+        };
 
         // 2. Initialize Sync Log
-        let db_type = if test_mode { DatabaseType::Test } else { DatabaseType::Production };
-        let conn = get_db_connection(db_type)?;
-        let start_time = get_current_timestamp();
-        
-        conn.execute(
-            "INSERT INTO sync_log (sync_started_at, status, created_at) VALUES (?1, ?2, ?3)",
-            rusqlite::params![start_time, "running", start_time],
-        )?;
-        let sync_log_id = conn.last_insert_rowid();
+        let sync_log_id = db.run(db_type, move |conn| SyncLogRepo::new(conn).start(account_id)).await?;
 
         // 3. Initialize IMAP service
-        let imap_service = ImapService::new(
-            settings.imap_server.clone(),
-            settings.imap_port as u16,
-            settings.imap_username.clone(),
-            password,
-            settings.imap_use_ssl,
-        );
-
-        // 4. Fetch unread emails
-        let emails = match imap_service.fetch_unread_emails().await {
-            Ok(e) => e,
+        let imap_service = match Self::imap_service(db_type, account, secret).await {
+            Ok(service) => service,
             Err(e) => {
-                conn.execute(
-                    "UPDATE sync_log SET status = ?1, error_message = ?2, sync_completed_at = ?3 WHERE id = ?4",
-                    rusqlite::params!["failed", e.to_string(), get_current_timestamp(), sync_log_id],
-                )?;
+                Self::fail_sync_log(db, db_type, sync_log_id, e.to_string()).await?;
                 return Err(e);
             }
         };
 
-        // 5. Initialize Ollama service
-        let ollama_service = OllamaService::new(
-            settings.ollama_endpoint.clone(),
-            settings.ollama_model.clone(),
-        );
+        Self::sync_folders(db, &imap_service, account, converter, extractor, test_mode, run, sync_log_id).await
+    }
 
-        // 6. Ensure MarkItDown is ready
-        if let Err(e) = MarkItDownService::ensure_markitdown() {
-            conn.execute(
-                "UPDATE sync_log SET status = ?1, error_message = ?2, sync_completed_at = ?3 WHERE id = ?4",
-                rusqlite::params!["failed", e.to_string(), get_current_timestamp(), sync_log_id],
-            )?;
+    /// Steps 4 to 7 of `sync_account`, once the mail source is ready
+    #[allow(clippy::too_many_arguments)]
+    async fn sync_folders<M: MailSource, C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        source: &M,
+        account: &EmailAccount,
+        converter: &C,
+        extractor: &X,
+        test_mode: bool,
+        run: &SyncRun,
+        sync_log_id: i64,
+    ) -> AppResult<()> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let account_id = account
+            .id
+            .ok_or_else(|| AppError::Validation("Email account has no ID".to_string()))?;
+
+        // 4. Log in once up front so a bad login fails before any folder is touched
+        let connected = tokio::select! {
+            biased;
+            _ = run.cancel.cancelled() => {
+                return Self::cancel_sync_log(db, db_type, sync_log_id, SyncStats::default()).await;
+            }
+            connected = source.test_connection() => connected,
+        };
+        if let Err(e) = connected {
+            Self::fail_sync_log(db, db_type, sync_log_id, e.to_string()).await?;
             return Err(e);
         }
+        run.report(SyncProgress { account_id: Some(account_id), ..SyncProgress::new(SyncStage::Connected) });
 
-        let mut processed = 0;
-        let mut imported = 0;
+        // 5. Ensure the converter is ready
+        if let Err(e) = converter.ensure_ready() {
+            Self::fail_sync_log(db, db_type, sync_log_id, e.to_string()).await?;
+            return Err(e);
+        }
 
-        for email in emails {
-            processed += 1;
-            match Self::process_email(email, &ollama_service, test_mode).await {
-                Ok(_) => imported += 1,
-                Err(e) => eprintln!("Error processing email: {}", e),
+        // 6. Fetch mail that arrived in each folder since the last run
+        let mut stats = SyncStats::default();
+        for mailbox in account.folders.iter().map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            if run.cancel.is_cancelled() {
+                break;
+            }
+            let synced = Self::sync_mailbox(
+                db,
+                source,
+                account,
+                mailbox,
+                converter,
+                extractor,
+                test_mode,
+                run,
+                sync_log_id,
+            )
+            .await;
+            match synced {
+                Ok(mailbox_stats) => stats.add(mailbox_stats),
+                Err(e) => {
+                    Self::fail_sync_log(db, db_type, sync_log_id, e.to_string()).await?;
+                    return Err(e);
+                }
             }
         }
 
         // 7. Finalize Sync Log
-        conn.execute(
-            "UPDATE sync_log SET status = ?1, emails_processed = ?2, emails_imported = ?3, sync_completed_at = ?4 WHERE id = ?5",
-            rusqlite::params!["completed", processed, imported, get_current_timestamp(), sync_log_id],
-        )?;
+        if run.cancel.is_cancelled() {
+            return Self::cancel_sync_log(db, db_type, sync_log_id, stats).await;
+        }
+        db.run(db_type, move |conn| {
+            SyncLogRepo::new(conn).complete(sync_log_id, stats.processed, stats.imported, stats.skipped)
+        })
+        .await
+    }
+
+    /// Import new mail from one folder, move its cursor past what was handled,
+    /// then run the account's post-processing actions on the new messages.
+    /// Messages are downloaded a few ahead of processing, and up to twice the
+    /// extraction limit are converted and extracted at once; results are
+    /// handled in UID order so the cursor never skips an unfinished message.
+    /// A cancelled run stops before the next message; the rest wait for the next run.
+    /// Every message handled gets a row under `sync_log_id`.
+    #[allow(clippy::too_many_arguments)]
+    async fn sync_mailbox<M: MailSource, C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        source: &M,
+        account: &EmailAccount,
+        mailbox: &str,
+        converter: &C,
+        extractor: &X,
+        test_mode: bool,
+        run: &SyncRun,
+        sync_log_id: i64,
+    ) -> AppResult<SyncStats> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let account_id = account
+            .id
+            .ok_or_else(|| AppError::Validation("Email account has no ID".to_string()))?;
+        let name = mailbox.to_string();
+        let cursor = db
+            .run(db_type, move |conn| MailboxCursorRepo::new(conn).find(account_id, &name))
+            .await?;
+        let new_mail = tokio::select! {
+            biased;
+            _ = run.cancel.cancelled() => return Ok(SyncStats::default()),
+            new_mail = source.find_new_emails(mailbox, cursor.as_ref()) => new_mail?,
+        };
+
+        let uids = new_mail.uids.clone();
+        let total = uids.len();
+        let uid_validity = new_mail.uid_validity;
+        let progress = |stage, index, detail| SyncProgress {
+            stage,
+            account_id: Some(account_id),
+            mailbox: Some(mailbox.to_string()),
+            index,
+            total: Some(total),
+            detail,
+        };
+        let progress = &progress;
+        run.report(progress(SyncStage::MessagesFound, None, None));
+
+        let mut cursor = MailboxCursor {
+            account_id,
+            mailbox: mailbox.to_string(),
+            uid_validity,
+            last_uid: new_mail.last_uid,
+        };
+
+        // (uid, database, receipt) of each message imported this run
+        let mut imported = Vec::new();
+        let mut junk = Vec::new();
+        let mut stats = SyncStats::default();
+        // Highest UID fully handled, and whether handling stopped short
+        let mut last_handled = None;
+        let mut stopped = false;
+
+        let (download, mut handled) = Self::stream_messages(source, new_mail, |index, fetched| async move {
+            let uid = fetched.uid;
+            let subject = Some(fetched.email.subject.clone());
+            let reached = Mutex::new(None);
+            let on_stage = |stage| {
+                *reached.lock().unwrap() = Some(stage);
+                run.report(progress(stage, index, subject.clone()));
+            };
+            let receipt_db = Self::route(&fetched.email, test_mode);
+            let mut item = Self::log_item(sync_log_id, mailbox, uid_validity, uid, &fetched.email);
+            let started = Instant::now();
+
+            let outcome = Self::process_email(
+                db,
+                fetched.email,
+                Some(account_id),
+                extractor,
+                converter,
+                test_mode,
+                &on_stage,
+            )
+            .await;
 
+            let failed_at = *reached.lock().unwrap();
+            Self::finish_item(&mut item, &outcome, failed_at, started.elapsed(), receipt_db == db_type);
+            (uid, index, receipt_db, item, outcome)
+        });
+        let process = async {
+            // Messages still in flight are abandoned; they are picked up again
+            // next run, and skipped there if their save had already gone through
+            while let Some((uid, index, receipt_db, item, outcome)) = run.unless_cancelled(handled.next()).await {
+                stats.processed += 1;
+                let subject = item.subject.clone();
+                db.run(db_type, move |conn| SyncLogItemRepo::new(conn).create(&item)).await?;
+
+                match outcome {
+                    Ok(ProcessOutcome::Imported { receipt_id, junk: is_junk, .. }) => {
+                        stats.imported += 1;
+                        let target = if is_junk { &mut junk } else { &mut imported };
+                        target.push((uid, receipt_db, receipt_id));
+                        run.report(progress(SyncStage::Saved, index, subject));
+                    }
+                    Ok(ProcessOutcome::Skipped) => {
+                        stats.skipped += 1;
+                        run.report(progress(SyncStage::Skipped, index, subject));
+                    }
+                    Ok(ProcessOutcome::Failed { error, .. }) => {
+                        // The receipt keeps the message, so the cursor can move on
+                        eprintln!("Extraction failed for email {} in {}: {}", uid, mailbox, error);
+                        run.report(progress(SyncStage::Failed, index, Some(error)));
+                    }
+                    Err(e) => {
+                        // Leave this message and everything after it for the next run
+                        eprintln!("Error processing email {} in {}: {}", uid, mailbox, e);
+                        run.report(progress(SyncStage::Failed, index, Some(e.to_string())));
+                        stopped = true;
+                        break;
+                    }
+                }
+                last_handled = Some(uid);
+            }
+            // Dropping the stream closes the channel, which ends the download
+            drop(handled);
+            AppResult::Ok(())
+        };
+
+        let (downloaded, processed) = tokio::join!(download, process);
+        processed?;
+
+        // Resume at the first message not handled
+        if stopped || run.cancel.is_cancelled() || downloaded.is_err() {
+            let next = uids.iter().find(|&&uid| last_handled.is_none_or(|handled| uid > handled));
+            if let Some(next) = next {
+                cursor.last_uid = next - 1;
+            }
+        }
+        db.run(db_type, move |conn| MailboxCursorRepo::new(conn).save(&cursor))
+            .await?;
+
+        Self::post_process(db, source, mailbox, &imported, &account.imported_action).await?;
+        Self::post_process(db, source, mailbox, &junk, &account.junk_action).await?;
+        downloaded?;
+        Ok(stats)
+    }
+
+    /// Process a failed message of an earlier run again and record the attempt
+    /// on its item. The account's post-processing action follows an import.
+    /// Cancelling leaves the item as it was.
+    pub async fn retry_item(db: &DbState, test_mode: bool, item_id: i64, run: &SyncRun) -> AppResult<SyncLogItem> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let (item, log, settings) = db
+            .run(db_type, move |conn| {
+                let item = SyncLogItemRepo::new(conn).get(item_id)?;
+                let log = SyncLogRepo::new(conn).get(item.sync_log_id)?;
+                Ok((item, log, load_settings(conn)?))
+            })
+            .await?;
+
+        if item.status != "failed" {
+            return Err(AppError::Validation("Only failed messages can be retried".to_string()));
+        }
+        let account_id = log
+            .account_id
+            .ok_or_else(|| AppError::Validation("The account of this sync run no longer exists".to_string()))?;
+        let account = db.run(db_type, move |conn| EmailAccountRepo::new(conn).get(account_id)).await?;
+        let secret = Self::account_secret(db_type, &account)
+            .ok_or_else(|| AppError::Validation(format!("{} is not fully configured", account.name)))?;
+
+        let imap_service = Self::imap_service(db_type, &account, secret).await?;
+        let converter = NativeConverter::detect();
+        converter.ensure_ready()?;
+        let extractor = Extractor::from_settings(&settings);
+        tokio::select! {
+            biased;
+            _ = run.cancel.cancelled() => {}
+            retried = Self::retry_with(db, &imap_service, &account, item, &converter, &extractor, test_mode) => retried?,
+        }
+
+        db.run(db_type, move |conn| SyncLogItemRepo::new(conn).get(item_id)).await
+    }
+
+    /// Fetch the item's message from `source` again and process it
+    async fn retry_with<M: MailSource, C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        source: &M,
+        account: &EmailAccount,
+        mut item: SyncLogItem,
+        converter: &C,
+        extractor: &X,
+        test_mode: bool,
+    ) -> AppResult<()> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let account_id = account
+            .id
+            .ok_or_else(|| AppError::Validation("Email account has no ID".to_string()))?;
+        let email = source.fetch_message(&item.mailbox, item.uid_validity, item.uid).await?;
+
+        item.message_id = Some(email.message_id.clone());
+        item.subject = Some(email.subject.clone());
+        let receipt_db = Self::route(&email, test_mode);
+        let reached = Mutex::new(None);
+        let started = Instant::now();
+        let outcome = Self::process_email(
+            db,
+            email,
+            Some(account_id),
+            extractor,
+            converter,
+            test_mode,
+            &|stage| *reached.lock().unwrap() = Some(stage),
+        )
+        .await;
+
+        let failed_at = *reached.lock().unwrap();
+        Self::finish_item(&mut item, &outcome, failed_at, started.elapsed(), receipt_db == db_type);
+        let attempt = item.clone();
+        db.run(db_type, move |conn| SyncLogItemRepo::new(conn).record_attempt(&attempt)).await?;
+
+        if let Ok(ProcessOutcome::Imported { receipt_id, junk, .. }) = outcome {
+            let action = if junk { &account.junk_action } else { &account.imported_action };
+            Self::post_process(db, source, &item.mailbox, &[(item.uid, receipt_db, receipt_id)], action).await?;
+        }
         Ok(())
     }
 
-    async fn process_email(
-        email: EmailContent,
-        ollama_service: &OllamaService,
+    /// Extract a stored receipt again, without fetching its message.
+    /// Cancelling leaves it as it was.
+    pub async fn reprocess_receipt(db: &DbState, test_mode: bool, receipt_id: i64, run: &SyncRun) -> AppResult<Receipt> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let (receipt, settings) = db
+            .run(db_type, move |conn| Ok((ReceiptRepo::new(conn).get(receipt_id)?, load_settings(conn)?)))
+            .await?;
+        if receipt.processing_state == "extracted" {
+            return Err(AppError::Validation(format!("Receipt {} has already been extracted", receipt_id)));
+        }
+
+        let converter = NativeConverter::detect();
+        converter.ensure_ready()?;
+        let extractor = Extractor::from_settings(&settings);
+        tokio::select! {
+            biased;
+            _ = run.cancel.cancelled() => {}
+            extracted = Self::extract(db, db_type, receipt_id, &extractor, &converter, &|_| {}) => {
+                extracted?;
+            }
+        }
+
+        db.run(db_type, move |conn| ReceiptRepo::new(conn).get(receipt_id)).await
+    }
+
+    /// Extract every receipt whose extraction failed again, until cancelled
+    pub async fn reprocess_failed_receipts(db: &DbState, test_mode: bool, run: &SyncRun) -> AppResult<ReprocessSummary> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let filter = ReceiptFilter { processing_state: Some("failed".to_string()), ..Default::default() };
+        let (receipts, settings) = db
+            .run(db_type, move |conn| Ok((ReceiptRepo::new(conn).list(&filter, None)?, load_settings(conn)?)))
+            .await?;
+
+        let mut summary = ReprocessSummary::default();
+        if receipts.is_empty() {
+            return Ok(summary);
+        }
+
+        let converter = NativeConverter::detect();
+        converter.ensure_ready()?;
+        let extractor = Extractor::from_settings(&settings);
+        for receipt_id in receipts.into_iter().filter_map(|receipt| receipt.id) {
+            let outcome = tokio::select! {
+                biased;
+                _ = run.cancel.cancelled() => {
+                    summary.cancelled = true;
+                    break;
+                }
+                outcome = Self::extract(db, db_type, receipt_id, &extractor, &converter, &|_| {}) => outcome?,
+            };
+            match outcome {
+                ProcessOutcome::Imported { .. } => summary.extracted += 1,
+                ProcessOutcome::Failed { .. } => summary.failed += 1,
+                ProcessOutcome::Skipped => summary.skipped += 1,
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Import the messages of an mbox file, a Maildir or `.eml` files at
+    /// `path` as pending imports. Progress is reported as for a sync, with
+    /// the path as mailbox. Importing the same files again skips what was
+    /// already imported.
+    pub async fn import_local(db: &DbState, test_mode: bool, path: PathBuf, run: &SyncRun) -> AppResult<ImportSummary> {
+        let db_type = DatabaseType::from_test_mode(test_mode);
+        let settings = db.run(db_type, load_settings).await?;
+
+        let label = path.display().to_string();
+        let source = tokio::task::spawn_blocking(move || LocalMailSource::open(&path))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to read {}: {}", label, e)))??;
+
+        let converter = NativeConverter::detect();
+        let extractor = Extractor::from_settings(&settings);
+        Self::import_from(db, &source, &label, &converter, &extractor, test_mode, run).await
+    }
+
+    /// Run every message of `source` through the extraction pipeline. Unlike
+    /// a sync there is no account, cursor or post-processing; an error that
+    /// keeps a message from being stored ends the import.
+    async fn import_from<M: MailSource, C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        source: &M,
+        label: &str,
+        converter: &C,
+        extractor: &X,
         test_mode: bool,
+        run: &SyncRun,
+    ) -> AppResult<ImportSummary> {
+        converter.ensure_ready()?;
+        let new_mail = source.find_new_emails(label, None).await?;
+
+        let mut summary = ImportSummary { found: new_mail.uids.len(), ..Default::default() };
+        let total = summary.found;
+        let progress = |stage, index, detail| SyncProgress {
+            stage,
+            account_id: None,
+            mailbox: Some(label.to_string()),
+            index,
+            total: Some(total),
+            detail,
+        };
+        let progress = &progress;
+        run.report(progress(SyncStage::MessagesFound, None, None));
+
+        let (download, mut handled) = Self::stream_messages(source, new_mail, |index, fetched| async move {
+            let subject = Some(fetched.email.subject.clone());
+            let on_stage = |stage| run.report(progress(stage, index, subject.clone()));
+            let outcome = Self::process_email(db, fetched.email, None, extractor, converter, test_mode, &on_stage).await;
+            (index, subject, outcome)
+        });
+        let process = async {
+            while let Some((index, subject, outcome)) = run.unless_cancelled(handled.next()).await {
+                match outcome? {
+                    ProcessOutcome::Imported { .. } => {
+                        summary.imported += 1;
+                        run.report(progress(SyncStage::Saved, index, subject));
+                    }
+                    ProcessOutcome::Skipped => {
+                        summary.skipped += 1;
+                        run.report(progress(SyncStage::Skipped, index, subject));
+                    }
+                    ProcessOutcome::Failed { error, .. } => {
+                        summary.failed += 1;
+                        run.report(progress(SyncStage::Failed, index, Some(error)));
+                    }
+                }
+            }
+            drop(handled);
+            AppResult::Ok(())
+        };
+
+        let (downloaded, processed) = tokio::join!(download, process);
+        processed?;
+        downloaded?;
+
+        let stage = if run.cancel.is_cancelled() { SyncStage::Cancelled } else { SyncStage::Finished };
+        run.report(progress(stage, None, None));
+        Ok(summary)
+    }
+
+    /// Download `new_mail` and run the messages through `process` a few at a
+    /// time. Returns the download, to be awaited alongside the stream of
+    /// results in download order; dropping the stream ends the download.
+    /// How many messages are in flight is bounded by `MAX_IN_FLIGHT` and
+    /// `IN_FLIGHT_BYTES`, not by how many extractions may run, so large
+    /// messages can't pile up in memory.
+    fn stream_messages<'a, M, P, Fut, T>(
+        source: &'a M,
+        new_mail: NewMail<M::Session>,
+        mut process: P,
+    ) -> (impl Future<Output = AppResult<()>> + 'a, BoxStream<'a, T>)
+    where
+        M: MailSource,
+        P: FnMut(Option<usize>, FetchedEmail) -> Fut + Send + 'a,
+        Fut: Future<Output = T> + Send + 'a,
+        T: Send + 'a,
+    {
+        let budget = Arc::new(Semaphore::new(IN_FLIGHT_BYTES));
+        let (tx, rx) = mpsc::channel(FETCH_AHEAD);
+        let download = source.download(new_mail, tx);
+
+        let mut position = 0;
+        let processed = rx
+            .then(move |fetched: FetchedEmail| {
+                let budget = budget.clone();
+                async move {
+                    let bytes = message_size(&fetched.email).min(IN_FLIGHT_BYTES);
+                    // Only fails once closed, which this semaphore never is
+                    let permit = budget.acquire_many_owned(bytes as u32).await.ok();
+                    (fetched, permit)
+                }
+            })
+            .map(move |(fetched, permit)| {
+                position += 1;
+                let processing = process(Some(position), fetched);
+                async move {
+                    let result = processing.await;
+                    drop(permit);
+                    result
+                }
+            })
+            .buffered(MAX_IN_FLIGHT)
+            // Boxed, or the compiler can't tell that a sync's future is Send
+            .boxed();
+
+        (download, processed)
+    }
+
+    /// A `sync_log_items` row for `email`, completed by `finish_item`
+    fn log_item(sync_log_id: i64, mailbox: &str, uid_validity: u32, uid: u32, email: &EmailContent) -> SyncLogItem {
+        SyncLogItem {
+            id: None,
+            sync_log_id,
+            mailbox: mailbox.to_string(),
+            uid,
+            uid_validity,
+            message_id: Some(email.message_id.clone()),
+            subject: Some(email.subject.clone()),
+            status: "failed".to_string(),
+            stage: None,
+            duration_ms: 0,
+            classification: None,
+            error_message: None,
+            receipt_id: None,
+            attempts: 1,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    /// Record how processing went. `failed_at` is the last stage reached,
+    /// which only matters on failure. Receipts in the other database can't
+    /// be linked.
+    fn finish_item(
+        item: &mut SyncLogItem,
+        outcome: &AppResult<ProcessOutcome>,
+        failed_at: Option<SyncStage>,
+        elapsed: Duration,
+        same_database: bool,
+    ) {
+        item.duration_ms = elapsed.as_millis() as i64;
+        item.classification = None;
+        item.error_message = None;
+        item.receipt_id = None;
+
+        let stage = match outcome {
+            Ok(ProcessOutcome::Imported { receipt_id, classification, .. }) => {
+                item.status = "imported".to_string();
+                item.classification = Some(classification.clone());
+                item.receipt_id = Some(*receipt_id).filter(|_| same_database);
+                Some(SyncStage::Saved)
+            }
+            Ok(ProcessOutcome::Skipped) => {
+                item.status = "skipped".to_string();
+                Some(SyncStage::Skipped)
+            }
+            Ok(ProcessOutcome::Failed { receipt_id, error }) => {
+                item.status = "failed".to_string();
+                item.error_message = Some(error.clone());
+                item.receipt_id = Some(*receipt_id).filter(|_| same_database);
+                failed_at
+            }
+            Err(e) => {
+                item.status = "failed".to_string();
+                item.error_message = Some(e.to_string());
+                failed_at
+            }
+        };
+        item.stage = stage.map(|stage| stage.as_str().to_string());
+    }
+
+    /// Apply `action` on the server and note it on each receipt. A failure is
+    /// logged and leaves the receipts without an action; the imports stand.
+    async fn post_process<M: MailSource>(
+        db: &DbState,
+        source: &M,
+        mailbox: &str,
+        messages: &[(u32, DatabaseType, i64)],
+        action: &MessageAction,
     ) -> AppResult<()> {
-        // Determine if this is a test email (subject contains [test])
-        let is_test = is_test_email(&email.subject);
-        
-        // Route to appropriate database
-        let db_type = if test_mode || is_test {
+        let Some(description) = action.describe() else {
+            return Ok(());
+        };
+
+        let uids: Vec<u32> = messages.iter().map(|(uid, _, _)| *uid).collect();
+        if let Err(e) = source.apply_action(mailbox, &uids, action).await {
+            eprintln!("Failed to post-process messages in {}: {}", mailbox, e);
+            return Ok(());
+        }
+
+        for &(_, db_type, receipt_id) in messages {
+            let description = description.clone();
+            db.run(db_type, move |conn| ReceiptRepo::new(conn).set_imap_action(receipt_id, &description))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// [test] emails always go to the test database
+    fn route(email: &EmailContent, test_mode: bool) -> DatabaseType {
+        if test_mode || is_test_email(&email.subject) {
             DatabaseType::Test
         } else {
             DatabaseType::Production
+        }
+    }
+
+    /// The password, or the refresh token to get an access token with.
+    /// None when the account is not fully configured.
+    pub(crate) fn account_secret(db_type: DatabaseType, account: &EmailAccount) -> Option<String> {
+        let account_id = account.id?;
+        let secret_kind = match account.auth_method {
+            ImapAuthMethod::Password => Secret::Password,
+            ImapAuthMethod::OAuth2 => Secret::OAuthRefreshToken,
         };
+        let secret = credentials::get(db_type, account_id, secret_kind).ok().flatten()?;
 
-        // 1. Select content to process
-        let (markdown, attachment_data, mime_type) = Self::extract_best_content(&email)?;
-
-        // 2. Extract data via Ollama
-        let extraction = ollama_service.extract_receipt_data(&markdown).await?;
-
-        // 3. Save receipt
-        let conn = get_db_connection(db_type)?;
-        let now = get_current_timestamp();
-
-        let receipt_id = if let Some(data) = attachment_data {
-            let file_data_base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data);
-            
-            conn.execute(
-                "INSERT INTO receipts (email_subject, email_from, email_date, attachment_mime_type, attachment_data, raw_email_body, created_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                rusqlite::params![
-                    email.subject,
-                    email.from,
-                    email.date,
-                    mime_type,
-                    file_data_base64,
-                    email.body,
-                    now
-                ],
-            )?;
-            Some(conn.last_insert_rowid())
-        } else {
-            conn.execute(
-                "INSERT INTO receipts (email_subject, email_from, email_date, raw_email_body, created_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
-                rusqlite::params![
-                    email.subject,
-                    email.from,
-                    email.date,
-                    email.body,
-                    now
-                ],
-            )?;
-            Some(conn.last_insert_rowid())
-        };
-
-        // 4. Save pending import
-        conn.execute(
-            "INSERT INTO pending_imports (email_subject, email_from, email_date, classification, confidence, extracted_data, receipt_id, status, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-            rusqlite::params![
-                email.subject,
-                email.from,
-                email.date,
-                extraction.classification,
-                extraction.confidence,
-                extraction.data.to_string(),
-                receipt_id,
-                "pending",
-                now
-            ],
-        )?;
+        let configured = !account.server.is_empty() && !account.username.is_empty() && !secret.is_empty();
+        configured.then_some(secret)
+    }
 
-        Ok(())
+    /// Build the IMAP client. With OAuth2 a fresh access token is fetched
+    /// first, and a rotated refresh token replaces the stored one.
+    pub(crate) async fn imap_service(db_type: DatabaseType, account: &EmailAccount, secret: String) -> AppResult<ImapService> {
+        let connector = ImapConnector::from_account(account)?;
+
+        let credentials = match account.auth_method {
+            ImapAuthMethod::Password => ImapCredentials::Password(secret),
+            ImapAuthMethod::OAuth2 => {
+                let client = OAuthClient::new(OAuthConfig::from_account(account)?);
+                let tokens = client.refresh(&secret).await?;
+                if let (Some(refresh_token), Some(account_id)) = (tokens.refresh_token, account.id) {
+                    credentials::save(db_type, account_id, Secret::OAuthRefreshToken, &refresh_token)?;
+                }
+                ImapCredentials::OAuth2(tokens.access_token)
+            }
+        };
+
+        Ok(ImapService::new(connector, account.username.clone(), credentials).with_criteria(account.search.clone()))
+    }
+
+    async fn fail_sync_log(
+        db: &DbState,
+        db_type: DatabaseType,
+        sync_log_id: i64,
+        error_message: String,
+    ) -> AppResult<()> {
+        db.run(db_type, move |conn| SyncLogRepo::new(conn).fail(sync_log_id, &error_message))
+            .await
+    }
+
+    async fn cancel_sync_log(
+        db: &DbState,
+        db_type: DatabaseType,
+        sync_log_id: i64,
+        stats: SyncStats,
+    ) -> AppResult<()> {
+        db.run(db_type, move |conn| {
+            SyncLogRepo::new(conn).cancel(sync_log_id, stats.processed, stats.imported, stats.skipped)
+        })
+        .await
     }
 
-    fn extract_best_content(email: &EmailContent) -> AppResult<(String, Option<Vec<u8>>, Option<String>)> {
-        // 1. Look for PDF
-        for attachment in &email.attachments {
-            if attachment.content_type.to_lowercase().contains("pdf") {
-                let markdown = MarkItDownService::convert_data_to_markdown(&attachment.data, ".pdf")?;
-                return Ok((markdown, Some(attachment.data.clone()), Some(attachment.content_type.clone())));
+    /// Store the message as a receipt, then extract it. `on_stage` hears when
+    /// conversion and extraction start. A message stored by an earlier run
+    /// whose extraction failed is extracted again from the stored copy.
+    /// `account_id` is None for mail imported from files.
+    async fn process_email<C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        email: EmailContent,
+        account_id: Option<i64>,
+        extractor: &X,
+        converter: &C,
+        test_mode: bool,
+        on_stage: &(dyn Fn(SyncStage) + Sync),
+    ) -> AppResult<ProcessOutcome> {
+        // Route to appropriate database
+        let db_type = Self::route(&email, test_mode);
+
+        // Accounts live in the database sync runs against; a [test] email
+        // routed to the other database can't refer to one
+        let account_id = account_id.filter(|_| db_type == DatabaseType::from_test_mode(test_mode));
+
+        // Skip before paying for conversion and the LLM call
+        let message_id = email.message_id.clone();
+        let (seen, stored_id) = db
+            .run(db_type, move |conn| {
+                Ok((
+                    PendingImportRepo::new(conn).has_message(&message_id)?,
+                    ReceiptRepo::new(conn).find_id_by_message_id(&message_id)?,
+                ))
+            })
+            .await?;
+        if seen {
+            return Ok(ProcessOutcome::Skipped);
+        }
+
+        // 1. Keep the message before anything can fail on it
+        let receipt_id = match stored_id {
+            Some(receipt_id) => receipt_id,
+            None => {
+                let attachments = db.attachments(db_type).clone();
+                let stored = db
+                    .run(db_type, move |conn| Self::store_email(conn, &attachments, email, account_id))
+                    .await?;
+                match stored {
+                    Some(receipt_id) => receipt_id,
+                    None => return Ok(ProcessOutcome::Skipped),
+                }
+            }
+        };
+
+        // 2. Convert and extract
+        Self::extract(db, db_type, receipt_id, extractor, converter, on_stage).await
+    }
+
+    /// Save the email as a "fetched" receipt with the parts worth reading,
+    /// the best attachment as its attachment. None when another sync stored
+    /// the same message first.
+    fn store_email(
+        conn: &Connection,
+        attachments: &AttachmentStore,
+        email: EmailContent,
+        account_id: Option<i64>,
+    ) -> AppResult<Option<i64>> {
+        let parts: Vec<_> = content::candidates(&email)
+            .into_iter()
+            .map(|candidate| (candidate.part, candidate.score, false))
+            .collect();
+        let mut best = None;
+        for (part, _, _) in &parts {
+            if let Part::Attachment(index) = part {
+                let attachment = &email.attachments[*index];
+                let stored = attachments.put(&attachment.data)?;
+                best.get_or_insert((attachment.content_type.clone(), stored));
+            }
+        }
+        let sources = Self::sources(&email, &parts);
+        let (mime_type, stored) = best.unzip();
+        let raw_message = if email.raw.is_empty() { None } else { Some(attachments.put(&email.raw)?) };
+
+        let result = (|| {
+            let tx = conn.unchecked_transaction()?;
+            let receipt_id = ReceiptRepo::new(&tx).create(&Receipt {
+                id: None,
+                subscription_id: None,
+                domain_id: None,
+                email_subject: Some(email.subject),
+                email_from: Some(email.from),
+                email_date: email.date,
+                file_type: mime_type,
+                file_hash: stored.as_ref().map(|f| f.hash.clone()),
+                file_size: stored.as_ref().map(|f| f.size),
+                raw_email_body: Some(email.body),
+                created_at: String::new(),
+                message_id: Some(email.message_id.clone()),
+                account_id,
+                imap_action: None,
+                processing_state: "fetched".to_string(),
+                processing_error: None,
+                raw_message_hash: raw_message.map(|f| f.hash),
+                ocr_text: None,
+                ocr_confidence: None,
+            })?;
+            ReceiptSourceRepo::new(&tx).replace(receipt_id, &sources)?;
+            tx.commit()?;
+            Ok(receipt_id)
+        })();
+
+        match result {
+            Ok(receipt_id) => Ok(Some(receipt_id)),
+            Err(_) if ReceiptRepo::new(conn).find_id_by_message_id(&email.message_id)?.is_some() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Convert a stored receipt and send it through the extractor. Conversion and
+    /// extraction errors mark the receipt "failed" and are returned as
+    /// `ProcessOutcome::Failed`, so the message can be retried from the store.
+    async fn extract<C: DocumentConverter, X: ReceiptExtractor>(
+        db: &DbState,
+        db_type: DatabaseType,
+        receipt_id: i64,
+        extractor: &X,
+        converter: &C,
+        on_stage: &(dyn Fn(SyncStage) + Sync),
+    ) -> AppResult<ProcessOutcome> {
+        let attachments = db.attachments(db_type).clone();
+        let (receipt, email) = db
+            .run(db_type, move |conn| {
+                let receipt = ReceiptRepo::new(conn).get(receipt_id)?;
+                let sources = ReceiptSourceRepo::new(conn).list(receipt_id)?;
+                let email = Self::stored_email(&receipt, &sources, &attachments)?;
+                Ok((receipt, email))
+            })
+            .await?;
+
+        // Conversion is CPU bound or runs MarkItDown, so keep it off the async workers
+        on_stage(SyncStage::Converting);
+        let converter = converter.clone();
+        let reads_images = extractor.reads_images();
+        let (converted, scans, email) = tokio::task::spawn_blocking(move || {
+            let converted = Self::extract_best_content(&email, &converter);
+            let scans: Vec<(usize, Vec<Vec<u8>>)> = match &converted {
+                Ok(selection) if selection.markdown.trim().chars().count() >= MIN_TEXT_CHARS => Vec::new(),
+                _ if reads_images => content::candidates(&email)
+                    .into_iter()
+                    .filter_map(|candidate| match candidate.part {
+                        Part::Attachment(index) => {
+                            Some((index, scanned_images(&email.attachments[index].data, candidate.extension)))
+                        }
+                        Part::Body => None,
+                    })
+                    .filter(|(_, images)| !images.is_empty())
+                    .collect(),
+                _ => Vec::new(),
+            };
+            (converted, scans, email)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Conversion task failed: {}", e)))?;
+
+        // Too little text in photos or scans: read them with OCR instead
+        let converted = if scans.is_empty() {
+            converted
+        } else {
+            on_stage(SyncStage::Recognizing);
+            let scanned: Vec<usize> = scans.iter().map(|(index, _)| *index).collect();
+            let images: Vec<Vec<u8>> = scans.into_iter().flat_map(|(_, images)| images).collect();
+            match Self::recognize(extractor, &images).await {
+                Ok(ocr) => {
+                    let markdown = ocr.text.clone();
+                    db.run(db_type, move |conn| {
+                        ReceiptRepo::new(conn).set_ocr(receipt_id, &ocr.text, ocr.confidence)
+                    })
+                    .await?;
+                    let parts = content::candidates(&email)
+                        .into_iter()
+                        .map(|c| (c.part, c.score, matches!(c.part, Part::Attachment(i) if scanned.contains(&i))))
+                        .collect();
+                    Ok(Selection { markdown, parts })
+                }
+                Err(e) => Err(e),
+            }
+        };
+        let extraction = match converted {
+            Ok(selection) => {
+                let sources = Self::sources(&email, &selection.parts);
+                db.run(db_type, move |conn| {
+                    let tx = conn.unchecked_transaction()?;
+                    ReceiptSourceRepo::new(&tx).replace(receipt_id, &sources)?;
+                    ReceiptRepo::new(&tx).set_processing_state(receipt_id, "converted", None)?;
+                    tx.commit()?;
+                    Ok(())
+                })
+                .await?;
+                on_stage(SyncStage::Extracting);
+                extractor.extract_receipt_data(&selection.markdown).await
+            }
+            Err(e) => Err(e),
+        };
+
+        match extraction {
+            Ok(extraction) => db.run(db_type, move |conn| Self::save_extraction(conn, &receipt, extraction)).await,
+            Err(e) => {
+                let error = e.to_string();
+                Self::set_processing_state(db, db_type, receipt_id, "failed", Some(error.clone())).await?;
+                Ok(ProcessOutcome::Failed { receipt_id, error })
             }
         }
+    }
+
+    /// The text of every scan, one after the other, with their average confidence
+    async fn recognize<X: ReceiptExtractor>(extractor: &X, scans: &[Vec<u8>]) -> AppResult<OcrText> {
+        let mut texts = Vec::new();
+        let mut confidence = 0.0;
+        for scan in scans {
+            let ocr = extractor.recognize_text(scan).await?;
+            texts.push(ocr.text);
+            confidence += ocr.confidence;
+        }
 
-        // 2. Look for images
-        for attachment in &email.attachments {
-            let ct = attachment.content_type.to_lowercase();
-            if ct.contains("image/jpeg") || ct.contains("image/png") {
-                let ext = if ct.contains("jpeg") { ".jpg" } else { ".png" };
-                let markdown = MarkItDownService::convert_data_to_markdown(&attachment.data, ext)?;
-                return Ok((markdown, Some(attachment.data.clone()), Some(attachment.content_type.clone())));
+        Ok(OcrText { text: texts.join("\n\n"), confidence: confidence / scans.len() as f64 })
+    }
+
+    async fn set_processing_state(
+        db: &DbState,
+        db_type: DatabaseType,
+        receipt_id: i64,
+        state: &'static str,
+        error: Option<String>,
+    ) -> AppResult<()> {
+        db.run(db_type, move |conn| {
+            ReceiptRepo::new(conn).set_processing_state(receipt_id, state, error.as_deref())
+        })
+        .await
+    }
+
+    /// The email as it was fetched, parsed again from the stored message.
+    /// Receipts stored before the message was kept are rebuilt from their
+    /// sources, or from their one attachment when older still.
+    fn stored_email(receipt: &Receipt, sources: &[ReceiptSource], attachments: &AttachmentStore) -> AppResult<EmailContent> {
+        if let Some(hash) = &receipt.raw_message_hash {
+            return parse_email(&attachments.read(hash)?);
+        }
+
+        let attachments = if sources.is_empty() {
+            match (&receipt.file_hash, &receipt.file_type) {
+                (Some(hash), Some(content_type)) => vec![Attachment {
+                    filename: String::new(),
+                    content_type: content_type.clone(),
+                    data: attachments.read(hash)?,
+                    inline: false,
+                }],
+                _ => Vec::new(),
             }
+        } else {
+            sources
+                .iter()
+                .filter_map(|source| {
+                    let hash = source.file_hash.as_ref()?;
+                    Some(attachments.read(hash).map(|data| Attachment {
+                        filename: source.name.clone(),
+                        content_type: source.content_type.clone(),
+                        data,
+                        inline: source.inline,
+                    }))
+                })
+                .collect::<AppResult<_>>()?
+        };
+
+        Ok(EmailContent {
+            message_id: receipt.message_id.clone().unwrap_or_default(),
+            subject: receipt.email_subject.clone().unwrap_or_default(),
+            from: receipt.email_from.clone().unwrap_or_default(),
+            date: receipt.email_date.clone(),
+            body: receipt.raw_email_body.clone().unwrap_or_default(),
+            attachments,
+            raw: Vec::new(),
+        })
+    }
+
+    /// Create the pending import and mark the receipt extracted in one
+    /// transaction. Losing a race against another sync counts as skipped.
+    fn save_extraction(conn: &Connection, receipt: &Receipt, extraction: LlmExtractionResult) -> AppResult<ProcessOutcome> {
+        let receipt_id = receipt
+            .id
+            .ok_or_else(|| AppError::Validation("Receipt has no ID".to_string()))?;
+        let classification = extraction.classification.clone();
+        let junk = classification == "junk";
+
+        let result = (|| {
+            let tx = conn.unchecked_transaction()?;
+
+            PendingImportRepo::new(&tx).create(&PendingImport {
+                id: None,
+                email_subject: receipt.email_subject.clone(),
+                email_from: receipt.email_from.clone().unwrap_or_default(),
+                email_date: Some(receipt.email_date.clone()),
+                classification: Some(extraction.classification),
+                confidence: Some(extraction.confidence),
+                extracted_data: extraction.data.to_string(),
+                receipt_id: Some(receipt_id),
+                status: "pending".to_string(),
+                created_at: String::new(),
+                message_id: receipt.message_id.clone(),
+            })?;
+            ReceiptRepo::new(&tx).set_processing_state(receipt_id, "extracted", None)?;
+
+            tx.commit()?;
+            Ok(())
+        })();
+
+        let lost_race = |message_id: &Option<String>| match message_id {
+            Some(message_id) => PendingImportRepo::new(conn).has_message(message_id),
+            None => Ok(false),
+        };
+        match result {
+            Ok(()) => Ok(ProcessOutcome::Imported { receipt_id, junk, classification }),
+            Err(_) if lost_race(&receipt.message_id)? => Ok(ProcessOutcome::Skipped),
+            Err(e) => Err(e),
         }
+    }
 
-        // 3. Use email body
-        let markdown = MarkItDownService::convert_data_to_markdown(email.body.as_bytes(), ".html")?;
-        Ok((markdown, None, None))
+    /// The parts of `email` as receipt sources
+    fn sources(email: &EmailContent, parts: &[(Part, i64, bool)]) -> Vec<ReceiptSource> {
+        parts
+            .iter()
+            .map(|&(part, score, used)| {
+                let (content_type, file_hash, file_size, inline) = match part {
+                    Part::Attachment(index) => {
+                        let attachment = &email.attachments[index];
+                        let hash = AttachmentStore::hash(&attachment.data);
+                        (attachment.content_type.clone(), Some(hash), attachment.data.len(), attachment.inline)
+                    }
+                    Part::Body => {
+                        let content_type = if looks_like_html(&email.body) { "text/html" } else { "text/plain" };
+                        (content_type.to_string(), None, email.body.len(), false)
+                    }
+                };
+                ReceiptSource {
+                    name: content::name(email, part),
+                    content_type,
+                    file_hash,
+                    file_size: file_size as i64,
+                    inline,
+                    score,
+                    used,
+                }
+            })
+            .collect()
     }
+
+    /// Convert the parts of the email worth reading and pick what extraction reads
+    fn extract_best_content(email: &EmailContent, converter: &impl DocumentConverter) -> AppResult<Selection> {
+        let converted = content::candidates(email)
+            .into_iter()
+            .map(|candidate| {
+                let data = match candidate.part {
+                    Part::Attachment(index) => &email.attachments[index].data[..],
+                    Part::Body => email.body.as_bytes(),
+                };
+                Converted { markdown: converter.convert(data, candidate.extension), candidate }
+            })
+            .collect();
+        content::select(email, converted)
+    }
+}
+
+/// Roughly what a downloaded message holds in memory
+fn message_size(email: &EmailContent) -> usize {
+    email.raw.len() + email.body.len() + email.attachments.iter().map(|a| a.data.len()).sum::<usize>()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::repo::email_accounts::test_account;
+    use crate::db::repo::PendingImportFilter;
     use crate::models::Attachment;
+    use crate::services::extraction_schema;
+    use crate::models::{AppSettings, LlmProviderKind};
+    use crate::services::llm::LlmExtractor;
+    use crate::services::ollama::OllamaService;
+    use crate::services::pipeline::NewMail;
+    use futures::SinkExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Stand-in for MarkItDown that tags the output with the file extension
+    #[derive(Clone)]
+    struct FakeConverter;
+
+    impl DocumentConverter for FakeConverter {
+        fn ensure_ready(&self) -> AppResult<()> {
+            Ok(())
+        }
+
+        fn convert(&self, data: &[u8], extension: &str) -> AppResult<String> {
+            Ok(format!("{} {}", extension, String::from_utf8_lossy(data)))
+        }
+    }
+
+    /// A mailbox held in memory; records the actions applied to it
+    struct FakeMailSource {
+        messages: Vec<(u32, EmailContent)>,
+        actions: Mutex<Vec<(Vec<u32>, MessageAction)>>,
+    }
+
+    impl FakeMailSource {
+        fn new(messages: Vec<(u32, EmailContent)>) -> Self {
+            Self { messages, actions: Mutex::new(Vec::new()) }
+        }
+    }
+
+    impl MailSource for FakeMailSource {
+        type Session = ();
+
+        async fn test_connection(&self) -> AppResult<()> {
+            Ok(())
+        }
+
+        async fn find_new_emails(&self, mailbox: &str, cursor: Option<&MailboxCursor>) -> AppResult<NewMail<()>> {
+            let after = cursor.map_or(0, |cursor| cursor.last_uid);
+            let uids: Vec<u32> = self.messages.iter().map(|(uid, _)| *uid).filter(|uid| *uid > after).collect();
+            Ok(NewMail {
+                session: (),
+                mailbox: mailbox.to_string(),
+                uid_validity: 1,
+                last_uid: uids.last().copied().unwrap_or(after),
+                uids,
+            })
+        }
+
+        async fn download(&self, new_mail: NewMail<()>, mut tx: mpsc::Sender<FetchedEmail>) -> AppResult<()> {
+            for (uid, email) in self.messages.iter().filter(|(uid, _)| new_mail.uids.contains(uid)) {
+                if tx.send(FetchedEmail { uid: *uid, email: email.clone() }).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        async fn fetch_message(&self, mailbox: &str, _uid_validity: u32, uid: u32) -> AppResult<EmailContent> {
+            self.messages
+                .iter()
+                .find(|(found, _)| *found == uid)
+                .map(|(_, email)| email.clone())
+                .ok_or_else(|| AppError::NotFound(format!("Message {} is no longer in {}", uid, mailbox)))
+        }
+
+        async fn apply_action(&self, _mailbox: &str, uids: &[u32], action: &MessageAction) -> AppResult<()> {
+            self.actions.lock().unwrap().push((uids.to_vec(), action.clone()));
+            Ok(())
+        }
+    }
+
+    /// Reads a sale as junk, anything garbled as an error and the rest as a subscription
+    struct FakeExtractor;
+
+    impl ReceiptExtractor for FakeExtractor {
+        async fn extract_receipt_data(&self, markdown: &str) -> AppResult<LlmExtractionResult> {
+            if markdown.contains("garbled") {
+                return Err(AppError::Internal("Could not parse the LLM response".to_string()));
+            }
+            let classification = if markdown.contains("Sale") { "junk" } else { "subscription" };
+            Ok(LlmExtractionResult {
+                classification: classification.to_string(),
+                confidence: 0.9,
+                data: serde_json::json!({ "name": "Netflix", "cost": 15.99 }),
+            })
+        }
+
+        fn reads_images(&self) -> bool {
+            true
+        }
+
+        async fn recognize_text(&self, image: &[u8]) -> AppResult<OcrText> {
+            if image == b"blurry" {
+                return Err(AppError::Internal("Could not read the image".to_string()));
+            }
+            Ok(OcrText { text: "NETFLIX Premium 15.99 USD".to_string(), confidence: 0.8 })
+        }
+    }
+
+    fn test_email(message_id: &str, subject: &str, body: &str) -> EmailContent {
+        EmailContent {
+            message_id: message_id.to_string(),
+            subject: subject.to_string(),
+            from: "sender@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: body.to_string(),
+            attachments: vec![],
+            raw: Vec::new(),
+        }
+    }
+
+    /// Serve a single Ollama `/api/generate` call that answers with `llm_output`
+    async fn fake_ollama(llm_output: &str) -> String {
+        fake_ollama_batch(llm_output, 1).await
+    }
+
+    /// Serve `calls` Ollama `/api/generate` calls, answering only once all of
+    /// them have arrived, so the calls must be in flight together
+    async fn fake_ollama_batch(llm_output: &str, calls: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let body = serde_json::json!({ "response": llm_output }).to_string();
+
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            for _ in 0..calls {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                read_request(&mut stream).await;
+                streams.push(stream);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            for mut stream in streams {
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        endpoint
+    }
+
+    /// Answer one `/api/generate` call with each output in turn, keeping
+    /// the request bodies
+    async fn fake_ollama_replies(llm_outputs: &[&str]) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let bodies: Vec<String> =
+            llm_outputs.iter().map(|output| serde_json::json!({ "response": output }).to_string()).collect();
+
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut stream).await;
+                seen.lock().unwrap().push(serde_json::from_str(&request).unwrap());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (endpoint, requests)
+    }
+
+    /// Read the whole request so the client isn't reset mid-send, returning its body
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        let line = line.to_ascii_lowercase();
+                        line.strip_prefix("content-length:")
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if request.len() >= header_end + 4 + content_length {
+                    return text[header_end + 4..].to_string();
+                }
+            }
+            if n == 0 {
+                return String::new();
+            }
+        }
+    }
+
+    fn read_parts(selection: &Selection) -> Vec<Part> {
+        selection.parts.iter().filter(|(_, _, used)| *used).map(|(part, _, _)| *part).collect()
+    }
 
     #[test]
     fn test_extract_best_content_pdf() {
         let email = EmailContent {
+            message_id: "<test@example.com>".to_string(),
             subject: "Test".to_string(),
             from: "sender@example.com".to_string(),
             date: "2024-01-01".to_string(),
@@ -204,33 +1455,27 @@ mod tests {
                     filename: "receipt.pdf".to_string(),
                     content_type: "application/pdf".to_string(),
                     data: b"PDFDATA".to_vec(),
+                    inline: false,
                 },
                 Attachment {
                     filename: "image.jpg".to_string(),
                     content_type: "image/jpeg".to_string(),
                     data: b"IMGDATA".to_vec(),
+                    inline: false,
                 },
             ],
+            raw: Vec::new(),
         };
 
-        // Note: MarkItDownService::convert_data_to_markdown will be called.
-        // In a real test environment, we might need to mock it.
-        // For now, we just check if it prioritizes PDF.
-        let result = SyncService::extract_best_content(&email);
-        
-        // If MarkItDown is not installed, this might fail.
-        // But we can at least check the logic if we mock MarkItDownService.
-        // Since we can't easily mock in Rust without traits/dependency injection,
-        // we'll assume MarkItDown is available or the test environment handles it.
-        if let Ok((_, data, mime)) = result {
-            assert_eq!(mime, Some("application/pdf".to_string()));
-            assert_eq!(data, Some(b"PDFDATA".to_vec()));
-        }
+        let selection = SyncService::extract_best_content(&email, &FakeConverter).unwrap();
+        assert_eq!(selection.markdown, ".pdf PDFDATA");
+        assert_eq!(read_parts(&selection), vec![Part::Attachment(0)]);
     }
 
     #[test]
     fn test_extract_best_content_image() {
         let email = EmailContent {
+            message_id: "<test@example.com>".to_string(),
             subject: "Test".to_string(),
             from: "sender@example.com".to_string(),
             date: "2024-01-01".to_string(),
@@ -240,31 +1485,640 @@ mod tests {
                     filename: "image.jpg".to_string(),
                     content_type: "image/jpeg".to_string(),
                     data: b"IMGDATA".to_vec(),
+                    inline: false,
                 },
             ],
+            raw: Vec::new(),
         };
 
-        let result = SyncService::extract_best_content(&email);
-        if let Ok((_, data, mime)) = result {
-            assert_eq!(mime, Some("image/jpeg".to_string()));
-            assert_eq!(data, Some(b"IMGDATA".to_vec()));
-        }
+        let selection = SyncService::extract_best_content(&email, &FakeConverter).unwrap();
+        assert_eq!(selection.markdown, ".jpg IMGDATA");
+        assert_eq!(read_parts(&selection), vec![Part::Attachment(0)]);
     }
 
     #[test]
     fn test_extract_best_content_body() {
         let email = EmailContent {
+            message_id: "<test@example.com>".to_string(),
             subject: "Test".to_string(),
             from: "sender@example.com".to_string(),
             date: "2024-01-01".to_string(),
             body: "Email body".to_string(),
             attachments: vec![],
+            raw: Vec::new(),
         };
 
-        let result = SyncService::extract_best_content(&email);
-        if let Ok((_, data, mime)) = result {
-            assert_eq!(mime, None);
-            assert_eq!(data, None);
-        }
+        let selection = SyncService::extract_best_content(&email, &FakeConverter).unwrap();
+        assert_eq!(selection.markdown, ".html Email body");
+        assert_eq!(read_parts(&selection), vec![Part::Body]);
+    }
+
+    #[tokio::test]
+    async fn test_process_email_saves_receipt_and_pending_import() {
+        let db = DbState::in_memory();
+        let endpoint = fake_ollama(
+            r#"{"type":"subscription","confidence":0.9,"data":{"name":"Netflix","cost":15.99,"currency":"USD","billingCycle":"monthly"}}"#,
+        )
+        .await;
+        let ollama = LlmExtractor::new(OllamaService::new(endpoint), "test-model".to_string());
+        let account_id = db
+            .run(DatabaseType::Test, |conn| EmailAccountRepo::new(conn).create(&test_account("Personal")))
+            .await
+            .unwrap();
+
+        let email = EmailContent {
+            message_id: "<receipt-1@netflix.com>".to_string(),
+            subject: "Your Netflix receipt".to_string(),
+            from: "info@netflix.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: "Thanks for your payment".to_string(),
+            attachments: vec![Attachment {
+                filename: "receipt.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                data: b"PDFDATA".to_vec(),
+                inline: false,
+            }],
+            raw: Vec::new(),
+        };
+
+        let stages = std::sync::Mutex::new(Vec::new());
+        let on_stage = |stage| stages.lock().unwrap().push(stage);
+        let outcome = SyncService::process_email(&db, email.clone(), Some(account_id), &ollama, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Imported { junk: false, .. }));
+        assert_eq!(*stages.lock().unwrap(), vec![SyncStage::Converting, SyncStage::Extracting]);
+
+        // A second sync skips the message without calling the LLM again
+        let outcome = SyncService::process_email(&db, email, Some(account_id), &ollama, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ProcessOutcome::Skipped);
+        assert_eq!(stages.lock().unwrap().len(), 2);
+
+        let (import, receipt) = db
+            .run(DatabaseType::Test, |conn| {
+                let import = PendingImportRepo::new(conn)
+                    .list(&PendingImportFilter::pending(), None)?
+                    .remove(0);
+                let receipt = ReceiptRepo::new(conn).get(import.receipt_id.unwrap())?;
+                Ok((import, receipt))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(import.classification, Some("subscription".to_string()));
+        assert_eq!(import.message_id, Some("<receipt-1@netflix.com>".to_string()));
+        assert_eq!(receipt.message_id, import.message_id);
+        assert_eq!(receipt.account_id, Some(account_id));
+        assert_eq!(import.email_subject, Some("Your Netflix receipt".to_string()));
+        assert!(import.extracted_data.contains("Netflix"));
+
+        assert_eq!(receipt.email_from, Some("info@netflix.com".to_string()));
+        assert_eq!(receipt.file_type, Some("application/pdf".to_string()));
+        assert_eq!(receipt.file_size, Some(7));
+        let stored = db
+            .attachments(DatabaseType::Test)
+            .read(receipt.file_hash.as_deref().unwrap())
+            .unwrap();
+        assert_eq!(stored, b"PDFDATA");
+        assert_eq!(receipt.raw_email_body, Some("Thanks for your payment".to_string()));
+
+        // Nothing leaked into the production database
+        let production_imports = db
+            .run(DatabaseType::Production, |conn| {
+                PendingImportRepo::new(conn).count(&PendingImportFilter::default())
+            })
+            .await
+            .unwrap();
+        assert_eq!(production_imports, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rules_import_receipts_without_a_model() {
+        let db = DbState::in_memory();
+        let settings = db
+            .run(DatabaseType::Test, |conn| {
+                let settings = AppSettings { llm_provider: LlmProviderKind::Rules, ..load_settings(conn)? };
+                crate::commands::settings::save_settings(conn, settings)?;
+                load_settings(conn)
+            })
+            .await
+            .unwrap();
+        let extractor = Extractor::from_settings(&settings);
+        assert!(matches!(extractor, Extractor::Rules(_)));
+
+        let email = test_email(
+            "<receipt-1@spotify.com>",
+            "Your receipt",
+            "Your Spotify Premium receipt. Total charged: $9.99 per month.",
+        );
+        let outcome = SyncService::process_email(&db, email, None, &extractor, &FakeConverter, true, &|_| {})
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Imported { junk: false, .. }));
+
+        let import = db
+            .run(DatabaseType::Test, |conn| {
+                Ok(PendingImportRepo::new(conn).list(&PendingImportFilter::pending(), None)?.remove(0))
+            })
+            .await
+            .unwrap();
+        assert_eq!(import.classification, Some("subscription".to_string()));
+        let data: serde_json::Value = serde_json::from_str(&import.extracted_data).unwrap();
+        assert_eq!(data["name"], "Spotify Premium");
+        assert_eq!(data["billingCycle"], "monthly");
+    }
+
+    #[tokio::test]
+    async fn test_extraction_asks_once_more_when_the_answer_is_off_schema() {
+        let valid = r#"{"type":"subscription","confidence":0.9,"data":{"name":"Netflix","cost":15.99,"currency":"USD","billingCycle":"monthly"}}"#;
+        let weekly = valid.replace("monthly", "weekly");
+        let (endpoint, requests) = fake_ollama_replies(&[&weekly, valid]).await;
+        let ollama = LlmExtractor::new(OllamaService::new(endpoint), "test-model".to_string());
+
+        let result = ollama.extract_receipt_data("Netflix 15.99 USD").await.unwrap();
+        assert_eq!(result.data["billingCycle"], "monthly");
+
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["format"], extraction_schema::response_schema());
+        assert_eq!(requests[0]["options"]["temperature"], 0.0);
+        assert!(!requests[0]["prompt"].as_str().unwrap().contains("PREVIOUS ANSWER"));
+        let repair = requests[1]["prompt"].as_str().unwrap();
+        assert!(repair.contains("billingCycle") && repair.contains(&weekly), "{}", repair);
+
+        // A second bad answer fails the extraction
+        let (endpoint, requests) = fake_ollama_replies(&["Here you go: {}", &weekly]).await;
+        let ollama = LlmExtractor::new(OllamaService::new(endpoint), "test-model".to_string());
+        let error = ollama.extract_receipt_data("Netflix 15.99 USD").await.unwrap_err();
+        assert!(error.to_string().contains("billingCycle"), "{}", error);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_token_wakes_waiters() {
+        let token = CancelToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        assert!(!token.is_cancelled());
+
+        token.cancel();
+        waiter.await.unwrap();
+        assert!(token.is_cancelled());
+        // Already cancelled: resolves right away
+        token.cancelled().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_run_reports_cancelled() {
+        let db = DbState::in_memory();
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let run = SyncRun {
+            progress: Some(Arc::new({
+                let reported = reported.clone();
+                move |progress: SyncProgress| reported.lock().unwrap().push(progress.stage)
+            })),
+            cancel: CancelToken::new(),
+        };
+
+        assert_eq!(SyncService::run_sync(&db, true, &run).await.unwrap(), SyncOutcome::Completed);
+        run.cancel.cancel();
+        assert_eq!(SyncService::run_sync(&db, true, &run).await.unwrap(), SyncOutcome::Cancelled);
+
+        assert_eq!(*reported.lock().unwrap(), vec![SyncStage::Finished, SyncStage::Cancelled]);
+    }
+
+    #[test]
+    fn test_finish_item_records_outcome() {
+        let email = EmailContent {
+            message_id: "<item@example.com>".to_string(),
+            subject: "Invoice".to_string(),
+            from: "billing@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: "Total: $10".to_string(),
+            attachments: vec![],
+            raw: Vec::new(),
+        };
+        let mut item = SyncService::log_item(3, "INBOX", 7, 42, &email);
+        assert_eq!(item.message_id.as_deref(), Some("<item@example.com>"));
+
+        let failed = Err(AppError::Internal("Ollama is not running".to_string()));
+        SyncService::finish_item(&mut item, &failed, Some(SyncStage::Extracting), Duration::from_millis(1500), true);
+        assert_eq!(item.status, "failed");
+        assert_eq!(item.stage.as_deref(), Some("extracting"));
+        assert_eq!(item.duration_ms, 1500);
+        assert!(item.error_message.as_deref().unwrap().contains("Ollama is not running"));
+
+        // A retry that imports clears the error; a receipt in the other database isn't linked
+        let imported = Ok(ProcessOutcome::Imported {
+            receipt_id: 9,
+            junk: false,
+            classification: "subscription".to_string(),
+        });
+        SyncService::finish_item(&mut item, &imported, Some(SyncStage::Extracting), Duration::from_millis(800), false);
+        assert_eq!(item.status, "imported");
+        assert_eq!(item.stage.as_deref(), Some("saved"));
+        assert_eq!(item.classification.as_deref(), Some("subscription"));
+        assert_eq!(item.error_message, None);
+        assert_eq!(item.receipt_id, None);
+    }
+
+    #[test]
+    fn test_save_email_loses_race_as_skipped() {
+        let db = DbState::in_memory();
+        let conn = db.get(DatabaseType::Test).unwrap();
+        let attachments = db.attachments(DatabaseType::Test).clone();
+
+        let email = EmailContent {
+            message_id: "<race@example.com>".to_string(),
+            subject: "Invoice".to_string(),
+            from: "billing@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: "Total: $10".to_string(),
+            attachments: vec![],
+            raw: Vec::new(),
+        };
+        let extraction = || LlmExtractionResult {
+            classification: "junk".to_string(),
+            confidence: 0.5,
+            data: serde_json::json!({}),
+        };
+
+        // Both syncs passed the early check; only one may store the message
+        let first = SyncService::store_email(&conn, &attachments, email.clone(), None).unwrap();
+        let second = SyncService::store_email(&conn, &attachments, email, None).unwrap();
+        assert!(first.is_some());
+        assert_eq!(second, None);
+
+        // ...and only one may create its pending import
+        let receipt = ReceiptRepo::new(&conn).get(first.unwrap()).unwrap();
+        let first = SyncService::save_extraction(&conn, &receipt, extraction()).unwrap();
+        let second = SyncService::save_extraction(&conn, &receipt, extraction()).unwrap();
+        assert!(matches!(first, ProcessOutcome::Imported { junk: true, .. }));
+        assert_eq!(second, ProcessOutcome::Skipped);
+
+        let receipts: i64 = conn
+            .query_row("SELECT COUNT(*) FROM receipts", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(receipts, 1);
+        assert_eq!(ReceiptRepo::new(&conn).get(receipt.id.unwrap()).unwrap().processing_state, "extracted");
+    }
+
+    #[tokio::test]
+    async fn test_failed_extraction_is_kept_for_reprocessing() {
+        let db = DbState::in_memory();
+        let email = EmailContent {
+            message_id: "<retry@example.com>".to_string(),
+            subject: "Your invoice".to_string(),
+            from: "billing@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: "Total: $10".to_string(),
+            attachments: vec![Attachment {
+                filename: "invoice.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                data: b"PDFDATA".to_vec(),
+                inline: false,
+            }],
+            raw: Vec::new(),
+        };
+
+        // Nothing listens on a port freed right away
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let ollama = LlmExtractor::new(OllamaService::new(unreachable), "test-model".to_string());
+        let account_id = db
+            .run(DatabaseType::Test, |conn| EmailAccountRepo::new(conn).create(&test_account("Personal")))
+            .await
+            .unwrap();
+
+        let outcome = SyncService::process_email(&db, email.clone(), Some(account_id), &ollama, &FakeConverter, true, &|_| {})
+            .await
+            .unwrap();
+        let ProcessOutcome::Failed { receipt_id, .. } = outcome else {
+            panic!("expected a failed extraction, got {:?}", outcome);
+        };
+        let receipt = db
+            .run(DatabaseType::Test, move |conn| ReceiptRepo::new(conn).get(receipt_id))
+            .await
+            .unwrap();
+        assert_eq!(receipt.processing_state, "failed");
+        assert!(receipt.processing_error.is_some());
+        assert_eq!(receipt.file_size, Some(7));
+
+        // The stored copy is extracted once Ollama answers, reusing the same receipt
+        let endpoint = fake_ollama(r#"{"type":"junk","confidence":0.8,"data":{}}"#).await;
+        let ollama = LlmExtractor::new(OllamaService::new(endpoint), "test-model".to_string());
+        let stages = std::sync::Mutex::new(Vec::new());
+        let outcome = SyncService::process_email(&db, email, Some(account_id), &ollama, &FakeConverter, true, &|stage| {
+            stages.lock().unwrap().push(stage)
+        })
+        .await
+        .unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Imported { receipt_id: id, junk: true, .. } if id == receipt_id));
+        assert_eq!(*stages.lock().unwrap(), vec![SyncStage::Converting, SyncStage::Extracting]);
+
+        let (receipt, receipts) = db
+            .run(DatabaseType::Test, move |conn| {
+                let receipts: i64 = conn.query_row("SELECT COUNT(*) FROM receipts", [], |r| r.get(0))?;
+                Ok((ReceiptRepo::new(conn).get(receipt_id)?, receipts))
+            })
+            .await
+            .unwrap();
+        assert_eq!(receipt.processing_state, "extracted");
+        assert_eq!(receipt.processing_error, None);
+        assert_eq!(receipts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_extractions_run_concurrently() {
+        let db = DbState::in_memory();
+        // Both calls are answered only once both have arrived
+        let endpoint = fake_ollama_batch(r#"{"type":"junk","confidence":0.8,"data":{}}"#, 2).await;
+        let ollama = LlmExtractor::new(OllamaService::new(endpoint), "test-model".to_string()).with_concurrency(2);
+        let account_id = db
+            .run(DatabaseType::Test, |conn| EmailAccountRepo::new(conn).create(&test_account("Personal")))
+            .await
+            .unwrap();
+        let email = |n: u32| EmailContent {
+            message_id: format!("<promo-{}@example.com>", n),
+            subject: "Sale".to_string(),
+            from: "shop@example.com".to_string(),
+            date: "2024-01-01".to_string(),
+            body: "Everything must go".to_string(),
+            attachments: vec![],
+            raw: Vec::new(),
+        };
+
+        let both = futures::future::join(
+            SyncService::process_email(&db, email(1), Some(account_id), &ollama, &FakeConverter, true, &|_| {}),
+            SyncService::process_email(&db, email(2), Some(account_id), &ollama, &FakeConverter, true, &|_| {}),
+        );
+        let (first, second) = tokio::time::timeout(Duration::from_secs(10), both)
+            .await
+            .expect("extractions ran one at a time");
+        assert!(matches!(first.unwrap(), ProcessOutcome::Imported { junk: true, .. }));
+        assert!(matches!(second.unwrap(), ProcessOutcome::Imported { junk: true, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_sync_mailbox_runs_the_whole_pipeline() {
+        let db = DbState::in_memory();
+        let mut account = test_account("Personal");
+        account.imported_action = MessageAction { mark_seen: true, ..Default::default() };
+        account.junk_action = MessageAction { move_to: Some("Junk".to_string()), ..Default::default() };
+        let (account_id, sync_log_id) = db
+            .run(DatabaseType::Test, move |conn| {
+                let account_id = EmailAccountRepo::new(conn).create(&account)?;
+                Ok((account_id, SyncLogRepo::new(conn).start(account_id)?))
+            })
+            .await
+            .unwrap();
+        let account = db.run(DatabaseType::Test, move |conn| EmailAccountRepo::new(conn).get(account_id)).await.unwrap();
+
+        let source = FakeMailSource::new(vec![
+            (3, test_email("<receipt@netflix.com>", "Your Netflix receipt", "Thanks for your payment")),
+            (5, test_email("<sale@shop.com>", "Weekend deals", "Sale: everything must go")),
+            (8, test_email("<scan@example.com>", "Invoice", "garbled")),
+        ]);
+        let run = SyncRun::default();
+
+        let stats = SyncService::sync_mailbox(
+            &db, &source, &account, "INBOX", &FakeConverter, &FakeExtractor, true, &run, sync_log_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!((stats.processed, stats.imported, stats.skipped), (3, 2, 0));
+
+        let (items, cursor, states) = db
+            .run(DatabaseType::Test, move |conn| {
+                let items = SyncLogItemRepo::new(conn).list_for_log(sync_log_id)?;
+                let cursor = MailboxCursorRepo::new(conn).find(account_id, "INBOX")?;
+                let states = ReceiptRepo::new(conn)
+                    .list(&ReceiptFilter::default(), None)?
+                    .into_iter()
+                    .map(|receipt| (receipt.email_subject.unwrap_or_default(), receipt.processing_state))
+                    .collect::<Vec<_>>();
+                Ok((items, cursor, states))
+            })
+            .await
+            .unwrap();
+        let statuses: Vec<_> = items.iter().map(|item| (item.uid, item.status.as_str())).collect();
+        assert_eq!(statuses, vec![(3, "imported"), (5, "imported"), (8, "failed")]);
+        assert_eq!(items[1].classification.as_deref(), Some("junk"));
+        assert_eq!(items[2].stage.as_deref(), Some("extracting"));
+        assert_eq!(cursor.unwrap().last_uid, 8);
+        assert_eq!(states.len(), 3);
+        assert!(states.contains(&("Invoice".to_string(), "failed".to_string())));
+
+        // Imports and junk each get their own action
+        let actions = source.actions.lock().unwrap().clone();
+        assert_eq!(actions, vec![(vec![3], account.imported_action.clone()), (vec![5], account.junk_action.clone())]);
+
+        // Everything was handled, so nothing is found again
+        let stats = SyncService::sync_mailbox(
+            &db, &source, &account, "INBOX", &FakeConverter, &FakeExtractor, true, &run, sync_log_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!(stats.processed, 0);
+    }
+
+    #[tokio::test]
+    async fn test_records_the_parts_read_from_each_email() {
+        let db = DbState::in_memory();
+        let attachment = |filename: &str, data: &str| Attachment {
+            filename: filename.to_string(),
+            content_type: "application/pdf".to_string(),
+            data: data.as_bytes().to_vec(),
+            inline: false,
+        };
+        let email = EmailContent {
+            attachments: vec![
+                attachment("Spring-Catalog.pdf", "Spring styles for everyone"),
+                attachment("invoice.pdf", "Invoice total 15.99 USD, paid by card"),
+            ],
+            ..test_email("<order@shop.com>", "Your order", "Hello from our shop")
+        };
+
+        let on_stage = |_| {};
+        let outcome = SyncService::process_email(&db, email, None, &FakeExtractor, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        let ProcessOutcome::Imported { receipt_id, .. } = outcome else {
+            panic!("unexpected outcome {:?}", outcome);
+        };
+
+        let attachments = db.attachments(DatabaseType::Test).clone();
+        let (receipt, sources, stored) = db
+            .run(DatabaseType::Test, move |conn| {
+                let receipt = ReceiptRepo::new(conn).get(receipt_id)?;
+                let sources = ReceiptSourceRepo::new(conn).list(receipt_id)?;
+                let stored = SyncService::stored_email(&receipt, &sources, &attachments)?;
+                Ok((receipt, sources, stored))
+            })
+            .await
+            .unwrap();
+
+        let read: Vec<_> = sources.iter().map(|source| (source.name.as_str(), source.used)).collect();
+        assert_eq!(read, vec![("invoice.pdf", true), ("Spring-Catalog.pdf", false), ("body", false)]);
+        assert_eq!(receipt.file_hash, sources[0].file_hash);
+
+        // Reprocessing reads the same parts again
+        let names: Vec<_> = stored.attachments.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, vec!["invoice.pdf", "Spring-Catalog.pdf"]);
+        assert_eq!(stored.body, "Hello from our shop");
+    }
+
+    #[tokio::test]
+    async fn test_reprocessing_parses_the_stored_message_again() {
+        let db = DbState::in_memory();
+        let raw = b"From: billing@shop.com\r\n\
+                    Message-ID: <raw@shop.com>\r\n\
+                    Subject: Your invoice\r\n\
+                    Date: Mon, 1 Jan 2024 10:00:00 +0000\r\n\
+                    Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+                    \r\n\
+                    --b\r\n\
+                    Content-Type: text/plain\r\n\
+                    \r\n\
+                    Thanks for your order.\r\n\
+                    --b\r\n\
+                    Content-Type: application/pdf\r\n\
+                    Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+                    \r\n\
+                    Invoice total 15.99 USD\r\n\
+                    --b\r\n\
+                    Content-Type: text/calendar\r\n\
+                    Content-Disposition: attachment; filename=\"delivery.ics\"\r\n\
+                    \r\n\
+                    BEGIN:VCALENDAR\r\n\
+                    --b--";
+        let email = parse_email(raw).unwrap();
+
+        let on_stage = |_| {};
+        let outcome = SyncService::process_email(&db, email.clone(), None, &FakeExtractor, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        let ProcessOutcome::Imported { receipt_id, .. } = outcome else {
+            panic!("unexpected outcome {:?}", outcome);
+        };
+
+        let attachments = db.attachments(DatabaseType::Test).clone();
+        let (receipt, stored) = db
+            .run(DatabaseType::Test, move |conn| {
+                let receipt = ReceiptRepo::new(conn).get(receipt_id)?;
+                let sources = ReceiptSourceRepo::new(conn).list(receipt_id)?;
+                let stored = SyncService::stored_email(&receipt, &sources, &attachments)?;
+                Ok((receipt, stored))
+            })
+            .await
+            .unwrap();
+
+        assert!(receipt.raw_message_hash.is_some());
+        assert_eq!(stored.raw, raw.to_vec());
+        assert_eq!(stored.message_id, "<raw@shop.com>");
+        // The calendar file was never a candidate, but the message still has it
+        let names: Vec<_> = stored.attachments.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, vec!["invoice.pdf", "delivery.ics"]);
+        assert_eq!(stored.body, email.body);
+    }
+
+    #[tokio::test]
+    async fn test_photographed_receipts_are_read_with_ocr() {
+        let db = DbState::in_memory();
+        let with_attachment = |message_id: &str, content_type: &str, data: &[u8]| EmailContent {
+            attachments: vec![Attachment {
+                filename: String::new(),
+                content_type: content_type.to_string(),
+                data: data.to_vec(),
+                inline: false,
+            }],
+            ..test_email(message_id, "Receipt", "")
+        };
+        let stages = std::sync::Mutex::new(Vec::new());
+        let on_stage = |stage| stages.lock().unwrap().push(stage);
+        let receipt = |outcome: ProcessOutcome| {
+            let receipt_id = match outcome {
+                ProcessOutcome::Imported { receipt_id, .. } | ProcessOutcome::Failed { receipt_id, .. } => receipt_id,
+                other => panic!("unexpected outcome {:?}", other),
+            };
+            db.run(DatabaseType::Test, move |conn| ReceiptRepo::new(conn).get(receipt_id))
+        };
+
+        let photo = with_attachment("<photo@example.com>", "image/png", b"PHOTO");
+        let outcome = SyncService::process_email(&db, photo, None, &FakeExtractor, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Imported { junk: false, .. }));
+        assert_eq!(
+            *stages.lock().unwrap(),
+            vec![SyncStage::Converting, SyncStage::Recognizing, SyncStage::Extracting]
+        );
+        let stored = receipt(outcome).await.unwrap();
+        assert_eq!(stored.ocr_text.as_deref(), Some("NETFLIX Premium 15.99 USD"));
+        assert_eq!(stored.ocr_confidence, Some(0.8));
+
+        // A document with enough text is not sent to OCR
+        stages.lock().unwrap().clear();
+        let text = "Netflix Premium, billed monthly. Total charged: 15.99 USD";
+        let pdf = with_attachment("<pdf@example.com>", "application/pdf", text.as_bytes());
+        let outcome = SyncService::process_email(&db, pdf, None, &FakeExtractor, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        assert_eq!(*stages.lock().unwrap(), vec![SyncStage::Converting, SyncStage::Extracting]);
+        assert_eq!(receipt(outcome).await.unwrap().ocr_text, None);
+
+        // An unreadable photo fails like any other extraction, to be retried
+        let blurry = with_attachment("<blurry@example.com>", "image/jpeg", b"blurry");
+        let outcome = SyncService::process_email(&db, blurry, None, &FakeExtractor, &FakeConverter, true, &on_stage)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, ProcessOutcome::Failed { .. }));
+        assert_eq!(receipt(outcome).await.unwrap().processing_state, "failed");
+    }
+
+    #[tokio::test]
+    async fn test_import_from_files_creates_pending_imports() {
+        let db = DbState::in_memory();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Invoices.mbox");
+        std::fs::write(
+            &path,
+            concat!(
+                "From a Mon Jan  1 10:00:00 2024\n",
+                "Message-ID: <receipt@netflix.com>\nSubject: Your receipt\nContent-Type: text/plain\n\n",
+                "Thanks for your payment\n\n",
+                "From b Tue Jan  2 10:00:00 2024\n",
+                "Message-ID: <scan@example.com>\nSubject: Scan\nContent-Type: text/plain\n\n",
+                "garbled\n",
+            ),
+        )
+        .unwrap();
+        let source = LocalMailSource::open(&path).unwrap();
+
+        let summary =
+            SyncService::import_from(&db, &source, "Invoices.mbox", &FakeConverter, &FakeExtractor, true, &SyncRun::default())
+                .await
+                .unwrap();
+        assert_eq!(summary, ImportSummary { found: 2, imported: 1, failed: 1, skipped: 0 });
+
+        let receipts = db
+            .run(DatabaseType::Test, |conn| ReceiptRepo::new(conn).list(&ReceiptFilter::default(), None))
+            .await
+            .unwrap();
+        assert_eq!(receipts.len(), 2);
+        assert!(receipts.iter().all(|receipt| receipt.account_id.is_none()));
+
+        // Importing again only retries the failed extraction
+        let summary =
+            SyncService::import_from(&db, &source, "Invoices.mbox", &FakeConverter, &FakeExtractor, true, &SyncRun::default())
+                .await
+                .unwrap();
+        assert_eq!(summary, ImportSummary { found: 2, imported: 0, failed: 1, skipped: 1 });
     }
 }
@@ -6,6 +6,9 @@ pub enum AppError {
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
 
+    #[error("Database pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
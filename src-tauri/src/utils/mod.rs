@@ -4,6 +4,7 @@
 pub mod error;
 
 use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
 pub use error::{AppError, AppResult};
 
 /// Get current timestamp in ISO 8601 format
@@ -12,6 +13,14 @@ pub fn get_current_timestamp() -> String {
     now.to_rfc3339()
 }
 
+/// Lowercase hex SHA-256 digest
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 /// Validate email address format (basic validation)
 pub fn is_valid_email(email: &str) -> bool {
     email.contains('@') && email.contains('.')
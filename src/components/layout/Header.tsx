@@ -1,20 +1,116 @@
 import { type ReactElement, useEffect, useState } from 'react';
 
-import { RefreshCw, Clock, Mail } from 'lucide-react';
+import { RefreshCw, Clock, Mail, Pause, Play, X } from 'lucide-react';
 
 import { Button } from '@/components/ui/button';
 import { useToast } from '@/hooks/use-toast';
-import { triggerEmailSync, getLastSyncTime } from '@/lib/tauri';
+import {
+  triggerEmailSync,
+  cancelEmailSync,
+  onSyncProgress,
+  getLastSyncTime,
+  getSyncStatus,
+  pauseSync,
+  resumeSync,
+} from '@/lib/tauri';
+import type { SchedulerStatus, SyncProgress } from '@/lib/types';
+
+function describeProgress(progress: SyncProgress): string {
+  const position =
+    progress.index !== null && progress.total !== null ? `${progress.index}/${progress.total}` : '';
+  switch (progress.stage) {
+    case 'connected':
+      return 'Connected';
+    case 'messagesFound':
+      return `${progress.total ?? 0} new in ${progress.mailbox ?? 'folder'}`;
+    case 'converting':
+      return `Converting ${position}`;
+    case 'recognizing':
+      return `Reading scan ${position}`;
+    case 'extracting':
+      return `Extracting ${position}`;
+    case 'saved':
+      return `Saved ${position}`;
+    case 'skipped':
+      return `Skipped ${position}`;
+    case 'failed':
+      return position ? `Failed ${position}` : 'Account failed';
+    case 'cancelled':
+      return 'Cancelled';
+    case 'finished':
+      return 'Done';
+  }
+}
 
 export function Header(): ReactElement {
   const { toast } = useToast();
   const [isSyncing, setIsSyncing] = useState(false);
   const [lastSync, setLastSync] = useState<string | null>(null);
+  const [status, setStatus] = useState<SchedulerStatus | null>(null);
+  const [progress, setProgress] = useState<SyncProgress | null>(null);
 
   useEffect(() => {
     void loadLastSync();
+    void loadStatus();
+    const timer = setInterval(() => void loadStatus(), 30_000);
+    return () => clearInterval(timer);
   }, []);
 
+  // Background syncs report here too, not just the ones started from this button
+  useEffect(() => {
+    const unlisten = onSyncProgress((update) => {
+      const done = update.stage === 'finished' || update.stage === 'cancelled';
+      setProgress(done ? null : update);
+      if (done) {
+        void loadLastSync();
+        void loadStatus();
+      }
+    }).catch((error) => {
+      console.error('Failed to listen for sync progress:', error);
+      return undefined;
+    });
+    return () => {
+      void unlisten.then((stop) => stop?.());
+    };
+  }, []);
+
+  async function handleCancel(): Promise<void> {
+    try {
+      await cancelEmailSync();
+    } catch (error) {
+      toast({
+        title: 'Error',
+        description: String(error),
+        variant: 'destructive',
+      });
+    }
+  }
+
+  async function loadStatus(): Promise<void> {
+    try {
+      setStatus(await getSyncStatus());
+    } catch (error) {
+      console.error('Failed to load sync status:', error);
+    }
+  }
+
+  async function handleTogglePause(): Promise<void> {
+    try {
+      if (status?.paused) {
+        await resumeSync();
+      } else {
+        await pauseSync();
+      }
+      await loadStatus();
+    } catch (error) {
+      toast({
+        title: 'Error',
+        description: String(error),
+        variant: 'destructive',
+      });
+    }
+  }
+
   async function loadLastSync(): Promise<void> {
     try {
       const time = await getLastSyncTime(false);
@@ -27,12 +123,13 @@ export function Header(): ReactElement {
   async function handleSync(): Promise<void> {
     setIsSyncing(true);
     try {
-      await triggerEmailSync(false);
+      const message = await triggerEmailSync(false);
       toast({
-        title: 'Sync Started',
-        description: 'Checking for new receipts...',
+        title: 'Sync Finished',
+        description: message,
       });
       await loadLastSync();
+      await loadStatus();
     } catch (error) {
       toast({
         title: 'Sync Failed',
@@ -63,6 +160,37 @@ export function Header(): ReactElement {
           </div>
         )}
 
+        {progress && (
+          <div
+            className="hidden max-w-xs items-center gap-2 truncate font-mono text-xs text-[#6b6b6b] md:flex"
+            title={progress.detail ?? undefined}
+          >
+            {describeProgress(progress)}
+            <button
+              onClick={() => void handleCancel()}
+              title="Cancel sync"
+              className="rounded p-1 hover:bg-[#f0ede8] hover:text-[#2a2a2a]"
+            >
+              <X className="h-3.5 w-3.5" />
+            </button>
+          </div>
+        )}
+
+        {status && (
+          <div className="hidden items-center gap-2 font-mono text-xs text-[#6b6b6b] md:flex">
+            {status.paused
+              ? 'Auto sync paused'
+              : status.nextRunAt && `Next: ${new Date(status.nextRunAt).toLocaleTimeString()}`}
+            <button
+              onClick={() => void handleTogglePause()}
+              title={status.paused ? 'Resume automatic sync' : 'Pause automatic sync'}
+              className="rounded p-1 hover:bg-[#f0ede8] hover:text-[#2a2a2a]"
+            >
+              {status.paused ? <Play className="h-3.5 w-3.5" /> : <Pause className="h-3.5 w-3.5" />}
+            </button>
+          </div>
+        )}
+
         <Button
           variant="outline"
           size="sm"
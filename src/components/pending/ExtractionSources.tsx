@@ -0,0 +1,65 @@
+import { type ReactElement, useEffect, useState } from 'react';
+
+import { FileText, ScanText } from 'lucide-react';
+
+import { getReceiptById, getReceiptSources } from '@/lib/tauri';
+import type { Receipt, ReceiptSource } from '@/lib/types';
+
+const PERCENTAGE_MULTIPLIER = 100;
+
+interface ExtractionSourcesProps {
+  receiptId: number;
+}
+
+/** What the LLM read: the parts of the email it was given and any OCR text */
+export function ExtractionSources({ receiptId }: ExtractionSourcesProps): ReactElement | null {
+  const [receipt, setReceipt] = useState<Receipt | null>(null);
+  const [sources, setSources] = useState<ReceiptSource[]>([]);
+
+  useEffect(() => {
+    let cancelled = false;
+    Promise.all([getReceiptById(receiptId, false), getReceiptSources(receiptId, false)])
+      .then(([found, foundSources]) => {
+        if (!cancelled) {
+          setReceipt(found);
+          setSources(foundSources);
+        }
+      })
+      .catch((error: unknown) => console.error('Failed to load receipt:', error));
+    return () => {
+      cancelled = true;
+    };
+  }, [receiptId]);
+
+  const read = sources.filter((source) => source.used);
+  if (read.length === 0 && !receipt?.ocrText) {
+    return null;
+  }
+
+  return (
+    <div className="mb-4 space-y-2">
+      {read.length > 0 && (
+        <div className="flex items-start gap-2">
+          <FileText className="mt-0.5 h-3.5 w-3.5 flex-shrink-0 text-[#6b6b6b]" />
+          <p className="font-mono text-xs text-[#6b6b6b]">
+            Read from {read.map((source) => source.name).join(' + ')}
+            {sources.length > read.length && ` (${sources.length - read.length} more skipped)`}
+          </p>
+        </div>
+      )}
+      {receipt?.ocrText && (
+        <details className="border-2 border-dashed border-[#e5e5e5] p-3">
+          <summary className="flex cursor-pointer items-center gap-2 font-mono text-xs text-[#6b6b6b]">
+            <ScanText className="h-3.5 w-3.5" />
+            Scanned text
+            {receipt.ocrConfidence != null &&
+              ` · ${Math.round(receipt.ocrConfidence * PERCENTAGE_MULTIPLIER)}% legible`}
+          </summary>
+          <pre className="mt-2 max-h-48 overflow-auto whitespace-pre-wrap font-mono text-xs text-[#2a2a2a]">
+            {receipt.ocrText}
+          </pre>
+        </details>
+      )}
+    </div>
+  );
+}
@@ -15,6 +15,7 @@ import type {
 
 import { ConfidenceScore } from './ConfidenceScore';
 import { EditDialog } from './EditDialog';
+import { ExtractionSources } from './ExtractionSources';
 import { SubscriptionFields, DomainFields } from './ImportDataFields';
 
 interface PendingImportCardProps {
@@ -184,6 +185,8 @@ export function PendingImportCard({
             )}
           </div>
 
+          {importItem.receiptId !== null && <ExtractionSources receiptId={importItem.receiptId} />}
+
           {/* Confidence Score */}
           <div className="mb-4">
             <ConfidenceScore score={importItem.confidence} />
@@ -76,6 +76,21 @@ export function DisplaySettings({
             How often to check for new emails.
           </p>
         </div>
+
+        <div className="space-y-2">
+          <div className="flex items-center space-x-2">
+            <input
+              id="pushSync"
+              type="checkbox"
+              checked={settings.pushSync}
+              onChange={(e) => onUpdate({ pushSync: e.target.checked })}
+            />
+            <Label htmlFor="pushSync">Push Sync</Label>
+          </div>
+          <p className="text-[10px] text-[#9b9b9b]">
+            Import new mail right away on servers with IMAP IDLE. Others keep using the interval.
+          </p>
+        </div>
       </div>
     </div>
   );
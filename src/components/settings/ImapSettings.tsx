@@ -1,113 +1,614 @@
-import { type ReactElement } from 'react';
+import { type ReactElement, useCallback, useEffect, useState } from 'react';
 import { Input } from '@/components/ui/input';
 import { Label } from '@/components/ui/label';
-import { Checkbox } from '@/components/ui/checkbox';
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from '@/components/ui/select';
 import { Button } from '@/components/ui/button';
-import { AppSettings } from '@/lib/types';
+import { useToast } from '@/hooks/use-toast';
+import {
+  EmailAccount,
+  ImapAuthMethod,
+  ImapSecurity,
+  MessageAction,
+  OAuthProvider,
+} from '@/lib/types';
+import {
+  authorizeAccountOAuth,
+  createEmailAccount,
+  deleteEmailAccount,
+  disconnectAccountOAuth,
+  getEmailAccounts,
+  isAccountOAuthAuthorized,
+  saveAccountPassword,
+  testImapConnection,
+  updateEmailAccount,
+} from '@/lib/tauri';
 
-interface ImapSettingsProps {
-  settings: AppSettings;
-  onUpdate: (updates: Partial<AppSettings>) => void;
-  onTestConnection: () => void;
-  isTesting: boolean;
+const inputClass = 'border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0';
+const outlineButtonClass =
+  'border-[#2a2a2a] text-[#2a2a2a] hover:bg-[#2a2a2a] hover:text-white';
+
+function newAccount(): EmailAccount {
+  return {
+    name: '',
+    server: '',
+    port: 993,
+    username: '',
+    security: 'tls',
+    caCertificate: null,
+    pinnedCertificate: null,
+    authMethod: 'password',
+    oauthProvider: 'google',
+    oauthClientId: null,
+    oauthClientSecret: null,
+    folders: ['INBOX'],
+    search: { since: null, from: [], subject: null },
+    importedAction: { markSeen: false, keyword: null, copyTo: null, moveTo: null },
+    junkAction: { markSeen: false, keyword: null, copyTo: null, moveTo: null },
+    enabled: true,
+  };
+}
+
+/** One entry per line, blanks dropped */
+function splitLines(value: string): string[] {
+  return value
+    .split('\n')
+    .map((line) => line.trim())
+    .filter((line) => line.length > 0);
+}
+
+interface MessageActionFieldsProps {
+  id: string;
+  title: string;
+  action: MessageAction;
+  onChange: (action: MessageAction) => void;
 }
 
-export function ImapSettings({
-  settings,
-  onUpdate,
-  onTestConnection,
-  isTesting,
-}: ImapSettingsProps): ReactElement {
+function MessageActionFields({ id, title, action, onChange }: MessageActionFieldsProps): ReactElement {
+  const update = (updates: Partial<MessageAction>) => onChange({ ...action, ...updates });
+
   return (
-    <div className="space-y-8">
-      <div>
-        <h3 className="font-display text-lg font-semibold text-[#2a2a2a]">
-          IMAP Configuration
-        </h3>
-        <p className="text-sm text-[#6b6b6b]">
-          Configure the email account where you receive your receipts.
-        </p>
+    <div className="space-y-3">
+      <h4 className="text-sm font-semibold text-[#2a2a2a]">{title}</h4>
+      <div className="flex items-center space-x-2">
+        <input
+          id={`${id}MarkSeen`}
+          type="checkbox"
+          checked={action.markSeen}
+          onChange={(e) => update({ markSeen: e.target.checked })}
+        />
+        <Label htmlFor={`${id}MarkSeen`}>Mark as read</Label>
+      </div>
+      <div className="space-y-2">
+        <Label htmlFor={`${id}Keyword`}>Add Keyword</Label>
+        <Input
+          id={`${id}Keyword`}
+          placeholder="$SubScriptImported"
+          value={action.keyword ?? ''}
+          onChange={(e) => update({ keyword: e.target.value || null })}
+          className={`${inputClass} font-mono`}
+        />
+      </div>
+      <div className="space-y-2">
+        <Label htmlFor={`${id}CopyTo`}>Copy To Folder</Label>
+        <Input
+          id={`${id}CopyTo`}
+          value={action.copyTo ?? ''}
+          onChange={(e) => update({ copyTo: e.target.value || null })}
+          className={inputClass}
+        />
+      </div>
+      <div className="space-y-2">
+        <Label htmlFor={`${id}MoveTo`}>Move To Folder</Label>
+        <Input
+          id={`${id}MoveTo`}
+          value={action.moveTo ?? ''}
+          onChange={(e) => update({ moveTo: e.target.value || null })}
+          className={inputClass}
+        />
       </div>
+    </div>
+  );
+}
 
-      <div className="grid gap-6 md:grid-cols-2">
-        <div className="space-y-2">
-          <Label htmlFor="imapServer">IMAP Server</Label>
-          <Input
-            id="imapServer"
-            placeholder="imap.gmail.com"
-            value={settings.imapServer}
-            onChange={(e) => onUpdate({ imapServer: e.target.value })}
-            className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
-          />
-        </div>
+interface AccountEditorProps {
+  account: EmailAccount;
+  onUpdate: (updates: Partial<EmailAccount>) => void;
+}
 
-        <div className="space-y-2">
-          <Label htmlFor="imapPort">Port</Label>
-          <Input
-            id="imapPort"
-            type="number"
-            placeholder="993"
-            value={settings.imapPort}
-            onChange={(e) => onUpdate({ imapPort: parseInt(e.target.value) || 0 })}
-            className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
-          />
-        </div>
+function OAuthSignIn({ account, onUpdate }: AccountEditorProps): ReactElement {
+  const [isAuthorized, setIsAuthorized] = useState(false);
+  const [isSigningIn, setIsSigningIn] = useState(false);
+  const [error, setError] = useState<string | null>(null);
 
-        <div className="space-y-2">
-          <Label htmlFor="imapUsername">Email Address</Label>
-          <Input
-            id="imapUsername"
-            type="email"
-            placeholder="your@email.com"
-            value={settings.imapUsername}
-            onChange={(e) => onUpdate({ imapUsername: e.target.value })}
-            className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
-          />
-        </div>
+  useEffect(() => {
+    if (account.id === undefined) {
+      setIsAuthorized(false);
+      return;
+    }
+    isAccountOAuthAuthorized(account.id)
+      .then(setIsAuthorized)
+      .catch(() => setIsAuthorized(false));
+  }, [account.id]);
 
-        <div className="space-y-2">
-          <Label htmlFor="imapPassword">App Password</Label>
-          <Input
-            id="imapPassword"
-            type="password"
-            placeholder="••••••••••••••••"
-            className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
-            // Note: Password is not stored in AppSettings for security, 
-            // it should be handled separately or via a secure vault.
-            // For now, we'll just show the field.
-          />
-          <p className="text-[10px] text-[#9b9b9b]">
-            Use an App Password if you have 2FA enabled.
-          </p>
-        </div>
+  const handleSignIn = async () => {
+    if (account.id === undefined) return;
+    setIsSigningIn(true);
+    setError(null);
+    try {
+      // Sign-in reads the provider and client from the stored account
+      await updateEmailAccount(account);
+      await authorizeAccountOAuth(account.id);
+      setIsAuthorized(true);
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setIsSigningIn(false);
+    }
+  };
+
+  const handleDisconnect = async () => {
+    if (account.id === undefined) return;
+    setError(null);
+    try {
+      await disconnectAccountOAuth(account.id);
+      setIsAuthorized(false);
+    } catch (err) {
+      setError(String(err));
+    }
+  };
+
+  return (
+    <>
+      <div className="space-y-2">
+        <Label htmlFor="oauthProvider">Provider</Label>
+        <Select
+          value={account.oauthProvider}
+          onValueChange={(value) => onUpdate({ oauthProvider: value as OAuthProvider })}
+        >
+          <SelectTrigger id="oauthProvider">
+            <SelectValue />
+          </SelectTrigger>
+          <SelectContent>
+            <SelectItem value="google">Google</SelectItem>
+            <SelectItem value="microsoft">Microsoft 365</SelectItem>
+          </SelectContent>
+        </Select>
       </div>
 
-      <div className="flex items-center space-x-2">
-        <Checkbox
-          id="imapUseSsl"
-          checked={settings.imapUseSsl}
-          onCheckedChange={(checked) =>
-            onUpdate({ imapUseSsl: checked === true })
-          }
+      <div className="space-y-2">
+        <Label htmlFor="oauthClientId">OAuth Client ID</Label>
+        <Input
+          id="oauthClientId"
+          value={account.oauthClientId ?? ''}
+          onChange={(e) => onUpdate({ oauthClientId: e.target.value || null })}
+          className={inputClass}
         />
-        <Label
-          htmlFor="imapUseSsl"
-          className="text-sm font-medium leading-none peer-disabled:cursor-not-allowed peer-disabled:opacity-70"
-        >
-          Use SSL/TLS
-        </Label>
       </div>
 
-      <div className="pt-4">
+      <div className="space-y-2">
+        <Label htmlFor="oauthClientSecret">Client Secret (optional)</Label>
+        <Input
+          id="oauthClientSecret"
+          value={account.oauthClientSecret ?? ''}
+          onChange={(e) => onUpdate({ oauthClientSecret: e.target.value || null })}
+          className={inputClass}
+        />
+        <p className="text-[10px] text-[#9b9b9b]">
+          Google desktop clients need one; Microsoft public clients do not.
+        </p>
+      </div>
+
+      <div className="space-y-2 md:col-span-2">
+        <div className="flex items-center space-x-3">
+          {isAuthorized ? (
+            <Button variant="outline" onClick={handleDisconnect} className={outlineButtonClass}>
+              Disconnect
+            </Button>
+          ) : (
+            <Button
+              variant="outline"
+              onClick={handleSignIn}
+              disabled={isSigningIn || account.id === undefined || !account.oauthClientId}
+              className={outlineButtonClass}
+            >
+              {isSigningIn ? 'Waiting for browser...' : 'Sign in with browser'}
+            </Button>
+          )}
+          <span className="text-sm font-mono text-[#6b6b6b]">
+            {account.id === undefined
+              ? 'Save the account first'
+              : isAuthorized
+                ? 'Signed in'
+                : 'Not signed in'}
+          </span>
+        </div>
+        {error && <p className="text-xs text-red-600">{error}</p>}
+      </div>
+    </>
+  );
+}
+
+export function ImapSettings(): ReactElement {
+  const { toast } = useToast();
+  const [accounts, setAccounts] = useState<EmailAccount[]>([]);
+  const [draft, setDraft] = useState<EmailAccount | null>(null);
+  const [password, setPassword] = useState('');
+  const [isSaving, setIsSaving] = useState(false);
+  const [isTesting, setIsTesting] = useState(false);
+
+  const loadAccounts = useCallback(async () => {
+    try {
+      const data = await getEmailAccounts();
+      setAccounts(data);
+      return data;
+    } catch (error) {
+      console.error('Failed to load email accounts:', error);
+      toast({
+        title: 'Error',
+        description: 'Failed to load email accounts.',
+        variant: 'destructive',
+      });
+      return [];
+    }
+  }, [toast]);
+
+  useEffect(() => {
+    loadAccounts().then((data) => setDraft(data[0] ?? null));
+  }, [loadAccounts]);
+
+  const handleSelect = (account: EmailAccount) => {
+    setDraft(account);
+    setPassword('');
+  };
+
+  const handleUpdate = (updates: Partial<EmailAccount>) => {
+    if (!draft) return;
+    setDraft({ ...draft, ...updates });
+  };
+
+  const handleSave = async () => {
+    if (!draft) return;
+    setIsSaving(true);
+    try {
+      const account = {
+        ...draft,
+        folders: splitLines(draft.folders.join('\n')),
+        search: { ...draft.search, from: splitLines(draft.search.from.join('\n')) },
+      };
+      let id = account.id;
+      if (id === undefined) {
+        id = await createEmailAccount(account);
+      } else {
+        await updateEmailAccount(account);
+      }
+      if (draft.authMethod === 'password' && password) {
+        await saveAccountPassword(id, password);
+        setPassword('');
+      }
+      const data = await loadAccounts();
+      setDraft(data.find((a) => a.id === id) ?? null);
+      toast({ title: 'Account Saved', description: `${draft.name} has been saved.` });
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    } finally {
+      setIsSaving(false);
+    }
+  };
+
+  const handleDelete = async () => {
+    if (draft?.id === undefined) {
+      setDraft(null);
+      return;
+    }
+    try {
+      await deleteEmailAccount(draft.id);
+      const data = await loadAccounts();
+      setDraft(data[0] ?? null);
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    }
+  };
+
+  const handleTestConnection = async () => {
+    if (!draft) return;
+    setIsTesting(true);
+    try {
+      const message = await testImapConnection(draft, password);
+      toast({ title: 'Connection Successful', description: message });
+    } catch (error) {
+      toast({ title: 'Connection Failed', description: String(error), variant: 'destructive' });
+    } finally {
+      setIsTesting(false);
+    }
+  };
+
+  return (
+    <div className="space-y-8">
+      <div>
+        <h3 className="font-display text-lg font-semibold text-[#2a2a2a]">
+          Email Accounts
+        </h3>
+        <p className="text-sm text-[#6b6b6b]">
+          Configure the email accounts where you receive your receipts.
+        </p>
+      </div>
+
+      <div className="flex flex-wrap items-center gap-2">
+        {accounts.map((account) => (
+          <button
+            key={account.id}
+            onClick={() => handleSelect(account)}
+            className={`rounded-lg px-3 py-1.5 text-sm font-medium transition-colors ${
+              draft?.id === account.id
+                ? 'bg-[#2a2a2a] text-white'
+                : 'text-[#6b6b6b] ring-1 ring-[#e5e5e5] hover:bg-[#f0ede8] hover:text-[#2a2a2a]'
+            } ${account.enabled ? '' : 'opacity-60'}`}
+          >
+            {account.name || account.username}
+          </button>
+        ))}
         <Button
           variant="outline"
-          onClick={onTestConnection}
-          disabled={isTesting}
-          className="border-[#2a2a2a] text-[#2a2a2a] hover:bg-[#2a2a2a] hover:text-white"
+          onClick={() => handleSelect(newAccount())}
+          className={outlineButtonClass}
         >
-          {isTesting ? 'Testing...' : 'Test Connection'}
+          Add Account
         </Button>
       </div>
+
+      {draft ? (
+        <>
+          <div className="grid gap-6 md:grid-cols-2">
+            <div className="space-y-2">
+              <Label htmlFor="accountName">Account Name</Label>
+              <Input
+                id="accountName"
+                placeholder="Personal"
+                value={draft.name}
+                onChange={(e) => handleUpdate({ name: e.target.value })}
+                className={inputClass}
+              />
+            </div>
+
+            <div className="flex items-end space-x-2 pb-2">
+              <input
+                id="accountEnabled"
+                type="checkbox"
+                checked={draft.enabled}
+                onChange={(e) => handleUpdate({ enabled: e.target.checked })}
+              />
+              <Label htmlFor="accountEnabled">Include in sync</Label>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="imapServer">IMAP Server</Label>
+              <Input
+                id="imapServer"
+                placeholder="imap.gmail.com"
+                value={draft.server}
+                onChange={(e) => handleUpdate({ server: e.target.value })}
+                className={inputClass}
+              />
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="imapPort">Port</Label>
+              <Input
+                id="imapPort"
+                type="number"
+                placeholder="993"
+                value={draft.port}
+                onChange={(e) => handleUpdate({ port: parseInt(e.target.value) || 0 })}
+                className={inputClass}
+              />
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="imapUsername">Email Address</Label>
+              <Input
+                id="imapUsername"
+                type="email"
+                placeholder="your@email.com"
+                value={draft.username}
+                onChange={(e) => handleUpdate({ username: e.target.value })}
+                className={inputClass}
+              />
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="imapAuthMethod">Sign-in Method</Label>
+              <Select
+                value={draft.authMethod}
+                onValueChange={(value) => handleUpdate({ authMethod: value as ImapAuthMethod })}
+              >
+                <SelectTrigger id="imapAuthMethod">
+                  <SelectValue />
+                </SelectTrigger>
+                <SelectContent>
+                  <SelectItem value="password">Password</SelectItem>
+                  <SelectItem value="oauth2">OAuth (Gmail, Microsoft 365)</SelectItem>
+                </SelectContent>
+              </Select>
+            </div>
+
+            {draft.authMethod === 'password' ? (
+              <div className="space-y-2">
+                <Label htmlFor="imapPassword">App Password</Label>
+                <Input
+                  id="imapPassword"
+                  type="password"
+                  placeholder="••••••••••••••••"
+                  value={password}
+                  onChange={(e) => setPassword(e.target.value)}
+                  className={inputClass}
+                />
+                <p className="text-[10px] text-[#9b9b9b]">
+                  Kept in the system keychain. Leave empty to keep the saved one.
+                </p>
+              </div>
+            ) : (
+              <OAuthSignIn account={draft} onUpdate={handleUpdate} />
+            )}
+          </div>
+
+          <div className="grid gap-6 md:grid-cols-2">
+            <div className="space-y-2">
+              <Label htmlFor="imapSecurity">Connection Security</Label>
+              <Select
+                value={draft.security}
+                onValueChange={(value) => handleUpdate({ security: value as ImapSecurity })}
+              >
+                <SelectTrigger id="imapSecurity">
+                  <SelectValue />
+                </SelectTrigger>
+                <SelectContent>
+                  <SelectItem value="tls">SSL/TLS</SelectItem>
+                  <SelectItem value="starttls">STARTTLS</SelectItem>
+                  <SelectItem value="plain">None (localhost only)</SelectItem>
+                </SelectContent>
+              </Select>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="imapCaCertificate">Custom CA Certificate</Label>
+              <Input
+                id="imapCaCertificate"
+                placeholder="/path/to/ca.pem"
+                value={draft.caCertificate ?? ''}
+                onChange={(e) => handleUpdate({ caCertificate: e.target.value || null })}
+                className={inputClass}
+              />
+            </div>
+
+            <div className="space-y-2 md:col-span-2">
+              <Label htmlFor="imapPinnedCertificate">Pinned Certificate (SHA-256)</Label>
+              <Input
+                id="imapPinnedCertificate"
+                placeholder="AB:CD:EF:..."
+                value={draft.pinnedCertificate ?? ''}
+                onChange={(e) => handleUpdate({ pinnedCertificate: e.target.value || null })}
+                className={`${inputClass} font-mono`}
+              />
+              <p className="text-[10px] text-[#9b9b9b]">
+                For self-signed servers. Only the certificate with this fingerprint is accepted.
+              </p>
+            </div>
+          </div>
+
+          <div className="grid gap-6 md:grid-cols-2">
+            <div className="space-y-2">
+              <Label htmlFor="accountFolders">Folders</Label>
+              <textarea
+                id="accountFolders"
+                rows={3}
+                placeholder={'INBOX\nReceipts'}
+                value={draft.folders.join('\n')}
+                onChange={(e) => handleUpdate({ folders: e.target.value.split('\n') })}
+                className={`w-full rounded-md border px-3 py-2 text-sm ${inputClass}`}
+              />
+              <p className="text-[10px] text-[#9b9b9b]">
+                One per line, e.g. [Gmail]/All Mail for archived mail.
+              </p>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="searchFrom">From (any of)</Label>
+              <textarea
+                id="searchFrom"
+                rows={3}
+                placeholder={'@stripe.com\nbilling@'}
+                value={draft.search.from.join('\n')}
+                onChange={(e) =>
+                  handleUpdate({ search: { ...draft.search, from: e.target.value.split('\n') } })
+                }
+                className={`w-full rounded-md border px-3 py-2 text-sm ${inputClass}`}
+              />
+              <p className="text-[10px] text-[#9b9b9b]">
+                Leave empty to consider every sender.
+              </p>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="searchSince">Since</Label>
+              <Input
+                id="searchSince"
+                type="date"
+                value={draft.search.since ?? ''}
+                onChange={(e) =>
+                  handleUpdate({ search: { ...draft.search, since: e.target.value || null } })
+                }
+                className={inputClass}
+              />
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="searchSubject">Subject Contains</Label>
+              <Input
+                id="searchSubject"
+                placeholder="receipt"
+                value={draft.search.subject ?? ''}
+                onChange={(e) =>
+                  handleUpdate({ search: { ...draft.search, subject: e.target.value || null } })
+                }
+                className={inputClass}
+              />
+            </div>
+          </div>
+
+          <div className="grid gap-6 md:grid-cols-2">
+            <MessageActionFields
+              id="importedAction"
+              title="After Import"
+              action={draft.importedAction}
+              onChange={(importedAction) => handleUpdate({ importedAction })}
+            />
+            <MessageActionFields
+              id="junkAction"
+              title="Junk Mail"
+              action={draft.junkAction}
+              onChange={(junkAction) => handleUpdate({ junkAction })}
+            />
+          </div>
+
+          <div className="flex items-center gap-3 pt-4">
+            <Button
+              onClick={handleSave}
+              disabled={isSaving || !draft.name || !draft.server}
+              className="bg-[#2a2a2a] text-white hover:bg-[#404040]"
+            >
+              {isSaving ? 'Saving...' : 'Save Account'}
+            </Button>
+            <Button
+              variant="outline"
+              onClick={handleTestConnection}
+              disabled={
+                isTesting ||
+                (draft.id === undefined && (draft.authMethod !== 'password' || !password))
+              }
+              className={outlineButtonClass}
+            >
+              {isTesting ? 'Testing...' : 'Test Connection'}
+            </Button>
+            <Button
+              variant="outline"
+              onClick={handleDelete}
+              className="border-red-600 text-red-600 hover:bg-red-600 hover:text-white"
+            >
+              {draft.id === undefined ? 'Discard' : 'Delete Account'}
+            </Button>
+          </div>
+        </>
+      ) : (
+        <p className="font-mono text-sm text-[#6b6b6b]">
+          No email accounts yet. Add one to start importing receipts.
+        </p>
+      )}
     </div>
   );
 }
@@ -0,0 +1,81 @@
+import { type ReactElement, useEffect, useState } from 'react';
+
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import { useToast } from '@/hooks/use-toast';
+import { cancelEmailSync, importLocalMail, onImportProgress } from '@/lib/tauri';
+import type { SyncProgress } from '@/lib/types';
+
+const inputClass = 'border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0';
+
+export function ImportSettings(): ReactElement {
+  const { toast } = useToast();
+  const [path, setPath] = useState('');
+  const [importing, setImporting] = useState(false);
+  const [progress, setProgress] = useState<SyncProgress | null>(null);
+
+  useEffect(() => {
+    const unlisten = onImportProgress(setProgress);
+    return () => {
+      void unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  async function handleImport(): Promise<void> {
+    setImporting(true);
+    setProgress(null);
+    try {
+      const summary = await importLocalMail(path.trim());
+      toast({
+        title: 'Import Finished',
+        description: `${summary.imported} of ${summary.found} imported for review, ${summary.failed} failed, ${summary.skipped} already imported`,
+      });
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    } finally {
+      setImporting(false);
+      setProgress(null);
+    }
+  }
+
+  return (
+    <div className="space-y-8">
+      <div>
+        <h3 className="font-display text-lg font-semibold text-[#2a2a2a]">Import From Files</h3>
+        <p className="text-sm text-[#6b6b6b]">
+          Import receipts from an mbox export (e.g. Thunderbird), a Maildir folder, a folder of
+          .eml files or a single .eml file. Results wait under Pending Imports for review.
+        </p>
+      </div>
+
+      <div className="space-y-2">
+        <Label htmlFor="importPath">Path</Label>
+        <Input
+          id="importPath"
+          placeholder="/Users/me/Mail/Invoices.mbox"
+          value={path}
+          onChange={(e) => setPath(e.target.value)}
+          className={`${inputClass} font-mono`}
+        />
+      </div>
+
+      <div className="flex items-center gap-4">
+        <Button disabled={importing || path.trim() === ''} onClick={() => void handleImport()}>
+          {importing ? 'Importing...' : 'Import'}
+        </Button>
+        {importing && (
+          <Button variant="outline" onClick={() => void cancelEmailSync()}>
+            Cancel
+          </Button>
+        )}
+        {progress?.index != null && progress.total != null && (
+          <span className="font-mono text-xs text-[#6b6b6b]">
+            {progress.index}/{progress.total} · {progress.stage}
+            {progress.detail && ` · ${progress.detail}`}
+          </span>
+        )}
+      </div>
+    </div>
+  );
+}
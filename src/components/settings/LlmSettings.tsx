@@ -0,0 +1,274 @@
+import { type ReactElement, useEffect, useState, useCallback } from 'react';
+import { Loader2, RefreshCw } from 'lucide-react';
+
+import { Button } from '@/components/ui/button';
+import { Input } from '@/components/ui/input';
+import { Label } from '@/components/ui/label';
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from '@/components/ui/select';
+import { getLlmModels } from '@/lib/tauri';
+import type { AppSettings, LlmProviderKind } from '@/lib/types';
+
+interface LlmSettingsProps {
+  settings: AppSettings;
+  onUpdate: (updates: Partial<AppSettings>) => void;
+}
+
+const PROVIDERS: { id: LlmProviderKind; label: string }[] = [
+  { id: 'ollama', label: 'Ollama' },
+  { id: 'openai', label: 'OpenAI-compatible (LM Studio, vLLM, llama.cpp)' },
+  { id: 'rules', label: 'Rules only (no model)' },
+];
+
+/** Where each provider keeps its endpoint and model in the settings */
+function providerFields(
+  settings: AppSettings
+): { endpoint: string; model: string; placeholder: string; name: string } {
+  if (settings.llmProvider === 'openai') {
+    return {
+      endpoint: settings.openaiEndpoint,
+      model: settings.openaiModel,
+      placeholder: 'http://localhost:1234/v1',
+      name: 'The server',
+    };
+  }
+  return {
+    endpoint: settings.ollamaEndpoint,
+    model: settings.ollamaModel,
+    placeholder: 'http://localhost:11434',
+    name: 'Ollama',
+  };
+}
+
+function LlmStatus({ name, isReachable }: { name: string; isReachable: boolean }): ReactElement {
+  return (
+    <div className="rounded-lg bg-[#fdfcfb] p-4 border border-[#e5e5e5]">
+      <h4 className="text-xs font-bold uppercase tracking-wider text-[#9b9b9b]">
+        Status
+      </h4>
+      <div className="mt-2 flex items-center space-x-2">
+        <div className={`h-2 w-2 rounded-full ${isReachable ? 'bg-green-500' : 'bg-red-500'}`} />
+        <span className="text-sm font-mono text-[#2a2a2a]">
+          {isReachable ? `${name} is reachable` : `${name} is unreachable`}
+        </span>
+      </div>
+    </div>
+  );
+}
+
+interface UseLlmModelsResult {
+  models: string[];
+  isLoading: boolean;
+  error: string | null;
+  fetchModels: () => Promise<void>;
+}
+
+function useLlmModels(
+  provider: LlmProviderKind,
+  endpoint: string,
+  currentModel: string,
+  onSelectModel: (model: string) => void
+): UseLlmModelsResult {
+  const [models, setModels] = useState<string[]>([]);
+  const [isLoading, setIsLoading] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const fetchModels = useCallback(async () => {
+    setModels([]);
+    setError(null);
+    if (provider === 'rules' || !endpoint) {
+      return;
+    }
+
+    setIsLoading(true);
+    try {
+      const availableModels = await getLlmModels(provider, endpoint);
+      setModels(availableModels);
+
+      if (availableModels.length > 0) {
+        if (!currentModel || !availableModels.includes(currentModel)) {
+          const defaultModel = availableModels.find(m => m.toLowerCase().includes('llama')) ?? availableModels[0];
+          onSelectModel(defaultModel);
+        }
+      }
+    } catch (err) {
+      console.error('Failed to fetch models:', err);
+      setError('Could not connect to the server. Make sure it is running.');
+    } finally {
+      setIsLoading(false);
+    }
+  }, [provider, endpoint, currentModel, onSelectModel]);
+
+  useEffect(() => {
+    void fetchModels();
+  }, [fetchModels]);
+
+  return { models, isLoading, error, fetchModels };
+}
+
+/** Radix Select can't use an empty value, so "off" stands in for it */
+const OCR_OFF = 'off';
+
+export function LlmSettings({
+  settings,
+  onUpdate,
+}: LlmSettingsProps): ReactElement {
+  const provider = settings.llmProvider;
+  const fields = providerFields(settings);
+  const updateEndpoint = (endpoint: string): void =>
+    onUpdate(provider === 'openai' ? { openaiEndpoint: endpoint } : { ollamaEndpoint: endpoint });
+  const selectModel = useCallback(
+    (model: string) => onUpdate(provider === 'openai' ? { openaiModel: model } : { ollamaModel: model }),
+    [provider, onUpdate]
+  );
+  const { models, isLoading, error, fetchModels } = useLlmModels(
+    provider,
+    fields.endpoint,
+    fields.model,
+    selectModel
+  );
+
+  return (
+    <div className="space-y-8">
+      <div>
+        <h3 className="font-display text-lg font-semibold text-[#2a2a2a]">
+          AI Configuration
+        </h3>
+        <p className="text-sm text-[#6b6b6b]">
+          Choose what reads your receipts.
+        </p>
+      </div>
+
+      <div className="grid gap-6">
+        <div className="space-y-2">
+          <Label htmlFor="llmProvider">Provider</Label>
+          <Select
+            value={provider}
+            onValueChange={(value) => onUpdate({ llmProvider: value as LlmProviderKind })}
+          >
+            <SelectTrigger id="llmProvider" className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0">
+              <SelectValue />
+            </SelectTrigger>
+            <SelectContent>
+              {PROVIDERS.map(({ id, label }) => (
+                <SelectItem key={id} value={id}>
+                  {label}
+                </SelectItem>
+              ))}
+            </SelectContent>
+          </Select>
+          <p className="text-[10px] text-[#9b9b9b]">
+            {provider === 'rules'
+              ? 'Reads amounts, dates and domain names with fixed patterns. Works without a model, but finds less and cannot read scans.'
+              : 'A local model server. Answers are constrained to the extraction schema.'}
+          </p>
+        </div>
+
+        {provider !== 'rules' && (
+          <>
+            <div className="space-y-2">
+              <Label htmlFor="llmEndpoint">API Endpoint</Label>
+              <div className="flex gap-2">
+                <Input
+                  id="llmEndpoint"
+                  placeholder={fields.placeholder}
+                  value={fields.endpoint}
+                  onChange={(e) => updateEndpoint(e.target.value)}
+                  className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
+                />
+                <Button
+                  variant="outline"
+                  size="icon"
+                  onClick={() => { void fetchModels(); }}
+                  disabled={isLoading}
+                  title="Refresh models"
+                >
+                  {isLoading ? <Loader2 className="h-4 w-4 animate-spin" /> : <RefreshCw className="h-4 w-4" />}
+                </Button>
+              </div>
+              <p className="text-[10px] text-[#9b9b9b]">
+                {provider === 'openai'
+                  ? 'The base URL of the server, e.g. http://localhost:1234/v1 for LM Studio or http://localhost:8080 for llama.cpp.'
+                  : 'The URL where your Ollama instance is running.'}
+              </p>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="llmModel">Model Name</Label>
+              <Select
+                value={fields.model}
+                onValueChange={selectModel}
+                disabled={isLoading || models.length === 0}
+              >
+                <SelectTrigger id="llmModel" className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0">
+                  <SelectValue placeholder={isLoading ? "Loading models..." : "Select a model"} />
+                </SelectTrigger>
+                <SelectContent>
+                  {models.map((model) => (
+                    <SelectItem key={model} value={model}>
+                      {model}
+                    </SelectItem>
+                  ))}
+                </SelectContent>
+              </Select>
+              {error && <p className="text-[10px] text-red-500">{error}</p>}
+              <p className="text-[10px] text-[#9b9b9b]">
+                Recommended: llama3, mistral, or phi3.
+              </p>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="extractionConcurrency">Parallel Extractions</Label>
+              <Input
+                id="extractionConcurrency"
+                type="number"
+                min={1}
+                max={16}
+                value={settings.extractionConcurrency}
+                onChange={(e) =>
+                  onUpdate({ extractionConcurrency: Math.min(16, Math.max(1, Number(e.target.value) || 1)) })
+                }
+                className="w-24 border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0"
+              />
+              <p className="text-[10px] text-[#9b9b9b]">
+                Emails sent to the model at once during sync. Raise it along with the server&apos;s parallel
+                slots (OLLAMA_NUM_PARALLEL for Ollama).
+              </p>
+            </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="ocrModel">OCR Model (vision)</Label>
+              <Select
+                value={settings.ocrModel === '' ? OCR_OFF : settings.ocrModel}
+                onValueChange={(value) => onUpdate({ ocrModel: value === OCR_OFF ? '' : value })}
+                disabled={isLoading}
+              >
+                <SelectTrigger id="ocrModel" className="border-[#e5e5e5] bg-white focus:border-[#2a2a2a] focus:ring-0">
+                  <SelectValue placeholder="Off" />
+                </SelectTrigger>
+                <SelectContent>
+                  <SelectItem value={OCR_OFF}>Off</SelectItem>
+                  {models.map((model) => (
+                    <SelectItem key={model} value={model}>
+                      {model}
+                    </SelectItem>
+                  ))}
+                </SelectContent>
+              </Select>
+              <p className="text-[10px] text-[#9b9b9b]">
+                Reads photographed receipts and scanned PDFs that have no text. Recommended: llava or llama3.2-vision.
+              </p>
+            </div>
+          </>
+        )}
+      </div>
+
+      {provider !== 'rules' && <LlmStatus name={fields.name} isReachable={models.length > 0} />}
+    </div>
+  );
+}
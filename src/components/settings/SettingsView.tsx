@@ -1,14 +1,16 @@
 import { type ReactElement, useEffect, useState } from 'react';
 import { ImapSettings } from './ImapSettings';
-import { OllamaSettings } from './OllamaSettings';
+import { LlmSettings } from './LlmSettings';
 import { DisplaySettings } from './DisplaySettings';
 import { AboutSettings } from './AboutSettings';
+import { SyncHistorySettings } from './SyncHistorySettings';
+import { ImportSettings } from './ImportSettings';
 import { Button } from '@/components/ui/button';
 import { useToast } from '@/hooks/use-toast';
 import { getSettings, updateSettings } from '@/lib/tauri';
 import { AppSettings } from '@/lib/types';
 
-type SettingsTab = 'imap' | 'ollama' | 'display' | 'about';
+type SettingsTab = 'imap' | 'llm' | 'display' | 'history' | 'import' | 'about';
 
 export function SettingsView(): ReactElement {
   const { toast } = useToast();
@@ -16,7 +18,6 @@ export function SettingsView(): ReactElement {
   const [settings, setSettings] = useState<AppSettings | null>(null);
   const [isLoading, setIsLoading] = useState(true);
   const [isSaving, setIsSaving] = useState(false);
-  const [isTestingImap, setIsTestingImap] = useState(false);
 
   useEffect(() => {
     async function loadSettings() {
@@ -64,18 +65,6 @@ export function SettingsView(): ReactElement {
     }
   };
 
-  const handleTestImap = async () => {
-    setIsTestingImap(true);
-    // Simulate testing for now
-    setTimeout(() => {
-      setIsTestingImap(false);
-      toast({
-        title: 'Connection Successful',
-        description: 'Successfully connected to the IMAP server.',
-      });
-    }, 1500);
-  };
-
   if (isLoading) {
     return (
       <div className="flex h-full items-center justify-center bg-[#faf8f5]">
@@ -94,8 +83,10 @@ export function SettingsView(): ReactElement {
 
   const tabs: { id: SettingsTab; label: string }[] = [
     { id: 'imap', label: 'Email (IMAP)' },
-    { id: 'ollama', label: 'AI (Ollama)' },
+    { id: 'llm', label: 'AI' },
     { id: 'display', label: 'Display' },
+    { id: 'history', label: 'Sync History' },
+    { id: 'import', label: 'Import' },
     { id: 'about', label: 'About' },
   ];
 
@@ -140,20 +131,15 @@ export function SettingsView(): ReactElement {
 
           {/* Content Area */}
           <div className="flex-1 rounded-2xl border border-[#e5e5e5] bg-white p-8 shadow-sm">
-            {activeTab === 'imap' && (
-              <ImapSettings
-                settings={settings}
-                onUpdate={handleUpdate}
-                onTestConnection={handleTestImap}
-                isTesting={isTestingImap}
-              />
-            )}
-            {activeTab === 'ollama' && (
-              <OllamaSettings settings={settings} onUpdate={handleUpdate} />
+            {activeTab === 'imap' && <ImapSettings />}
+            {activeTab === 'llm' && (
+              <LlmSettings settings={settings} onUpdate={handleUpdate} />
             )}
             {activeTab === 'display' && (
               <DisplaySettings settings={settings} onUpdate={handleUpdate} />
             )}
+            {activeTab === 'history' && <SyncHistorySettings />}
+            {activeTab === 'import' && <ImportSettings />}
             {activeTab === 'about' && <AboutSettings />}
           </div>
         </div>
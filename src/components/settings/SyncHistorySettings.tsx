@@ -0,0 +1,178 @@
+import { type ReactElement, useEffect, useState } from 'react';
+
+import { Button } from '@/components/ui/button';
+import { useToast } from '@/hooks/use-toast';
+import {
+  cancelEmailSync,
+  getSyncLogDetails,
+  getSyncLogs,
+  reprocessFailedReceipts,
+  retrySyncLogItem,
+} from '@/lib/tauri';
+import type { SyncLog, SyncLogDetails, SyncLogItem } from '@/lib/types';
+
+const PAGE_SIZE = 20;
+
+const STATUS_COLORS: Record<string, string> = {
+  completed: 'text-green-700',
+  imported: 'text-green-700',
+  running: 'text-[#6b6b6b]',
+  skipped: 'text-[#6b6b6b]',
+  cancelled: 'text-amber-700',
+  failed: 'text-red-600',
+};
+
+export function SyncHistorySettings(): ReactElement {
+  const { toast } = useToast();
+  const [logs, setLogs] = useState<SyncLog[]>([]);
+  const [details, setDetails] = useState<SyncLogDetails | null>(null);
+  const [retrying, setRetrying] = useState<number | null>(null);
+  const [reprocessing, setReprocessing] = useState(false);
+
+  useEffect(() => {
+    getSyncLogs(false, { limit: PAGE_SIZE })
+      .then(setLogs)
+      .catch((error) => console.error('Failed to load sync history:', error));
+  }, []);
+
+  async function handleOpen(log: SyncLog): Promise<void> {
+    if (details?.log.id === log.id) {
+      setDetails(null);
+      return;
+    }
+    try {
+      setDetails(await getSyncLogDetails(log.id));
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    }
+  }
+
+  async function handleRetry(item: SyncLogItem): Promise<void> {
+    setRetrying(item.id);
+    try {
+      const updated = await retrySyncLogItem(item.id);
+      setDetails((current) =>
+        current && {
+          ...current,
+          items: current.items.map((i) => (i.id === updated.id ? updated : i)),
+        }
+      );
+      toast({
+        title: updated.status === 'failed' ? 'Retry Failed' : 'Retry Succeeded',
+        description: updated.errorMessage ?? `Message ${updated.status}`,
+        variant: updated.status === 'failed' ? 'destructive' : undefined,
+      });
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    } finally {
+      setRetrying(null);
+    }
+  }
+
+  async function handleReprocessFailed(): Promise<void> {
+    setReprocessing(true);
+    try {
+      const summary = await reprocessFailedReceipts();
+      toast({
+        title: summary.cancelled ? 'Retry Cancelled' : 'Retried Failed Receipts',
+        description: `${summary.extracted} extracted, ${summary.failed} still failing, ${summary.skipped} already done`,
+      });
+    } catch (error) {
+      toast({ title: 'Error', description: String(error), variant: 'destructive' });
+    } finally {
+      setReprocessing(false);
+    }
+  }
+
+  return (
+    <div className="space-y-8">
+      <div className="flex items-start justify-between gap-4">
+        <div>
+          <h3 className="font-display text-lg font-semibold text-[#2a2a2a]">Sync History</h3>
+          <p className="text-sm text-[#6b6b6b]">
+            Recent sync runs and what happened to each message. Failed messages can be retried.
+          </p>
+        </div>
+        <div className="flex items-center gap-2">
+          {(reprocessing || retrying !== null) && (
+            <Button variant="outline" size="sm" onClick={() => void cancelEmailSync()}>
+              Cancel
+            </Button>
+          )}
+          <Button
+            variant="outline"
+            size="sm"
+            disabled={reprocessing}
+            onClick={() => void handleReprocessFailed()}
+            title="Extract stored receipts whose extraction failed, without fetching mail"
+          >
+            {reprocessing ? 'Retrying...' : 'Retry All Failed'}
+          </Button>
+        </div>
+      </div>
+
+      {logs.length === 0 && <p className="font-mono text-xs text-[#9b9b9b]">No syncs yet.</p>}
+
+      <div className="space-y-2">
+        {logs.map((log) => (
+          <div key={log.id} className="rounded-lg border border-[#e5e5e5]">
+            <button
+              onClick={() => void handleOpen(log)}
+              className="flex w-full items-center justify-between px-4 py-3 text-left hover:bg-[#faf8f5]"
+            >
+              <span className="font-mono text-xs text-[#2a2a2a]">
+                {new Date(log.syncStartedAt).toLocaleString()}
+              </span>
+              <span className="font-mono text-xs text-[#6b6b6b]">
+                {log.emailsImported} imported · {log.emailsSkipped} skipped ·{' '}
+                <span className={STATUS_COLORS[log.status]}>{log.status}</span>
+              </span>
+            </button>
+
+            {details?.log.id === log.id && (
+              <div className="space-y-2 border-t border-[#e5e5e5] px-4 py-3">
+                {log.errorMessage && (
+                  <p className="font-mono text-xs text-red-600">{log.errorMessage}</p>
+                )}
+                {details.items.length === 0 && (
+                  <p className="font-mono text-xs text-[#9b9b9b]">No messages in this run.</p>
+                )}
+                {details.items.map((item) => (
+                  <div key={item.id} className="flex items-start justify-between gap-4 text-xs">
+                    <div className="min-w-0">
+                      <p className="truncate font-medium text-[#2a2a2a]">
+                        {item.subject ?? item.messageId ?? `UID ${item.uid}`}
+                      </p>
+                      <p className="font-mono text-[#6b6b6b]">
+                        {item.mailbox} · {item.stage ?? 'not started'} ·{' '}
+                        {(item.durationMs / 1000).toFixed(1)}s
+                        {item.classification && ` · ${item.classification}`}
+                        {item.attempts > 1 && ` · ${item.attempts} attempts`}
+                      </p>
+                      {item.errorMessage && (
+                        <p className="font-mono text-red-600">{item.errorMessage}</p>
+                      )}
+                    </div>
+                    <div className="flex shrink-0 items-center gap-2">
+                      <span className={`font-mono ${STATUS_COLORS[item.status]}`}>{item.status}</span>
+                      {item.status === 'failed' && (
+                        <Button
+                          variant="outline"
+                          size="sm"
+                          disabled={retrying !== null}
+                          onClick={() => void handleRetry(item)}
+                        >
+                          {retrying === item.id ? 'Retrying...' : 'Retry'}
+                        </Button>
+                      )}
+                    </div>
+                  </div>
+                ))}
+              </div>
+            )}
+          </div>
+        ))}
+      </div>
+    </div>
+  );
+}
@@ -9,6 +9,7 @@
  */
 
 import { invoke as tauriInvoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 // Safe invoke wrapper that checks if Tauri is available
 function invoke<T>(cmd: string, args?: Record<string, unknown>): Promise<T> {
@@ -27,7 +28,17 @@ import type {
   Domain,
   PendingImport,
   Receipt,
+  ReceiptSource,
+  ReprocessSummary,
+  ImportSummary,
   AppSettings,
+  LlmProviderKind,
+  EmailAccount,
+  SchedulerStatus,
+  SyncProgress,
+  SyncLog,
+  SyncLogDetails,
+  SyncLogItem,
 } from './types';
 
 // ============================================================================
@@ -181,6 +192,49 @@ export async function getReceiptById(
   return invoke<Receipt | null>('get_receipt_by_id', { id, testMode });
 }
 
+/** Attachments and body considered for the receipt, best first */
+export async function getReceiptSources(
+  id: number,
+  testMode: boolean = false
+): Promise<ReceiptSource[]> {
+  return invoke<ReceiptSource[]>('get_receipt_sources', { id, testMode });
+}
+
+export async function getReceiptAttachment(
+  id: number,
+  testMode: boolean = false
+): Promise<ArrayBuffer> {
+  return invoke<ArrayBuffer>('get_receipt_attachment', { id, testMode });
+}
+
+export async function getReceiptAttachmentPath(
+  id: number,
+  testMode: boolean = false
+): Promise<string | null> {
+  return invoke<string | null>('get_receipt_attachment_path', { id, testMode });
+}
+
+export async function deleteReceipt(
+  id: number,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('delete_receipt', { id, testMode });
+}
+
+/** Converts and extracts a stored receipt again, without fetching mail */
+export async function reprocessReceipt(
+  id: number,
+  testMode: boolean = false
+): Promise<Receipt> {
+  return invoke<Receipt>('reprocess_receipt', { id, testMode });
+}
+
+export async function reprocessFailedReceipts(
+  testMode: boolean = false
+): Promise<ReprocessSummary> {
+  return invoke<ReprocessSummary>('reprocess_failed_receipts', { testMode });
+}
+
 export async function deleteOldReceipts(
   testMode: boolean = false
 ): Promise<number> {
@@ -197,8 +251,11 @@ export async function getSettings(
   return invoke<AppSettings>('get_settings', { testMode });
 }
 
-export async function getOllamaModels(endpoint: string): Promise<string[]> {
-  return invoke<string[]>('get_ollama_models', { endpoint });
+export async function getLlmModels(
+  provider: LlmProviderKind,
+  endpoint: string
+): Promise<string[]> {
+  return invoke<string[]>('get_llm_models', { provider, endpoint });
 }
 
 export async function updateSettings(
@@ -208,32 +265,85 @@ export async function updateSettings(
   return invoke('update_settings', { settings, testMode });
 }
 
-export async function saveImapPassword(password: string): Promise<void> {
-  return invoke('save_imap_password', { password });
+/**
+ * Logs in with the account as edited, before it is saved. Password accounts use
+ * `password`, or the stored one when it is empty; OAuth2 accounts must be authorized.
+ */
+export async function testImapConnection(
+  account: EmailAccount,
+  password: string = '',
+  testMode: boolean = false
+): Promise<string> {
+  return invoke<string>('test_imap_connection', { account, password, testMode });
 }
 
-export async function getImapPassword(): Promise<string> {
-  return invoke<string>('get_imap_password');
+// ============================================================================
+// Email Account Commands
+// ============================================================================
+
+export async function getEmailAccounts(
+  testMode: boolean = false
+): Promise<EmailAccount[]> {
+  return invoke<EmailAccount[]>('get_email_accounts', { testMode });
 }
 
-export interface ImapConnectionConfig {
-  server: string;
-  port: number;
-  username: string;
-  password: string;
-  useSsl: boolean;
+export async function getEmailAccountById(
+  id: number,
+  testMode: boolean = false
+): Promise<EmailAccount> {
+  return invoke<EmailAccount>('get_email_account_by_id', { id, testMode });
 }
 
-export async function testImapConnection(
-  config: ImapConnectionConfig
-): Promise<string> {
-  return invoke<string>('test_imap_connection', {
-    server: config.server,
-    port: config.port,
-    username: config.username,
-    password: config.password,
-    useSsl: config.useSsl,
-  });
+export async function createEmailAccount(
+  account: Omit<EmailAccount, 'id' | 'createdAt' | 'updatedAt'>,
+  testMode: boolean = false
+): Promise<number> {
+  return invoke<number>('create_email_account', { account, testMode });
+}
+
+export async function updateEmailAccount(
+  account: EmailAccount,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('update_email_account', { account, testMode });
+}
+
+/** Also removes the account's password and OAuth token from the keyring */
+export async function deleteEmailAccount(
+  id: number,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('delete_email_account', { id, testMode });
+}
+
+export async function saveAccountPassword(
+  accountId: number,
+  password: string,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('save_account_password', { accountId, password, testMode });
+}
+
+/** Opens the provider's sign-in page and resolves once the refresh token is stored */
+export async function authorizeAccountOAuth(
+  accountId: number,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('authorize_account_oauth', { accountId, testMode });
+}
+
+export async function isAccountOAuthAuthorized(
+  accountId: number,
+  testMode: boolean = false
+): Promise<boolean> {
+  return invoke<boolean>('is_account_oauth_authorized', { accountId, testMode });
+}
+
+export async function disconnectAccountOAuth(
+  accountId: number,
+  testMode: boolean = false
+): Promise<void> {
+  return invoke('disconnect_account_oauth', { accountId, testMode });
 }
 
 // ============================================================================
@@ -252,6 +362,73 @@ export async function getLastSyncTime(
   return invoke<string | null>('get_last_sync_time', { testMode });
 }
 
+export async function getSyncStatus(): Promise<SchedulerStatus> {
+  return invoke<SchedulerStatus>('get_sync_status');
+}
+
+/** Starts the scheduled sync early, without waiting for it */
+export async function syncNow(): Promise<void> {
+  return invoke('sync_now');
+}
+
+export async function pauseSync(): Promise<void> {
+  return invoke('pause_sync');
+}
+
+export async function resumeSync(): Promise<void> {
+  return invoke('resume_sync');
+}
+
+/** Sync runs, most recent first */
+export async function getSyncLogs(
+  testMode: boolean = false,
+  page?: { limit: number; offset?: number }
+): Promise<SyncLog[]> {
+  return invoke<SyncLog[]>('get_sync_logs', { testMode, page });
+}
+
+export async function getSyncLogDetails(
+  id: number,
+  testMode: boolean = false
+): Promise<SyncLogDetails> {
+  return invoke<SyncLogDetails>('get_sync_log_details', { id, testMode });
+}
+
+/** Processes a failed message again and returns its updated row */
+export async function retrySyncLogItem(
+  id: number,
+  testMode: boolean = false
+): Promise<SyncLogItem> {
+  return invoke<SyncLogItem>('retry_sync_log_item', { id, testMode });
+}
+
+/** Stops the sync, import, retry or reprocessing in progress; unfinished mail is picked up next run */
+export async function cancelEmailSync(): Promise<void> {
+  return invoke('cancel_email_sync');
+}
+
+/** Calls `handler` for every progress event of a running sync */
+export async function onSyncProgress(
+  handler: (progress: SyncProgress) => void
+): Promise<UnlistenFn> {
+  return listen<SyncProgress>('sync-progress', (event) => handler(event.payload));
+}
+
+/** Imports an mbox file, a Maildir or .eml files at `path` as pending imports, after any running sync */
+export async function importLocalMail(
+  path: string,
+  testMode: boolean = false
+): Promise<ImportSummary> {
+  return invoke<ImportSummary>('import_local_mail', { path, testMode });
+}
+
+/** Calls `handler` for every progress event of a running import */
+export async function onImportProgress(
+  handler: (progress: SyncProgress) => void
+): Promise<UnlistenFn> {
+  return listen<SyncProgress>('import-progress', (event) => handler(event.payload));
+}
+
 // ============================================================================
 // Database Management Commands
 // ============================================================================
@@ -260,6 +437,7 @@ export async function clearTestDatabase(): Promise<void> {
   return invoke('clear_test_db');
 }
 
+/** Exports the database and its attachment files into a new folder in Downloads; returns its path */
 export async function exportDatabase(
   testMode: boolean = false
 ): Promise<string> {
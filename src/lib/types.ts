@@ -62,6 +62,7 @@ export interface PendingImport {
   receiptId: number | null;
   status: string;
   createdAt: string; // ISO 8601 datetime
+  messageId?: string; // Message-ID of the source email
 }
 
 // Parsed extracted data interfaces (JSON.parse of extractedData field)
@@ -95,28 +96,189 @@ export interface Receipt {
   emailSubject: string;
   emailFrom: string;
   emailDate: string; // ISO 8601 datetime
-  attachmentData?: string; // Base64 encoded
   attachmentMimeType?: string;
+  attachmentHash?: string; // SHA-256 of the stored file
+  attachmentSize?: number; // bytes
   rawEmailBody?: string;
   createdAt: string; // ISO 8601 datetime
+  messageId?: string; // Message-ID of the source email
+  accountId?: number; // Email account the receipt was imported from
+  imapAction?: string | null; // e.g. "seen, moved to Receipts"
+  processingState: ReceiptProcessingState;
+  processingError?: string | null; // why extraction failed
+  rawMessageHash?: string | null; // SHA-256 of the stored source message
+  ocrText?: string | null; // text recognized in an image or scanned PDF
+  ocrConfidence?: number | null; // 0 to 1
+}
+
+/** An attachment or the email body considered for a receipt */
+export interface ReceiptSource {
+  name: string; // attachment filename, or "body"
+  contentType: string;
+  fileHash?: string | null;
+  fileSize: number; // bytes
+  inline: boolean; // embedded in the message rather than attached
+  score: number; // higher looks more like the receipt
+  used: boolean; // part of what the LLM read
+}
+
+export type ReceiptProcessingState = 'fetched' | 'converted' | 'extracted' | 'failed';
+
+/** Result of retrying every failed receipt */
+export interface ReprocessSummary {
+  extracted: number;
+  failed: number;
+  skipped: number; // extracted meanwhile by a sync
+  cancelled: boolean; // stopped before every receipt was retried
+}
+
+export interface ImportSummary {
+  found: number;
+  imported: number;
+  failed: number; // stored, but extraction failed
+  skipped: number; // imported before
 }
 
 // ============================================================================
 // Settings Types
 // ============================================================================
 
+export type ImapSecurity = 'tls' | 'starttls' | 'plain';
+export type ImapAuthMethod = 'password' | 'oauth2';
+export type OAuthProvider = 'google' | 'microsoft';
+
+/** Server-side IMAP SEARCH filters; every set criterion must match */
+export interface SearchCriteria {
+  since?: string | null; // YYYY-MM-DD; also where the first sync starts
+  from: string[]; // sender substrings, any one matches
+  subject?: string | null;
+}
+
+/** What happens to a message on the server after import */
+export interface MessageAction {
+  markSeen: boolean;
+  keyword?: string | null; // e.g. "$SubScriptImported"
+  copyTo?: string | null;
+  moveTo?: string | null;
+}
+
+/** An IMAP mailbox receipts are imported from */
+export interface EmailAccount {
+  id?: number;
+  name: string;
+  server: string;
+  port: number;
+  username: string;
+  security: ImapSecurity;
+  /** Path to a PEM file with an extra CA to trust */
+  caCertificate?: string | null;
+  /** SHA-256 fingerprint of the server certificate to accept */
+  pinnedCertificate?: string | null;
+  authMethod: ImapAuthMethod;
+  oauthProvider: OAuthProvider;
+  oauthClientId?: string | null;
+  oauthClientSecret?: string | null;
+  /** Folders to scan, e.g. "INBOX" or "[Gmail]/All Mail" */
+  folders: string[];
+  search: SearchCriteria;
+  /** Applied to messages imported as subscriptions or domains */
+  importedAction: MessageAction;
+  /** Applied to messages classified as junk */
+  junkAction: MessageAction;
+  enabled: boolean;
+  createdAt?: string; // ISO 8601 datetime
+  updatedAt?: string; // ISO 8601 datetime
+}
+
+/** What reads receipts: Ollama, a server with the OpenAI API (LM Studio, vLLM, llama.cpp) or fixed rules */
+export type LlmProviderKind = 'ollama' | 'openai' | 'rules';
+
 export interface AppSettings {
-  imapServer: string;
-  imapPort: number;
-  imapUsername: string;
-  imapUseSsl: boolean;
+  llmProvider: LlmProviderKind;
   ollamaEndpoint: string;
   ollamaModel: string;
+  /** Base URL of an OpenAI-compatible server, with or without /v1 */
+  openaiEndpoint: string;
+  openaiModel: string;
   defaultCurrency: string;
   syncIntervalMinutes: number;
+  /** Sync as soon as mail arrives, using IMAP IDLE where the server supports it */
+  pushSync: boolean;
+  /** Emails sent to the model at once during sync */
+  extractionConcurrency: number;
+  /** Vision model that reads photographed receipts and scanned PDFs; empty turns OCR off */
+  ocrModel: string;
   theme: string;
 }
 
+/** One sync run of one account */
+export interface SyncLog {
+  id: number;
+  syncStartedAt: string; // ISO 8601 datetime
+  syncCompletedAt: string | null;
+  emailsProcessed: number;
+  emailsImported: number;
+  emailsSkipped: number;
+  status: 'running' | 'completed' | 'failed' | 'cancelled';
+  errorMessage: string | null;
+  createdAt: string;
+  accountId: number | null;
+}
+
+/** What happened to one message during a sync run */
+export interface SyncLogItem {
+  id: number;
+  syncLogId: number;
+  mailbox: string;
+  uid: number;
+  uidValidity: number;
+  messageId: string | null;
+  subject: string | null;
+  status: 'imported' | 'skipped' | 'failed';
+  stage: string | null; // last stage reached, e.g. 'extracting'
+  durationMs: number;
+  classification: string | null;
+  errorMessage: string | null;
+  receiptId: number | null;
+  attempts: number;
+  createdAt: string;
+  updatedAt: string;
+}
+
+export interface SyncLogDetails {
+  log: SyncLog;
+  items: SyncLogItem[];
+}
+
+export type SyncStage =
+  | 'connected'
+  | 'messagesFound'
+  | 'converting'
+  | 'recognizing'
+  | 'extracting'
+  | 'saved'
+  | 'skipped'
+  | 'failed'
+  | 'cancelled'
+  | 'finished';
+
+/** Payload of the `sync-progress` event */
+export interface SyncProgress {
+  stage: SyncStage;
+  accountId: number | null;
+  mailbox: string | null;
+  index: number | null; // 1-based position of the message in its folder's batch
+  total: number | null;
+  detail: string | null; // message subject, or the error for failed stages
+}
+
+/** Background sync scheduler state */
+export interface SchedulerStatus {
+  paused: boolean;
+  running: boolean; // a scheduled or manual sync is in progress
+  nextRunAt: string | null; // ISO 8601 datetime; null while paused or running
+}
+
 // ============================================================================
 // UI/Component Types
 // ============================================================================